@@ -0,0 +1,486 @@
+//! On-disk cache for the built BVH, so that scenes with large meshes do not have to rebuild it on every render.
+//!
+//! The cache stores the nodes of the [`FlatBVH`] and, for each primitive slot, the index of the primitive in the list of hitables created from the scene file. The cache file is keyed by a hash of the object descriptions, the contents of the files they refer to including the external buffers of glTF files, and the [`BvhAlgorithm`].
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use clovers::{
+    aabb::AABB,
+    bvh::{BvhAlgorithm, FlatBVH, FlatNode},
+    hitable::{Hitable, HitableTrait},
+    interval::Interval,
+    objects::Object,
+    Float,
+};
+use serde_json::Value;
+use tracing::{debug, info};
+
+/// Identifies the cache files
+const MAGIC: &[u8; 8] = b"CLOVBVH\0";
+/// Version of the cache file format. Bump this whenever the format or the BVH builders change.
+const FORMAT_VERSION: u32 = 1;
+
+/// Returns the default directory for the BVH cache
+#[must_use]
+pub fn default_cache_directory() -> PathBuf {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir);
+    base.join("clovers").join("bvh")
+}
+
+/// A cache file for a specific scene and [`BvhAlgorithm`]
+#[derive(Debug, Clone)]
+pub struct BvhCache {
+    key: u64,
+    path: PathBuf,
+    bvh_algorithm: BvhAlgorithm,
+}
+
+impl BvhCache {
+    /// Creates the cache handle for the given objects and [`BvhAlgorithm`], hashing the object descriptions and the contents of the files they refer to.
+    ///
+    /// # Errors
+    /// Returns an error if the objects cannot be serialized, or a file referred to by an object or a buffer referred to by a glTF file cannot be read.
+    pub fn new(
+        directory: &Path,
+        objects: &[Object],
+        bvh_algorithm: BvhAlgorithm,
+    ) -> Result<Self, Box<dyn Error>> {
+        let description = serde_json::to_value(objects)?;
+        let mut hash = Fnv1a::default();
+        hash.write(&FORMAT_VERSION.to_le_bytes());
+        hash.write(bvh_algorithm.to_string().as_bytes());
+        hash.write(description.to_string().as_bytes());
+        let mut paths = Vec::new();
+        file_paths(&description, &mut paths);
+        for path in paths {
+            let bytes = fs::read(path)?;
+            hash.write(path.as_bytes());
+            hash.write(&bytes);
+            // The geometry of a .gltf file lives in its external buffers
+            if Path::new(path)
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("gltf"))
+            {
+                for buffer in gltf_buffer_paths(Path::new(path), &bytes)? {
+                    hash.write(buffer.to_string_lossy().as_bytes());
+                    hash.write(&fs::read(&buffer)?);
+                }
+            }
+        }
+        let key = hash.finish();
+        let path = directory.join(format!("{key:016x}.bvh"));
+        Ok(BvhCache {
+            key,
+            path,
+            bvh_algorithm,
+        })
+    }
+
+    /// Loads the cached BVH for the given hitables. Returns `None` if there is no cache file, or if it is not valid for these hitables.
+    #[must_use]
+    pub fn load<'scene>(&self, hitables: &[Hitable<'scene>]) -> Option<FlatBVH<'scene>> {
+        let bytes = fs::read(&self.path).ok()?;
+        let result = self.parse(&bytes, hitables);
+        if result.is_none() {
+            debug!("Ignoring invalid BVH cache file {}", self.path.display());
+        }
+        result
+    }
+
+    fn parse<'scene>(&self, bytes: &[u8], hitables: &[Hitable<'scene>]) -> Option<FlatBVH<'scene>> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC
+            || reader.u32()? != FORMAT_VERSION
+            || reader.u64()? != self.key
+            || reader.usize()? != hitables.len()
+        {
+            return None;
+        }
+
+        let node_count = reader.usize()?;
+        let mut nodes = Vec::with_capacity(node_count.min(bytes.len()));
+        for _ in 0..node_count {
+            let mut interval = || Some(Interval::new(reader.float()?, reader.float()?));
+            let aabb = AABB::new(interval()?, interval()?, interval()?);
+            nodes.push(FlatNode {
+                aabb,
+                offset: reader.u32()?,
+                count: reader.u32()?,
+            });
+        }
+
+        let primitive_count = reader.usize()?;
+        let mut primitives = Vec::with_capacity(primitive_count.min(bytes.len()));
+        for _ in 0..primitive_count {
+            primitives.push(hitables.get(reader.u32()? as usize)?.clone());
+        }
+        if !reader.bytes.is_empty()
+            || !is_valid(&nodes, primitives.len())
+            || !leaves_fit(&nodes, &primitives, self.bvh_algorithm)
+        {
+            return None;
+        }
+
+        Some(FlatBVH::from_parts(nodes, primitives))
+    }
+
+    /// Saves the BVH into the cache. The `index` must have been created from the hitables the BVH was built from.
+    ///
+    /// # Errors
+    /// Returns an error if the cache file cannot be written, or if a primitive of the BVH cannot be found in the `index`.
+    pub fn save(&self, bvh: &FlatBVH, index: &PrimitiveIndex) -> Result<(), Box<dyn Error>> {
+        let mut cursors: HashMap<PrimitiveKey, usize> = HashMap::new();
+        let mut indices = Vec::with_capacity(bvh.primitives.len());
+        for primitive in &bvh.primitives {
+            let key = PrimitiveKey::new(primitive);
+            let candidates = index
+                .0
+                .get(&key)
+                .ok_or("BVH primitive not found in the hitables")?;
+            // Primitives with the same key are interchangeable. Cycling through them makes sure each is referenced.
+            let cursor = cursors.entry(key).or_default();
+            indices.push(u32::try_from(candidates[*cursor % candidates.len()])?);
+            *cursor += 1;
+        }
+
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        // Write into a temporary file first, so that an interrupted write never leaves a partial cache file behind
+        let temporary = self.path.with_extension("tmp");
+        let mut writer = BufWriter::new(fs::File::create(&temporary)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&self.key.to_le_bytes())?;
+        writer.write_all(&(index.1 as u64).to_le_bytes())?;
+        writer.write_all(&(bvh.nodes.len() as u64).to_le_bytes())?;
+        for node in &bvh.nodes {
+            for axis in 0..3 {
+                writer.write_all(&node.aabb.axis(axis).min.to_le_bytes())?;
+                writer.write_all(&node.aabb.axis(axis).max.to_le_bytes())?;
+            }
+            writer.write_all(&node.offset.to_le_bytes())?;
+            writer.write_all(&node.count.to_le_bytes())?;
+        }
+        writer.write_all(&(indices.len() as u64).to_le_bytes())?;
+        for index in indices {
+            writer.write_all(&index.to_le_bytes())?;
+        }
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&temporary, &self.path)?;
+        info!("Saved the BVH into the cache file {}", self.path.display());
+        Ok(())
+    }
+}
+
+/// Collects the values of all `path` fields in the object descriptions
+fn file_paths<'a>(value: &'a Value, paths: &mut Vec<&'a str>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match value {
+                    Value::String(path) if key == "path" => paths.push(path),
+                    _ => file_paths(value, paths),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                file_paths(value, paths);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Returns the paths of the external buffers of a .gltf file, relative to the directory of the file. Buffers embedded as data URIs are part of the file itself.
+fn gltf_buffer_paths(path: &Path, bytes: &[u8]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let gltf: Value = serde_json::from_slice(bytes)?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let buffers = gltf
+        .get("buffers")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    Ok(buffers
+        .iter()
+        .filter_map(|buffer| buffer.get("uri").and_then(Value::as_str))
+        .filter(|uri| !uri.starts_with("data:"))
+        .map(|uri| directory.join(uri))
+        .collect())
+}
+
+/// Checks that the primitives of each leaf node lie within the bounding box of the node. With spatial splits, the primitives are clipped to the node, so they only need to overlap it.
+fn leaves_fit(nodes: &[FlatNode], primitives: &[Hitable], bvh_algorithm: BvhAlgorithm) -> bool {
+    let clipped = bvh_algorithm == BvhAlgorithm::Sbvh;
+    nodes.iter().filter(|node| node.is_leaf()).all(|node| {
+        let offset = node.offset as usize;
+        primitives[offset..offset + node.count as usize]
+            .iter()
+            .filter_map(Hitable::aabb)
+            .all(|aabb| {
+                (0..3).all(|axis| {
+                    let inner = aabb.axis(axis);
+                    let outer = node.aabb.axis(axis);
+                    if clipped {
+                        inner.min <= outer.max && outer.min <= inner.max
+                    } else {
+                        outer.min <= inner.min && inner.max <= outer.max
+                    }
+                })
+            })
+    })
+}
+
+/// Checks that the nodes only refer to existing nodes and primitives, and that the child nodes come after their parents
+fn is_valid(nodes: &[FlatNode], primitive_count: usize) -> bool {
+    nodes.iter().enumerate().all(|(index, node)| {
+        let offset = node.offset as usize;
+        if node.is_leaf() {
+            offset + node.count as usize <= primitive_count
+        } else {
+            index + 1 < nodes.len() && index < offset && offset < nodes.len()
+        }
+    })
+}
+
+/// Identifies a primitive by everything the BVH builders look at: the bounding box, the centroid, and the vertices of triangles. Primitives with the same key are interchangeable within the BVH.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct PrimitiveKey([u64; 18]);
+
+impl PrimitiveKey {
+    fn new(hitable: &Hitable) -> Self {
+        let mut values: [Float; 18] = [0.0; 18];
+        if let Some(aabb) = hitable.aabb() {
+            for axis in 0..3 {
+                values[2 * axis] = aabb.axis(axis).min;
+                values[2 * axis + 1] = aabb.axis(axis).max;
+            }
+        }
+        let centroid = hitable.centroid();
+        values[6..9].copy_from_slice(centroid.as_slice());
+        let triangle = match hitable {
+            Hitable::Triangle(triangle) => Some(triangle),
+            Hitable::OBJTriangle(triangle) => Some(&triangle.triangle),
+            _ => None,
+        };
+        if let Some(triangle) = triangle {
+            values[9..12].copy_from_slice(triangle.q.as_slice());
+            values[12..15].copy_from_slice(triangle.u.as_slice());
+            values[15..18].copy_from_slice(triangle.v.as_slice());
+        }
+        PrimitiveKey(values.map(|value| f64::from(value).to_bits()))
+    }
+}
+
+/// Lookup from the [`PrimitiveKey`]s of the hitables to their indices, used for saving a BVH into the cache. Also holds the count of the hitables.
+#[derive(Debug, Clone)]
+pub struct PrimitiveIndex(HashMap<PrimitiveKey, Vec<usize>>, usize);
+
+impl PrimitiveIndex {
+    /// Creates the lookup for the given hitables. Must be called before the hitables are consumed by the BVH build.
+    #[must_use]
+    pub fn new(hitables: &[Hitable]) -> Self {
+        let mut index: HashMap<PrimitiveKey, Vec<usize>> = HashMap::new();
+        for (i, hitable) in hitables.iter().enumerate() {
+            index.entry(PrimitiveKey::new(hitable)).or_default().push(i);
+        }
+        PrimitiveIndex(index, hitables.len())
+    }
+}
+
+/// The 64-bit FNV-1a hash. Unlike the standard library hashers, guaranteed to stay the same between runs and versions.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Reads little-endian values from the front of a byte slice
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < count {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.array()?))
+    }
+
+    fn usize(&mut self) -> Option<usize> {
+        usize::try_from(self.u64()?).ok()
+    }
+
+    fn float(&mut self) -> Option<Float> {
+        Some(Float::from_le_bytes(self.array()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clovers::{bvh::BVHNode, materials::Material, objects::Sphere, Position};
+
+    use super::*;
+
+    /// Creates an empty temporary directory, unique to the test
+    fn temporary_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("clovers-bvh-cache-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn spheres(offset: Float) -> Vec<Hitable<'static>> {
+        let material: &'static Material = Box::leak(Box::default());
+        (0..16)
+            .map(|i| {
+                #[allow(clippy::cast_precision_loss)]
+                let x = offset + (i * i % 7) as Float;
+                Hitable::Sphere(Sphere::new(
+                    Position::new(x, i as Float, 0.0),
+                    0.5,
+                    material,
+                ))
+            })
+            .collect()
+    }
+
+    fn gltf_objects(path: &Path) -> Vec<Object> {
+        let description = serde_json::json!([{ "kind": "GLTF", "path": path }]);
+        serde_json::from_value(description).unwrap()
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let directory = temporary_directory("round-trip");
+        for bvh_algorithm in [
+            BvhAlgorithm::Lam,
+            BvhAlgorithm::Sah,
+            BvhAlgorithm::BinnedSah,
+            BvhAlgorithm::Sbvh,
+        ] {
+            let cache = BvhCache::new(&directory, &[], bvh_algorithm).unwrap();
+            let hitables = spheres(0.0);
+            let index = PrimitiveIndex::new(&hitables);
+            let built = BVHNode::from_list(bvh_algorithm, hitables.clone()).flatten();
+            cache.save(&built, &index).unwrap();
+
+            let loaded = cache.load(&hitables).expect("cached BVH should load");
+            assert_eq!(loaded.nodes.len(), built.nodes.len());
+            for (a, b) in loaded.nodes.iter().zip(&built.nodes) {
+                assert_eq!((a.offset, a.count), (b.offset, b.count));
+                assert_eq!(a.aabb.centroid(), b.aabb.centroid());
+            }
+            for (a, b) in loaded.primitives.iter().zip(&built.primitives) {
+                assert_eq!(a.centroid(), b.centroid());
+            }
+        }
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn rejects_primitives_outside_leaves() {
+        let directory = temporary_directory("outside-leaves");
+        let cache = BvhCache::new(&directory, &[], BvhAlgorithm::Sah).unwrap();
+        let hitables = spheres(0.0);
+        let index = PrimitiveIndex::new(&hitables);
+        let built = BVHNode::from_list(BvhAlgorithm::Sah, hitables).flatten();
+        cache.save(&built, &index).unwrap();
+
+        // Same count of hitables, but different geometry
+        assert!(cache.load(&spheres(100.0)).is_none());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn key_changes_with_gltf_buffer() {
+        let directory = temporary_directory("gltf-buffer");
+        let gltf = directory.join("mesh.gltf");
+        fs::write(
+            &gltf,
+            r#"{"buffers": [{"uri": "mesh.bin", "byteLength": 4}]}"#,
+        )
+        .unwrap();
+        fs::write(directory.join("mesh.bin"), [0, 1, 2, 3]).unwrap();
+        let objects = gltf_objects(&gltf);
+
+        let before = BvhCache::new(&directory, &objects, BvhAlgorithm::Sah).unwrap();
+        let unchanged = BvhCache::new(&directory, &objects, BvhAlgorithm::Sah).unwrap();
+        assert_eq!(before.key, unchanged.key);
+
+        fs::write(directory.join("mesh.bin"), [3, 2, 1, 0]).unwrap();
+        let after = BvhCache::new(&directory, &objects, BvhAlgorithm::Sah).unwrap();
+        assert_ne!(before.key, after.key);
+        assert_ne!(before.path, after.path);
+
+        // A missing buffer cannot be hashed
+        fs::remove_file(directory.join("mesh.bin")).unwrap();
+        assert!(BvhCache::new(&directory, &objects, BvhAlgorithm::Sah).is_err());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn key_changes_with_input_file() {
+        let directory = temporary_directory("input-file");
+        let gltf = directory.join("embedded.gltf");
+        let embedded = r#"{"buffers": [{"uri": "data:application/octet-stream;base64,AAEC", "byteLength": 3}]}"#;
+        fs::write(&gltf, embedded).unwrap();
+        let objects = gltf_objects(&gltf);
+
+        let before = BvhCache::new(&directory, &objects, BvhAlgorithm::Sah).unwrap();
+        fs::write(&gltf, embedded.replace("AAEC", "AgEA")).unwrap();
+        let after = BvhCache::new(&directory, &objects, BvhAlgorithm::Sah).unwrap();
+        assert_ne!(before.key, after.key);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn key_changes_with_algorithm() {
+        let directory = temporary_directory("algorithm");
+        let sah = BvhCache::new(&directory, &[], BvhAlgorithm::Sah).unwrap();
+        let lam = BvhCache::new(&directory, &[], BvhAlgorithm::Lam).unwrap();
+        assert_ne!(sah.key, lam.key);
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -4,7 +4,7 @@ use clovers::wavelength::{
     random_wavelength, rotate_wavelength, wavelength_into_xyz, WAVE_SAMPLE_COUNT,
 };
 use clovers::Vec2;
-use clovers::{ray::Ray, scenes::Scene, Float};
+use clovers::{scenes::Scene, Float};
 use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use palette::chromatic_adaptation::AdaptInto;
 use palette::white_point::E;
@@ -17,8 +17,11 @@ use crate::debug_visualizations::{bvh_testcount, primitive_testcount};
 use crate::normals::normal_map;
 use crate::render::{RenderMode, RenderOptions};
 use crate::sampler::blue::BlueSampler;
+use crate::sampler::halton::HaltonSampler;
+use crate::sampler::pmj::Pmj02Sampler;
 use crate::sampler::random::RandomSampler;
-use crate::sampler::{Randomness, Sampler, SamplerTrait};
+use crate::sampler::sobol::SobolSampler;
+use crate::sampler::{PixelSample, Randomness, Sampler, SamplerTrait};
 use crate::trace::trace;
 use crate::GlobalOptions;
 
@@ -40,6 +43,8 @@ pub fn draw(
         mode,
         sampler,
         bvh: _,
+        bvh_layout: _,
+        no_bvh_cache: _,
         formats: _,
     } = *render_options;
     let bar = progress_bar(height, quiet);
@@ -56,7 +61,10 @@ pub fn draw(
             let mut sampler_rng = SmallRng::from_os_rng();
             let mut sampler: Box<dyn SamplerTrait> = match sampler {
                 Sampler::Blue => Box::new(BlueSampler::new(samples)),
+                Sampler::Halton => Box::new(HaltonSampler::new()),
+                Sampler::Pmj02 => Box::new(Pmj02Sampler::new()),
                 Sampler::Random => Box::new(RandomSampler::new(&mut sampler_rng)),
+                Sampler::Sobol => Box::new(SobolSampler::new()),
             };
 
             let mut rng = SmallRng::from_os_rng();
@@ -122,11 +130,18 @@ fn render_pixel(
             (pixel_location.y + pixel_offset.y) / canvas_size.y,
         );
         // note get_ray wants uv 0..1 location
-        let ray: Ray = scene
-            .camera
-            .get_ray(pixel_uv, lens_offset, time, wavelength);
+        let Some((ray, weight)) =
+            scene
+                .camera
+                .get_weighted_ray(pixel_uv, Some(lens_offset), time, wavelength)
+        else {
+            // Outside of the area covered by the camera projection
+            continue;
+        };
         let waves = rotate_wavelength(wavelength);
-        let spectral_powers = trace(&ray, scene, 0, max_depth, rng, sampler);
+        let mut pixel_sample = PixelSample::new(sampler, x as i32, y as i32, sample as i32);
+        let spectral_powers =
+            trace(&ray, scene, 0, max_depth, rng, &mut pixel_sample).map(|power| power * weight);
         // Does our path have terminated wavelengths, i.e. does the path include a dispersive material?
         if spectral_powers[1..].iter().all(|&p| p == 0.0) {
             // Yes; colorize based on hero wavelength only
@@ -155,12 +170,11 @@ fn render_pixel_normalmap(
     let (x, y, width, height) = index_to_params(opts, index);
     let color: LinSrgb = {
         let pixel_location = Vec2::new(x / width, y / height);
-        let lens_offset = Vec2::new(0.0, 0.0);
         let wavelength = random_wavelength(rng);
         let time = rng.random();
-        let ray: Ray = scene
-            .camera
-            .get_ray(pixel_location, lens_offset, time, wavelength);
+        let Some(ray) = scene.camera.get_ray(pixel_location, None, time, wavelength) else {
+            return Xyz::new(0.0, 0.0, 0.0);
+        };
         normal_map(&ray, scene, rng)
     };
     color.adapt_into()
@@ -176,12 +190,11 @@ fn render_pixel_bvhtestcount(
 ) -> Xyz<E> {
     let (x, y, width, height) = index_to_params(render_options, index);
     let pixel_location = Vec2::new(x / width, y / height);
-    let lens_offset = Vec2::new(0.0, 0.0);
     let wavelength = random_wavelength(rng);
     let time = rng.random();
-    let ray: Ray = scene
-        .camera
-        .get_ray(pixel_location, lens_offset, time, wavelength);
+    let Some(ray) = scene.camera.get_ray(pixel_location, None, time, wavelength) else {
+        return Xyz::new(0.0, 0.0, 0.0);
+    };
 
     bvh_testcount(&ray, scene, rng)
 }
@@ -196,12 +209,11 @@ fn render_pixel_primitivetestcount(
 ) -> Xyz<E> {
     let (x, y, width, height) = index_to_params(render_options, index);
     let pixel_location = Vec2::new(x / width, y / height);
-    let lens_offset = Vec2::new(0.0, 0.0);
     let wavelength = random_wavelength(rng);
     let time = rng.random();
-    let ray: Ray = scene
-        .camera
-        .get_ray(pixel_location, lens_offset, time, wavelength);
+    let Some(ray) = scene.camera.get_ray(pixel_location, None, time, wavelength) else {
+        return Xyz::new(0.0, 0.0, 0.0);
+    };
 
     primitive_testcount(&ray, scene, rng)
 }
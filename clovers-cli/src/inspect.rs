@@ -0,0 +1,183 @@
+use std::{error::Error, fs, path::Path};
+
+use clap::Args;
+use clovers::{aabb::AABB, bvh::BvhStats};
+use serde_json::Value;
+
+use crate::bvh_cache::default_cache_directory;
+use crate::json_scene;
+use crate::render::{BvhAlgorithm, BvhLayout};
+
+#[derive(Args, Debug)]
+pub struct InspectParams {
+    /// Input filename / location
+    #[arg()]
+    input: String,
+    /// BVH construction algorithm.
+    #[arg(long, default_value = "sah")]
+    bvh: BvhAlgorithm,
+    /// Layout of the BVH.
+    #[arg(long, default_value = "binary")]
+    bvh_layout: BvhLayout,
+    /// Always rebuild the BVH, instead of loading it from the cache when possible.
+    #[arg(long)]
+    no_bvh_cache: bool,
+}
+
+/// Material kinds that emit light
+const LIGHT_KINDS: [&str; 2] = ["DiffuseLight", "ConeLight"];
+
+pub(crate) fn inspect(params: InspectParams) -> Result<(), Box<dyn Error>> {
+    let InspectParams {
+        input,
+        bvh,
+        bvh_layout,
+        no_bvh_cache,
+    } = params;
+    let path = Path::new(&input);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => (),
+        _ => panic!("Unknown file type"),
+    }
+
+    // The descriptions are listed from the raw file, as the initialized scene no longer knows about objects and materials
+    let description: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let objects = array(&description, "objects");
+    let materials = array(&description, "materials");
+
+    println!("Objects: {}", objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        println!("  {index:>4}: {}", describe_object(object));
+    }
+    println!("Materials: {}", materials.len());
+    for material in materials {
+        println!(
+            "  {}: {}",
+            string(material, "name").unwrap_or("<unnamed>"),
+            string(material, "kind").unwrap_or("<unknown>")
+        );
+    }
+    let lights: Vec<_> = objects
+        .iter()
+        .enumerate()
+        .filter(|(_, object)| is_light(object, materials))
+        .collect();
+    println!("Lights: {}", lights.len());
+    for (index, object) in lights {
+        println!("  {index:>4}: {}", describe_object(object));
+    }
+    println!();
+
+    let bvh_cache = (!no_bvh_cache).then(default_cache_directory);
+    let (scene, _) = json_scene::initialize(
+        path,
+        bvh.into(),
+        bvh_layout.into(),
+        bvh_cache.as_deref(),
+        1,
+        1,
+    )?;
+    match scene.bvh_root.aabb() {
+        Some(aabb) => print_bounds(&aabb),
+        None => println!("Scene bounds: empty"),
+    }
+    println!();
+
+    let bvh_algorithm: clovers::bvh::BvhAlgorithm = bvh.into();
+    let bvh_layout: clovers::bvh::BvhLayout = bvh_layout.into();
+    println!("BVH: {bvh_algorithm}, {bvh_layout}");
+    print_stats(&scene.bvh_root.stats());
+    Ok(())
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .map_or(&[], Vec::as_slice)
+}
+
+fn string<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key).and_then(Value::as_str)
+}
+
+/// Returns a one-line description of an object: the kind, followed by the comment, file path and material where present
+fn describe_object(object: &Value) -> String {
+    let mut description = string(object, "kind").unwrap_or("<unknown>").to_owned();
+    if let Some(comment) = string(object, "comment") {
+        description += &format!(" \"{comment}\"");
+    }
+    if let Some(path) = string(object, "path") {
+        description += &format!(" from {path}");
+    }
+    match object.get("material") {
+        Some(Value::String(name)) => description += &format!(", material: {name}"),
+        Some(material) => {
+            let kind = string(material, "kind").unwrap_or("<unknown>");
+            description += &format!(", material: inline {kind}");
+        }
+        None => (),
+    }
+    if object.get("priority").and_then(Value::as_bool) == Some(true) {
+        description += ", priority";
+    }
+    description
+}
+
+/// Returns true if the material of the object, either inline or shared by name, emits light
+fn is_light(object: &Value, materials: &[Value]) -> bool {
+    let kind = match object.get("material") {
+        Some(Value::String(name)) => materials
+            .iter()
+            .find(|material| string(material, "name") == Some(name))
+            .and_then(|material| string(material, "kind")),
+        Some(material) => string(material, "kind"),
+        None => None,
+    };
+    kind.is_some_and(|kind| LIGHT_KINDS.contains(&kind))
+}
+
+fn print_bounds(aabb: &AABB) {
+    let (min, max) = aabb.bounding_positions();
+    let size = max - min;
+    println!("Scene bounds:");
+    println!("  min:  [{}, {}, {}]", min.x, min.y, min.z);
+    println!("  max:  [{}, {}, {}]", max.x, max.y, max.z);
+    println!("  size: [{}, {}, {}]", size.x, size.y, size.z);
+}
+
+fn print_stats(stats: &BvhStats) {
+    println!("  Nodes: {}", stats.node_count);
+    println!("  Leaves: {}", stats.leaf_count);
+    println!("  Primitive references: {}", stats.primitive_count);
+    println!("  SAH cost: {:.3}", stats.sah_cost);
+    println!(
+        "  Average child overlap: {:.2}%",
+        stats.average_child_overlap * 100.0
+    );
+    println!(
+        "  Memory footprint: {}B",
+        human_format::Formatter::new()
+            .with_decimals(2)
+            .with_separator(" ")
+            .with_scales(human_format::Scales::Binary())
+            .format(stats.memory_bytes as f64)
+    );
+    println!("  Leaf depth histogram:");
+    print_histogram(&stats.depth_histogram);
+    println!("  Leaf size histogram:");
+    print_histogram(&stats.leaf_size_histogram);
+}
+
+/// Prints the non-empty buckets of a histogram, with a bar scaled to the largest bucket
+fn print_histogram(histogram: &[usize]) {
+    const BAR_WIDTH: usize = 40;
+    let largest = histogram.iter().copied().max().unwrap_or(0).max(1);
+    for (bucket, &count) in histogram.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        let bar = "#".repeat((count * BAR_WIDTH).div_ceil(largest));
+        println!("    {bucket:>4}: {count:>8} {bar}");
+    }
+}
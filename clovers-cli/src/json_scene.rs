@@ -1,4 +1,4 @@
-use clovers::bvh::BvhAlgorithm;
+use clovers::bvh::{BvhAlgorithm, BvhLayout};
 use clovers::scenes::Scene;
 use std::error::Error;
 use std::fs::File;
@@ -7,21 +7,33 @@ use std::path::Path;
 
 use tracing::info;
 
-use crate::scenefile::SceneFile;
+use crate::scenefile::{SceneFile, Shots};
 
 pub fn initialize<'scene>(
     path: &Path,
     bvh_algorithm: BvhAlgorithm,
+    bvh_layout: BvhLayout,
+    bvh_cache: Option<&Path>,
     width: u32,
     height: u32,
-) -> Result<Scene<'scene>, Box<dyn Error>> {
+) -> Result<(Scene<'scene>, Shots), Box<dyn Error>> {
     let mut file = File::open(path)?;
     let mut contents: String = String::new();
     file.read_to_string(&mut contents)?;
     info!("Parsing the scene file");
     let scene_file: SceneFile = serde_json::from_str(&contents)?;
     info!("Initializing the scene");
-    let scene: Scene = SceneFile::initialize(scene_file, bvh_algorithm, width, height);
-    info!("Count of nodes in the BVH tree: {}", scene.bvh_root.count());
-    Ok(scene)
+    let (scene, shots) = SceneFile::initialize(
+        scene_file,
+        bvh_algorithm,
+        bvh_layout,
+        bvh_cache,
+        width,
+        height,
+    )?;
+    info!(
+        "Count of nodes in the flattened BVH: {}",
+        scene.bvh_root.count()
+    );
+    Ok((scene, shots))
 }
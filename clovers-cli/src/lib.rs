@@ -2,6 +2,7 @@
 
 use clap::Args;
 
+pub mod bvh_cache;
 pub mod debug_visualizations;
 pub mod draw_cpu;
 pub mod json_scene;
@@ -10,10 +10,14 @@ use std::error::Error;
 
 // Internal imports
 #[doc(hidden)]
+mod bvh_cache;
+#[doc(hidden)]
 pub mod debug_visualizations;
 #[doc(hidden)]
 mod draw_cpu;
 #[doc(hidden)]
+mod inspect;
+#[doc(hidden)]
 mod json_scene;
 #[doc(hidden)]
 pub mod normals;
@@ -30,6 +34,7 @@ mod validate;
 #[doc(hidden)]
 mod write;
 
+use inspect::{inspect, InspectParams};
 use render::render;
 use validate::{validate, ValidateParams};
 
@@ -51,6 +56,9 @@ pub enum Commands {
     #[command(arg_required_else_help = true)]
     /// Validate a given scene file
     Validate(ValidateParams),
+    #[command(arg_required_else_help = true)]
+    /// Print the objects, materials, lights, bounds and BVH statistics of a given scene file
+    Inspect(InspectParams),
 }
 
 #[doc(hidden)]
@@ -59,5 +67,6 @@ fn main() -> Result<(), Box<dyn Error>> {
     match args.command {
         Commands::Render(params) => render(args.global_options, params),
         Commands::Validate(params) => validate(params),
+        Commands::Inspect(params) => inspect(params),
     }
 }
@@ -1,8 +1,6 @@
 //! Alternative rendering method. Only returns a normalmap of the image, colorized using standard conventions.
 
-use clovers::{
-    hitable::HitableTrait, ray::Ray, scenes::Scene, Direction, Float, Vec3, EPSILON_SHADOW_ACNE,
-};
+use clovers::{ray::Ray, scenes::Scene, Direction, Float, Vec3, EPSILON_SHADOW_ACNE};
 use palette::LinSrgb;
 use rand::rngs::SmallRng;
 
@@ -1,15 +1,23 @@
 use std::path::Path;
-use std::{error::Error, fs, time::Instant};
+use std::{
+    error::Error,
+    fs,
+    time::{Duration, Instant},
+};
 
 use clap::{Args, ValueEnum};
+use clovers::scenes::Scene;
 use humantime::format_duration;
+use palette::{white_point::E, Xyz};
 use time::OffsetDateTime;
 use tracing::{debug, info, Level};
 use tracing_subscriber::fmt::time::UtcTime;
 
+use crate::bvh_cache::default_cache_directory;
 use crate::draw_cpu;
 use crate::json_scene::initialize;
 use crate::sampler::Sampler;
+use crate::scenefile::RigLayout;
 use crate::write;
 use crate::GlobalOptions;
 
@@ -18,7 +26,7 @@ pub struct RenderOptions {
     /// Input filename / location
     #[arg(short, long)]
     pub input: String,
-    /// Output file path, without extension. Defaults to `./renders/unix_timestamp`.
+    /// Output file path, without extension. Defaults to `./renders/unix_timestamp`. For animated scenes, the frame number is appended, as in `output_0001`. For camera rigs with separate images, the name of the view is appended, as in `output_left`.
     #[arg(short, long)]
     pub output: Option<String>,
     /// Width of the image in pixels.
@@ -42,6 +50,12 @@ pub struct RenderOptions {
     /// BVH construction algorithm.
     #[arg(long, default_value = "sah")]
     pub bvh: BvhAlgorithm,
+    /// Layout of the BVH used for rendering.
+    #[arg(long, default_value = "binary")]
+    pub bvh_layout: BvhLayout,
+    /// Always rebuild the BVH, instead of loading it from the cache when possible.
+    #[arg(long)]
+    pub no_bvh_cache: bool,
     /// File format selection for the output.
     /// Multiple formats can be provided to save the same image in multiple formats.
     #[arg(short, long, default_value = "png", num_args = 1..)]
@@ -66,6 +80,42 @@ pub enum BvhAlgorithm {
     Lam,
     /// Split based on the Surface Area Heuristic.
     Sah,
+    /// Split based on the Surface Area Heuristic, evaluated between bins. Fast to build for large scenes.
+    BinnedSah,
+    /// Spatial split BVH, splitting large primitives into both children where useful.
+    Sbvh,
+}
+
+// TODO: improve ergonomics?
+impl From<BvhAlgorithm> for clovers::bvh::BvhAlgorithm {
+    fn from(bvh: BvhAlgorithm) -> Self {
+        match bvh {
+            BvhAlgorithm::Lam => clovers::bvh::BvhAlgorithm::Lam,
+            BvhAlgorithm::Sah => clovers::bvh::BvhAlgorithm::Sah,
+            BvhAlgorithm::BinnedSah => clovers::bvh::BvhAlgorithm::BinnedSah,
+            BvhAlgorithm::Sbvh => clovers::bvh::BvhAlgorithm::Sbvh,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+pub enum BvhLayout {
+    /// Binary BVH, two children per node
+    Binary,
+    /// Collapsed into a 4-wide BVH.
+    Wide4,
+    /// Collapsed into an 8-wide BVH.
+    Wide8,
+}
+
+impl From<BvhLayout> for clovers::bvh::BvhLayout {
+    fn from(layout: BvhLayout) -> Self {
+        match layout {
+            BvhLayout::Binary => clovers::bvh::BvhLayout::Binary,
+            BvhLayout::Wide4 => clovers::bvh::BvhLayout::Wide4,
+            BvhLayout::Wide8 => clovers::bvh::BvhLayout::Wide8,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
@@ -93,7 +143,9 @@ pub(crate) fn render(
         mode,
         sampler,
         bvh,
-        ref formats,
+        bvh_layout,
+        no_bvh_cache,
+        formats: _,
     } = render_options;
 
     if debug {
@@ -128,51 +180,174 @@ pub(crate) fn render(
         panic!("the blue sampler only supports the following sample-per-pixel counts: [1, 2, 4, 8, 16, 32, 64, 128, 256]");
     }
 
-    // TODO: improve ergonomics?
-    let bvh_algorithm: clovers::bvh::BvhAlgorithm = match bvh {
-        BvhAlgorithm::Lam => clovers::bvh::BvhAlgorithm::Lam,
-        BvhAlgorithm::Sah => clovers::bvh::BvhAlgorithm::Sah,
-    };
+    let bvh_algorithm: clovers::bvh::BvhAlgorithm = bvh.into();
+    let bvh_layout: clovers::bvh::BvhLayout = bvh_layout.into();
+
+    let bvh_cache = (!no_bvh_cache).then(default_cache_directory);
 
     info!("Reading the scene file");
     let path = Path::new(&input);
-    let scene = match path.extension() {
+    let (mut scene, shots) = match path.extension() {
         Some(ext) => match &ext.to_str() {
-            Some("json") => initialize(path, bvh_algorithm, width, height),
+            Some("json") => initialize(
+                path,
+                bvh_algorithm,
+                bvh_layout,
+                bvh_cache.as_deref(),
+                width,
+                height,
+            ),
             _ => panic!("Unknown file type"),
         },
         None => panic!("Unknown file type"),
     }?;
 
+    // Default to using a timestamp & `renders/` directory
+    let output = match output {
+        Some(filename) => filename.clone(),
+        None => {
+            let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+            fs::create_dir_all("renders")?;
+            format!("renders/{timestamp}")
+        }
+    };
+
+    let (view_width, view_height) = shots.view_size(width, height);
+    let view_options = RenderOptions {
+        width: view_width,
+        height: view_height,
+        ..render_options.clone()
+    };
+    let frames: Vec<Option<u32>> = match shots.frames() {
+        Some(frames) => frames.map(Some).collect(),
+        None => vec![None],
+    };
+    for frame in frames {
+        let frame_output = match frame {
+            Some(frame) => {
+                info!("Rendering frame {frame}");
+                if !quiet {
+                    println!("Frame {frame}");
+                }
+                format!("{output}_{frame:04}")
+            }
+            None => output.clone(),
+        };
+
+        let mut images = Vec::new();
+        let mut total_duration = Duration::ZERO;
+        for view in shots.views(frame)? {
+            if !view.name.is_empty() {
+                info!("Rendering view {}", view.name);
+                if !quiet {
+                    println!("View {}", view.name);
+                }
+            }
+            scene.camera = view.camera;
+            let (pixelbuffer, duration) = draw_view(&global_options, &view_options, &scene);
+            total_duration += duration;
+            match shots.layout() {
+                RigLayout::Separate => {
+                    let target = match view.name {
+                        "" => frame_output.clone(),
+                        name => format!("{frame_output}_{name}"),
+                    };
+                    save(&pixelbuffer, &view_options, &target, duration)?;
+                }
+                RigLayout::SideBySide | RigLayout::OverUnder => images.push(pixelbuffer),
+            }
+        }
+
+        if images.is_empty() {
+            continue;
+        }
+        let count = u32::try_from(images.len())?;
+        let (pixelbuffer, combined_options) = match shots.layout() {
+            RigLayout::SideBySide => (
+                side_by_side(&images, view_width as usize),
+                RenderOptions {
+                    width: view_width * count,
+                    ..view_options.clone()
+                },
+            ),
+            _ => (
+                images.concat(),
+                RenderOptions {
+                    height: view_height * count,
+                    ..view_options.clone()
+                },
+            ),
+        };
+        save(
+            &pixelbuffer,
+            &combined_options,
+            &frame_output,
+            total_duration,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Renders the scene with its current camera, returning the pixelbuffer and the duration of the render
+fn draw_view(
+    global_options: &GlobalOptions,
+    render_options: &RenderOptions,
+    scene: &Scene,
+) -> (Vec<Xyz<E>>, Duration) {
     info!("Calling draw()");
     let start = Instant::now();
-    let pixelbuffer = draw_cpu::draw(&global_options, &render_options, &scene, sampler);
+    let pixelbuffer = draw_cpu::draw(
+        global_options,
+        render_options,
+        scene,
+        render_options.sampler,
+    );
     let duration = Instant::now() - start;
-    let duration = format_duration(duration);
-    info!("Finished render in {}", duration);
-    if !quiet {
-        println!("Finished render in {}", duration);
+    info!("Finished render in {}", format_duration(duration));
+    if !global_options.quiet {
+        println!("Finished render in {}", format_duration(duration));
     }
+    (pixelbuffer, duration)
+}
+
+/// Places the rows of the images next to each other, forming a single image
+fn side_by_side(images: &[Vec<Xyz<E>>], width: usize) -> Vec<Xyz<E>> {
+    let rows = images.first().map_or(0, |image| image.len() / width);
+    let mut pixelbuffer = Vec::with_capacity(rows * width * images.len());
+    for row in 0..rows {
+        for image in images {
+            pixelbuffer.extend_from_slice(&image[row * width..(row + 1) * width]);
+        }
+    }
+    pixelbuffer
+}
+
+/// Saves the image in each of the formats, at the `output` path with the extension of the format
+fn save(
+    pixelbuffer: &[Xyz<E>],
+    render_options: &RenderOptions,
+    output: &str,
+    duration: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let RenderOptions {
+        width,
+        height,
+        ref formats,
+        ..
+    } = *render_options;
+    let duration = format_duration(duration);
 
     for format in formats {
         let extension = match format {
             Format::Png => "png",
             Format::Exr => "exr",
         };
-
-        let target = match output {
-            Some(filename) => format!("{filename}.{extension}"),
-            None => {
-                // Default to using a timestamp & `renders/` directory
-                let timestamp = OffsetDateTime::now_utc().unix_timestamp();
-                fs::create_dir_all("renders")?;
-                format!("renders/{timestamp}.{extension}")
-            }
-        };
+        let target = format!("{output}.{extension}");
 
         match format {
-            Format::Png => write::png(&pixelbuffer, &target, &duration, &render_options),
-            Format::Exr => write::exr(&pixelbuffer, width, height, &target),
+            Format::Png => write::png(pixelbuffer, &target, &duration, render_options),
+            Format::Exr => write::exr(pixelbuffer, width, height, &target),
         }?;
 
         info!("Image saved to {}", target);
@@ -3,17 +3,19 @@
 use std::fmt::Display;
 
 use clap::ValueEnum;
-use clovers::{wavelength::Wavelength, Float, Vec2};
+use clovers::{random::BounceSample, wavelength::Wavelength, Float, Vec2};
 
 pub mod blue;
+pub mod halton;
+pub mod pmj;
 pub mod random;
+pub mod sobol;
 
 pub trait SamplerTrait<'scene> {
     // TODO: better types
     fn sample(&mut self, i: i32, j: i32, index: i32) -> Randomness;
 
     /// Manually request a sample from the specific dimension
-    #[allow(dead_code)] // TODO: remove
     fn sample_dimension(
         &mut self,
         i: i32,
@@ -21,13 +23,54 @@ pub trait SamplerTrait<'scene> {
         index: i32,
         dimension: SamplerDimension,
     ) -> Float;
+
+    /// Returns the sample values for the random decisions made at the given bounce `depth` of the path
+    fn sample_bounce(&mut self, i: i32, j: i32, index: i32, depth: u32) -> BounceSample {
+        let mut get = |dimension| {
+            self.sample_dimension(i, j, index, SamplerDimension::Bounce(depth, dimension))
+        };
+        BounceSample {
+            scatter: Vec2::new(
+                get(BounceDimension::ScatterX),
+                get(BounceDimension::ScatterY),
+            ),
+            light: Vec2::new(get(BounceDimension::LightX), get(BounceDimension::LightY)),
+            mixture: get(BounceDimension::Mixture),
+            lobe: get(BounceDimension::Lobe),
+        }
+    }
+}
+
+/// A single sample of a pixel, for requesting the sample values of each bounce along the path of the sample
+pub struct PixelSample<'a, 'scene> {
+    sampler: &'a mut dyn SamplerTrait<'scene>,
+    i: i32,
+    j: i32,
+    index: i32,
+}
+
+impl<'a, 'scene> PixelSample<'a, 'scene> {
+    pub fn new(sampler: &'a mut dyn SamplerTrait<'scene>, i: i32, j: i32, index: i32) -> Self {
+        Self {
+            sampler,
+            i,
+            j,
+            index,
+        }
+    }
+
+    /// Returns the sample values for the given bounce `depth` of the path
+    pub fn bounce(&mut self, depth: u32) -> BounceSample {
+        self.sampler
+            .sample_bounce(self.i, self.j, self.index, depth)
+    }
 }
 
 /// A collection of random values to be used for each sample. Returned as a struct to ensure the correct sampling order for the underlying source of randomness.
 pub struct Randomness {
     /// Intra-pixel `(x,y)` offset, both in range `[0..1]`. Used for antialiasing.
     pub pixel_offset: Vec2,
-    /// The `(x,y)` offset used in the lens equations for aperture / depth-of-field simulation. The coordinates are within the range `[-0.5..0.5]` and within a unit disk.
+    /// The `(x,y)` sample used for picking a position on the lens aperture for depth-of-field simulation, both in range `[0..1]`. Mapped onto the aperture shape by the camera.
     pub lens_offset: Vec2,
     /// The time of the ray, in range `[0..1]`
     pub time: Float,
@@ -40,22 +83,31 @@ pub struct Randomness {
 pub enum Sampler {
     /// Blue noise based sampler
     Blue,
+    /// Halton sequence with random digit permutations
+    Halton,
+    /// Progressive multi-jittered (0,2) sequence
+    Pmj02,
     /// Random number generator based sampler
     Random,
+    /// Owen-scrambled Sobol sequence
+    Sobol,
 }
 
 impl Display for Sampler {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
             Sampler::Blue => "blue",
+            Sampler::Halton => "halton",
+            Sampler::Pmj02 => "pmj02",
             Sampler::Random => "random",
+            Sampler::Sobol => "sobol",
         };
         write!(f, "{s}")
     }
 }
 
 /// Various sampling dimensions used by the samplers
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum SamplerDimension {
     PixelOffsetX,
     PixelOffsetY,
@@ -63,4 +115,145 @@ pub enum SamplerDimension {
     LensOffsetY,
     Time,
     Wavelength,
+    /// Dimension of a random decision at the given bounce depth of the path
+    Bounce(u32, BounceDimension),
+}
+
+/// Sampling dimensions of the random decisions made at each bounce of a path
+#[derive(Clone, Copy, Debug)]
+pub enum BounceDimension {
+    ScatterX,
+    ScatterY,
+    LightX,
+    LightY,
+    Mixture,
+    Lobe,
+}
+
+/// Count of the dimensions of the camera ray, preceding the dimensions of the bounces
+const CAMERA_DIMENSIONS: usize = 6;
+/// Count of the dimensions of each bounce
+const BOUNCE_DIMENSIONS: usize = 6;
+
+impl SamplerDimension {
+    /// Index of the dimension. The dimensions of the camera ray come first, followed by the dimensions of each bounce in order of depth.
+    pub(crate) fn index(self) -> usize {
+        match self {
+            SamplerDimension::PixelOffsetX => 0,
+            SamplerDimension::PixelOffsetY => 1,
+            SamplerDimension::LensOffsetX => 2,
+            SamplerDimension::LensOffsetY => 3,
+            SamplerDimension::Time => 4,
+            SamplerDimension::Wavelength => 5,
+            SamplerDimension::Bounce(depth, dimension) => {
+                CAMERA_DIMENSIONS + depth as usize * BOUNCE_DIMENSIONS + dimension as usize
+            }
+        }
+    }
+
+    /// Index of the pair of dimensions this dimension belongs to, and the index within the pair. Used by the samplers that generate two-dimensional samples.
+    pub(crate) fn pair(self) -> (u32, usize) {
+        let index = self.index();
+        ((index / 2) as u32, index % 2)
+    }
+}
+
+/// Hashes the values into a single well-mixed value. Used for decorrelating the sequences of the pixels and dimensions.
+pub(crate) fn hash(values: &[u32]) -> u32 {
+    values.iter().fold(0x9e37_79b9, |hash, &value| {
+        // The "lowbias32" integer hash by Chris Wellons
+        let mut x = hash ^ value.wrapping_mul(0x85eb_ca6b);
+        x ^= x >> 16;
+        x = x.wrapping_mul(0x7feb_352d);
+        x ^= x >> 15;
+        x = x.wrapping_mul(0x846c_a68b);
+        x ^= x >> 16;
+        x
+    })
+}
+
+/// Converts a 32-bit fixed point value into a float in the range `[0..1)`
+pub(crate) fn fixed_to_float(value: u32) -> Float {
+    // Only the bits representable in the mantissa are kept, so that the value is never rounded up to one
+    (value >> 8) as Float / (1 << 24) as Float
+}
+
+/// Asserts that the points hit each of the elementary intervals exactly once, for every shape of the intervals from `1 x count` to `count x 1`. The count of the points must be a power of two.
+#[cfg(test)]
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(crate) fn assert_elementary_intervals(points: &[(f64, f64)]) {
+    let count = points.len();
+    assert!(count.is_power_of_two());
+    let log_count = count.ilog2();
+    for shape in 0..=log_count {
+        let (columns, rows) = (1_usize << shape, 1_usize << (log_count - shape));
+        let mut hit = vec![false; count];
+        for &(x, y) in points {
+            assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+            let cell = (x * columns as f64) as usize + (y * rows as f64) as usize * columns;
+            assert!(
+                !hit[cell],
+                "Two of {count} points in the same {columns} x {rows} interval"
+            );
+            hit[cell] = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    const BOUNCE: [BounceDimension; BOUNCE_DIMENSIONS] = [
+        BounceDimension::ScatterX,
+        BounceDimension::ScatterY,
+        BounceDimension::LightX,
+        BounceDimension::LightY,
+        BounceDimension::Mixture,
+        BounceDimension::Lobe,
+    ];
+
+    #[test]
+    fn dimensions_are_distinct() {
+        let camera = [
+            SamplerDimension::PixelOffsetX,
+            SamplerDimension::PixelOffsetY,
+            SamplerDimension::LensOffsetX,
+            SamplerDimension::LensOffsetY,
+            SamplerDimension::Time,
+            SamplerDimension::Wavelength,
+        ];
+        let bounces = (0..8).flat_map(|depth| {
+            BOUNCE
+                .into_iter()
+                .map(move |dimension| SamplerDimension::Bounce(depth, dimension))
+        });
+        let dimensions: Vec<SamplerDimension> = camera.into_iter().chain(bounces).collect();
+
+        let indices: HashSet<usize> = dimensions.iter().map(|d| d.index()).collect();
+        assert_eq!(indices.len(), dimensions.len());
+        let pairs: HashSet<(u32, usize)> = dimensions.iter().map(|d| d.pair()).collect();
+        assert_eq!(pairs.len(), dimensions.len());
+
+        // The camera dimensions come first, and the bounce dimensions never overlap them
+        for dimension in &dimensions[CAMERA_DIMENSIONS..] {
+            assert!(dimension.index() >= CAMERA_DIMENSIONS);
+        }
+        // The two coordinates of a two-dimensional sample share a pair
+        for depth in 0..8 {
+            for (x, y) in [
+                (BounceDimension::ScatterX, BounceDimension::ScatterY),
+                (BounceDimension::LightX, BounceDimension::LightY),
+            ] {
+                let (x, y) = (
+                    SamplerDimension::Bounce(depth, x).pair(),
+                    SamplerDimension::Bounce(depth, y).pair(),
+                );
+                assert_eq!(x.0, y.0);
+                assert_eq!((x.1, y.1), (0, 1));
+            }
+        }
+    }
 }
@@ -1,10 +1,13 @@
 //! A sampler based on blue noise. Works especially well at low samples-per-pixel counts.
 //!
-//! Utilizes library code from <https://github.com/Jasper-Bekkers/blue-noise-sampler>.
+//! Utilizes library code from <https://github.com/Jasper-Bekkers/blue-noise-sampler>. The blue noise is optimized for the first 8 dimensions only: the camera ray and the scattering direction of the first bounce. The further dimensions use the [`SobolSampler`].
 
-use clovers::{wavelength::sample_wavelength, Float, Vec2, PI};
+use clovers::{wavelength::sample_wavelength, Float, Vec2};
 
-use super::{Randomness, SamplerDimension, SamplerTrait};
+use super::{sobol::SobolSampler, Randomness, SamplerDimension, SamplerTrait};
+
+/// Count of the dimensions with optimized blue noise
+const BLUE_DIMENSIONS: usize = 8;
 
 pub struct BlueSampler {
     get: fn(i32, i32, i32, SamplerDimension) -> Float,
@@ -36,7 +39,7 @@ impl SamplerTrait<'_> for BlueSampler {
             (self.get)(i, j, index, SamplerDimension::PixelOffsetX),
             (self.get)(i, j, index, SamplerDimension::PixelOffsetY),
         );
-        let lens_offset = in_unit_disk(
+        let lens_offset = Vec2::new(
             (self.get)(i, j, index, SamplerDimension::LensOffsetX),
             (self.get)(i, j, index, SamplerDimension::LensOffsetY),
         );
@@ -59,7 +62,11 @@ impl SamplerTrait<'_> for BlueSampler {
         index: i32,
         dimension: SamplerDimension,
     ) -> Float {
-        (self.get)(i, j, index, dimension)
+        if dimension.index() < BLUE_DIMENSIONS {
+            (self.get)(i, j, index, dimension)
+        } else {
+            SobolSampler::new().sample_dimension(i, j, index, dimension)
+        }
     }
 }
 
@@ -71,7 +78,7 @@ macro_rules! define_blue_sampler {
                 mut pixel_j: i32,
                 mut sample_index: i32,
                 sample_dimension: SamplerDimension) -> Float {
-                    let mut sample_dimension = sample_dimension as i32;
+                    let mut sample_dimension = sample_dimension.index() as i32;
 
                     use blue_noise_sampler::$spp::*;
 
@@ -116,15 +123,3 @@ define_blue_sampler!(spp32);
 define_blue_sampler!(spp64);
 define_blue_sampler!(spp128);
 define_blue_sampler!(spp256);
-
-/// Given two samples in range `[0..1]`, return a sample within `[-0.5 .. 0.5]` unit disk.
-/// Based on <https://stackoverflow.com/a/50746409>
-fn in_unit_disk(x: Float, y: Float) -> Vec2 {
-    // Polar coordinates + correcting for the distribution using sqrt
-    let r = x.sqrt();
-    let theta = y * 2.0 * PI;
-    // Conversion to Cartesian coordinates
-    let x = r * theta.cos();
-    let y = r * theta.sin();
-    Vec2::new(x, y)
-}
@@ -0,0 +1,207 @@
+//! A sampler based on the Halton sequence, with a prime base for each dimension. The digits of each pixel and dimension are scrambled with random permutations, as in Physically Based Rendering, chapter [8.6 Halton Sampler](https://pbr-book.org/4ed/Sampling_and_Reconstruction/Halton_Sampler).
+//!
+//! The sequence is not limited to any sample count, and rendering can be continued with more samples.
+
+use clovers::{random::ONE_MINUS_EPSILON, wavelength::sample_wavelength, Float, Vec2};
+
+use super::{hash, Randomness, SamplerDimension, SamplerTrait};
+
+/// Prime bases for the dimensions, in the order of [`SamplerDimension::index`]. Dimensions beyond the table, at very deep bounces, wrap around to its start.
+const BASES: [u32; 1024] = primes();
+
+#[derive(Default)]
+pub struct HaltonSampler;
+
+impl HaltonSampler {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn sample_dimension_index(i: i32, j: i32, index: i32, dimension: usize) -> Float {
+        let seed = hash(&[i as u32, j as u32, dimension as u32]);
+        scrambled_radical_inverse(BASES[dimension % BASES.len()], index as u64, seed)
+    }
+}
+
+impl SamplerTrait<'_> for HaltonSampler {
+    fn sample(&mut self, i: i32, j: i32, index: i32) -> Randomness {
+        let get = |dimension| Self::sample_dimension_index(i, j, index, dimension);
+        Randomness {
+            pixel_offset: Vec2::new(get(0), get(1)),
+            lens_offset: Vec2::new(get(2), get(3)),
+            time: get(4),
+            wavelength: sample_wavelength(get(5)),
+        }
+    }
+
+    fn sample_dimension(
+        &mut self,
+        i: i32,
+        j: i32,
+        index: i32,
+        dimension: SamplerDimension,
+    ) -> Float {
+        Self::sample_dimension_index(i, j, index, dimension.index())
+    }
+}
+
+/// Returns the first `N` prime numbers
+const fn primes<const N: usize>() -> [u32; N] {
+    let mut primes = [0; N];
+    let mut count = 0;
+    let mut candidate = 2;
+    while count < N {
+        let mut k = 0;
+        while k < count && primes[k] * primes[k] <= candidate && candidate % primes[k] != 0 {
+            k += 1;
+        }
+        if k == count || primes[k] * primes[k] > candidate {
+            primes[count] = candidate;
+            count += 1;
+        }
+        candidate += 1;
+    }
+    primes
+}
+
+/// Mirrors the digits of the index in the given base around the radix point, permuting each digit with a random permutation. The permutations also apply to the infinitely many leading zero digits of the index, so the digits are generated until they no longer affect the result.
+fn scrambled_radical_inverse(base: u32, index: u64, seed: u32) -> Float {
+    radical_inverse(base, index, |digit, digit_index| {
+        permutation_element(digit, base, hash(&[seed, digit_index]))
+    })
+}
+
+/// Mirrors the digits of the index in the given base around the radix point, mapping each digit with the given function of the digit and its position
+fn radical_inverse(base: u32, mut index: u64, permute: impl Fn(u32, u32) -> u32) -> Float {
+    let inverse_base = 1.0 / f64::from(base);
+    let mut factor = inverse_base;
+    let mut result = 0.0;
+    let mut digit_index = 0;
+    while factor > f64::from(Float::EPSILON) * inverse_base {
+        let digit = (index % u64::from(base)) as u32;
+        index /= u64::from(base);
+        result += f64::from(permute(digit, digit_index)) * factor;
+        factor *= inverse_base;
+        digit_index += 1;
+    }
+    (result as Float).min(ONE_MINUS_EPSILON)
+}
+
+/// Returns the element at position `i` of a random permutation of `0..length`, chosen by the `seed`. From "Correlated Multi-Jittered Sampling" by Andrew Kensler, 2013.
+fn permutation_element(mut i: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    // Cycle walking: repeat the hash until the value falls within the length
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= mask;
+        i ^= i >> 5;
+        if i < length {
+            break;
+        }
+    }
+    ((u64::from(i) + u64::from(seed)) % u64::from(length)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bases_are_primes() {
+        assert_eq!(BASES[..8], [2, 3, 5, 7, 11, 13, 17, 19]);
+        assert_eq!(BASES[1023], 8161);
+    }
+
+    #[test]
+    fn radical_inverse_known_values() {
+        let identity = |digit, _| digit;
+        let expected = [
+            (2, 1, 0.5),
+            (2, 2, 0.25),
+            (2, 3, 0.75),
+            (2, 6, 0.375),
+            (3, 1, 1.0 / 3.0),
+            (3, 2, 2.0 / 3.0),
+            (3, 4, 4.0 / 9.0),
+            (3, 5, 7.0 / 9.0),
+            (5, 7, 11.0 / 25.0),
+        ];
+        for (base, index, value) in expected {
+            assert!((radical_inverse(base, index, identity) - value).abs() <= 1e-6);
+        }
+    }
+
+    #[test]
+    fn permutation_element_is_permutation() {
+        for length in [2, 3, 5, 7, 11, 8161] {
+            let mut seen = vec![false; length as usize];
+            for i in 0..length {
+                let element = permutation_element(i, length, hash(&[length]));
+                assert!(!seen[element as usize]);
+                seen[element as usize] = true;
+            }
+        }
+    }
+
+    #[test]
+    fn samples_in_unit_interval() {
+        let mut sampler = HaltonSampler::new();
+        for index in 0..1024 {
+            let sample = sampler.sample(3, 7, index);
+            for value in [
+                sample.pixel_offset.x,
+                sample.pixel_offset.y,
+                sample.lens_offset.x,
+                sample.lens_offset.y,
+                sample.time,
+            ] {
+                assert!((0.0..1.0).contains(&value));
+            }
+            let bounce = sampler.sample_bounce(3, 7, index, 5);
+            for value in [
+                bounce.scatter.x,
+                bounce.scatter.y,
+                bounce.light.x,
+                bounce.light.y,
+                bounce.mixture,
+                bounce.lobe,
+            ] {
+                assert!((0.0..1.0).contains(&value));
+            }
+        }
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn non_power_of_two_count_is_stratified() {
+        // The first 4 * 9 samples of the dimensions with bases 2 and 3 hit each cell of a 4 x 9 grid exactly once, with any scrambling
+        let mut sampler = HaltonSampler::new();
+        let mut hit = [false; 36];
+        for index in 0..36 {
+            let offset = sampler.sample(12, 34, index).pixel_offset;
+            let cell = (offset.x * 4.0) as usize + (offset.y * 9.0) as usize * 4;
+            assert!(!hit[cell]);
+            hit[cell] = true;
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! A sampler based on progressive multi-jittered (0,2) sequences, from "Progressive Multi-Jittered Sample Sequences" by Per Christensen, Andrew Kensler and Charlie Kilpatrick, 2018.
+//!
+//! Every power-of-two prefix of the samples is stratified in all the elementary intervals, so rendering can be continued with more samples. A few sets of samples are generated once and shared by all pixels, each pixel and pair of dimensions picking a set and scrambling it with a random XOR of the bits, which keeps the stratification.
+
+use std::sync::OnceLock;
+
+use clovers::{wavelength::sample_wavelength, Float, Vec2};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use super::{fixed_to_float, hash, Randomness, SamplerDimension, SamplerTrait};
+
+/// Count of the generated sets of samples
+const SETS: usize = 8;
+/// Count of the samples in each set. Larger sample counts continue with the next set, without the stratification between the sets.
+const SET_SIZE: usize = 4096;
+
+/// The generated sets of samples, as 32-bit fixed point
+static SAMPLE_SETS: OnceLock<Vec<Vec<[u32; 2]>>> = OnceLock::new();
+
+pub struct Pmj02Sampler {
+    sets: &'static [Vec<[u32; 2]>],
+}
+
+impl Pmj02Sampler {
+    pub fn new() -> Self {
+        let sets = SAMPLE_SETS.get_or_init(|| {
+            (0..SETS)
+                .map(|set| {
+                    let mut rng = SmallRng::seed_from_u64(set as u64);
+                    Generator::new(SET_SIZE)
+                        .generate(&mut rng)
+                        .into_iter()
+                        // The points are below one, and their fixed point values fit into the 32 bits
+                        .map(|(x, y)| [(x * 2f64.powi(32)) as u32, (y * 2f64.powi(32)) as u32])
+                        .collect()
+                })
+                .collect()
+        });
+        Self { sets }
+    }
+
+    fn sample_pair(&self, i: i32, j: i32, index: i32, pair: u32) -> Vec2 {
+        let seed = hash(&[i as u32, j as u32, pair]);
+        let index = index as usize;
+        let set = (seed as usize + index / SET_SIZE) % SETS;
+        let [x, y] = self.sets[set][index % SET_SIZE];
+        Vec2::new(
+            fixed_to_float(x ^ hash(&[seed, 0])),
+            fixed_to_float(y ^ hash(&[seed, 1])),
+        )
+    }
+}
+
+impl Default for Pmj02Sampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SamplerTrait<'_> for Pmj02Sampler {
+    fn sample(&mut self, i: i32, j: i32, index: i32) -> Randomness {
+        let pixel_offset = self.sample_pair(i, j, index, 0);
+        let lens_offset = self.sample_pair(i, j, index, 1);
+        let time_wavelength = self.sample_pair(i, j, index, 2);
+
+        Randomness {
+            pixel_offset,
+            lens_offset,
+            time: time_wavelength.x,
+            wavelength: sample_wavelength(time_wavelength.y),
+        }
+    }
+
+    fn sample_dimension(
+        &mut self,
+        i: i32,
+        j: i32,
+        index: i32,
+        dimension: SamplerDimension,
+    ) -> Float {
+        let (pair, component) = dimension.pair();
+        self.sample_pair(i, j, index, pair)[component]
+    }
+}
+
+/// Generates a progressive multi-jittered (0,2) sequence. The sequence is extended by doubling the count of samples, placing each new sample in an unoccupied subquadrant of the stratum of an earlier sample, at a random position that is not occupied in any of the elementary intervals.
+struct Generator {
+    size: usize,
+    samples: Vec<(f64, f64)>,
+    /// Occupied cells of the elementary intervals, for each shape from `1 x count` to `count x 1`
+    occupied: Vec<Vec<bool>>,
+    /// Base two logarithm of the count of samples the occupied cells are tracked for
+    log_count: u32,
+}
+
+impl Generator {
+    fn new(size: usize) -> Self {
+        Generator {
+            size,
+            samples: Vec::with_capacity(size),
+            occupied: Vec::new(),
+            log_count: 0,
+        }
+    }
+
+    fn generate(mut self, rng: &mut SmallRng) -> Vec<(f64, f64)> {
+        self.samples.push((rng.random(), rng.random()));
+        while self.samples.len() < self.size {
+            self.extend_even(rng);
+            if self.samples.len() < self.size {
+                self.extend_odd(rng);
+            }
+        }
+        self.samples.truncate(self.size);
+        self.samples
+    }
+
+    /// Extends the sequence from a power of four samples, placing the new samples in the diagonally opposite subquadrants of the old ones
+    fn extend_even(&mut self, rng: &mut SmallRng) {
+        let count = self.samples.len();
+        let strata = count.isqrt();
+        self.mark_occupied(2 * count);
+        for index in 0..count {
+            let (i, j, x_half, y_half) = self.subquadrant(index, strata);
+            self.place(i, j, 1 - x_half, 1 - y_half, strata, rng);
+        }
+    }
+
+    /// Extends the sequence from twice a power of four samples, filling the two remaining subquadrants of each stratum
+    fn extend_odd(&mut self, rng: &mut SmallRng) {
+        let count = self.samples.len();
+        let strata = (count / 2).isqrt();
+        self.mark_occupied(2 * count);
+        let mut halves = Vec::with_capacity(count / 2);
+        for index in 0..count / 2 {
+            let (i, j, mut x_half, mut y_half) = self.subquadrant(index, strata);
+            // Either of the two remaining subquadrants first
+            if rng.random::<bool>() {
+                x_half = 1 - x_half;
+            } else {
+                y_half = 1 - y_half;
+            }
+            halves.push((i, j, x_half, y_half));
+            self.place(i, j, x_half, y_half, strata, rng);
+        }
+        for (i, j, x_half, y_half) in halves {
+            self.place(i, j, 1 - x_half, 1 - y_half, strata, rng);
+        }
+    }
+
+    /// Returns the stratum of the sample in a grid of `strata x strata`, and the subquadrant of the sample within the stratum
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn subquadrant(&self, index: usize, strata: usize) -> (usize, usize, usize, usize) {
+        let (x, y) = self.samples[index];
+        let (x, y) = (x * strata as f64, y * strata as f64);
+        let (i, j) = (x as usize, y as usize);
+        let x_half = (2.0 * (x - i as f64)) as usize;
+        let y_half = (2.0 * (y - j as f64)) as usize;
+        (i, j, x_half, y_half)
+    }
+
+    /// Resets the occupied cells for a sequence of `count` samples, and marks the cells of the existing samples
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn mark_occupied(&mut self, count: usize) {
+        self.log_count = count.ilog2();
+        self.occupied = vec![vec![false; count]; self.log_count as usize + 1];
+        for index in 0..self.samples.len() {
+            let (x, y) = self.samples[index];
+            let cell = ((x * count as f64) as usize, (y * count as f64) as usize);
+            self.mark(cell);
+        }
+    }
+
+    /// Returns the index within each shape of elementary intervals for the cell of the finest grid
+    fn intervals(&self, (x, y): (usize, usize)) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let log_count = self.log_count as usize;
+        (0..=log_count).map(move |shape| {
+            let column = x >> (log_count - shape);
+            let row = y >> shape;
+            (shape, column + (row << shape))
+        })
+    }
+
+    fn mark(&mut self, cell: (usize, usize)) {
+        let intervals: Vec<_> = self.intervals(cell).collect();
+        for (shape, index) in intervals {
+            self.occupied[shape][index] = true;
+        }
+    }
+
+    fn is_free(&self, cell: (usize, usize)) -> bool {
+        self.intervals(cell)
+            .all(|(shape, index)| !self.occupied[shape][index])
+    }
+
+    /// Places a new sample in the given subquadrant of the stratum `(i, j)`, in a random cell of the finest grid that is free in all the elementary intervals
+    #[allow(clippy::cast_precision_loss)]
+    fn place(
+        &mut self,
+        i: usize,
+        j: usize,
+        x_half: usize,
+        y_half: usize,
+        strata: usize,
+        rng: &mut SmallRng,
+    ) {
+        let count = 1 << self.log_count;
+        let cells = count / (2 * strata);
+        let origin = ((2 * i + x_half) * cells, (2 * j + y_half) * cells);
+        let random_cell = |rng: &mut SmallRng| {
+            (
+                origin.0 + rng.random_range(0..cells),
+                origin.1 + rng.random_range(0..cells),
+            )
+        };
+        // Usually a free cell is found quickly by trying random ones; otherwise, pick among all the free cells
+        let cell = (0..4 * cells)
+            .map(|_| random_cell(rng))
+            .find(|&cell| self.is_free(cell))
+            .unwrap_or_else(|| {
+                let free: Vec<_> = (0..cells * cells)
+                    .map(|k| (origin.0 + k % cells, origin.1 + k / cells))
+                    .filter(|&cell| self.is_free(cell))
+                    .collect();
+                assert!(!free.is_empty(), "No free cell for a pmj02 sample");
+                free[rng.random_range(0..free.len())]
+            });
+        self.mark(cell);
+        self.samples.push((
+            (cell.0 as f64 + rng.random::<f64>()) / count as f64,
+            (cell.1 as f64 + rng.random::<f64>()) / count as f64,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::assert_elementary_intervals;
+
+    #[test]
+    fn generated_prefixes_are_stratified() {
+        for seed in 0..16 {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let samples = Generator::new(1024).generate(&mut rng);
+            for log_count in 0..=10 {
+                assert_elementary_intervals(&samples[..1 << log_count]);
+            }
+        }
+    }
+
+    #[test]
+    fn generates_non_power_of_two_count() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let samples = Generator::new(100).generate(&mut rng);
+        assert_eq!(samples.len(), 100);
+        assert_elementary_intervals(&samples[..64]);
+        for (x, y) in samples {
+            assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+        }
+    }
+
+    #[test]
+    fn scrambled_prefixes_are_stratified() {
+        // Generates all the shared sets, none of which may run out of free cells for a sample
+        let sampler = Pmj02Sampler::new();
+        for (i, j, pair) in [(0, 0, 0), (3, 7, 1), (511, 64, 9)] {
+            let points: Vec<(f64, f64)> = (0..SET_SIZE as i32)
+                .map(|index| {
+                    let point = sampler.sample_pair(i, j, index, pair);
+                    (f64::from(point.x), f64::from(point.y))
+                })
+                .collect();
+            for log_count in 0..=SET_SIZE.ilog2() {
+                assert_elementary_intervals(&points[..1 << log_count]);
+            }
+        }
+    }
+
+    #[test]
+    fn samples_in_unit_interval() {
+        let mut sampler = Pmj02Sampler::new();
+        // Sample counts beyond the size of a set continue with the next set
+        for index in (0..1024).chain(SET_SIZE as i32..SET_SIZE as i32 + 1024) {
+            let sample = sampler.sample(3, 7, index);
+            for value in [
+                sample.pixel_offset.x,
+                sample.pixel_offset.y,
+                sample.lens_offset.x,
+                sample.lens_offset.y,
+                sample.time,
+            ] {
+                assert!((0.0..1.0).contains(&value));
+            }
+            let bounce = sampler.sample_bounce(3, 7, index, 5);
+            for value in [
+                bounce.scatter.x,
+                bounce.scatter.y,
+                bounce.light.x,
+                bounce.light.y,
+                bounce.mixture,
+                bounce.lobe,
+            ] {
+                assert!((0.0..1.0).contains(&value));
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
 //! A sampler based on a random number generator. This is the default sampler used in this renderer. It works especially well at high samples-per-pixel counts.
 
-use clovers::{random::random_in_unit_disk, wavelength::random_wavelength, Vec2};
+use clovers::{random::BounceSample, wavelength::random_wavelength, Vec2};
 use rand::{rngs::SmallRng, Rng};
 
 use super::{Randomness, SamplerTrait};
@@ -19,7 +19,7 @@ impl<'scene> RandomSampler<'scene> {
 impl<'scene> SamplerTrait<'scene> for RandomSampler<'scene> {
     fn sample(&mut self, _i: i32, _j: i32, _index: i32) -> Randomness {
         let pixel_offset = Vec2::new(self.rng.random(), self.rng.random());
-        let lens_offset = random_in_unit_disk(self.rng);
+        let lens_offset = Vec2::new(self.rng.random(), self.rng.random());
         let time = self.rng.random();
         let wavelength = random_wavelength(self.rng);
 
@@ -40,4 +40,8 @@ impl<'scene> SamplerTrait<'scene> for RandomSampler<'scene> {
     ) -> clovers::Float {
         self.rng.random()
     }
+
+    fn sample_bounce(&mut self, _i: i32, _j: i32, _index: i32, _depth: u32) -> BounceSample {
+        BounceSample::random(self.rng)
+    }
 }
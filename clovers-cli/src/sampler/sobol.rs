@@ -0,0 +1,148 @@
+//! A sampler based on the Owen-scrambled Sobol sequence. Each pair of dimensions uses the first two dimensions of the Sobol sequence, with independent scrambling and shuffling, as in "Practical Hash-based Owen Scrambling" by Brent Burley, 2020.
+//!
+//! Every power-of-two prefix of the samples of a pixel is well stratified, so any sample count works, and rendering can be continued with more samples.
+
+use clovers::{wavelength::sample_wavelength, Float, Vec2};
+
+use super::{fixed_to_float, hash, Randomness, SamplerDimension, SamplerTrait};
+
+#[derive(Default)]
+pub struct SobolSampler;
+
+impl SobolSampler {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn sample_pair(i: i32, j: i32, index: i32, pair: u32) -> Vec2 {
+        let seed = hash(&[i as u32, j as u32, pair]);
+        // Shuffling the index keeps the power-of-two prefixes as aligned blocks of the sequence
+        let index = nested_uniform_scramble(index as u32, seed);
+        Vec2::new(
+            fixed_to_float(nested_uniform_scramble(
+                index.reverse_bits(),
+                hash(&[seed, 0]),
+            )),
+            fixed_to_float(nested_uniform_scramble(
+                sobol_second_dimension(index),
+                hash(&[seed, 1]),
+            )),
+        )
+    }
+}
+
+impl SamplerTrait<'_> for SobolSampler {
+    fn sample(&mut self, i: i32, j: i32, index: i32) -> Randomness {
+        let pixel_offset = Self::sample_pair(i, j, index, 0);
+        let lens_offset = Self::sample_pair(i, j, index, 1);
+        let time_wavelength = Self::sample_pair(i, j, index, 2);
+
+        Randomness {
+            pixel_offset,
+            lens_offset,
+            time: time_wavelength.x,
+            wavelength: sample_wavelength(time_wavelength.y),
+        }
+    }
+
+    fn sample_dimension(
+        &mut self,
+        i: i32,
+        j: i32,
+        index: i32,
+        dimension: SamplerDimension,
+    ) -> Float {
+        let (pair, component) = dimension.pair();
+        Self::sample_pair(i, j, index, pair)[component]
+    }
+}
+
+/// The second dimension of the Sobol sequence, as 32-bit fixed point. The first dimension is the van der Corput sequence, given by reversing the bits of the index.
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut result = 0;
+    let mut direction: u32 = 1 << 31;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+/// Owen scrambling of a 32-bit fixed point value: each bit is flipped based on a hash of the bits above it
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    laine_karras_permutation(value.reverse_bits(), seed).reverse_bits()
+}
+
+/// A hash in which each bit only affects the bits above it, from "Practical Hash-based Owen Scrambling" by Brent Burley, 2020
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::assert_elementary_intervals;
+
+    fn points(i: i32, j: i32, indices: std::ops::Range<i32>, pair: u32) -> Vec<(f64, f64)> {
+        indices
+            .map(|index| {
+                let point = SobolSampler::sample_pair(i, j, index, pair);
+                (f64::from(point.x), f64::from(point.y))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn samples_in_unit_interval() {
+        let mut sampler = SobolSampler::new();
+        for index in 0..1024 {
+            let sample = sampler.sample(3, 7, index);
+            for value in [
+                sample.pixel_offset.x,
+                sample.pixel_offset.y,
+                sample.lens_offset.x,
+                sample.lens_offset.y,
+                sample.time,
+            ] {
+                assert!((0.0..1.0).contains(&value));
+            }
+            let bounce = sampler.sample_bounce(3, 7, index, 5);
+            for value in [
+                bounce.scatter.x,
+                bounce.scatter.y,
+                bounce.light.x,
+                bounce.light.y,
+                bounce.mixture,
+                bounce.lobe,
+            ] {
+                assert!((0.0..1.0).contains(&value));
+            }
+        }
+    }
+
+    #[test]
+    fn power_of_two_prefixes_are_stratified() {
+        for (i, j, pair) in [(0, 0, 0), (3, 7, 1), (511, 64, 9)] {
+            let points = points(i, j, 0..1024, pair);
+            for log_count in 0..=10 {
+                assert_elementary_intervals(&points[..1 << log_count]);
+            }
+        }
+    }
+
+    #[test]
+    fn non_power_of_two_count_is_stratified_in_blocks() {
+        // The samples of a count of 96 are made of two stratified blocks, of 64 and 32 samples
+        let points = points(12, 34, 0..96, 2);
+        assert_elementary_intervals(&points[..64]);
+        assert_elementary_intervals(&points[64..]);
+    }
+}
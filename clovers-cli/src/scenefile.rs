@@ -1,19 +1,22 @@
-use std::boxed::Box;
+use std::{boxed::Box, error::Error, ops::RangeInclusive, path::Path};
 
 use clovers::{
-    bvh::{BVHNode, BvhAlgorithm},
-    camera::{Camera, CameraInit},
-    hitable::Hitable,
+    aabb::AABB,
+    bvh::{BVHNode, BvhAlgorithm, BvhLayout, SceneBVH},
+    camera::{Camera, CameraInit, CameraKeyframe, RigInit, RigView},
+    hitable::{Hitable, HitableTrait},
     materials::SharedMaterial,
-    objects::{object_to_hitable, Object},
+    objects::{object_to_hitable, Object, GLTF},
     scenes::Scene,
-    Float, Vec,
+    Float, Position, Vec,
 };
 
 use palette::{
     chromatic_adaptation::AdaptInto, convert::IntoColorUnclamped, white_point::E, Srgb, Xyz,
 };
-use tracing::info;
+use tracing::{info, warn};
+
+use crate::bvh_cache::{BvhCache, PrimitiveIndex};
 
 // TODO: better naming
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -22,64 +25,272 @@ pub struct SceneFile {
     time_0: Float,
     time_1: Float,
     background_color: Srgb,
-    camera: CameraInit,
+    #[serde(default)]
+    camera: Option<CameraInit>,
     objects: Vec<Object>,
     #[serde(default)]
     materials: Vec<SharedMaterial>,
+    /// Renders a sequence of frames instead of a single image. Replaces the `time_0` and `time_1` of the scene with the shutter intervals of the frames.
+    #[serde(default)]
+    animation: Option<AnimationInit>,
+    /// Renders multiple views around the camera instead of a single one
+    #[serde(default)]
+    rig: Option<RigFile>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+/// A serialized representation of the frames of an animation. See [`Shots`].
+pub struct AnimationInit {
+    /// Number of the first frame to render
+    #[serde(default = "default_frame_start")]
+    frame_start: u32,
+    /// Number of the last frame to render
+    frame_end: u32,
+    /// Frames per second. Frame `n` opens the shutter at the time `n / fps`.
+    fps: Float,
+    /// Fraction of the frame duration the shutter stays open. Defaults to half, a 180 degree shutter.
+    #[serde(default = "default_shutter")]
+    shutter: Float,
+    /// Keyframes of the camera parameters, with times in seconds
+    #[serde(default)]
+    camera_keyframes: Vec<CameraKeyframe>,
+}
+
+fn default_frame_start() -> u32 {
+    1
+}
+
+fn default_shutter() -> Float {
+    0.5
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+/// A serialized representation of a camera rig, with the layout of its images
+pub struct RigFile {
+    #[serde(flatten)]
+    rig: RigInit,
+    #[serde(default)]
+    layout: RigLayout,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+/// Arrangement of the views of a camera rig into images
+pub enum RigLayout {
+    /// An image for each view, named after the view
+    #[default]
+    Separate,
+    /// A single image with the views next to each other, from left to right
+    SideBySide,
+    /// A single image with the views on top of each other, from top to bottom
+    OverUnder,
+}
+
+/// The cameras a [Scene] is rendered with: a view for each camera of the rig, for each frame of the animation.
+///
+/// Objects are animated with their keyframed transforms at the times of the rays, so the BVH built over their whole motion serves every frame and view; only the cameras are created anew.
+#[derive(Clone, Debug)]
+pub struct Shots {
+    camera: CameraInit,
+    focus_target: Option<Position>,
+    aspect_ratio: Float,
+    time_0: Float,
+    time_1: Float,
+    animation: Option<AnimationInit>,
+    rig: Option<RigFile>,
+}
+
+impl Shots {
+    /// Returns the numbers of the frames to render, or `None` for a scene without an animation
+    #[must_use]
+    pub fn frames(&self) -> Option<RangeInclusive<u32>> {
+        self.animation
+            .as_ref()
+            .map(|animation| animation.frame_start..=animation.frame_end)
+    }
+
+    /// Returns the times at which the shutter opens and closes for the given frame, or for the whole scene without a frame
+    #[must_use]
+    pub fn shutter_interval(&self, frame: Option<u32>) -> (Float, Float) {
+        match (&self.animation, frame) {
+            (Some(animation), Some(frame)) => {
+                #[allow(clippy::cast_precision_loss)]
+                let time_0 = frame as Float / animation.fps;
+                (time_0, time_0 + animation.shutter / animation.fps)
+            }
+            _ => (self.time_0, self.time_1),
+        }
+    }
+
+    /// Returns the views to render for the given frame, with the camera keyframes evaluated at the opening of the shutter. Without a rig, there is a single view with an empty name.
+    ///
+    /// # Errors
+    /// Returns an error if the camera of a view cannot be created.
+    pub fn views(&self, frame: Option<u32>) -> Result<Vec<RigView>, String> {
+        let (time_0, time_1) = self.shutter_interval(frame);
+        let init = match &self.animation {
+            Some(animation) => self.camera.at_time(&animation.camera_keyframes, time_0),
+            None => self.camera.clone(),
+        };
+        match &self.rig {
+            Some(RigFile { rig, .. }) => rig.views(
+                &init,
+                self.focus_target,
+                rig.view_aspect_ratio(self.aspect_ratio),
+                time_0,
+                time_1,
+            ),
+            None => Ok(vec![RigView {
+                name: "",
+                camera: Camera::from_init(
+                    &init,
+                    self.focus_target,
+                    self.aspect_ratio,
+                    time_0,
+                    time_1,
+                )?,
+            }]),
+        }
+    }
+
+    /// Returns the arrangement of the views into images
+    #[must_use]
+    pub fn layout(&self) -> RigLayout {
+        self.rig
+            .as_ref()
+            .map_or(RigLayout::Separate, |rig| rig.layout)
+    }
+
+    /// Returns the width and height of a single view, for an image of the given height. The faces of a cube map are square.
+    #[must_use]
+    pub fn view_size(&self, width: u32, height: u32) -> (u32, u32) {
+        match &self.rig {
+            Some(RigFile {
+                rig: RigInit::CubeMap,
+                ..
+            }) => (height, height),
+            _ => (width, height),
+        }
+    }
 }
 
 impl SceneFile {
     /// Initializes a new [Scene] instance by parsing the contents of a [`SceneFile`] structure and then using those details to construct the [Scene].
-    #[must_use]
+    ///
+    /// If a `bvh_cache` directory is given, the BVH is loaded from there when a valid cache file exists for the scene, and saved there otherwise.
+    ///
+    /// Also returns the [Shots] to render, and the camera of the [Scene] is the first view of the first frame.
+    ///
+    /// # Errors
+    /// Returns an error if the animation has no frames or a frame rate that is not positive, or if neither the scene file nor its glTF files have a camera.
     pub fn initialize<'scene>(
         scene_file: SceneFile,
         bvh_algorithm: BvhAlgorithm,
+        bvh_layout: BvhLayout,
+        bvh_cache: Option<&Path>,
         width: u32,
         height: u32,
-    ) -> Scene<'scene> {
+    ) -> Result<(Scene<'scene>, Shots), Box<dyn Error>> {
         let time_0 = scene_file.time_0;
         let time_1 = scene_file.time_1;
+        if let Some(animation) = &scene_file.animation {
+            if animation.frame_start > animation.frame_end {
+                return Err("The animation has no frames".into());
+            }
+            if animation.fps <= 0.0 || animation.fps.is_nan() {
+                return Err("The animation frame rate must be positive".into());
+            }
+        }
 
         let background: Xyz = scene_file.background_color.into_color_unclamped();
         let background: Xyz<E> = background.adapt_into();
 
-        #[allow(clippy::cast_precision_loss)]
-        let camera = Camera::new(
-            scene_file.camera.look_from,
-            scene_file.camera.look_at,
-            scene_file.camera.up,
-            scene_file.camera.vertical_fov,
-            width as Float / height as Float,
-            scene_file.camera.aperture,
-            scene_file.camera.focus_distance,
-            time_0,
-            time_1,
-        );
         let mut materials = scene_file.materials;
         materials.push(SharedMaterial::default());
         let materials = Box::leak(Box::new(materials));
 
+        let bvh_cache = bvh_cache.and_then(|directory| {
+            BvhCache::new(directory, &scene_file.objects, bvh_algorithm)
+                .inspect_err(|error| warn!("Not using the BVH cache: {error}"))
+                .ok()
+        });
+
         info!("Creating a flattened list from the objects");
         let mut hitables: Vec<Hitable> = Vec::new();
         let mut priority_hitables: Vec<Hitable> = Vec::new();
+        let mut camera_init = scene_file.camera;
+        let focus_object = camera_init.as_ref().and_then(CameraInit::focus_object);
+        let mut focus_target: Option<Position> = None;
 
         // TODO: this isn't the greatest ergonomics, but it gets the job done for now
-        for object in scene_file.objects {
+        for (index, object) in scene_file.objects.into_iter().enumerate() {
+            let is_focus_object = focus_object == Some(index);
             let priority = match &object {
                 Object::Boxy(i) => i.priority,
+                Object::Cone(i) => i.priority,
                 Object::ConstantMedium(i) => i.priority,
+                Object::CSG(i) => i.priority,
+                Object::Curves(i) => i.priority,
+                Object::Cylinder(i) => i.priority,
+                Object::Disk(i) => i.priority,
+                Object::Heightfield(i) => i.priority,
+                Object::Instances(i) => i.priority,
+                Object::KeyframedTransform(i) => i.priority,
                 Object::MovingSphere(i) => i.priority,
                 Object::ObjectList(i) => i.priority,
                 Object::Quad(i) => i.priority,
                 Object::RotateY(i) => i.priority,
+                Object::SDF(i) => i.priority,
                 Object::Sphere(i) => i.priority,
                 Object::STL(i) => i.priority,
                 Object::PLY(i) => i.priority,
+                Object::PointCloud(i) => i.priority,
                 Object::GLTF(i) => i.priority,
+                Object::OBJ(i) => i.priority,
+                Object::Torus(i) => i.priority,
+                Object::Transform(i) => i.priority,
                 Object::Translate(i) => i.priority,
                 Object::Triangle(i) => i.priority,
             };
-            let hitable = object_to_hitable(object, materials);
+            let priority = if priority && !can_sample(&object) {
+                warn!("Ignoring the priority of object {index}: random points cannot be sampled on its surface");
+                false
+            } else {
+                priority
+            };
+            // glTF files may contain a camera and lights in addition to the meshes
+            if let Object::GLTF(gltf_init) = object {
+                let gltf = GLTF::new(gltf_init);
+                if is_focus_object {
+                    focus_target = gltf
+                        .hitables
+                        .iter()
+                        .filter_map(Hitable::aabb)
+                        .cloned()
+                        .reduce(|a, b| AABB::combine(&a, &b))
+                        .map(|aabb| aabb.centroid());
+                }
+                if let Some(camera) = gltf.camera {
+                    info!("Using the camera of the glTF file");
+                    camera_init = Some(camera);
+                }
+                for h in gltf.hitables.into_iter().chain(gltf.lights) {
+                    if priority {
+                        priority_hitables.push(h.clone());
+                    }
+                    hitables.push(h);
+                }
+                continue;
+            }
+            let hitable = match object_to_hitable(object, materials, bvh_algorithm) {
+                Ok(hitable) => hitable,
+                Err(error) => {
+                    warn!("Skipping object {index}: {error}");
+                    continue;
+                }
+            };
+            if is_focus_object {
+                focus_target = hitable.aabb().map(AABB::centroid);
+            }
             // If we get a HitableList, flatten it
             if let Hitable::HitableList(l) = hitable {
                 for h in l.hitables {
@@ -102,17 +313,159 @@ impl SceneFile {
             }
         }
         info!("All objects parsed into hitables");
-        info!("Building the BVH root for hitables");
-        let bvh_root = BVHNode::from_list(bvh_algorithm, hitables);
+
+        let camera_init =
+            camera_init.ok_or("No camera in the scene file and no camera imported from glTF")?;
+        if focus_object.is_some() && focus_target.is_none() {
+            warn!("The focus object of the camera was not found, focusing on the look_at position");
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let aspect_ratio = width as Float / height as Float;
+        let shots = Shots {
+            camera: camera_init,
+            focus_target,
+            aspect_ratio,
+            time_0,
+            time_1,
+            animation: scene_file.animation,
+            rig: scene_file.rig,
+        };
+        let first_frame = shots.frames().map(|frames| *frames.start());
+        let camera = shots.views(first_frame)?.swap_remove(0).camera;
+        let cached = bvh_cache.as_ref().and_then(|cache| cache.load(&hitables));
+        let bvh_root = if let Some(flat) = cached {
+            info!("Loaded the BVH root for hitables from the cache");
+            SceneBVH::from_flat(bvh_layout, flat)
+        } else {
+            info!("Building the BVH root for hitables");
+            let index = bvh_cache.as_ref().map(|_| PrimitiveIndex::new(&hitables));
+            let flat = BVHNode::from_list(bvh_algorithm, hitables).flatten();
+            if let (Some(cache), Some(index)) = (&bvh_cache, &index) {
+                if let Err(error) = cache.save(&flat, index) {
+                    warn!("Could not save the BVH into the cache: {error}");
+                }
+            }
+            SceneBVH::from_flat(bvh_layout, flat)
+        };
         info!("Building the MIS BVH root for priority hitables");
         let mis_bvh_root = Hitable::BVHNode(BVHNode::from_list(bvh_algorithm, priority_hitables));
         info!("BVH root nodes built");
 
-        Scene {
+        let scene = Scene {
             camera,
             bvh_root,
             mis_bvh_root,
             background,
+        };
+        Ok((scene, shots))
+    }
+}
+
+/// Returns false for objects that cannot be used as priority objects, as sampling random points on their surface is not supported
+fn can_sample(object: &Object) -> bool {
+    match object {
+        Object::CSG(_)
+        | Object::Curves(_)
+        | Object::Heightfield(_)
+        | Object::PointCloud(_)
+        | Object::SDF(_) => false,
+        Object::Instances(i) => can_sample(&i.object),
+        Object::KeyframedTransform(i) => can_sample(&i.object),
+        Object::ObjectList(i) => i.objects.iter().all(can_sample),
+        Object::Transform(i) => can_sample(&i.object),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn scene_file(camera: &Value, animation: &Value) -> SceneFile {
+        let description = json!({
+            "time_0": 0,
+            "time_1": 1,
+            "background_color": [0, 0, 0],
+            "camera": camera,
+            "objects": [{ "kind": "Sphere", "center": [0, 0, 0], "radius": 1, "priority": true }],
+            "animation": animation,
+        });
+        serde_json::from_value(description).unwrap()
+    }
+
+    fn error(scene_file: SceneFile) -> String {
+        match SceneFile::initialize(
+            scene_file,
+            BvhAlgorithm::default(),
+            BvhLayout::default(),
+            None,
+            1,
+            1,
+        ) {
+            Ok(_) => panic!("The scene file was initialized"),
+            Err(error) => error.to_string(),
         }
     }
+
+    fn camera() -> Value {
+        json!({
+            "look_from": [0, 0, -10],
+            "look_at": [0, 0, 0],
+            "up": [0, 1, 0],
+            "vertical_fov": 40,
+            "aperture": 0,
+            "focus_distance": 10
+        })
+    }
+
+    #[test]
+    fn invalid_animation() {
+        let no_frames = json!({ "frame_start": 5, "frame_end": 2, "fps": 24 });
+        assert_eq!(
+            error(scene_file(&camera(), &no_frames)),
+            "The animation has no frames"
+        );
+        let no_fps = json!({ "frame_end": 2, "fps": 0 });
+        assert_eq!(
+            error(scene_file(&camera(), &no_fps)),
+            "The animation frame rate must be positive"
+        );
+        let valid = json!({ "frame_end": 2, "fps": 24 });
+        let scene_file = scene_file(&camera(), &valid);
+        assert!(SceneFile::initialize(
+            scene_file,
+            BvhAlgorithm::default(),
+            BvhLayout::default(),
+            None,
+            1,
+            1,
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn missing_camera() {
+        assert_eq!(
+            error(scene_file(&Value::Null, &Value::Null)),
+            "No camera in the scene file and no camera imported from glTF"
+        );
+    }
+
+    #[test]
+    fn invalid_camera() {
+        let focused_too_close = json!({
+            "look_from": [0, 0, -10],
+            "look_at": [0, 0, 0],
+            "up": [0, 1, 0],
+            "focal_length": 50,
+            "f_number": 2,
+            "focus_distance": 0.01
+        });
+        assert_eq!(
+            error(scene_file(&focused_too_close, &Value::Null)),
+            "Cannot focus the camera closer than the focal length"
+        );
+    }
 }
@@ -1,30 +1,45 @@
 //! An opinionated colorize method. Given a [Ray] and a [Scene], evaluates the ray's path and returns a color.
 
 use clovers::{
-    hitable::HitableTrait,
     materials::MaterialType,
-    pdf::{HitablePDF, MixturePDF, PDFTrait, PDF},
+    pdf::{power_heuristic, HitablePDF, PDFTrait, PDF},
+    random::BounceSample,
     ray::Ray,
     scenes::Scene,
     spectrum::spectral_powers,
     wavelength::{rotate_wavelength, WAVE_SAMPLE_COUNT},
-    Float, EPSILON_SHADOW_ACNE,
+    Float, HitRecord, EPSILON_SHADOW_ACNE,
 };
 use nalgebra::Unit;
 use rand::rngs::SmallRng;
 
-use crate::sampler::SamplerTrait;
+use crate::sampler::PixelSample;
 
 /// The main path tracing function. Sends a [`Ray`] to the [`Scene`], sees if it hits anything, and eventually returns a spectral intensity. Taking into account the [Material](clovers::materials::Material) that is hit, the method recurses with various adjustments, with a new [`Ray`] started from the location that was hit.
+///
+/// On diffuse materials, the direct lighting is estimated with next-event estimation: a point on the priority objects of the scene is sampled and a shadow ray is traced towards it. The emission found by the shadow ray and the emission found by the scattered ray are combined with multiple importance sampling, using the power heuristic.
 #[must_use]
-#[allow(clippy::only_used_in_recursion)] // TODO: use sampler in more places!
 pub fn trace(
     ray: &Ray,
     scene: &Scene,
     depth: u32,
     max_depth: u32,
     rng: &mut SmallRng,
-    sampler: &dyn SamplerTrait,
+    sample: &mut PixelSample,
+) -> [Float; WAVE_SAMPLE_COUNT] {
+    // Camera rays cannot be generated by light sampling, so the emission they find gets the full weight
+    trace_path(ray, scene, depth, max_depth, rng, sample, 1.0)
+}
+
+/// Recursive part of [`trace`]. The `emission_weight` is the multiple importance sampling weight of the emission found by the given [`Ray`], which depends on how the ray was generated at the previous bounce.
+fn trace_path(
+    ray: &Ray,
+    scene: &Scene,
+    depth: u32,
+    max_depth: u32,
+    rng: &mut SmallRng,
+    sample: &mut PixelSample,
+    emission_weight: Float,
 ) -> [Float; WAVE_SAMPLE_COUNT] {
     let hero = ray.wavelength;
     let wavelengths = rotate_wavelength(hero);
@@ -43,16 +58,21 @@ pub fn trace(
         .bvh_root
         .hit(ray, EPSILON_SHADOW_ACNE, Float::MAX, rng)
     else {
-        // If the ray hits nothing, early return the background color as emissivity
+        // If the ray hits nothing, early return the background color as emissivity.
+        // The background is never sampled as a light, so it is not weighted
         return bg;
     };
 
-    // Get the emitted color from the surface that we just hit
-    let emitted =
-        std::array::from_fn(|i| hit_record.material.emit(ray, wavelengths[i], &hit_record));
+    // Get the emitted color from the surface that we just hit, weighted by the strategy that found it
+    let emitted: [Float; WAVE_SAMPLE_COUNT] = std::array::from_fn(|i| {
+        emission_weight * hit_record.material.emit(ray, wavelengths[i], &hit_record)
+    });
+
+    // Get the sample values for the random decisions at this bounce
+    let bounce = sample.bounce(depth);
 
     // Do we scatter?
-    let Some(scatter_record) = hit_record.material.scatter(ray, &hit_record, rng) else {
+    let Some(scatter_record) = hit_record.material.scatter(ray, &hit_record, bounce) else {
         // No scatter, early return the emitted color only
         return emitted;
     };
@@ -72,23 +92,41 @@ pub fn trace(
     // Check the material type and recurse accordingly:
     match scatter_record.material_type {
         MaterialType::Specular => {
-            // If we hit a specular material, recurse with a specular ray, and multiply it with the attenuation
+            // If we hit a specular material, recurse with a specular ray, and multiply it with the attenuation.
+            // The specular direction is a delta distribution that light sampling cannot generate, so the next emission gets the full weight
             let scatter_ray = scatter_record.specular_ray.unwrap();
-            let specular = trace(&scatter_ray, scene, depth + 1, max_depth, rng, sampler);
-            std::array::from_fn(|i| specular[i] * attenuations[i])
+            let specular = trace_path(&scatter_ray, scene, depth + 1, max_depth, rng, sample, 1.0);
+            std::array::from_fn(|i| emitted[i] + specular[i] * attenuations[i])
         }
         MaterialType::Diffuse => {
-            // Multiple Importance Sampling:
+            // Multiple Importance Sampling between the light sampling and the material sampling:
 
             // Create a new PDF object from the priority hitables of the scene, given the current hit_record position
-            let light_ptr =
-                PDF::HitablePDF(HitablePDF::new(&scene.mis_bvh_root, hit_record.position));
+            let light_pdf = PDF::HitablePDF(HitablePDF::new(
+                &scene.mis_bvh_root,
+                hit_record.position,
+                ray.time,
+            ));
+            let material_pdf = scatter_record.pdf_ptr;
 
-            // Create a mixture PDF from the above + the PDF from the scatter_record
-            let mixture_pdf = MixturePDF::new(light_ptr, scatter_record.pdf_ptr);
+            // Next-event estimation: the direct lighting via a shadow ray towards a point on the lights.
+            // The scattering ray of the last bounce finds no emission, so the light sampling is skipped too: otherwise the direct lighting would only get its partial weight
+            let direct = if depth < max_depth {
+                sample_light(
+                    ray,
+                    scene,
+                    &hit_record,
+                    &light_pdf,
+                    &material_pdf,
+                    bounce,
+                    rng,
+                )
+            } else {
+                [0.0; WAVE_SAMPLE_COUNT]
+            };
 
-            // Generate a direction for the scattering ray to go towards, weighed by the mixture PDF
-            let direction = Unit::new_normalize(mixture_pdf.generate(rng));
+            // Generate a direction for the scattering ray to go towards, weighed by the material PDF
+            let direction = Unit::new_normalize(material_pdf.generate(bounce));
 
             // Create the ray
             let scatter_ray = Ray {
@@ -99,24 +137,191 @@ pub fn trace(
             };
 
             // Get the distribution value for the PDF
-            // TODO: improve correctness & optimization!
-            let mis_pdf_value = mixture_pdf.value(direction, hero, ray.time, rng);
+            let material_pdf_value = material_pdf.value(direction, hero, ray.time, rng);
 
             // Calculate the PDF weighting for the scatter
-            // TODO: improve correctness & optimization!
-            let Some(scattering_pdf) = hit_record
+            let scattering_pdf = match hit_record
                 .material
                 .scattering_pdf(&hit_record, &scatter_ray)
-            else {
-                // No scatter, only emit
-                return emitted;
+            {
+                Some(scattering_pdf) if material_pdf_value > 0.0 => scattering_pdf,
+                // No scatter, only emit and take the direct lighting
+                _ => {
+                    return std::array::from_fn(|i| emitted[i] + direct[i] * attenuations[i]);
+                }
             };
 
+            // The emission found by the scattering ray could also have been found by light sampling, weigh it accordingly
+            let light_pdf_value = light_pdf.value(direction, hero, ray.time, rng);
+            let weight = power_heuristic(material_pdf_value, light_pdf_value);
+
             // Recurse for the scattering ray
-            let recurse = trace(&scatter_ray, scene, depth + 1, max_depth, rng, sampler);
+            let recurse = trace_path(
+                &scatter_ray,
+                scene,
+                depth + 1,
+                max_depth,
+                rng,
+                sample,
+                weight,
+            );
             std::array::from_fn(|i| {
-                emitted[i] + recurse[i] * attenuations[i] * scattering_pdf / mis_pdf_value
+                emitted[i]
+                    + attenuations[i]
+                        * (direct[i] + recurse[i] * scattering_pdf / material_pdf_value)
             })
         }
     }
 }
+
+/// Next-event estimation. Samples a point on the priority objects of the scene, traces a shadow ray towards it, and returns the emission found by the shadow ray, weighted by the scattering of the material and the power heuristic. The attenuation of the material is not included.
+fn sample_light(
+    ray: &Ray,
+    scene: &Scene,
+    hit_record: &HitRecord,
+    light_pdf: &PDF,
+    material_pdf: &PDF,
+    bounce: BounceSample,
+    rng: &mut SmallRng,
+) -> [Float; WAVE_SAMPLE_COUNT] {
+    let hero = ray.wavelength;
+    let wavelengths = rotate_wavelength(hero);
+
+    // Pick a light and a point on it
+    let direction = Unit::new_normalize(light_pdf.generate(bounce));
+    let light_pdf_value = light_pdf.value(direction, hero, ray.time, rng);
+    if light_pdf_value <= 0.0 {
+        return [0.0; WAVE_SAMPLE_COUNT];
+    }
+
+    let shadow_ray = Ray {
+        origin: hit_record.position,
+        direction,
+        time: ray.time,
+        wavelength: ray.wavelength,
+    };
+
+    // Directions the material cannot scatter into receive no direct lighting
+    let Some(scattering_pdf) = hit_record.material.scattering_pdf(hit_record, &shadow_ray) else {
+        return [0.0; WAVE_SAMPLE_COUNT];
+    };
+
+    // Trace the shadow ray. The light is visible, if the closest hit along the direction is emissive.
+    // If an occluder is hit instead, its emission is zero
+    let Some(light_record) = scene
+        .bvh_root
+        .hit(&shadow_ray, EPSILON_SHADOW_ACNE, Float::MAX, rng)
+    else {
+        return [0.0; WAVE_SAMPLE_COUNT];
+    };
+
+    // The same direction could also have been generated by the material, weigh accordingly
+    let material_pdf_value = material_pdf.value(direction, hero, ray.time, rng);
+    let weight = power_heuristic(light_pdf_value, material_pdf_value);
+
+    std::array::from_fn(|i| {
+        let emitted = light_record
+            .material
+            .emit(&shadow_ray, wavelengths[i], &light_record);
+        emitted * scattering_pdf * weight / light_pdf_value
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use clovers::{
+        bvh::{BvhAlgorithm, BvhLayout},
+        Position, Vec3,
+    };
+    use rand::SeedableRng;
+    use serde_json::json;
+
+    use crate::{
+        sampler::{random::RandomSampler, SamplerTrait},
+        scenefile::SceneFile,
+    };
+
+    use super::*;
+
+    /// A diffuse floor under a downwards facing light. A non-emissive priority sphere under the floor keeps the scene valid when the light is not a priority object.
+    fn scene(light_priority: bool) -> Scene<'static> {
+        let description = json!({
+            "time_0": 0,
+            "time_1": 1,
+            "background_color": [0, 0, 0],
+            "camera": {
+                "look_from": [0, 1, -3],
+                "look_at": [0, 0, 0],
+                "up": [0, 1, 0],
+                "vertical_fov": 40,
+                "aperture": 0,
+                "focus_distance": 10
+            },
+            "objects": [
+                { "kind": "Quad", "q": [-2, 0, -2], "u": [0, 0, 4], "v": [4, 0, 0], "material": "floor" },
+                { "kind": "Quad", "q": [-1, 2, -1], "u": [2, 0, 0], "v": [0, 0, 2], "material": "lamp", "priority": light_priority },
+                { "kind": "Sphere", "center": [0, -3, 0], "radius": 0.5, "material": "floor", "priority": true }
+            ],
+            "materials": [
+                { "name": "floor", "kind": "Lambertian", "albedo": { "kind": "SolidColor", "color": [0.8, 0.8, 0.8] } },
+                { "name": "lamp", "kind": "DiffuseLight", "emit": { "kind": "IlluminantD50", "intensity": 1.0 } }
+            ]
+        });
+        let scene_file: SceneFile = serde_json::from_value(description).unwrap();
+        let (scene, _shots) = SceneFile::initialize(
+            scene_file,
+            BvhAlgorithm::default(),
+            BvhLayout::default(),
+            None,
+            1,
+            1,
+        )
+        .unwrap();
+        scene
+    }
+
+    /// Mean of the traced intensities over all wavelengths, for rays towards the floor under the light
+    fn mean_intensity(scene: &Scene, max_depth: u32, samples: u32) -> Float {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut sampler_rng = SmallRng::seed_from_u64(1);
+        let mut sampler = RandomSampler::new(&mut sampler_rng);
+        let ray = Ray {
+            origin: Position::new(0.0, 1.0, -3.0),
+            direction: Unit::new_normalize(Vec3::new(0.0, -1.0, 3.0)),
+            time: 0.0,
+            wavelength: 550,
+        };
+        let mut sum = 0.0;
+        for index in 0..samples {
+            #[allow(clippy::cast_possible_wrap)]
+            let mut sample =
+                PixelSample::new(&mut sampler as &mut dyn SamplerTrait, 0, 0, index as i32);
+            sum += trace(&ray, scene, 0, max_depth, &mut rng, &mut sample)
+                .iter()
+                .sum::<Float>();
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let mean = sum / samples as Float;
+        mean
+    }
+
+    #[test]
+    fn light_sampling_matches_material_sampling() {
+        let with_light_sampling = scene(true);
+        let material_sampling_only = scene(false);
+        for max_depth in [0, 1, 2] {
+            let nee = mean_intensity(&with_light_sampling, max_depth, 5_000);
+            let bsdf = mean_intensity(&material_sampling_only, max_depth, 50_000);
+            if max_depth == 0 {
+                // The scattered ray of the only bounce is not traced, and the light is not sampled either
+                assert!(nee.abs() <= Float::EPSILON && bsdf.abs() <= Float::EPSILON);
+            } else {
+                assert!(bsdf > 0.0);
+                assert!(
+                    (nee - bsdf).abs() <= 0.05 * bsdf,
+                    "max depth {max_depth}: light sampling {nee}, material sampling {bsdf}"
+                );
+            }
+        }
+    }
+}
@@ -31,6 +31,8 @@ pub fn png(
         mode,
         sampler: _,
         bvh: _,
+        bvh_layout: _,
+        no_bvh_cache: _,
         formats: _,
     } = render_options;
 
@@ -1,3 +1,4 @@
+use clovers::bvh::{WideNode4, WideNode8};
 use clovers::interval::Interval;
 use clovers::random::random_unit_vector;
 use clovers::ray::Ray;
@@ -34,6 +35,43 @@ fn hit(bencher: divan::Bencher) {
         .bench_values(|(aabb, ray)| black_box(aabb.hit(&ray, Float::NEG_INFINITY, Float::INFINITY)))
 }
 
+#[divan::bench(consts = [4, 8])]
+fn hit_sequential<const N: usize>(bencher: divan::Bencher) {
+    bencher
+        .with_inputs(random_aabbs_and_ray::<N>)
+        .counter(N as u32)
+        .bench_values(|(aabbs, ray)| {
+            black_box(
+                aabbs
+                    .iter()
+                    .filter(|aabb| aabb.hit(&ray, Float::NEG_INFINITY, Float::INFINITY))
+                    .count(),
+            )
+        })
+}
+
+#[divan::bench]
+fn hit_wide4(bencher: divan::Bencher) {
+    bencher
+        .with_inputs(|| {
+            let (aabbs, ray) = random_aabbs_and_ray::<4>();
+            (WideNode4::new(&aabbs.each_ref()), ray)
+        })
+        .counter(4u32)
+        .bench_values(|(node, ray)| black_box(node.hit(&ray, Float::NEG_INFINITY, Float::INFINITY)))
+}
+
+#[divan::bench]
+fn hit_wide8(bencher: divan::Bencher) {
+    bencher
+        .with_inputs(|| {
+            let (aabbs, ray) = random_aabbs_and_ray::<8>();
+            (WideNode8::new(&aabbs.each_ref()), ray)
+        })
+        .counter(8u32)
+        .bench_values(|(node, ray)| black_box(node.hit(&ray, Float::NEG_INFINITY, Float::INFINITY)))
+}
+
 // Helper functions
 
 fn random_intervals(rng: &mut SmallRng) -> (Interval, Interval, Interval) {
@@ -71,3 +109,10 @@ fn random_aabb_and_ray() -> (AABB, Ray) {
     let ray = black_box(random_ray(&mut rng));
     (aabb, ray)
 }
+
+fn random_aabbs_and_ray<const N: usize>() -> ([AABB; N], Ray) {
+    let mut rng = SmallRng::from_os_rng();
+    let aabbs = black_box(core::array::from_fn(|_| random_aabb(&mut rng)));
+    let ray = black_box(random_ray(&mut rng));
+    (aabbs, ray)
+}
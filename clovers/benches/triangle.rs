@@ -1,9 +1,11 @@
-use clovers::hitable::HitableTrait;
+use clovers::bvh::{BvhAlgorithm, BvhLayout, SceneBVH};
+use clovers::hitable::{Hitable, HitableTrait};
 use clovers::materials::Material;
 use clovers::objects::Triangle;
 use clovers::random::random_unit_vector;
 use clovers::ray::Ray;
 use clovers::wavelength::random_wavelength;
+use clovers::Direction;
 use clovers::Float;
 use clovers::Vec3;
 use divan::black_box;
@@ -44,6 +46,35 @@ fn hit(bencher: divan::Bencher) {
         })
 }
 
+#[divan::bench(args = [
+    BvhAlgorithm::Sah,
+    BvhAlgorithm::BinnedSah,
+    BvhAlgorithm::Sbvh,
+])]
+fn hit_bvh(bencher: divan::Bencher, bvh_algorithm: BvhAlgorithm) {
+    let bvh = random_triangle_bvh(bvh_algorithm, BvhLayout::Binary);
+    bench_bvh_hit(bencher, &bvh);
+}
+
+#[divan::bench(args = [BvhLayout::Binary, BvhLayout::Wide4, BvhLayout::Wide8])]
+fn hit_bvh_layout(bencher: divan::Bencher, bvh_layout: BvhLayout) {
+    let bvh = random_triangle_bvh(BvhAlgorithm::Sah, bvh_layout);
+    bench_bvh_hit(bencher, &bvh);
+}
+
+fn bench_bvh_hit(bencher: divan::Bencher, bvh: &SceneBVH) {
+    bencher
+        .with_inputs(|| {
+            let mut rng = SmallRng::from_os_rng();
+            let ray = random_ray_towards_bvh(&mut rng);
+            (ray, rng)
+        })
+        .counter(1u32)
+        .bench_values(|(ray, mut rng)| {
+            black_box(bvh.hit(&ray, 0.0, Float::INFINITY, &mut rng).is_some())
+        })
+}
+
 fn random_vec(rng: &mut SmallRng) -> Vec3 {
     Vec3::new(rng.random(), rng.random(), rng.random())
 }
@@ -80,3 +111,28 @@ fn random_ray() -> Ray {
 fn random_triangle_and_ray() -> (Triangle<'static>, Ray) {
     (random_triangle(), random_ray())
 }
+
+fn random_triangle_bvh(bvh_algorithm: BvhAlgorithm, bvh_layout: BvhLayout) -> SceneBVH<'static> {
+    let mut rng = SmallRng::from_os_rng();
+    let material: &'static Material = Box::leak(Box::default());
+    let triangles = (0..10_000)
+        .map(|_| {
+            let q = random_vec(&mut rng) * 10.0;
+            let u = random_vec(&mut rng) * 0.2;
+            let v = random_vec(&mut rng) * 0.2;
+            Hitable::Triangle(Triangle::new(q, u, v, material))
+        })
+        .collect();
+    SceneBVH::from_list(bvh_algorithm, bvh_layout, triangles)
+}
+
+fn random_ray_towards_bvh(rng: &mut SmallRng) -> Ray {
+    let origin = Vec3::new(5.0, 5.0, -10.0);
+    let target = random_vec(rng) * 10.0;
+    black_box(Ray {
+        origin,
+        direction: Direction::new_normalize(target - origin),
+        time: rng.random(),
+        wavelength: random_wavelength(rng),
+    })
+}
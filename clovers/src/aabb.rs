@@ -95,6 +95,21 @@ impl AABB {
         }
     }
 
+    /// Given two axis-aligned bounding boxes, return the [AABB] of their intersection, or `None` if they do not overlap.
+    #[must_use]
+    pub fn intersection(box0: &AABB, box1: &AABB) -> Option<AABB> {
+        let interval = |a: &Interval, b: &Interval| {
+            let min = a.min.max(b.min);
+            let max = a.max.min(b.max);
+            (min <= max).then(|| Interval::new(min, max))
+        };
+        Some(AABB {
+            x: interval(&box0.x, &box1.x)?,
+            y: interval(&box0.y, &box1.y)?,
+            z: interval(&box0.z, &box1.z)?,
+        })
+    }
+
     /// Make sure we don't have a zero-thickness AABB, padding if necessary.
     pub fn pad(&mut self) {
         // TODO: refactor
@@ -3,16 +3,27 @@
 use core::fmt::Display;
 use std::time::Instant;
 
-use build::{longest_axis_midpoint, surface_area_heuristic};
+use build::{
+    binned_surface_area_heuristic, longest_axis_midpoint, spatial_split, surface_area_heuristic,
+};
 #[cfg(feature = "tracing")]
 use tracing::info;
 
-use crate::{aabb::AABB, hitable::Hitable, Box};
+use rand::rngs::SmallRng;
+
+use crate::{aabb::AABB, hitable::Hitable, ray::Ray, Box, Float, HitRecord};
 
 pub(crate) mod build;
+mod flat;
 mod hitable_trait;
 mod primitive_testcount;
+mod stats;
 mod testcount;
+mod wide;
+
+pub use flat::{FlatBVH, FlatNode};
+pub use stats::BvhStats;
+pub use wide::{SimdLanes, WideBVH, WideBVH4, WideBVH8, WideNode, WideNode4, WideNode8};
 
 /// Bounding Volume Hierarchy Node.
 ///
@@ -37,6 +48,10 @@ pub enum BvhAlgorithm {
     ///
     /// Heavily inspired by the wonderful blog series <https://jacco.ompf2.com/2022/04/18/how-to-build-a-bvh-part-2-faster-rays/>.
     Sah,
+    /// Splitting method based on the Surface Area Heuristic, evaluated between a fixed number of bins. Much faster to build for large scenes.
+    BinnedSah,
+    /// Spatial split BVH: the binned Surface Area Heuristic, with the option of splitting large primitives into both children. Slower to build, but faster to render for scenes with large overlapping primitives.
+    Sbvh,
 }
 
 impl Display for BvhAlgorithm {
@@ -44,6 +59,30 @@ impl Display for BvhAlgorithm {
         match self {
             BvhAlgorithm::Lam => write!(f, "Longest Axis Midpoint"),
             BvhAlgorithm::Sah => write!(f, "Surface Area Heuristic"),
+            BvhAlgorithm::BinnedSah => write!(f, "Binned Surface Area Heuristic"),
+            BvhAlgorithm::Sbvh => write!(f, "Spatial Split BVH"),
+        }
+    }
+}
+
+/// The layout of the flattened Bounding Volume Hierarchy used for rendering. The tree is always built as a binary tree with the chosen [`BvhAlgorithm`], and then collapsed into the chosen layout.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum BvhLayout {
+    /// Binary hierarchy, with two children per node
+    #[default]
+    Binary,
+    /// Hierarchy with four children per node, tested together with SIMD
+    Wide4,
+    /// Hierarchy with eight children per node, tested together with SIMD
+    Wide8,
+}
+
+impl Display for BvhLayout {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BvhLayout::Binary => write!(f, "binary"),
+            BvhLayout::Wide4 => write!(f, "4-wide"),
+            BvhLayout::Wide8 => write!(f, "8-wide"),
         }
     }
 }
@@ -64,6 +103,8 @@ impl BVHNode<'_> {
         let bvh = match bvh_algorithm {
             BvhAlgorithm::Lam => longest_axis_midpoint(hitables),
             BvhAlgorithm::Sah => surface_area_heuristic(hitables),
+            BvhAlgorithm::BinnedSah => binned_surface_area_heuristic(hitables),
+            BvhAlgorithm::Sbvh => spatial_split(hitables),
         };
         let end = Instant::now();
         let duration = (end - start).as_millis();
@@ -87,3 +128,111 @@ impl BVHNode<'_> {
         leftsum + rightsum
     }
 }
+
+/// The flattened Bounding Volume Hierarchy used for rendering a scene. Either a binary [`FlatBVH`], or a [`WideBVH`] collapsed from it, depending on the chosen [`BvhLayout`].
+#[derive(Debug, Clone)]
+pub enum SceneBVH<'scene> {
+    /// Binary hierarchy
+    Binary(FlatBVH<'scene>),
+    /// Hierarchy with four children per node
+    Wide4(WideBVH4<'scene>),
+    /// Hierarchy with eight children per node
+    Wide8(WideBVH8<'scene>),
+}
+
+impl<'scene> SceneBVH<'scene> {
+    /// Builds a [`BVHNode`] tree from a given list of [Object](crate::objects::Object)s with the given [`BvhAlgorithm`], and flattens it into the given [`BvhLayout`]
+    #[must_use]
+    pub fn from_list(
+        bvh_algorithm: BvhAlgorithm,
+        bvh_layout: BvhLayout,
+        hitables: Vec<Hitable<'scene>>,
+    ) -> Self {
+        let flat = BVHNode::from_list(bvh_algorithm, hitables).flatten();
+        SceneBVH::from_flat(bvh_layout, flat)
+    }
+
+    /// Converts a binary [`FlatBVH`] into the given [`BvhLayout`]
+    #[must_use]
+    pub fn from_flat(bvh_layout: BvhLayout, flat: FlatBVH<'scene>) -> Self {
+        match bvh_layout {
+            BvhLayout::Binary => SceneBVH::Binary(flat),
+            BvhLayout::Wide4 => SceneBVH::Wide4(flat.widen()),
+            BvhLayout::Wide8 => SceneBVH::Wide8(flat.widen()),
+        }
+    }
+
+    #[must_use]
+    /// Returns the count of the nodes in the hierarchy
+    pub fn count(&self) -> usize {
+        match self {
+            SceneBVH::Binary(bvh) => bvh.count(),
+            SceneBVH::Wide4(bvh) => bvh.count(),
+            SceneBVH::Wide8(bvh) => bvh.count(),
+        }
+    }
+
+    /// Returns the bounding box of the hierarchy, or `None` if it is empty
+    #[must_use]
+    pub fn aabb(&self) -> Option<AABB> {
+        match self {
+            SceneBVH::Binary(bvh) => bvh.aabb().cloned(),
+            SceneBVH::Wide4(bvh) => bvh.aabb(),
+            SceneBVH::Wide8(bvh) => bvh.aabb(),
+        }
+    }
+
+    /// The main `hit` function for a [`SceneBVH`]. Given a [Ray], and an interval `distance_min` and `distance_max`, returns either `None` or `Some(HitRecord)` based on whether the ray intersects with the encased objects during that interval.
+    #[must_use]
+    pub fn hit(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        match self {
+            SceneBVH::Binary(bvh) => bvh.hit(ray, distance_min, distance_max, rng),
+            SceneBVH::Wide4(bvh) => bvh.hit(ray, distance_min, distance_max, rng),
+            SceneBVH::Wide8(bvh) => bvh.hit(ray, distance_min, distance_max, rng),
+        }
+    }
+
+    /// Alternate hit method that maintains a test count for the BVH traversals.
+    pub fn testcount(
+        &self,
+        depth: &mut usize,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        match self {
+            SceneBVH::Binary(bvh) => bvh.testcount(depth, ray, distance_min, distance_max, rng),
+            SceneBVH::Wide4(bvh) => bvh.testcount(depth, ray, distance_min, distance_max, rng),
+            SceneBVH::Wide8(bvh) => bvh.testcount(depth, ray, distance_min, distance_max, rng),
+        }
+    }
+
+    /// Alternate hit method that maintains a test count for the primitives
+    pub fn primitive_testcount(
+        &self,
+        count: &mut usize,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        rng: &mut SmallRng,
+    ) {
+        match self {
+            SceneBVH::Binary(bvh) => {
+                bvh.primitive_testcount(count, ray, distance_min, distance_max, rng);
+            }
+            SceneBVH::Wide4(bvh) => {
+                bvh.primitive_testcount(count, ray, distance_min, distance_max, rng);
+            }
+            SceneBVH::Wide8(bvh) => {
+                bvh.primitive_testcount(count, ray, distance_min, distance_max, rng);
+            }
+        }
+    }
+}
@@ -1,6 +1,10 @@
+mod binned_surface_area_heuristic;
 mod longest_axis_midpoint;
+mod spatial_split;
 mod surface_area_heuristic;
+pub(crate) use binned_surface_area_heuristic::build as binned_surface_area_heuristic;
 pub(crate) use longest_axis_midpoint::build as longest_axis_midpoint;
+pub(crate) use spatial_split::build as spatial_split;
 pub(crate) use surface_area_heuristic::build as surface_area_heuristic;
 
 // Internal use only
@@ -0,0 +1,273 @@
+//! Binned Surface Area Heuristic for the BVH tree construction.
+//!
+//! Instead of evaluating every candidate split against every primitive, the centroids of the primitives are sorted into a fixed number of bins per axis, and the split candidates are evaluated between the bins. Based on "On fast Construction of SAH-based Bounding Volume Hierarchies" by Ingo Wald, 2007.
+
+#[cfg(feature = "std")]
+use rayon::prelude::*;
+
+use crate::{
+    aabb::AABB,
+    bvh::BVHNode,
+    hitable::{Empty, Hitable, HitableTrait},
+    Float,
+};
+
+use super::utils::{join, vec_bounding_box};
+
+/// Count of the bins per axis
+const BIN_COUNT: usize = 16;
+/// Count of the primitives in a node above which the binning is done in parallel
+#[cfg(feature = "std")]
+const PARALLEL_THRESHOLD: usize = 4096;
+
+pub fn build(mut hitables: Vec<Hitable>) -> BVHNode {
+    let aabb = vec_bounding_box(&hitables).unwrap_or_default();
+
+    // Possible leaf nodes
+    if hitables.len() <= 2 {
+        let mut hitables = hitables.into_iter();
+        let left = Box::new(hitables.next().unwrap_or(Hitable::Empty(Empty {})));
+        let right = Box::new(hitables.next().unwrap_or(Hitable::Empty(Empty {})));
+        return BVHNode { left, right, aabb };
+    }
+
+    let hitables_right = match find_best_split(&hitables, hitable_aabb) {
+        Some(split) => {
+            let (left, right) = hitables
+                .into_iter()
+                .partition(|hitable| split.is_left(hitable_aabb(hitable)));
+            hitables = left;
+            right
+        }
+        // All centroids are at the same position, split in half
+        None => hitables.split_off(hitables.len() / 2),
+    };
+
+    let (left, right) = join(|| build(hitables), || build(hitables_right));
+
+    BVHNode {
+        left: Box::new(Hitable::BVHNode(left)),
+        right: Box::new(Hitable::BVHNode(right)),
+        aabb,
+    }
+}
+
+fn hitable_aabb<'a>(hitable: &'a Hitable) -> &'a AABB {
+    hitable.aabb().expect("No bounding box for objects")
+}
+
+/// A bin of primitives
+#[derive(Clone, Debug, Default)]
+struct Bin {
+    /// Bounding box of the primitives in the bin
+    aabb: Option<AABB>,
+    /// Count of the primitives in the bin
+    count: usize,
+}
+
+impl Bin {
+    fn grow(&mut self, aabb: &AABB) {
+        self.aabb = Some(match &self.aabb {
+            Some(bin_aabb) => AABB::combine(bin_aabb, aabb),
+            None => aabb.clone(),
+        });
+        self.count += 1;
+    }
+
+    fn merge(&mut self, other: &Bin) {
+        if let Some(aabb) = &other.aabb {
+            self.aabb = Some(match &self.aabb {
+                Some(bin_aabb) => AABB::combine(bin_aabb, aabb),
+                None => aabb.clone(),
+            });
+        }
+        self.count += other.count;
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn cost(&self) -> Float {
+        self.aabb
+            .as_ref()
+            .map_or(0.0, |aabb| self.count as Float * aabb.area())
+    }
+}
+
+/// The best found split: primitives with their centroid in the bins up to and including `bin` on the `axis` go to the left
+#[derive(Clone, Debug)]
+pub(super) struct Split {
+    /// Axis of the split
+    pub axis: usize,
+    /// Last bin on the left side of the split
+    pub bin: usize,
+    /// Minimum of the centroid bounds on the split axis
+    pub min: Float,
+    /// Scale from the centroid coordinate into the bin index
+    pub scale: Float,
+    /// Surface Area Heuristic cost of the split
+    pub cost: Float,
+    /// Bounding box of the left side
+    pub left_aabb: AABB,
+    /// Bounding box of the right side
+    pub right_aabb: AABB,
+}
+
+impl Split {
+    /// Returns true if a primitive with the given bounding box belongs to the left side of the split
+    pub fn is_left(&self, aabb: &AABB) -> bool {
+        bin_index(aabb.centroid()[self.axis], self.min, self.scale) <= self.bin
+    }
+}
+
+/// Returns the index of the bin for the given coordinate
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn bin_index(coordinate: Float, min: Float, scale: Float) -> usize {
+    (((coordinate - min) * scale) as usize).min(BIN_COUNT - 1)
+}
+
+/// Bins the items by the centroids of their bounding boxes, and finds the split with the lowest Surface Area Heuristic cost. Returns `None` if the centroids cannot be separated.
+pub(super) fn find_best_split<T: Sync>(items: &[T], aabb: fn(&T) -> &AABB) -> Option<Split> {
+    // Bounds of the centroids, so that the bins cover the range where the splits can happen
+    let mut min = [Float::INFINITY; 3];
+    let mut max = [Float::NEG_INFINITY; 3];
+    for item in items {
+        let centroid = aabb(item).centroid();
+        for axis in 0..3 {
+            min[axis] = min[axis].min(centroid[axis]);
+            max[axis] = max[axis].max(centroid[axis]);
+        }
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let scale: [Float; 3] = core::array::from_fn(|axis| {
+        let extent = max[axis] - min[axis];
+        if extent > 0.0 {
+            // Slightly less than the full range, so that the maximum maps into the last bin
+            BIN_COUNT as Float * (1.0 - Float::EPSILON) / extent
+        } else {
+            0.0
+        }
+    });
+
+    let bins = bin_items(items, aabb, &min, &scale);
+
+    let mut best: Option<Split> = None;
+    for axis in 0..3 {
+        if scale[axis] == 0.0 {
+            continue;
+        }
+        // Sweep from the right to get the cumulative right sides of each split
+        let mut right_bins: [Bin; BIN_COUNT] = Default::default();
+        let mut accumulated = Bin::default();
+        for bin in (1..BIN_COUNT).rev() {
+            accumulated.merge(&bins[axis][bin]);
+            right_bins[bin] = accumulated.clone();
+        }
+        // Sweep from the left, evaluating the split after each bin
+        let mut left = Bin::default();
+        for bin in 0..BIN_COUNT - 1 {
+            left.merge(&bins[axis][bin]);
+            let right = &right_bins[bin + 1];
+            if left.count == 0 || right.count == 0 {
+                continue;
+            }
+            let cost = left.cost() + right.cost();
+            if best.as_ref().is_none_or(|best| cost < best.cost) {
+                best = Some(Split {
+                    axis,
+                    bin,
+                    min: min[axis],
+                    scale: scale[axis],
+                    cost,
+                    left_aabb: left.aabb.clone().unwrap_or_default(),
+                    right_aabb: right.aabb.clone().unwrap_or_default(),
+                });
+            }
+        }
+    }
+
+    best
+}
+
+/// Sorts the items into bins on each axis
+fn bin_items<T: Sync>(
+    items: &[T],
+    aabb: fn(&T) -> &AABB,
+    min: &[Float; 3],
+    scale: &[Float; 3],
+) -> [[Bin; BIN_COUNT]; 3] {
+    let bin_chunk = |chunk: &[T]| {
+        let mut bins: [[Bin; BIN_COUNT]; 3] = Default::default();
+        for item in chunk {
+            let aabb = aabb(item);
+            let centroid = aabb.centroid();
+            for axis in 0..3 {
+                bins[axis][bin_index(centroid[axis], min[axis], scale[axis])].grow(aabb);
+            }
+        }
+        bins
+    };
+
+    #[cfg(feature = "std")]
+    if items.len() > PARALLEL_THRESHOLD {
+        return items
+            .par_chunks(PARALLEL_THRESHOLD)
+            .map(bin_chunk)
+            .reduce(Default::default, merge_bins);
+    }
+    bin_chunk(items)
+}
+
+#[cfg(feature = "std")]
+fn merge_bins(mut a: [[Bin; BIN_COUNT]; 3], b: [[Bin; BIN_COUNT]; 3]) -> [[Bin; BIN_COUNT]; 3] {
+    for (a, b) in a.iter_mut().zip(b) {
+        for (a, b) in a.iter_mut().zip(b) {
+            a.merge(&b);
+        }
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use crate::{materials::Material, objects::Sphere, Position};
+
+    use super::*;
+
+    fn count_primitives(hitable: &Hitable) -> usize {
+        match hitable {
+            Hitable::BVHNode(node) => count_primitives(&node.left) + count_primitives(&node.right),
+            Hitable::Empty(_) => 0,
+            _ => 1,
+        }
+    }
+
+    #[test]
+    fn contains_all_primitives() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let material = Material::default();
+        let spheres: Vec<Hitable> = (0..10_000)
+            .map(|_| {
+                let center = Position::new(rng.random(), rng.random(), rng.random()) * 100.0;
+                Hitable::Sphere(Sphere::new(center, 0.2, &material))
+            })
+            .collect();
+        let bvh = build(spheres);
+        assert_eq!(count_primitives(&Hitable::BVHNode(bvh)), 10_000);
+    }
+
+    #[test]
+    fn coincident_centroids() {
+        let material = Material::default();
+        let spheres: Vec<Hitable> = (0..100)
+            .map(|_| Hitable::Sphere(Sphere::new(Position::new(1.0, 2.0, 3.0), 1.0, &material)))
+            .collect();
+        let bvh = build(spheres);
+        assert_eq!(count_primitives(&Hitable::BVHNode(bvh)), 100);
+    }
+}
@@ -0,0 +1,407 @@
+//! Spatial split Bounding Volume Hierarchy (SBVH) construction.
+//!
+//! In addition to the object splits of the binned Surface Area Heuristic, considers splitting space itself: primitives that straddle a spatial split are clipped and referenced on both sides. This greatly reduces the overlap between nodes for scenes with large or long primitives. Based on "Spatial Splits in Bounding Volume Hierarchies" by Martin Stich, Heiko Friedrich and Andreas Dietrich, 2009.
+
+use crate::{
+    aabb::AABB,
+    bvh::BVHNode,
+    hitable::{Empty, Hitable, HitableTrait},
+    interval::Interval,
+    objects::Triangle,
+    Float, Position,
+};
+
+use super::{
+    binned_surface_area_heuristic::find_best_split,
+    utils::{join, vec_bounding_box},
+};
+
+/// Count of the bins per axis for the spatial splits
+const BIN_COUNT: usize = 16;
+/// Spatial splits are only considered if the overlap of the best object split is larger than this fraction of the root surface area
+const OVERLAP_THRESHOLD: Float = 1e-5;
+/// Maximum depth at which spatial splits are still considered, to limit the duplication of references
+const MAX_SPATIAL_DEPTH: usize = 48;
+
+/// A reference to a primitive, with the bounding box of the part of the primitive within the current node
+#[derive(Clone, Debug)]
+struct Reference {
+    index: usize,
+    aabb: AABB,
+}
+
+/// A spatial split: references on the `axis` below the `position` go to the left, above it go to the right, and the ones in between to both sides
+struct SpatialSplit {
+    axis: usize,
+    position: Float,
+    cost: Float,
+}
+
+// Takes ownership for consistency with the other build algorithms; the primitives are cloned into the leaves
+#[allow(clippy::needless_pass_by_value)]
+pub fn build(hitables: Vec<Hitable>) -> BVHNode {
+    let references: Vec<Reference> = hitables
+        .iter()
+        .enumerate()
+        .map(|(index, hitable)| Reference {
+            index,
+            aabb: hitable.aabb().expect("No bounding box for objects").clone(),
+        })
+        .collect();
+    let root_area = vec_bounding_box(&hitables).map_or(0.0, |aabb| aabb.area());
+    build_node(&hitables, references, root_area, 0)
+}
+
+fn build_node<'scene>(
+    hitables: &[Hitable<'scene>],
+    mut references: Vec<Reference>,
+    root_area: Float,
+    depth: usize,
+) -> BVHNode<'scene> {
+    let aabb = references
+        .iter()
+        .map(|reference| reference.aabb.clone())
+        .reduce(|a, b| AABB::combine(&a, &b))
+        .unwrap_or_default();
+
+    // Possible leaf nodes
+    if references.len() <= 2 {
+        let mut references = references.iter();
+        let mut next = || {
+            references
+                .next()
+                .map_or(Hitable::Empty(Empty {}), |reference| {
+                    hitables[reference.index].clone()
+                })
+        };
+        let left = Box::new(next());
+        let right = Box::new(next());
+        return BVHNode { left, right, aabb };
+    }
+
+    let object_split = find_best_split(&references, |reference| &reference.aabb);
+
+    // Only try spatial splits if the children of the object split would overlap significantly
+    let spatial_split = if depth < MAX_SPATIAL_DEPTH
+        && object_split.as_ref().is_none_or(|split| {
+            overlap(&split.left_aabb, &split.right_aabb) > OVERLAP_THRESHOLD * root_area
+        }) {
+        find_spatial_split(hitables, &references, &aabb)
+    } else {
+        None
+    };
+
+    let count = references.len();
+    let mut partition = None;
+    if let Some(spatial_split) = spatial_split {
+        if object_split
+            .as_ref()
+            .is_none_or(|split| spatial_split.cost < split.cost)
+        {
+            let (left, right) = split_references(hitables, &references, &spatial_split);
+            // Make sure the split makes progress
+            if left.len() < count && right.len() < count {
+                partition = Some((left, right));
+            }
+        }
+    }
+    let (left, right) = match (partition, object_split) {
+        (Some(partition), _) => partition,
+        (None, Some(split)) => references
+            .into_iter()
+            .partition(|reference| split.is_left(&reference.aabb)),
+        // All centroids are at the same position, split in half
+        (None, None) => {
+            let right = references.split_off(count / 2);
+            (references, right)
+        }
+    };
+
+    let (left, right) = join(
+        || build_node(hitables, left, root_area, depth + 1),
+        || build_node(hitables, right, root_area, depth + 1),
+    );
+
+    BVHNode {
+        left: Box::new(Hitable::BVHNode(left)),
+        right: Box::new(Hitable::BVHNode(right)),
+        aabb,
+    }
+}
+
+/// Returns the surface area of the overlap of the two bounding boxes
+fn overlap(a: &AABB, b: &AABB) -> Float {
+    AABB::intersection(a, b).map_or(0.0, |aabb| aabb.area())
+}
+
+/// A bin of the spatial split, counting the references that start and end within it
+#[derive(Clone, Debug, Default)]
+struct SpatialBin {
+    aabb: Option<AABB>,
+    entries: usize,
+    exits: usize,
+}
+
+impl SpatialBin {
+    fn grow(&mut self, aabb: &AABB) {
+        self.aabb = Some(match &self.aabb {
+            Some(bin_aabb) => AABB::combine(bin_aabb, aabb),
+            None => aabb.clone(),
+        });
+    }
+}
+
+/// Finds the spatial split with the lowest Surface Area Heuristic cost, clipping the primitives into the bins they overlap
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn find_spatial_split(
+    hitables: &[Hitable],
+    references: &[Reference],
+    aabb: &AABB,
+) -> Option<SpatialSplit> {
+    let mut best: Option<SpatialSplit> = None;
+
+    for axis in 0..3 {
+        let min = aabb.axis(axis).min;
+        let extent = aabb.axis(axis).size();
+        if extent <= 0.0 {
+            continue;
+        }
+        let width = extent / BIN_COUNT as Float;
+        let bin_index =
+            |coordinate: Float| (((coordinate - min) / width) as usize).min(BIN_COUNT - 1);
+
+        let mut bins: [SpatialBin; BIN_COUNT] = Default::default();
+        for reference in references {
+            let first = bin_index(reference.aabb.axis(axis).min);
+            let last = bin_index(reference.aabb.axis(axis).max);
+            bins[first].entries += 1;
+            bins[last].exits += 1;
+            for (bin_number, bin) in bins.iter_mut().enumerate().take(last + 1).skip(first) {
+                let bin_min = min + bin_number as Float * width;
+                if let Some(clipped) = clip(
+                    &hitables[reference.index],
+                    &reference.aabb,
+                    axis,
+                    bin_min,
+                    bin_min + width,
+                ) {
+                    bin.grow(&clipped);
+                }
+            }
+        }
+
+        // Sweep from the right to get the cumulative right sides of each split
+        let mut right_aabbs: [Option<AABB>; BIN_COUNT] = Default::default();
+        let mut right_counts = [0; BIN_COUNT];
+        let mut accumulated: Option<AABB> = None;
+        let mut count = 0;
+        for bin in (1..BIN_COUNT).rev() {
+            accumulated = combine(accumulated, bins[bin].aabb.as_ref());
+            count += bins[bin].exits;
+            right_aabbs[bin].clone_from(&accumulated);
+            right_counts[bin] = count;
+        }
+        // Sweep from the left, evaluating the split after each bin
+        let mut left_aabb: Option<AABB> = None;
+        let mut left_count = 0;
+        for bin in 0..BIN_COUNT - 1 {
+            left_aabb = combine(left_aabb, bins[bin].aabb.as_ref());
+            left_count += bins[bin].entries;
+            let right_count = right_counts[bin + 1];
+            let (Some(left), Some(right)) = (&left_aabb, &right_aabbs[bin + 1]) else {
+                continue;
+            };
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+            let cost = left_count as Float * left.area() + right_count as Float * right.area();
+            if best.as_ref().is_none_or(|best| cost < best.cost) {
+                best = Some(SpatialSplit {
+                    axis,
+                    position: min + (bin + 1) as Float * width,
+                    cost,
+                });
+            }
+        }
+    }
+
+    best
+}
+
+fn combine(a: Option<AABB>, b: Option<&AABB>) -> Option<AABB> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(AABB::combine(&a, b)),
+        (a, None) => a,
+        (None, Some(b)) => Some(b.clone()),
+    }
+}
+
+/// Distributes the references to the sides of the spatial split, duplicating the ones that straddle the split position
+fn split_references(
+    hitables: &[Hitable],
+    references: &[Reference],
+    split: &SpatialSplit,
+) -> (Vec<Reference>, Vec<Reference>) {
+    let SpatialSplit { axis, position, .. } = *split;
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+    for reference in references {
+        let interval = reference.aabb.axis(axis);
+        if interval.max <= position {
+            left.push(reference.clone());
+        } else if interval.min >= position {
+            right.push(reference.clone());
+        } else {
+            let hitable = &hitables[reference.index];
+            if let Some(aabb) = clip(hitable, &reference.aabb, axis, interval.min, position) {
+                left.push(Reference {
+                    index: reference.index,
+                    aabb,
+                });
+            }
+            if let Some(aabb) = clip(hitable, &reference.aabb, axis, position, interval.max) {
+                right.push(Reference {
+                    index: reference.index,
+                    aabb,
+                });
+            }
+        }
+    }
+    (left, right)
+}
+
+/// Returns the bounding box of the part of the primitive within the given bounding box and the slab between `min` and `max` on the `axis`. Triangles are clipped exactly, other primitives by their bounding box.
+fn clip(hitable: &Hitable, aabb: &AABB, axis: usize, min: Float, max: Float) -> Option<AABB> {
+    let slab_min = min.max(aabb.axis(axis).min);
+    let slab_max = max.min(aabb.axis(axis).max);
+    if slab_min > slab_max {
+        return None;
+    }
+    let mut clipped = aabb.clone();
+    match axis {
+        0 => clipped.x = Interval::new(slab_min, slab_max),
+        1 => clipped.y = Interval::new(slab_min, slab_max),
+        _ => clipped.z = Interval::new(slab_min, slab_max),
+    }
+
+    let triangle = match hitable {
+        Hitable::Triangle(triangle) => triangle,
+        #[cfg(feature = "obj")]
+        Hitable::OBJTriangle(triangle) => &triangle.triangle,
+        _ => return Some(clipped),
+    };
+    let mut clipped = AABB::intersection(
+        &clip_triangle(triangle, axis, slab_min, slab_max)?,
+        &clipped,
+    )?;
+    clipped.pad();
+    Some(clipped)
+}
+
+/// Clips the triangle to the slab between `min` and `max` on the `axis`, returning the bounding box of the remaining polygon
+fn clip_triangle(triangle: &Triangle, axis: usize, min: Float, max: Float) -> Option<AABB> {
+    let vertices = [triangle.q, triangle.q + triangle.u, triangle.q + triangle.v];
+    let polygon = clip_polygon(&vertices, axis, min, false);
+    let polygon = clip_polygon(&polygon, axis, max, true);
+    let (first, rest) = polygon.split_first()?;
+    Some(
+        rest.iter()
+            .fold(AABB::new_from_coords(*first, *first), |aabb, &vertex| {
+                AABB::combine(&aabb, &AABB::new_from_coords(vertex, vertex))
+            }),
+    )
+}
+
+/// Sutherland-Hodgman clipping of a convex polygon against an axis-aligned plane, keeping the side above the plane, or below it if `below` is set
+fn clip_polygon(polygon: &[Position], axis: usize, plane: Float, below: bool) -> Vec<Position> {
+    let inside = |vertex: &Position| {
+        if below {
+            vertex[axis] <= plane
+        } else {
+            vertex[axis] >= plane
+        }
+    };
+    let mut output = Vec::with_capacity(polygon.len() + 1);
+    for (i, current) in polygon.iter().enumerate() {
+        let previous = &polygon[(i + polygon.len() - 1) % polygon.len()];
+        if inside(current) != inside(previous) {
+            let t = (plane - previous[axis]) / (current[axis] - previous[axis]);
+            let mut crossing = previous + (current - previous) * t;
+            // Avoid floating point errors moving the vertex off the plane
+            crossing[axis] = plane;
+            output.push(crossing);
+        }
+        if inside(current) {
+            output.push(*current);
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use nalgebra::Unit;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use crate::{materials::Material, ray::Ray, Vec3};
+
+    use super::*;
+
+    #[test]
+    fn clipped_triangle_bounds() {
+        let material = Material::default();
+        let triangle = Triangle::new(
+            Position::new(0.0, 0.0, 0.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 4.0, 0.0),
+            &material,
+        );
+        let aabb = clip_triangle(&triangle, 0, 1.0, 2.0).unwrap();
+        assert!((aabb.x.min - 1.0).abs() < 1e-6);
+        assert!((aabb.x.max - 2.0).abs() < 1e-6);
+        assert!((aabb.y.min - 0.0).abs() < 1e-6);
+        assert!((aabb.y.max - 3.0).abs() < 1e-6);
+        assert!(clip_triangle(&triangle, 0, 5.0, 6.0).is_none());
+    }
+
+    #[test]
+    fn matches_object_split() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let material = Material::default();
+        // Long, thin triangles that overlap a lot, where spatial splits are useful
+        let triangles: Vec<Hitable> = (0..1000)
+            .map(|_| {
+                let q = Position::new(rng.random(), rng.random(), rng.random()) * 10.0;
+                let u = Vec3::new(rng.random(), rng.random(), rng.random()) * 10.0;
+                let v = Vec3::new(rng.random(), rng.random(), rng.random()) * 0.1;
+                Hitable::Triangle(Triangle::new(q, u, v, &material))
+            })
+            .collect();
+        let sbvh = build(triangles.clone());
+        let binned = super::super::binned_surface_area_heuristic(triangles);
+        for _ in 0..1000 {
+            let ray = Ray {
+                origin: Position::new(rng.random(), rng.random(), -1.0) * 10.0,
+                direction: Unit::new_normalize(Vec3::new(
+                    rng.random::<Float>() - 0.5,
+                    rng.random::<Float>() - 0.5,
+                    1.0,
+                )),
+                time: 0.0,
+                wavelength: 600,
+            };
+            let expected = binned
+                .hit(&ray, 0.001, Float::INFINITY, &mut rng)
+                .map(|hit| hit.distance);
+            let hit = sbvh
+                .hit(&ray, 0.001, Float::INFINITY, &mut rng)
+                .map(|hit| hit.distance);
+            assert_eq!(expected, hit);
+        }
+    }
+}
@@ -72,3 +72,21 @@ pub(crate) fn vec_bounding_box(vec: &Vec<Hitable>) -> Option<AABB> {
     // Return the final combined output_box
     output_box
 }
+
+/// Runs the two closures, in parallel if the `std` feature is enabled
+pub(crate) fn join<A, B, RA, RB>(a: A, b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA + Send,
+    B: FnOnce() -> RB + Send,
+    RA: Send,
+    RB: Send,
+{
+    #[cfg(feature = "std")]
+    {
+        rayon::join(a, b)
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        (a(), b())
+    }
+}
@@ -0,0 +1,414 @@
+//! Flattened Bounding Volume Hierarchy, for cache-friendly traversal.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use rand::rngs::SmallRng;
+
+use crate::{
+    aabb::AABB,
+    hitable::{Hitable, HitableTrait},
+    ray::Ray,
+    Float, HitRecord,
+};
+
+use super::{primitive_testcount::primitive_testcount_recurse_condition, BVHNode};
+
+/// Size of the traversal stack that is kept on the program stack. Deeper trees use a heap-allocated stack instead.
+const STACK_SIZE: usize = 64;
+
+/// A node of a [`FlatBVH`]. Exactly 32 bytes: the bounding box, and either a child reference or a range of primitives.
+#[derive(Debug, Clone)]
+pub struct FlatNode {
+    /// Bounding box containing everything within the node
+    pub aabb: AABB,
+    /// For interior nodes, the index of the second child node. The first child node is always directly after its parent. For leaf nodes, the index of the first primitive.
+    pub offset: u32,
+    /// Count of the primitives in a leaf node. Zero for interior nodes.
+    pub count: u32,
+}
+
+impl FlatNode {
+    /// Returns true if the node is a leaf node, containing primitives
+    #[must_use]
+    pub fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+/// Flattened Bounding Volume Hierarchy.
+///
+/// The nodes of a [`BVHNode`] tree stored in a linear array in depth-first order, with the primitives stored in a separate array in the order of the leaf nodes. Traversal is iterative with an explicit stack, instead of recursing through the [Hitable] enum for every node.
+#[derive(Debug, Clone)]
+pub struct FlatBVH<'scene> {
+    /// The nodes of the hierarchy, in depth-first order. The root node is at index zero.
+    pub nodes: Vec<FlatNode>,
+    /// The primitives of the hierarchy, referenced by the leaf nodes.
+    pub primitives: Vec<Hitable<'scene>>,
+    /// Depth of the deepest leaf node
+    depth: usize,
+}
+
+impl<'scene> BVHNode<'scene> {
+    /// Flattens the tree into a [`FlatBVH`] for faster traversal. Leaf nodes with two primitives are merged into their parent.
+    ///
+    /// # Panics
+    /// This method may panic if a primitive in the tree does not have a bounding box, or if the tree has more nodes or primitives than can be addressed with `u32` indices.
+    #[must_use]
+    pub fn flatten(self) -> FlatBVH<'scene> {
+        let mut flat = FlatBVH {
+            nodes: Vec::new(),
+            primitives: Vec::new(),
+            depth: 0,
+        };
+        flat.push(Hitable::BVHNode(self), 1);
+        flat
+    }
+}
+
+impl<'scene> FlatBVH<'scene> {
+    /// Creates a [`FlatBVH`] from previously built nodes and primitives, e.g. when loading a cached hierarchy.
+    ///
+    /// # Panics
+    /// Panics if a node refers to a node or primitive that does not exist.
+    #[must_use]
+    pub fn from_parts(nodes: Vec<FlatNode>, primitives: Vec<Hitable<'scene>>) -> Self {
+        let mut depth = 0;
+        let mut stack = Vec::new();
+        if !nodes.is_empty() {
+            stack.push((0, 1));
+        }
+        while let Some((index, node_depth)) = stack.pop() {
+            depth = usize::max(depth, node_depth);
+            let node: &FlatNode = &nodes[index];
+            let offset = node.offset as usize;
+            if node.is_leaf() {
+                assert!(
+                    offset + node.count as usize <= primitives.len(),
+                    "FlatBVH leaf node refers to missing primitives"
+                );
+            } else {
+                assert!(
+                    index < offset && offset < nodes.len(),
+                    "FlatBVH interior node refers to a missing child node"
+                );
+                stack.push((index + 1, node_depth + 1));
+                stack.push((offset, node_depth + 1));
+            }
+        }
+        FlatBVH {
+            nodes,
+            primitives,
+            depth,
+        }
+    }
+
+    /// Recursively adds the given part of a tree into the flattened hierarchy
+    fn push(&mut self, hitable: Hitable<'scene>, depth: usize) {
+        self.depth = self.depth.max(depth);
+        let Hitable::BVHNode(node) = hitable else {
+            let aabb = hitable
+                .aabb()
+                .expect("No bounding box for a primitive in BVH flattening")
+                .clone();
+            self.push_leaf(aabb, vec![hitable]);
+            return;
+        };
+
+        let BVHNode { left, right, aabb } = node;
+        match (*left, *right) {
+            (Hitable::Empty(_), Hitable::Empty(_)) => (),
+            (child, Hitable::Empty(_)) | (Hitable::Empty(_), child) => self.push(child, depth),
+            (left, right)
+                if !matches!(left, Hitable::BVHNode(_))
+                    && !matches!(right, Hitable::BVHNode(_)) =>
+            {
+                self.push_leaf(aabb, vec![left, right]);
+            }
+            (left, right) => {
+                let index = self.nodes.len();
+                self.nodes.push(FlatNode {
+                    aabb,
+                    offset: 0,
+                    count: 0,
+                });
+                self.push(left, depth + 1);
+                self.nodes[index].offset =
+                    u32::try_from(self.nodes.len()).expect("Too many nodes in BVH flattening");
+                self.push(right, depth + 1);
+            }
+        }
+    }
+
+    /// Adds a leaf node with the given primitives
+    fn push_leaf(&mut self, aabb: AABB, primitives: Vec<Hitable<'scene>>) {
+        let offset =
+            u32::try_from(self.primitives.len()).expect("Too many primitives in BVH flattening");
+        let count = u32::try_from(primitives.len()).expect("Too many primitives in a leaf");
+        self.primitives.extend(primitives);
+        self.nodes.push(FlatNode {
+            aabb,
+            offset,
+            count,
+        });
+    }
+
+    /// Returns the axis-aligned bounding box [AABB] of the whole hierarchy, or `None` if the hierarchy is empty.
+    #[must_use]
+    pub fn aabb(&self) -> Option<&AABB> {
+        self.nodes.first().map(|node| &node.aabb)
+    }
+
+    #[must_use]
+    /// Returns the count of the nodes in the hierarchy
+    pub fn count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The main `hit` function for a [`FlatBVH`]. Given a [Ray], and an interval `distance_min` and `distance_max`, returns either `None` or `Some(HitRecord)` based on whether the ray intersects with the encased objects during that interval.
+    #[must_use]
+    pub fn hit(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        self.traverse(
+            ray,
+            distance_min,
+            distance_max,
+            rng,
+            &mut || {},
+            &mut |_, _, _, _, _| {},
+        )
+    }
+
+    /// Alternate hit method that maintains a test count for the BVH traversals.
+    pub fn testcount(
+        &self,
+        depth: &mut usize,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        self.traverse(
+            ray,
+            distance_min,
+            distance_max,
+            rng,
+            &mut || *depth += 1,
+            &mut |_, _, _, _, _| {},
+        )
+    }
+
+    /// Alternate hit method that maintains a test count for the primitives
+    pub fn primitive_testcount(
+        &self,
+        count: &mut usize,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        rng: &mut SmallRng,
+    ) {
+        let _ = self.traverse(
+            ray,
+            distance_min,
+            distance_max,
+            rng,
+            &mut || {},
+            &mut |primitive, ray, distance_min, distance_max, rng| {
+                primitive_testcount_recurse_condition(
+                    primitive,
+                    count,
+                    ray,
+                    distance_min,
+                    distance_max,
+                    rng,
+                );
+            },
+        );
+    }
+
+    /// Traverses the hierarchy, calling `on_node` for each visited node and `on_primitive` before testing each primitive. The callbacks are generic, so that the plain `hit` compiles without any instrumentation.
+    fn traverse(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        rng: &mut SmallRng,
+        on_node: &mut impl FnMut(),
+        on_primitive: &mut impl FnMut(&Hitable, &Ray, Float, Float, &mut SmallRng),
+    ) -> Option<HitRecord<'_>> {
+        if self.depth <= STACK_SIZE {
+            let mut stack = [(0, 0.0); STACK_SIZE];
+            self.traverse_with_stack(
+                &mut stack,
+                ray,
+                distance_min,
+                distance_max,
+                rng,
+                on_node,
+                on_primitive,
+            )
+        } else {
+            let mut stack = vec![(0, 0.0); self.depth];
+            self.traverse_with_stack(
+                &mut stack,
+                ray,
+                distance_min,
+                distance_max,
+                rng,
+                on_node,
+                on_primitive,
+            )
+        }
+    }
+
+    /// Returns the distance to the bounding box of the given node, if it can be hit within the given interval
+    fn entry_distance(
+        &self,
+        index: usize,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+    ) -> Option<Float> {
+        let aabb = &self.nodes[index].aabb;
+        if !aabb.hit(ray, distance_min, distance_max) {
+            return None;
+        }
+        Some(aabb.distance(ray).unwrap_or(distance_min))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn traverse_with_stack(
+        &self,
+        stack: &mut [(usize, Float)],
+        ray: &Ray,
+        distance_min: Float,
+        mut distance_max: Float,
+        rng: &mut SmallRng,
+        on_node: &mut impl FnMut(),
+        on_primitive: &mut impl FnMut(&Hitable, &Ray, Float, Float, &mut SmallRng),
+    ) -> Option<HitRecord<'_>> {
+        let mut closest: Option<HitRecord> = None;
+        let mut stack_size = 0;
+        let mut current = 0;
+
+        if self.nodes.is_empty()
+            || self
+                .entry_distance(0, ray, distance_min, distance_max)
+                .is_none()
+        {
+            return None;
+        }
+
+        loop {
+            on_node();
+            let node = &self.nodes[current];
+            let offset = node.offset as usize;
+            if node.is_leaf() {
+                for primitive in &self.primitives[offset..offset + node.count as usize] {
+                    on_primitive(primitive, ray, distance_min, distance_max, rng);
+                    if let Some(hit_record) = primitive.hit(ray, distance_min, distance_max, rng) {
+                        distance_max = hit_record.distance;
+                        closest = Some(hit_record);
+                    }
+                }
+            } else {
+                // Visit the closer child first, and save the other one for later
+                let first = current + 1;
+                let second = offset;
+                match (
+                    self.entry_distance(first, ray, distance_min, distance_max),
+                    self.entry_distance(second, ray, distance_min, distance_max),
+                ) {
+                    (Some(first_distance), Some(second_distance)) => {
+                        let (near, far, far_distance) = if first_distance <= second_distance {
+                            (first, second, second_distance)
+                        } else {
+                            (second, first, first_distance)
+                        };
+                        stack[stack_size] = (far, far_distance);
+                        stack_size += 1;
+                        current = near;
+                        continue;
+                    }
+                    (Some(_), None) => {
+                        current = first;
+                        continue;
+                    }
+                    (None, Some(_)) => {
+                        current = second;
+                        continue;
+                    }
+                    (None, None) => (),
+                }
+            }
+
+            // Continue from the stack, skipping nodes that are further away than the closest hit so far
+            loop {
+                if stack_size == 0 {
+                    return closest;
+                }
+                stack_size -= 1;
+                let (index, entry) = stack[stack_size];
+                if entry <= distance_max {
+                    current = index;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use nalgebra::Unit;
+    use rand::{Rng, SeedableRng};
+
+    use crate::{bvh::BvhAlgorithm, materials::Material, objects::Sphere, Position, Vec3};
+
+    use super::*;
+
+    #[test]
+    fn node_size() {
+        assert_eq!(size_of::<FlatNode>(), 32);
+    }
+
+    #[test]
+    fn matches_tree() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let material = Material::default();
+        for algorithm in [BvhAlgorithm::Lam, BvhAlgorithm::Sah] {
+            let spheres: Vec<Hitable> = (0..500)
+                .map(|_| {
+                    let center = Position::new(rng.random(), rng.random(), rng.random()) * 10.0;
+                    Hitable::Sphere(Sphere::new(center, 0.2, &material))
+                })
+                .collect();
+            let tree = BVHNode::from_list(algorithm, spheres);
+            let flat = tree.clone().flatten();
+            assert_eq!(flat.primitives.len(), 500);
+            for _ in 0..500 {
+                let ray = Ray {
+                    origin: Position::new(rng.random(), rng.random(), -1.0) * 10.0,
+                    direction: Unit::new_normalize(Vec3::new(
+                        rng.random::<Float>() - 0.5,
+                        rng.random::<Float>() - 0.5,
+                        1.0,
+                    )),
+                    time: 0.0,
+                    wavelength: 600,
+                };
+                let expected = tree.hit(&ray, 0.001, Float::INFINITY, &mut rng);
+                let hit = flat.hit(&ray, 0.001, Float::INFINITY, &mut rng);
+                assert_eq!(
+                    expected.map(|hit| hit.distance),
+                    hit.map(|hit| hit.distance)
+                );
+            }
+        }
+    }
+}
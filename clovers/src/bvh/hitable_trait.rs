@@ -1,11 +1,12 @@
-use rand::{rngs::SmallRng, Rng};
+use rand::rngs::SmallRng;
 
 use crate::{
     aabb::AABB,
     hitable::{Hitable, HitableTrait},
+    random::remap_choice,
     ray::Ray,
     wavelength::Wavelength,
-    Direction, Displacement, Float, HitRecord, Position,
+    Direction, Displacement, Float, HitRecord, Position, Vec2,
 };
 
 use super::BVHNode;
@@ -112,15 +113,17 @@ impl HitableTrait for BVHNode<'_> {
 
     // TODO: improve correctness & optimization!
     /// Returns a random point on the surface of one of the children
-    fn random(&self, origin: Position, rng: &mut SmallRng) -> Displacement {
+    fn random(&self, origin: Position, sample: Vec2, time: Float) -> Displacement {
         match (&*self.left, &*self.right) {
-            (_, Hitable::Empty(_)) => self.left.random(origin, rng),
-            (Hitable::Empty(_), _) => self.right.random(origin, rng),
+            (_, Hitable::Empty(_)) => self.left.random(origin, sample, time),
+            (Hitable::Empty(_), _) => self.right.random(origin, sample, time),
             (_, _) => {
-                if rng.random::<bool>() {
-                    self.left.random(origin, rng)
+                let (left, x) = remap_choice(sample.x, 0.5);
+                let sample = Vec2::new(x, sample.y);
+                if left {
+                    self.left.random(origin, sample, time)
                 } else {
-                    self.right.random(origin, rng)
+                    self.right.random(origin, sample, time)
                 }
             }
         }
@@ -40,7 +40,7 @@ impl<'scene> BVHNode<'scene> {
     }
 }
 
-fn primitive_testcount_recurse_condition(
+pub(super) fn primitive_testcount_recurse_condition(
     bvhnode: &Hitable, // BVHNode
     count: &mut usize,
     ray: &Ray,
@@ -58,12 +58,35 @@ fn primitive_testcount_recurse_condition(
         Hitable::MovingSphere(_)
         | Hitable::Quad(_)
         | Hitable::Sphere(_)
+        | Hitable::SDF(_)
+        | Hitable::Cone(_)
+        | Hitable::Curve(_)
+        | Hitable::Cylinder(_)
+        | Hitable::Disk(_)
+        | Hitable::Torus(_)
         | Hitable::ConstantMedium(_)
         | Hitable::Triangle(_)
         | Hitable::GLTFTriangle(_)
         | Hitable::RotateY(_)
+        | Hitable::CSG(_)
+        | Hitable::KeyframedTransform(_)
+        | Hitable::Transform(_)
         | Hitable::Translate(_) => {
-            // TODO: currently RotateY and Translate are counted wrong. They may contain more primitives!
+            // TODO: currently RotateY, CSG, KeyframedTransform, Transform and Translate are counted wrong. They may contain more primitives!
+            *count += 1;
+        }
+        #[cfg(feature = "obj")]
+        Hitable::OBJTriangle(_) => {
+            *count += 1;
+        }
+        #[cfg(feature = "ply")]
+        Hitable::PointCloud(_) => {
+            // TODO: count the points tested within the point cloud
+            *count += 1;
+        }
+        #[cfg(feature = "heightfield")]
+        Hitable::Heightfield(_) => {
+            // TODO: count the triangles tested within the heightfield
             *count += 1;
         }
         Hitable::HitableList(l) => {
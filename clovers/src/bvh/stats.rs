@@ -0,0 +1,226 @@
+//! Quality statistics for the Bounding Volume Hierarchies.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use crate::{aabb::AABB, hitable::Hitable, Float};
+
+use super::{FlatBVH, FlatNode, SceneBVH, SimdLanes, WideBVH, WideNode};
+
+/// Relative cost of visiting a node, used for the Surface Area Heuristic cost
+const TRAVERSAL_COST: Float = 1.0;
+/// Relative cost of testing a primitive, used for the Surface Area Heuristic cost
+const INTERSECTION_COST: Float = 1.0;
+
+/// Statistics describing the quality of a Bounding Volume Hierarchy
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BvhStats {
+    /// Count of the nodes in the hierarchy
+    pub node_count: usize,
+    /// Count of the leaves in the hierarchy
+    pub leaf_count: usize,
+    /// Count of the primitive references in the leaves. Can be larger than the count of primitives in the scene if primitives are referenced from multiple leaves.
+    pub primitive_count: usize,
+    /// Expected cost of tracing a random ray through the hierarchy according to the Surface Area Heuristic, relative to the cost of a single primitive test
+    pub sah_cost: Float,
+    /// Count of the leaves at each depth, with the root node at depth zero
+    pub depth_histogram: Vec<usize>,
+    /// Count of the leaves with each count of primitives
+    pub leaf_size_histogram: Vec<usize>,
+    /// Average over the interior nodes of the surface area of the pairwise overlaps of the children, relative to the surface area of the node
+    pub average_child_overlap: Float,
+    /// Memory used by the nodes and the primitive array, in bytes. Does not include memory owned by the primitives themselves.
+    pub memory_bytes: usize,
+}
+
+/// Accumulates the statistics while walking through a hierarchy
+#[derive(Default)]
+struct Accumulator {
+    stats: BvhStats,
+    root_area: Float,
+    interior_count: usize,
+    total_overlap: Float,
+}
+
+impl Accumulator {
+    fn new(root_aabb: Option<&AABB>) -> Self {
+        let root_area = root_aabb.map_or(0.0, AABB::area);
+        Accumulator {
+            root_area: if root_area > 0.0 { root_area } else { 1.0 },
+            ..Default::default()
+        }
+    }
+
+    fn interior(&mut self, aabb: &AABB, children: &[AABB]) {
+        self.stats.node_count += 1;
+        self.interior_count += 1;
+        self.stats.sah_cost += TRAVERSAL_COST * aabb.area() / self.root_area;
+        let mut overlap = 0.0;
+        for (i, a) in children.iter().enumerate() {
+            for b in &children[i + 1..] {
+                overlap += AABB::intersection(a, b).map_or(0.0, |aabb| aabb.area());
+            }
+        }
+        let area = aabb.area();
+        if area > 0.0 {
+            self.total_overlap += overlap / area;
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn leaf(&mut self, aabb: &AABB, count: usize, depth: usize) {
+        self.stats.leaf_count += 1;
+        self.stats.primitive_count += count;
+        self.stats.sah_cost += INTERSECTION_COST * count as Float * aabb.area() / self.root_area;
+        increment(&mut self.stats.depth_histogram, depth);
+        increment(&mut self.stats.leaf_size_histogram, count);
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn finish(mut self, memory_bytes: usize) -> BvhStats {
+        if self.interior_count > 0 {
+            self.stats.average_child_overlap = self.total_overlap / self.interior_count as Float;
+        }
+        self.stats.memory_bytes = memory_bytes;
+        self.stats
+    }
+}
+
+fn increment(histogram: &mut Vec<usize>, index: usize) {
+    if histogram.len() <= index {
+        histogram.resize(index + 1, 0);
+    }
+    histogram[index] += 1;
+}
+
+impl FlatBVH<'_> {
+    /// Returns the quality statistics of the hierarchy
+    #[must_use]
+    pub fn stats(&self) -> BvhStats {
+        let mut accumulator = Accumulator::new(self.aabb());
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push((0, 0));
+        }
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.is_leaf() {
+                // Leaves are counted as nodes too, as they have a node of their own
+                accumulator.stats.node_count += 1;
+                accumulator.leaf(&node.aabb, node.count as usize, depth);
+            } else {
+                let (first, second) = (index + 1, node.offset as usize);
+                accumulator.interior(
+                    &node.aabb,
+                    &[
+                        self.nodes[first].aabb.clone(),
+                        self.nodes[second].aabb.clone(),
+                    ],
+                );
+                stack.push((first, depth + 1));
+                stack.push((second, depth + 1));
+            }
+        }
+        accumulator.finish(
+            self.nodes.len() * size_of::<FlatNode>() + self.primitives.len() * size_of::<Hitable>(),
+        )
+    }
+}
+
+impl<L: SimdLanes, const N: usize> WideBVH<'_, L, N> {
+    /// Returns the quality statistics of the hierarchy
+    #[must_use]
+    pub fn stats(&self) -> BvhStats {
+        let root_aabb = self.aabb();
+        let mut accumulator = Accumulator::new(root_aabb.as_ref());
+        let mut stack = Vec::new();
+        if let Some(aabb) = root_aabb {
+            stack.push((0, 0, aabb));
+        }
+        while let Some((index, depth, aabb)) = stack.pop() {
+            let node = &self.nodes[index];
+            let mut children = vec![];
+            for lane in 0..N {
+                let Some(child) = node.child_aabb(lane) else {
+                    continue;
+                };
+                if node.counts[lane] > 0 {
+                    // Leaves are stored within their parent node
+                    accumulator.leaf(&child, node.counts[lane] as usize, depth + 1);
+                } else {
+                    stack.push((node.offsets[lane] as usize, depth + 1, child.clone()));
+                }
+                children.push(child);
+            }
+            accumulator.interior(&aabb, &children);
+        }
+        accumulator.finish(
+            self.nodes.len() * size_of::<WideNode<L, N>>()
+                + self.primitives.len() * size_of::<Hitable>(),
+        )
+    }
+}
+
+impl SceneBVH<'_> {
+    /// Returns the quality statistics of the hierarchy
+    #[must_use]
+    pub fn stats(&self) -> BvhStats {
+        match self {
+            SceneBVH::Binary(bvh) => bvh.stats(),
+            SceneBVH::Wide4(bvh) => bvh.stats(),
+            SceneBVH::Wide8(bvh) => bvh.stats(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::{
+        bvh::{BVHNode, BvhAlgorithm, WideBVH4},
+        materials::Material,
+        objects::Sphere,
+        Position,
+    };
+
+    use super::*;
+
+    fn spheres(material: &Material) -> Vec<Hitable<'_>> {
+        (0..16u8)
+            .map(|i| {
+                let center = Position::new(Float::from(i) * 4.0, 0.0, 0.0);
+                Hitable::Sphere(Sphere::new(center, 1.0, material))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn binary() {
+        let material = Material::default();
+        let flat = BVHNode::from_list(BvhAlgorithm::Sah, spheres(&material)).flatten();
+        let stats = flat.stats();
+        assert_eq!(stats.primitive_count, 16);
+        assert_eq!(stats.node_count, flat.count());
+        assert_eq!(stats.leaf_count, 8);
+        assert_eq!(stats.leaf_size_histogram, [0, 0, 8]);
+        assert_eq!(stats.depth_histogram.iter().sum::<usize>(), 8);
+        assert!(stats.average_child_overlap.abs() < Float::EPSILON);
+        // At least the cost of the root, at most the cost if every node was as large as the root
+        assert!(stats.sah_cost > TRAVERSAL_COST);
+        assert!(stats.sah_cost < 15.0 * TRAVERSAL_COST + 16.0 * INTERSECTION_COST);
+    }
+
+    #[test]
+    fn wide() {
+        let material = Material::default();
+        let flat = BVHNode::from_list(BvhAlgorithm::Sah, spheres(&material)).flatten();
+        let wide: WideBVH4 = flat.clone().widen();
+        let stats = wide.stats();
+        assert_eq!(stats.primitive_count, 16);
+        assert_eq!(stats.leaf_count, 8);
+        assert_eq!(stats.node_count, wide.count());
+        assert!(stats.sah_cost < flat.stats().sah_cost);
+    }
+}
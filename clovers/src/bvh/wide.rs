@@ -0,0 +1,595 @@
+//! Wide Bounding Volume Hierarchy, testing the bounding boxes of several children at once with SIMD.
+
+use core::ops::{Mul, Sub};
+
+use alloc::vec;
+use alloc::vec::Vec;
+use rand::rngs::SmallRng;
+use wide::{f32x4, f32x8, CmpLe};
+
+use crate::{
+    aabb::AABB,
+    hitable::{Hitable, HitableTrait},
+    interval::Interval,
+    ray::Ray,
+    Float, HitRecord,
+};
+
+use super::{primitive_testcount::primitive_testcount_recurse_condition, FlatBVH, FlatNode};
+
+/// Size of the traversal stack that is kept on the program stack. Deeper trees use a heap-allocated stack instead.
+const STACK_SIZE: usize = 128;
+
+/// A SIMD vector of [Float]s, used for testing the bounding boxes of all children of a [`WideNode`] at once.
+pub trait SimdLanes: Copy + Sub<Output = Self> + Mul<Output = Self> {
+    /// Count of the lanes in the vector
+    const WIDTH: usize;
+    /// Creates a vector with all lanes set to the given value
+    fn splat(value: Float) -> Self;
+    /// Creates a vector from a slice of exactly `WIDTH` values
+    fn from_slice(values: &[Float]) -> Self;
+    /// Lane-wise minimum
+    #[must_use]
+    fn min(self, other: Self) -> Self;
+    /// Lane-wise maximum
+    #[must_use]
+    fn max(self, other: Self) -> Self;
+    /// Returns a bitmask with the bit for each lane set if `self <= other` in that lane
+    fn le_mask(self, other: Self) -> u32;
+    /// Returns the lanes as a slice
+    fn as_slice(&self) -> &[Float];
+}
+
+macro_rules! impl_simd_lanes {
+    ($simd:ty, $width:literal) => {
+        impl SimdLanes for $simd {
+            const WIDTH: usize = $width;
+
+            fn splat(value: Float) -> Self {
+                <$simd>::splat(value)
+            }
+
+            fn from_slice(values: &[Float]) -> Self {
+                let mut array = [0.0; $width];
+                array.copy_from_slice(values);
+                <$simd>::from(array)
+            }
+
+            fn min(self, other: Self) -> Self {
+                <$simd>::min(self, other)
+            }
+
+            fn max(self, other: Self) -> Self {
+                <$simd>::max(self, other)
+            }
+
+            fn le_mask(self, other: Self) -> u32 {
+                self.cmp_le(other).move_mask().cast_unsigned()
+            }
+
+            fn as_slice(&self) -> &[Float] {
+                self.as_array_ref()
+            }
+        }
+    };
+}
+
+impl_simd_lanes!(f32x4, 4);
+impl_simd_lanes!(f32x8, 8);
+
+/// A ray prepared for testing against [`WideNode`]s: the origin and inverse direction splatted into all lanes.
+#[derive(Debug, Clone, Copy)]
+struct RayLanes<L: SimdLanes> {
+    origin: [L; 3],
+    inverse_direction: [L; 3],
+    /// Whether the ray travels in the negative direction on each axis, i.e. enters the boxes through their max side
+    negative: [bool; 3],
+}
+
+impl<L: SimdLanes> RayLanes<L> {
+    fn new(ray: &Ray) -> Self {
+        let mut origin = [L::splat(0.0); 3];
+        let mut inverse_direction = [L::splat(0.0); 3];
+        let mut negative = [false; 3];
+        for axis in 0..3 {
+            origin[axis] = L::splat(ray.origin[axis]);
+            let inverse = 1.0 / ray.direction[axis];
+            inverse_direction[axis] = L::splat(inverse);
+            // Checking the inverse instead of the direction handles negative zero correctly
+            negative[axis] = inverse < 0.0;
+        }
+        RayLanes {
+            origin,
+            inverse_direction,
+            negative,
+        }
+    }
+}
+
+/// A node of a [`WideBVH`], with up to `N` children. The bounds of the children are stored in structure-of-arrays layout, so that all of them can be tested against a ray at once.
+///
+/// Unused lanes have an inverted, empty bounding box that is never hit.
+#[derive(Debug, Clone)]
+pub struct WideNode<L: SimdLanes, const N: usize> {
+    /// Lower bounds of the children on the X, Y and Z axes
+    pub min: [L; 3],
+    /// Upper bounds of the children on the X, Y and Z axes
+    pub max: [L; 3],
+    /// For interior children, the index of the child node. For leaf children, the index of the first primitive.
+    pub offsets: [u32; N],
+    /// Count of the primitives in leaf children. Zero for interior children and unused lanes.
+    pub counts: [u32; N],
+}
+
+/// A [`WideNode`] with four children
+pub type WideNode4 = WideNode<f32x4, 4>;
+/// A [`WideNode`] with eight children
+pub type WideNode8 = WideNode<f32x8, 8>;
+
+impl<L: SimdLanes, const N: usize> WideNode<L, N> {
+    /// Creates a new node with the given child bounding boxes, with all offsets and counts set to zero.
+    ///
+    /// # Panics
+    /// Panics if `N` does not match the width of the SIMD vector type, or if more than `N` bounding boxes are given.
+    #[must_use]
+    pub fn new(aabbs: &[&AABB]) -> Self {
+        const { assert!(N == L::WIDTH, "WideNode width must match its SIMD lanes") };
+        assert!(aabbs.len() <= N, "Too many children for a WideNode");
+        let mut min = [[Float::INFINITY; N]; 3];
+        let mut max = [[Float::NEG_INFINITY; N]; 3];
+        for (lane, aabb) in aabbs.iter().enumerate() {
+            for axis in 0..3 {
+                min[axis][lane] = aabb.axis(axis).min;
+                max[axis][lane] = aabb.axis(axis).max;
+            }
+        }
+        WideNode {
+            min: min.map(|values| L::from_slice(&values)),
+            max: max.map(|values| L::from_slice(&values)),
+            offsets: [0; N],
+            counts: [0; N],
+        }
+    }
+
+    /// Returns the bounding box of the child in the given lane, or `None` if the lane is unused
+    #[must_use]
+    pub fn child_aabb(&self, lane: usize) -> Option<AABB> {
+        let interval = |axis: usize| {
+            let min = self.min[axis].as_slice()[lane];
+            let max = self.max[axis].as_slice()[lane];
+            (min <= max).then(|| Interval::new(min, max))
+        };
+        Some(AABB::new(interval(0)?, interval(1)?, interval(2)?))
+    }
+
+    /// Returns the bounding box containing all children of the node, or `None` if the node has no children
+    #[must_use]
+    pub fn aabb(&self) -> Option<AABB> {
+        (0..N)
+            .filter_map(|lane| self.child_aabb(lane))
+            .reduce(|a, b| AABB::combine(&a, &b))
+    }
+
+    /// Given a [Ray], returns a bitmask of the children whose bounding boxes the ray hits within the interval `distance_min` and `distance_max`.
+    #[must_use]
+    pub fn hit(&self, ray: &Ray, distance_min: Float, distance_max: Float) -> u32 {
+        self.hit_lanes(&RayLanes::new(ray), distance_min, distance_max)
+            .0
+    }
+
+    /// Tests all children at once, returning the bitmask of the hit children and their entry distances
+    fn hit_lanes(&self, ray: &RayLanes<L>, distance_min: Float, distance_max: Float) -> (u32, L) {
+        let mut near = L::splat(distance_min);
+        let mut far = L::splat(distance_max);
+        for axis in 0..3 {
+            let (entry, exit) = if ray.negative[axis] {
+                (self.max[axis], self.min[axis])
+            } else {
+                (self.min[axis], self.max[axis])
+            };
+            near = near.max((entry - ray.origin[axis]) * ray.inverse_direction[axis]);
+            far = far.min((exit - ray.origin[axis]) * ray.inverse_direction[axis]);
+        }
+        (near.le_mask(far), near)
+    }
+}
+
+/// Wide Bounding Volume Hierarchy.
+///
+/// A binary [`FlatBVH`] collapsed into a tree of [`WideNode`]s with up to `N` children each, stored in a linear array in depth-first order. Each visited node tests the bounding boxes of all of its children at once with SIMD, and the tree is shallower than the binary one.
+#[derive(Debug, Clone)]
+pub struct WideBVH<'scene, L: SimdLanes, const N: usize> {
+    /// The nodes of the hierarchy, in depth-first order. The root node is at index zero.
+    pub nodes: Vec<WideNode<L, N>>,
+    /// The primitives of the hierarchy, referenced by the leaf children of the nodes.
+    pub primitives: Vec<Hitable<'scene>>,
+    /// Depth of the deepest node
+    depth: usize,
+}
+
+/// A [`WideBVH`] with four children per node
+pub type WideBVH4<'scene> = WideBVH<'scene, f32x4, 4>;
+/// A [`WideBVH`] with eight children per node
+pub type WideBVH8<'scene> = WideBVH<'scene, f32x8, 8>;
+
+impl<'scene> FlatBVH<'scene> {
+    /// Collapses the binary hierarchy into a [`WideBVH`] with up to `N` children per node. The children with the largest surface area are opened up first.
+    #[must_use]
+    pub fn widen<L: SimdLanes, const N: usize>(self) -> WideBVH<'scene, L, N> {
+        let FlatBVH {
+            nodes, primitives, ..
+        } = self;
+        let mut wide = WideBVH {
+            nodes: Vec::new(),
+            primitives,
+            depth: 0,
+        };
+        if !nodes.is_empty() {
+            wide.push(&nodes, 0, 1);
+        }
+        wide
+    }
+}
+
+impl<L: SimdLanes, const N: usize> WideBVH<'_, L, N> {
+    /// Recursively adds the subtree of the binary hierarchy at `index` as a wide node, returning the index of the new node
+    fn push(&mut self, flat: &[FlatNode], index: usize, depth: usize) -> u32 {
+        self.depth = self.depth.max(depth);
+
+        // Gather up to N children by opening up the largest interior nodes
+        let mut children = [0; N];
+        let mut count = 1;
+        children[0] = index;
+        while count < N {
+            let Some(largest) =
+                (0..count)
+                    .filter(|&i| !flat[children[i]].is_leaf())
+                    .max_by(|&a, &b| {
+                        flat[children[a]]
+                            .aabb
+                            .area()
+                            .total_cmp(&flat[children[b]].aabb.area())
+                    })
+            else {
+                break;
+            };
+            let node = children[largest];
+            children[largest] = node + 1;
+            children[count] = flat[node].offset as usize;
+            count += 1;
+        }
+
+        let aabbs: Vec<&AABB> = children[..count]
+            .iter()
+            .map(|&child| &flat[child].aabb)
+            .collect();
+        let wide_index = self.nodes.len();
+        self.nodes.push(WideNode::new(&aabbs));
+
+        let mut offsets = [0; N];
+        let mut counts = [0; N];
+        for (lane, &child) in children[..count].iter().enumerate() {
+            let node = &flat[child];
+            if node.is_leaf() {
+                offsets[lane] = node.offset;
+                counts[lane] = node.count;
+            } else {
+                offsets[lane] = self.push(flat, child, depth + 1);
+            }
+        }
+        self.nodes[wide_index].offsets = offsets;
+        self.nodes[wide_index].counts = counts;
+        u32::try_from(wide_index).expect("Too many nodes in BVH widening")
+    }
+
+    /// Returns the axis-aligned bounding box [AABB] of the whole hierarchy, or `None` if the hierarchy is empty.
+    #[must_use]
+    pub fn aabb(&self) -> Option<AABB> {
+        self.nodes.first().and_then(WideNode::aabb)
+    }
+
+    /// Returns the count of the nodes in the hierarchy
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The main `hit` function for a [`WideBVH`]. Given a [Ray], and an interval `distance_min` and `distance_max`, returns either `None` or `Some(HitRecord)` based on whether the ray intersects with the encased objects during that interval.
+    #[must_use]
+    pub fn hit(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        self.traverse(
+            ray,
+            distance_min,
+            distance_max,
+            rng,
+            &mut || {},
+            &mut |_, _, _, _, _| {},
+        )
+    }
+
+    /// Alternate hit method that maintains a test count for the BVH traversals.
+    pub fn testcount(
+        &self,
+        depth: &mut usize,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        self.traverse(
+            ray,
+            distance_min,
+            distance_max,
+            rng,
+            &mut || *depth += 1,
+            &mut |_, _, _, _, _| {},
+        )
+    }
+
+    /// Alternate hit method that maintains a test count for the primitives
+    pub fn primitive_testcount(
+        &self,
+        count: &mut usize,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        rng: &mut SmallRng,
+    ) {
+        let _ = self.traverse(
+            ray,
+            distance_min,
+            distance_max,
+            rng,
+            &mut || {},
+            &mut |primitive, ray, distance_min, distance_max, rng| {
+                primitive_testcount_recurse_condition(
+                    primitive,
+                    count,
+                    ray,
+                    distance_min,
+                    distance_max,
+                    rng,
+                );
+            },
+        );
+    }
+
+    /// Traverses the hierarchy, calling `on_node` for each visited node and `on_primitive` before testing each primitive. The callbacks are generic, so that the plain `hit` compiles without any instrumentation.
+    fn traverse(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        rng: &mut SmallRng,
+        on_node: &mut impl FnMut(),
+        on_primitive: &mut impl FnMut(&Hitable, &Ray, Float, Float, &mut SmallRng),
+    ) -> Option<HitRecord<'_>> {
+        // Every visited node leaves at most N - 1 of its children on the stack
+        let stack_size = (N - 1) * self.depth + 1;
+        if stack_size <= STACK_SIZE {
+            let mut stack = [(0, 0, 0.0); STACK_SIZE];
+            self.traverse_with_stack(
+                &mut stack,
+                ray,
+                distance_min,
+                distance_max,
+                rng,
+                on_node,
+                on_primitive,
+            )
+        } else {
+            let mut stack = vec![(0, 0, 0.0); stack_size];
+            self.traverse_with_stack(
+                &mut stack,
+                ray,
+                distance_min,
+                distance_max,
+                rng,
+                on_node,
+                on_primitive,
+            )
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn traverse_with_stack(
+        &self,
+        stack: &mut [(u32, u32, Float)],
+        ray: &Ray,
+        distance_min: Float,
+        mut distance_max: Float,
+        rng: &mut SmallRng,
+        on_node: &mut impl FnMut(),
+        on_primitive: &mut impl FnMut(&Hitable, &Ray, Float, Float, &mut SmallRng),
+    ) -> Option<HitRecord<'_>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let ray_lanes = RayLanes::new(ray);
+        let mut closest: Option<HitRecord> = None;
+        // Stack entries are either nodes with a count of zero, or ranges of primitives
+        stack[0] = (0, 0, distance_min);
+        let mut stack_size = 1;
+
+        while stack_size > 0 {
+            stack_size -= 1;
+            let (offset, count, entry) = stack[stack_size];
+            // Skip entries that are further away than the closest hit so far
+            if entry > distance_max {
+                continue;
+            }
+            on_node();
+
+            if count > 0 {
+                let offset = offset as usize;
+                for primitive in &self.primitives[offset..offset + count as usize] {
+                    on_primitive(primitive, ray, distance_min, distance_max, rng);
+                    if let Some(hit_record) = primitive.hit(ray, distance_min, distance_max, rng) {
+                        distance_max = hit_record.distance;
+                        closest = Some(hit_record);
+                    }
+                }
+                continue;
+            }
+
+            let node = &self.nodes[offset as usize];
+            let (mask, near) = node.hit_lanes(&ray_lanes, distance_min, distance_max);
+            if mask == 0 {
+                continue;
+            }
+            let near = near.as_slice();
+            let mut hits = [(0, 0, 0.0); N];
+            let mut hit_count = 0;
+            for (lane, &distance) in near.iter().enumerate() {
+                if mask & (1 << lane) != 0 {
+                    hits[hit_count] = (node.offsets[lane], node.counts[lane], distance);
+                    hit_count += 1;
+                }
+            }
+            // Push the children furthest first, so that the closest child is visited next
+            let hits = &mut hits[..hit_count];
+            hits.sort_unstable_by(|a, b| b.2.total_cmp(&a.2));
+            stack[stack_size..stack_size + hit_count].copy_from_slice(hits);
+            stack_size += hit_count;
+        }
+
+        closest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use nalgebra::Unit;
+    use rand::{Rng, SeedableRng};
+
+    use crate::{
+        bvh::{BVHNode, BvhAlgorithm, BvhLayout, SceneBVH},
+        interval::Interval,
+        materials::Material,
+        objects::Sphere,
+        Position, Vec3,
+    };
+
+    use super::*;
+
+    fn random_ray(rng: &mut SmallRng) -> Ray {
+        Ray {
+            origin: Position::new(rng.random(), rng.random(), -1.0) * 10.0,
+            direction: Unit::new_normalize(Vec3::new(
+                rng.random::<Float>() - 0.5,
+                rng.random::<Float>() - 0.5,
+                1.0,
+            )),
+            time: 0.0,
+            wavelength: 600,
+        }
+    }
+
+    #[test]
+    fn node_matches_aabb() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for _ in 0..1000 {
+            let aabbs: Vec<AABB> = (0..8)
+                .map(|_| {
+                    let a = Position::new(rng.random(), rng.random(), rng.random()) * 10.0;
+                    let b = Position::new(rng.random(), rng.random(), rng.random()) * 10.0;
+                    AABB::new_from_coords(a, b)
+                })
+                .collect();
+            let node = WideNode8::new(&aabbs.iter().collect::<Vec<_>>());
+            let ray = random_ray(&mut rng);
+            let mask = node.hit(&ray, 0.001, Float::INFINITY);
+            for (lane, aabb) in aabbs.iter().enumerate() {
+                assert_eq!(
+                    mask & (1 << lane) != 0,
+                    aabb.hit(&ray, 0.001, Float::INFINITY)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unused_lanes_miss() {
+        let aabb = AABB::new(
+            Interval::new(-1.0, 1.0),
+            Interval::new(-1.0, 1.0),
+            Interval::new(-1.0, 1.0),
+        );
+        let node = WideNode4::new(&[&aabb]);
+        for direction in [Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, -0.5, -1.0)] {
+            let ray = Ray {
+                origin: Position::new(0.0, 0.0, 0.0),
+                direction: Unit::new_normalize(direction),
+                time: 0.0,
+                wavelength: 600,
+            };
+            assert_eq!(node.hit(&ray, 0.0, Float::INFINITY), 0b0001);
+        }
+    }
+
+    #[test]
+    fn matches_flat() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let material = Material::default();
+        let spheres: Vec<Hitable> = (0..500)
+            .map(|_| {
+                let center = Position::new(rng.random(), rng.random(), rng.random()) * 10.0;
+                Hitable::Sphere(Sphere::new(center, 0.2, &material))
+            })
+            .collect();
+        let flat = BVHNode::from_list(BvhAlgorithm::Sah, spheres).flatten();
+        let wide4: WideBVH4 = flat.clone().widen();
+        let wide8: WideBVH8 = flat.clone().widen();
+        assert!(wide4.count() < flat.count());
+        assert!(wide8.count() < wide4.count());
+        for _ in 0..500 {
+            let ray = random_ray(&mut rng);
+            let expected = flat
+                .hit(&ray, 0.001, Float::INFINITY, &mut rng)
+                .map(|hit| hit.distance);
+            let hit4 = wide4.hit(&ray, 0.001, Float::INFINITY, &mut rng);
+            let hit8 = wide8.hit(&ray, 0.001, Float::INFINITY, &mut rng);
+            assert_eq!(expected, hit4.map(|hit| hit.distance));
+            assert_eq!(expected, hit8.map(|hit| hit.distance));
+        }
+    }
+
+    #[test]
+    fn layouts_combine_with_algorithms() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let material = Material::default();
+        let spheres: Vec<Hitable> = (0..200)
+            .map(|_| {
+                let center = Position::new(rng.random(), rng.random(), rng.random()) * 10.0;
+                Hitable::Sphere(Sphere::new(center, 0.2, &material))
+            })
+            .collect();
+        for algorithm in [
+            BvhAlgorithm::Lam,
+            BvhAlgorithm::Sah,
+            BvhAlgorithm::BinnedSah,
+            BvhAlgorithm::Sbvh,
+        ] {
+            let layouts = [BvhLayout::Binary, BvhLayout::Wide4, BvhLayout::Wide8]
+                .map(|layout| SceneBVH::from_list(algorithm, layout, spheres.clone()));
+            assert!(matches!(layouts[1], SceneBVH::Wide4(_)));
+            assert!(matches!(layouts[2], SceneBVH::Wide8(_)));
+            for _ in 0..200 {
+                let ray = random_ray(&mut rng);
+                let distances = layouts.each_ref().map(|bvh| {
+                    bvh.hit(&ray, 0.001, Float::INFINITY, &mut rng)
+                        .map(|hit| hit.distance)
+                });
+                assert_eq!(distances[0], distances[1], "{algorithm}");
+                assert_eq!(distances[0], distances[2], "{algorithm}");
+            }
+        }
+    }
+}
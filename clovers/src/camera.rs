@@ -5,8 +5,25 @@
 use crate::wavelength::Wavelength;
 use crate::{ray::Ray, Float, Vec3, PI};
 use crate::{Direction, Position, Vec2};
+#[cfg(feature = "std")]
+use alloc::format;
+use alloc::string::String;
 use nalgebra::Unit;
 
+mod aperture;
+mod keyframes;
+mod realistic;
+mod rig;
+mod shutter;
+
+#[cfg(feature = "aperture_mask")]
+pub use aperture::ApertureMask;
+pub use aperture::{Aperture, ApertureInit};
+pub use keyframes::CameraKeyframe;
+pub use realistic::{parse_prescription, set_stop_diameter, LensSurface, LensSystem};
+pub use rig::{RigInit, RigView};
+pub use shutter::{RollingDirection, Shutter, ShutterCurve, ShutterInit};
+
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
 /// The main [Camera] object used in the ray tracing.
@@ -19,7 +36,7 @@ pub struct Camera {
     pub vertical: Vec3,
     /// Defines the origin of the camera.
     pub origin: Position,
-    /// Defines the lens radius for the camera. TODO: understand and explain better
+    /// Radius of the lens aperture. Rays originate from a disk of this radius around the origin, blurring everything outside the focus distance.
     pub lens_radius: Float,
     /// Defines the earliest starting time for the camera, used when generating [Rays](Ray).
     pub time_0: Float,
@@ -32,6 +49,60 @@ pub struct Camera {
     pub v: Direction,
     /// The forward direction of the camera; the difference between `look_from` and `look_at` normalized to a unit vector.
     pub w: Direction,
+    /// Projection used for mapping the image positions into ray directions
+    pub projection: Projection,
+    /// Ratio of the image width to the image height
+    pub aspect_ratio: Float,
+    /// Distance of the plane of focus from the camera
+    pub focus_distance: Float,
+    /// Shape of the lens aperture
+    pub aperture: Aperture,
+    /// Strength of the optical vignetting. See [`CameraInit::vignetting`].
+    pub vignetting: Float,
+    /// Lens system to trace the rays through, replacing the thin lens of the perspective projection
+    pub lens_system: Option<LensSystem>,
+    /// Length of a millimeter of the `lens_system` in scene units
+    pub millimeter: Float,
+    /// Shutter used for distributing the ray times within `time_0..time_1`
+    pub shutter: Shutter,
+    /// Signed distance of the eye from the center of an omni-directional stereo panorama, negative for the left eye. The eye turns around the center with the direction of the ray. Only used by the [`Projection::Equirectangular`].
+    pub eye_offset: Float,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde-derive", serde(tag = "kind"))]
+/// Projection of the camera, mapping the image positions into ray directions
+pub enum Projection {
+    /// Pinhole or thin lens perspective projection, the default
+    #[default]
+    Perspective,
+    /// Parallel rays perpendicular to the image plane, without perspective foreshortening
+    Orthographic {
+        /// Height of the visible area, in scene units. The width is derived from the aspect ratio of the image.
+        height: Float,
+    },
+    /// Circular fisheye projection. The image circle is inscribed into the height of the image, and image positions outside it produce no rays.
+    Fisheye {
+        /// Mapping from the angle between the ray and the view direction into the distance from the image center
+        #[cfg_attr(feature = "serde-derive", serde(default))]
+        mapping: FisheyeMapping,
+        /// Field of view across the image circle, in degrees. Up to 360 degrees.
+        fov: Float,
+    },
+    /// Full spherical latitude-longitude panorama, covering 360 degrees horizontally and 180 degrees vertically. The view direction is at the center of the image.
+    Equirectangular,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// Mapping of a [`Projection::Fisheye`] camera
+pub enum FisheyeMapping {
+    /// The distance from the image center is proportional to the angle. Preserves angular distances.
+    #[default]
+    Equidistant,
+    /// The distance from the image center is proportional to the sine of half the angle. Preserves areas.
+    Equisolid,
 }
 
 #[derive(Clone, Debug)]
@@ -44,17 +115,289 @@ pub struct CameraInit {
     pub look_at: Position,
     /// Describes the subjective "up" direction for the camera to define the orientation
     pub up: Vec3,
-    /// Describes the vertical field of view for the camera
+    /// Describes the lens of the camera, either directly with the field of view and aperture, or with physical parameters
+    #[cfg_attr(feature = "serde-derive", serde(flatten))]
+    pub lens: LensInit,
+    /// Projection of the camera. Defaults to a perspective projection.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub projection: Projection,
+    /// Shape of the lens aperture, giving the shape of the out-of-focus highlights. Defaults to a circular aperture.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub bokeh: ApertureInit,
+    /// Strength of the optical vignetting. The lens barrel is modelled as a second opening the size of the aperture, shifted away from the center of the frame by this many aperture radii at the image corners. Towards the edges of the frame, the two openings overlap only partially, darkening the image and clipping the bokeh into a cat's-eye shape. Defaults to zero, for no vignetting.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub vignetting: Float,
+    /// Shutter of the camera, with the shutter curve and the rolling readout. Defaults to a global shutter that opens and closes instantly.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub shutter: ShutterInit,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde-derive", serde(untagged))]
+/// The lens of the camera. The variant is selected by the fields present in the scene file.
+pub enum LensInit {
+    #[cfg(feature = "std")]
+    /// Lens system from a lens prescription file
+    Realistic(RealisticLensInit),
+    /// Lens described by the physical parameters of a real camera
+    Physical(PhysicalLensInit),
+    /// Lens described directly by the field of view and aperture
+    Simple(SimpleLensInit),
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// Lens described directly by the field of view and aperture
+pub struct SimpleLensInit {
+    /// Describes the vertical field of view for the camera, in degrees
     pub vertical_fov: Float,
-    /// Describes the size of the aperture of the camera.
-    // TODO: does it really though
+    /// Diameter of the lens aperture, in scene units. Zero gives a pinhole camera with everything in focus.
     pub aperture: Float,
     /// Describes the distance at which the camera has been focused to
     pub focus_distance: Float,
 }
 
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// Lens described by the physical parameters of a real camera. The field of view and the aperture are derived from these with the thin lens equation.
+///
+/// The focus is chosen from the first of `focus_object`, `focus_point` and `focus_distance` that is given, and defaults to the `look_at` position.
+pub struct PhysicalLensInit {
+    /// Width of the sensor, in millimeters
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_sensor_width"))]
+    pub sensor_width: Float,
+    /// Height of the sensor, in millimeters
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_sensor_height"))]
+    pub sensor_height: Float,
+    /// Focal length of the lens, in millimeters
+    pub focal_length: Float,
+    /// Ratio of the focal length to the diameter of the aperture. Smaller values give a shallower depth of field.
+    pub f_number: Float,
+    /// Distance at which the camera has been focused to, in scene units
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub focus_distance: Option<Float>,
+    /// Position to focus the camera on
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub focus_point: Option<Position>,
+    /// Index of the object in the scene file to focus the camera on. The center of its bounding box is used as the focus point.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub focus_object: Option<usize>,
+    /// Count of scene units in a meter, used for converting the millimeters of the lens into scene units
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_units_per_meter"))]
+    pub units_per_meter: Float,
+}
+
+#[cfg(feature = "serde-derive")]
+fn default_sensor_width() -> Float {
+    36.0
+}
+
+#[cfg(feature = "serde-derive")]
+fn default_sensor_height() -> Float {
+    24.0
+}
+
+#[cfg(feature = "serde-derive")]
+fn default_units_per_meter() -> Float {
+    1.0
+}
+
+impl PhysicalLensInit {
+    /// Returns the [`SimpleLensInit`] matching these physical parameters for the given image aspect ratio. The `focus_target` is the position to focus on, used when there is no `focus_distance`.
+    ///
+    /// If the aspect ratio of the image differs from the sensor, the image is cropped from the sensor like in a real camera.
+    ///
+    /// # Errors
+    /// Returns an error if the camera is focused closer than the focal length, where the lens cannot form an image.
+    pub fn to_simple(
+        &self,
+        look_from: Position,
+        look_at: Position,
+        focus_target: Option<Position>,
+        aspect_ratio: Float,
+    ) -> Result<SimpleLensInit, String> {
+        let focus_distance = focus_distance(
+            look_from,
+            look_at,
+            focus_target.or(self.focus_point),
+            self.focus_distance,
+        );
+        let millimeter = self.units_per_meter / 1000.0;
+        let focal_length = self.focal_length * millimeter;
+        if focus_distance <= focal_length {
+            return Err(String::from(
+                "Cannot focus the camera closer than the focal length",
+            ));
+        }
+        // Thin lens equation: the sensor sits at the image distance behind the lens, slightly further than the focal length
+        let image_distance = focal_length * focus_distance / (focus_distance - focal_length);
+        let sensor_height = self.sensor_height.min(self.sensor_width / aspect_ratio) * millimeter;
+        let vertical_fov = 2.0 * (sensor_height / 2.0 / image_distance).atan() * 180.0 / PI;
+        Ok(SimpleLensInit {
+            vertical_fov,
+            aperture: focal_length / self.f_number,
+            focus_distance,
+        })
+    }
+}
+
+/// Returns the distance of the plane of focus: perpendicular to the view direction through the `focus_target` if given, otherwise at the `focus_distance` if given, otherwise through the `look_at` position
+fn focus_distance(
+    look_from: Position,
+    look_at: Position,
+    focus_target: Option<Position>,
+    focus_distance: Option<Float>,
+) -> Float {
+    let forward: Direction = Unit::new_normalize(look_at - look_from);
+    match (focus_target, focus_distance) {
+        (Some(target), _) => (target - look_from).dot(&forward),
+        (None, Some(distance)) => distance,
+        (None, None) => (look_at - look_from).dot(&forward),
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// Lens system from a lens prescription file, traced spectrally surface by surface. See [`parse_prescription`] for the file format.
+///
+/// The field of view follows from the lens and the sensor size, and the depth of field from the aperture stop of the lens. The focus is chosen like in [`PhysicalLensInit`], measured from the front of the lens.
+pub struct RealisticLensInit {
+    /// Path of the lens prescription file
+    pub lens_file: String,
+    /// Width of the sensor, in millimeters
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_sensor_width"))]
+    pub sensor_width: Float,
+    /// Height of the sensor, in millimeters
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_sensor_height"))]
+    pub sensor_height: Float,
+    /// Diameter of the aperture stop in millimeters, for stopping the lens down. Defaults to the diameter in the prescription.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub aperture_diameter: Option<Float>,
+    /// Distance at which the camera has been focused to, in scene units
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub focus_distance: Option<Float>,
+    /// Position to focus the camera on
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub focus_point: Option<Position>,
+    /// Index of the object in the scene file to focus the camera on. The center of its bounding box is used as the focus point.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub focus_object: Option<usize>,
+    /// Count of scene units in a meter, used for converting the millimeters of the lens into scene units
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_units_per_meter"))]
+    pub units_per_meter: Float,
+}
+
+#[cfg(feature = "std")]
+impl RealisticLensInit {
+    /// Loads the lens prescription and focuses the lens. If the aspect ratio of the image differs from the sensor, the image is cropped from the sensor.
+    ///
+    /// # Errors
+    /// Returns an error if the lens file cannot be read or parsed, or if the lens cannot focus at the chosen distance.
+    pub fn lens_system(
+        &self,
+        look_from: Position,
+        look_at: Position,
+        focus_target: Option<Position>,
+        aspect_ratio: Float,
+    ) -> Result<LensSystem, String> {
+        let text = std::fs::read_to_string(&self.lens_file)
+            .map_err(|error| format!("Could not read the lens file {}: {error}", self.lens_file))?;
+        let mut surfaces = parse_prescription(&text)?;
+        if let Some(diameter) = self.aperture_diameter {
+            set_stop_diameter(&mut surfaces, diameter);
+        }
+        let focus_distance = focus_distance(
+            look_from,
+            look_at,
+            focus_target.or(self.focus_point),
+            self.focus_distance,
+        );
+        let millimeter = self.units_per_meter / 1000.0;
+        let sensor_height = self.sensor_height.min(self.sensor_width / aspect_ratio);
+        let sensor_size = Vec2::new(sensor_height * aspect_ratio, sensor_height);
+        LensSystem::new(surfaces, sensor_size, focus_distance / millimeter)
+    }
+}
+
+impl CameraInit {
+    /// Returns the [`SimpleLensInit`] of the camera, deriving it from the physical parameters if necessary. See [`PhysicalLensInit::to_simple`].
+    ///
+    /// # Errors
+    /// Returns an error if the physical parameters cannot form an image, see [`PhysicalLensInit::to_simple`].
+    pub fn simple_lens(
+        &self,
+        focus_target: Option<Position>,
+        aspect_ratio: Float,
+    ) -> Result<SimpleLensInit, String> {
+        let lens = match &self.lens {
+            LensInit::Simple(lens) => lens.clone(),
+            LensInit::Physical(lens) => {
+                lens.to_simple(self.look_from, self.look_at, focus_target, aspect_ratio)?
+            }
+            // The lens system replaces the thin lens, but the other projections still use these
+            #[cfg(feature = "std")]
+            LensInit::Realistic(_) => SimpleLensInit {
+                vertical_fov: 40.0,
+                aperture: 0.0,
+                focus_distance: 1.0,
+            },
+        };
+        Ok(lens)
+    }
+
+    /// Returns the index of the object in the scene file the camera should focus on, if any
+    #[must_use]
+    pub fn focus_object(&self) -> Option<usize> {
+        match &self.lens {
+            LensInit::Simple(_) => None,
+            LensInit::Physical(lens) => lens.focus_object,
+            #[cfg(feature = "std")]
+            LensInit::Realistic(lens) => lens.focus_object,
+        }
+    }
+}
+
 impl Camera {
-    /// Creates a new [Camera] with the given parameters.
+    /// Creates a new [Camera] from the [`CameraInit`] of a scene file. The `focus_target` is the position of the object the camera focuses on, if any. See [`PhysicalLensInit::to_simple`].
+    ///
+    /// # Errors
+    /// Returns an error if the lens of the camera cannot be created.
+    pub fn from_init(
+        init: &CameraInit,
+        focus_target: Option<Position>,
+        aspect_ratio: Float,
+        time_0: Float,
+        time_1: Float,
+    ) -> Result<Self, String> {
+        let lens = init.simple_lens(focus_target, aspect_ratio)?;
+        let camera = Camera::new(
+            init.look_from,
+            init.look_at,
+            init.up,
+            lens.vertical_fov,
+            aspect_ratio,
+            lens.aperture,
+            lens.focus_distance,
+            time_0,
+            time_1,
+            init.projection.clone(),
+        )
+        .with_bokeh(Aperture::new(init.bokeh.clone())?, init.vignetting)
+        .with_shutter(Shutter::new(init.shutter.clone()));
+        match &init.lens {
+            #[cfg(feature = "std")]
+            LensInit::Realistic(lens) => {
+                let lens_system =
+                    lens.lens_system(init.look_from, init.look_at, focus_target, aspect_ratio)?;
+                Ok(camera.with_lens_system(lens_system, lens.units_per_meter / 1000.0))
+            }
+            _ => Ok(camera),
+        }
+    }
+
+    /// Creates a new [Camera] with the given parameters. The `aperture` is the diameter of the lens aperture. The `vertical_fov` is only used by the [`Projection::Perspective`].
     #[must_use]
     pub fn new(
         look_from: Position,
@@ -66,23 +409,36 @@ impl Camera {
         focus_distance: Float,
         time_0: Float,
         time_1: Float,
+        projection: Projection,
     ) -> Self {
         let lens_radius: Float = aperture / 2.0;
-        let theta: Float = vertical_fov * PI / 180.0;
-        let half_height: Float = (theta / 2.0).tan();
-        let half_width: Float = aspect_ratio * half_height;
         let origin: Position = look_from;
         let w: Direction = Unit::new_normalize(look_from - look_at);
         let u: Direction = Unit::new_normalize(up.cross(&w));
         let v: Direction = Unit::new_normalize(w.cross(&u));
 
-        // TODO: understand this defocus
-        let lower_left_corner: Vec3 = origin
-            - half_width * focus_distance * *u
-            - half_height * focus_distance * *v
-            - focus_distance * *w;
-        let horizontal: Vec3 = 2.0 * half_width * focus_distance * *u;
-        let vertical: Vec3 = 2.0 * half_height * focus_distance * *v;
+        // The image rectangle: on the plane of focus for the perspective projection, on the lens plane for the orthographic projection
+        let (lower_left_corner, horizontal, vertical) =
+            if let Projection::Orthographic { height } = projection {
+                let horizontal: Vec3 = aspect_ratio * height * *u;
+                let vertical: Vec3 = height * *v;
+                (
+                    origin - horizontal / 2.0 - vertical / 2.0,
+                    horizontal,
+                    vertical,
+                )
+            } else {
+                let theta: Float = vertical_fov * PI / 180.0;
+                let half_height: Float = (theta / 2.0).tan();
+                let half_width: Float = aspect_ratio * half_height;
+                let lower_left_corner: Vec3 = origin
+                    - half_width * focus_distance * *u
+                    - half_height * focus_distance * *v
+                    - focus_distance * *w;
+                let horizontal: Vec3 = 2.0 * half_width * focus_distance * *u;
+                let vertical: Vec3 = 2.0 * half_height * focus_distance * *v;
+                (lower_left_corner, horizontal, vertical)
+            };
 
         Camera {
             lower_left_corner,
@@ -95,33 +451,310 @@ impl Camera {
             u,
             v,
             w,
+            projection,
+            aspect_ratio,
+            focus_distance,
+            aperture: Aperture::default(),
+            vignetting: 0.0,
+            lens_system: None,
+            millimeter: 0.001,
+            shutter: Shutter::default(),
+            eye_offset: 0.0,
+        }
+    }
+
+    /// Shifts the image sideways by the given fraction of its width, like a shift lens. Only used by the perspective and orthographic projections.
+    #[must_use]
+    pub fn with_shift(self, shift: Float) -> Self {
+        Camera {
+            lower_left_corner: self.lower_left_corner + shift * self.horizontal,
+            ..self
+        }
+    }
+
+    /// Sets the eye of an omni-directional stereo panorama. See [`Camera::eye_offset`].
+    #[must_use]
+    pub fn with_eye_offset(self, eye_offset: Float) -> Self {
+        Camera { eye_offset, ..self }
+    }
+
+    /// Sets the shutter used for distributing the ray times
+    #[must_use]
+    pub fn with_shutter(self, shutter: Shutter) -> Self {
+        Camera { shutter, ..self }
+    }
+
+    /// Sets the lens system to trace the rays through, with the length of its millimeter in scene units
+    #[must_use]
+    pub fn with_lens_system(self, lens_system: LensSystem, millimeter: Float) -> Self {
+        Camera {
+            lens_system: Some(lens_system),
+            millimeter,
+            ..self
+        }
+    }
+
+    /// Sets the shape of the lens aperture and the strength of the optical vignetting
+    #[must_use]
+    pub fn with_bokeh(self, aperture: Aperture, vignetting: Float) -> Self {
+        Camera {
+            aperture,
+            vignetting,
+            ..self
         }
     }
 
-    /// Generates a new [Ray] from the camera
+    /// Generates a new [Ray] from the camera. The `time` sample in the range `0..1` is mapped by the [`Shutter`] into the shutter interval `time_0..time_1` of the camera.
+    ///
+    /// The `lens_sample` in the range `0..1` is mapped onto the [`Aperture`], or onto the exit pupil of the [`LensSystem`]. If it is `None`, the ray starts from the center of the lens, as in a pinhole camera. The lens is only used by the perspective and orthographic projections, and the lens system only by the perspective projection.
+    ///
+    /// Returns `None` for image positions the [`Projection`] does not cover, such as the corners of a circular fisheye image, and for rays blocked by the optical vignetting.
     #[must_use]
     pub fn get_ray(
         &self,
         pixel_uv: Vec2, // pixel location in image uv coordinates, range 0..1
-        mut lens_offset: Vec2,
+        lens_sample: Option<Vec2>,
         time: Float,
         wavelength: Wavelength,
-    ) -> Ray {
-        let (x_offset, y_offset) = (pixel_uv.x, pixel_uv.y);
-        // TODO: add a better defocus blur / depth of field implementation
-        lens_offset.x *= &self.lens_radius;
-        lens_offset.y *= &self.lens_radius;
-        let offset: Vec3 = *self.u * lens_offset.x + *self.v * lens_offset.y;
-        let direction =
-            self.lower_left_corner + x_offset * self.horizontal + y_offset * self.vertical
-                - self.origin
-                - offset;
+    ) -> Option<Ray> {
+        self.get_weighted_ray(pixel_uv, lens_sample, time, wavelength)
+            .map(|(ray, _weight)| ray)
+    }
+
+    /// Generates a new [Ray] from the camera like [`Camera::get_ray`], together with the radiometric weight of the ray. The weight is `1.0`, except for the rays through a [`LensSystem`], see [`LensSystem::generate`].
+    #[must_use]
+    pub fn get_weighted_ray(
+        &self,
+        pixel_uv: Vec2, // pixel location in image uv coordinates, range 0..1
+        lens_sample: Option<Vec2>,
+        time: Float,
+        wavelength: Wavelength,
+    ) -> Option<(Ray, Float)> {
+        let (origin, direction, weight) = if let (Some(lens_system), Projection::Perspective) =
+            (&self.lens_system, &self.projection)
+        {
+            let pupil_sample = lens_sample.unwrap_or(Vec2::new(0.5, 0.5));
+            let (origin, direction, weight) =
+                lens_system.generate(pixel_uv, pupil_sample, wavelength)?;
+            // The lens looks towards its positive z axis, which is the negative w axis of the camera
+            let to_world = |v: Vec3| v.x * *self.u + v.y * *self.v - v.z * *self.w;
+            (
+                self.origin + to_world(origin) * self.millimeter,
+                to_world(direction),
+                weight,
+            )
+        } else {
+            let (origin, direction) = self.thin_lens_ray(pixel_uv, lens_sample)?;
+            (origin, direction, 1.0)
+        };
         let direction = Unit::new_normalize(direction);
-        Ray {
-            origin: self.origin + offset,
+        let time =
+            self.time_0 + self.shutter.sample(time, pixel_uv.y) * (self.time_1 - self.time_0);
+        let ray = Ray {
+            origin,
             direction,
             time,
             wavelength,
+        };
+        Some((ray, weight))
+    }
+
+    /// Returns the origin and direction of the ray for the thin lens model of the camera
+    fn thin_lens_ray(&self, pixel_uv: Vec2, lens_sample: Option<Vec2>) -> Option<(Position, Vec3)> {
+        let (x_offset, y_offset) = (pixel_uv.x, pixel_uv.y);
+        let lens_position = match lens_sample {
+            Some(sample) => self.aperture.sample(sample),
+            None => Vec2::new(0.0, 0.0),
+        };
+        if self.vignetting > 0.0 {
+            // Position of the pixel relative to the center of the frame, with the corners at unit distance
+            let frame_position = Vec2::new(
+                (2.0 * x_offset - 1.0) * self.aspect_ratio,
+                2.0 * y_offset - 1.0,
+            ) / self.aspect_ratio.hypot(1.0);
+            let barrel_center = frame_position * self.vignetting;
+            if (lens_position - barrel_center).norm() > 1.0 {
+                return None;
+            }
+        }
+        let lens_offset = lens_position * self.lens_radius;
+        let offset: Vec3 = *self.u * lens_offset.x + *self.v * lens_offset.y;
+        let image_position =
+            self.lower_left_corner + x_offset * self.horizontal + y_offset * self.vertical;
+        let (origin, direction) = match self.projection {
+            Projection::Perspective => {
+                (self.origin + offset, image_position - self.origin - offset)
+            }
+            Projection::Orthographic { .. } => {
+                // Rays through the same point on the plane of focus converge, giving the same depth of field as the perspective projection
+                let focus_position = image_position - self.focus_distance * *self.w;
+                (
+                    image_position + offset,
+                    focus_position - image_position - offset,
+                )
+            }
+            Projection::Fisheye { mapping, fov } => {
+                let x = (2.0 * x_offset - 1.0) * self.aspect_ratio;
+                let y = 2.0 * y_offset - 1.0;
+                let radius = x.hypot(y);
+                if radius > 1.0 {
+                    return None;
+                }
+                let half_fov = (fov * PI / 360.0).min(PI);
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => radius * half_fov,
+                    FisheyeMapping::Equisolid => 2.0 * (radius * (half_fov / 2.0).sin()).asin(),
+                };
+                let phi = y.atan2(x);
+                (self.origin, self.spherical_direction(theta, phi))
+            }
+            Projection::Equirectangular => {
+                let longitude = (x_offset - 0.5) * 2.0 * PI;
+                let latitude = (y_offset - 0.5) * PI;
+                let direction = latitude.cos() * longitude.sin() * *self.u
+                    + latitude.sin() * *self.v
+                    - latitude.cos() * longitude.cos() * *self.w;
+                // The eye sits on the side of the horizontal direction of the ray
+                let side = direction.cross(&self.v);
+                let origin = if self.eye_offset != 0.0 && side.norm() > 0.0 {
+                    self.origin + self.eye_offset * side.normalize()
+                } else {
+                    self.origin
+                };
+                (origin, direction)
+            }
+        };
+        Some((origin, direction))
+    }
+
+    /// Returns the direction at the angle `theta` from the view direction, rotated by the angle `phi` around it starting from the horizontal axis
+    fn spherical_direction(&self, theta: Float, phi: Float) -> Vec3 {
+        theta.sin() * phi.cos() * *self.u + theta.sin() * phi.sin() * *self.v
+            - theta.cos() * *self.w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lens() -> PhysicalLensInit {
+        PhysicalLensInit {
+            sensor_width: 36.0,
+            sensor_height: 24.0,
+            focal_length: 50.0,
+            f_number: 2.0,
+            focus_distance: None,
+            focus_point: None,
+            focus_object: None,
+            units_per_meter: 1.0,
+        }
+    }
+
+    #[test]
+    fn full_frame_50mm() {
+        let look_from = Position::new(0.0, 0.0, 0.0);
+        let look_at = Position::new(0.0, 0.0, -1000.0);
+        let simple = lens().to_simple(look_from, look_at, None, 1.5).unwrap();
+        // Focused near infinity, the field of view is given by the focal length alone
+        assert!((simple.vertical_fov - 26.99).abs() < 0.01);
+        assert!((simple.aperture - 0.025).abs() < 1e-6);
+        assert!((simple.focus_distance - 1000.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn focus_breathing_and_crop() {
+        let look_from = Position::new(0.0, 0.0, 0.0);
+        let look_at = Position::new(0.0, 0.0, -1000.0);
+        let far = lens().to_simple(look_from, look_at, None, 1.5).unwrap();
+        let near = PhysicalLensInit {
+            focus_distance: Some(0.5),
+            ..lens()
+        }
+        .to_simple(look_from, look_at, None, 1.5)
+        .unwrap();
+        assert!(near.vertical_fov < far.vertical_fov);
+        // A wider image than the sensor is cropped from the top and bottom
+        let wide = lens().to_simple(look_from, look_at, None, 2.0).unwrap();
+        assert!(wide.vertical_fov < far.vertical_fov);
+    }
+
+    #[test]
+    fn focus_on_target() {
+        let look_from = Position::new(0.0, 0.0, 0.0);
+        let look_at = Position::new(0.0, 0.0, -10.0);
+        let target = Position::new(3.0, 0.0, -4.0);
+        let simple = lens()
+            .to_simple(look_from, look_at, Some(target), 1.5)
+            .unwrap();
+        // The plane of focus is perpendicular to the view direction
+        assert!((simple.focus_distance - 4.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn focus_closer_than_focal_length() {
+        let look_from = Position::new(0.0, 0.0, 0.0);
+        let look_at = Position::new(0.0, 0.0, -10.0);
+        let lens = PhysicalLensInit {
+            focus_distance: Some(0.04),
+            ..lens()
+        };
+        assert!(lens.to_simple(look_from, look_at, None, 1.5).is_err());
+    }
+
+    fn camera(projection: Projection) -> Camera {
+        Camera::new(
+            Position::new(0.0, 0.0, 0.0),
+            Position::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            2.0,
+            0.0,
+            1.0,
+            0.0,
+            1.0,
+            projection,
+        )
+    }
+
+    fn direction(camera: &Camera, x: Float, y: Float) -> Option<Vec3> {
+        camera
+            .get_ray(Vec2::new(x, y), None, 0.0, 500)
+            .map(|ray| *ray.direction)
+    }
+
+    #[test]
+    fn orthographic() {
+        let camera = camera(Projection::Orthographic { height: 4.0 });
+        let corner = camera.get_ray(Vec2::new(0.0, 0.0), None, 0.0, 500).unwrap();
+        assert!((corner.origin - Position::new(-4.0, -2.0, 0.0)).norm() < 1e-6);
+        assert!((*corner.direction - Vec3::new(0.0, 0.0, -1.0)).norm() < 1e-6);
+    }
+
+    #[test]
+    fn fisheye() {
+        let fov = 180.0;
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let camera = camera(Projection::Fisheye { mapping, fov });
+            let center = direction(&camera, 0.5, 0.5).unwrap();
+            assert!((center - Vec3::new(0.0, 0.0, -1.0)).norm() < 1e-6);
+            // The top edge of the image circle is at half of the field of view
+            let top = direction(&camera, 0.5, 1.0).unwrap();
+            assert!((top - Vec3::new(0.0, 1.0, 0.0)).norm() < 1e-6);
+            assert!(direction(&camera, 0.0, 0.0).is_none());
         }
     }
+
+    #[test]
+    fn equirectangular() {
+        let camera = camera(Projection::Equirectangular);
+        let center = direction(&camera, 0.5, 0.5).unwrap();
+        assert!((center - Vec3::new(0.0, 0.0, -1.0)).norm() < 1e-6);
+        let right = direction(&camera, 0.75, 0.5).unwrap();
+        assert!((right - Vec3::new(1.0, 0.0, 0.0)).norm() < 1e-6);
+        let behind = direction(&camera, 0.0, 0.5).unwrap();
+        assert!((behind - Vec3::new(0.0, 0.0, 1.0)).norm() < 1e-6);
+        let up = direction(&camera, 0.3, 1.0).unwrap();
+        assert!((up - Vec3::new(0.0, 1.0, 0.0)).norm() < 1e-6);
+    }
 }
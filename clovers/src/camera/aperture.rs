@@ -0,0 +1,295 @@
+//! Apertures of the camera lens. The shape of the aperture defines the shape of the out-of-focus highlights, also known as bokeh.
+
+#[cfg(feature = "aperture_mask")]
+use alloc::format;
+use alloc::string::String;
+#[cfg(feature = "aperture_mask")]
+use alloc::vec::Vec;
+
+use crate::{Float, Vec2, PI};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde-derive", serde(tag = "kind"))]
+/// Represents the aperture shapes that can be described in a Scene file
+pub enum ApertureInit {
+    /// Circular aperture, the default
+    #[default]
+    Circular,
+    /// Regular polygon formed by the blades of the aperture diaphragm
+    Polygonal {
+        /// Count of the blades, at least three
+        blades: u32,
+        /// Rotation of the polygon, in degrees
+        #[cfg_attr(feature = "serde-derive", serde(default))]
+        rotation: Float,
+    },
+    #[cfg(feature = "aperture_mask")]
+    /// Aperture shape from a grayscale image. The image is stretched over the square enclosing the aperture circle, and the brightness of each pixel gives its transmission.
+    Mask {
+        /// Path of the image file
+        path: String,
+    },
+}
+
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// The aperture of the camera lens, used for mapping lens samples into positions on the lens
+pub enum Aperture {
+    /// Circular aperture, the default
+    #[default]
+    Circular,
+    /// Regular polygon formed by the blades of the aperture diaphragm
+    Polygonal {
+        /// Count of the blades
+        blades: u32,
+        /// Rotation of the polygon, in radians
+        rotation: Float,
+    },
+    #[cfg(feature = "aperture_mask")]
+    /// Aperture shape from a grayscale image
+    Mask(ApertureMask),
+}
+
+impl Aperture {
+    /// Creates the [`Aperture`] described by the given [`ApertureInit`].
+    ///
+    /// # Errors
+    /// Returns an error if a polygonal aperture has fewer than three blades, or if a mask image cannot be opened, cannot be decoded, or is fully black.
+    pub fn new(init: ApertureInit) -> Result<Self, String> {
+        match init {
+            ApertureInit::Circular => Ok(Aperture::Circular),
+            ApertureInit::Polygonal { blades, rotation } => {
+                if blades < 3 {
+                    return Err(String::from(
+                        "A polygonal aperture needs at least three blades",
+                    ));
+                }
+                Ok(Aperture::Polygonal {
+                    blades,
+                    rotation: rotation.to_radians(),
+                })
+            }
+            #[cfg(feature = "aperture_mask")]
+            ApertureInit::Mask { path } => ApertureMask::new(&path).map(Aperture::Mask),
+        }
+    }
+
+    /// Maps a sample from the unit square `[0..1]` into a uniformly distributed position on the aperture, within the unit circle
+    #[must_use]
+    pub fn sample(&self, sample: Vec2) -> Vec2 {
+        match self {
+            Aperture::Circular => concentric_disk(sample),
+            Aperture::Polygonal { blades, rotation } => polygon(sample, *blades, *rotation),
+            #[cfg(feature = "aperture_mask")]
+            Aperture::Mask(mask) => mask.sample(sample),
+        }
+    }
+}
+
+/// Maps a sample from the unit square into the unit disk, keeping the relative areas and the adjacency of the samples. The center of the square maps into the center of the disk.
+///
+/// Based on "A Low Distortion Map Between Disk and Square" by Peter Shirley and Kenneth Chiu, 1997.
+fn concentric_disk(sample: Vec2) -> Vec2 {
+    let x = 2.0 * sample.x - 1.0;
+    let y = 2.0 * sample.y - 1.0;
+    if x == 0.0 && y == 0.0 {
+        return Vec2::new(0.0, 0.0);
+    }
+    let (radius, theta) = if x.abs() > y.abs() {
+        (x, PI / 4.0 * (y / x))
+    } else {
+        (y, PI / 2.0 - PI / 4.0 * (x / y))
+    };
+    Vec2::new(radius * theta.cos(), radius * theta.sin())
+}
+
+/// Maps a sample from the unit square into a regular polygon inscribed in the unit circle. The first coordinate picks a triangle between the center and an edge, and a position along the edge; the second coordinate picks the distance from the center.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn polygon(sample: Vec2, blades: u32, rotation: Float) -> Vec2 {
+    let position = sample.x * blades as Float;
+    let edge = (position as u32).min(blades - 1);
+    let along = position - edge as Float;
+    let vertex = |index: u32| {
+        let angle = rotation + 2.0 * PI * index as Float / blades as Float;
+        Vec2::new(angle.cos(), angle.sin())
+    };
+    let point = vertex(edge) * (1.0 - along) + vertex(edge + 1) * along;
+    // The square root keeps the density uniform, as the triangle widens away from the center
+    point * sample.y.sqrt()
+}
+
+#[cfg(feature = "aperture_mask")]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// An aperture shape from a grayscale image. Positions are sampled in proportion to the brightness of the pixels.
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    /// Cumulative distribution of the rows, normalized to end at one
+    rows: Vec<Float>,
+    /// Cumulative distribution of the pixels within each row, normalized to end at one
+    columns: Vec<Float>,
+}
+
+#[cfg(feature = "aperture_mask")]
+impl ApertureMask {
+    /// Loads the mask from an image file.
+    ///
+    /// # Errors
+    /// Returns an error if the image cannot be opened or decoded, or if it is fully black.
+    pub fn new(path: &str) -> Result<Self, String> {
+        let image = image::open(path)
+            .map_err(|error| format!("Could not open the aperture mask {path}: {error}"))?
+            .to_luma32f();
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+        let values: Vec<Float> = image.pixels().map(|pixel| pixel[0].max(0.0)).collect();
+        ApertureMask::from_values(width, height, &values)
+    }
+
+    /// Creates the mask from the transmission values of the pixels, in rows from the top
+    ///
+    /// # Errors
+    /// Returns an error if the values do not match the size, or if they are all zero.
+    pub fn from_values(width: usize, height: usize, values: &[Float]) -> Result<Self, String> {
+        if width == 0 || values.len() != width * height {
+            return Err(String::from("Aperture mask size mismatch"));
+        }
+        let mut rows = Vec::with_capacity(height);
+        let mut columns = Vec::with_capacity(width * height);
+        let mut total = 0.0;
+        for row in values.chunks(width) {
+            let mut row_total = 0.0;
+            for value in row {
+                row_total += value;
+                columns.push(row_total);
+            }
+            let start = columns.len() - width;
+            for cumulative in &mut columns[start..] {
+                *cumulative = if row_total > 0.0 {
+                    *cumulative / row_total
+                } else {
+                    1.0
+                };
+            }
+            total += row_total;
+            rows.push(total);
+        }
+        if total <= 0.0 {
+            return Err(String::from("The aperture mask is fully black"));
+        }
+        for cumulative in &mut rows {
+            *cumulative /= total;
+        }
+        Ok(ApertureMask {
+            width,
+            height,
+            rows,
+            columns,
+        })
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn sample(&self, sample: Vec2) -> Vec2 {
+        let (row, y) = sample_cumulative(&self.rows, sample.y);
+        let row_columns = &self.columns[row * self.width..(row + 1) * self.width];
+        let (column, x) = sample_cumulative(row_columns, sample.x);
+        // The first row is the top of the image
+        let u = (column as Float + x) / self.width as Float;
+        let v = (row as Float + y) / self.height as Float;
+        Vec2::new(2.0 * u - 1.0, 1.0 - 2.0 * v)
+    }
+}
+
+/// Finds the bucket of the cumulative distribution containing the sample, and the relative position of the sample within the bucket
+#[cfg(feature = "aperture_mask")]
+fn sample_cumulative(cumulative: &[Float], sample: Float) -> (usize, Float) {
+    // The first bucket ending after the sample. Keeping the sample below one skips any empty buckets at the end.
+    let sample = sample.min(1.0 - Float::EPSILON);
+    let index = cumulative
+        .partition_point(|&value| value <= sample)
+        .min(cumulative.len() - 1);
+    let start = if index == 0 {
+        0.0
+    } else {
+        cumulative[index - 1]
+    };
+    let width = cumulative[index] - start;
+    let position = if width > 0.0 {
+        ((sample - start) / width).clamp(0.0, 1.0)
+    } else {
+        0.5
+    };
+    (index, position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> impl Iterator<Item = Vec2> {
+        (0..=16u8).flat_map(|x| {
+            (0..=16u8).map(move |y| Vec2::new(Float::from(x) / 16.0, Float::from(y) / 16.0))
+        })
+    }
+
+    #[test]
+    fn circular_within_unit_circle() {
+        let aperture = Aperture::Circular;
+        assert!(aperture.sample(Vec2::new(0.5, 0.5)).norm() < 1e-6);
+        for sample in grid() {
+            assert!(aperture.sample(sample).norm() <= 1.0 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn polygonal_within_polygon() {
+        let blades: u16 = 6;
+        let aperture = Aperture::new(ApertureInit::Polygonal {
+            blades: blades.into(),
+            rotation: 0.0,
+        })
+        .unwrap();
+        // The inscribed circle of the hexagon
+        let apothem = (PI / Float::from(blades)).cos();
+        for sample in grid() {
+            let point = aperture.sample(sample);
+            let angle = point
+                .y
+                .atan2(point.x)
+                .rem_euclid(2.0 * PI / Float::from(blades));
+            let limit = apothem / (angle - PI / Float::from(blades)).cos();
+            assert!(point.norm() <= limit + 1e-5);
+        }
+    }
+
+    #[test]
+    fn invalid_apertures_are_rejected() {
+        assert!(Aperture::new(ApertureInit::Polygonal {
+            blades: 2,
+            rotation: 0.0,
+        })
+        .is_err());
+        #[cfg(feature = "aperture_mask")]
+        {
+            assert!(ApertureMask::from_values(2, 2, &[0.0; 4]).is_err());
+            assert!(ApertureMask::from_values(2, 2, &[1.0; 3]).is_err());
+        }
+    }
+
+    #[cfg(feature = "aperture_mask")]
+    #[test]
+    fn mask_only_samples_open_pixels() {
+        // Only the top right pixel of the 2x2 mask is open
+        let mask = ApertureMask::from_values(2, 2, &[0.0, 1.0, 0.0, 0.0]).unwrap();
+        for sample in grid() {
+            let point = mask.sample(sample);
+            assert!(point.x >= 0.0 && point.y >= 0.0, "{point:?}");
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Keyframes for animating the camera over a sequence of frames.
+
+use crate::{Float, Position, Vec3};
+
+use super::{CameraInit, LensInit};
+
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// A keyframe of the camera: the parameters of the camera at a given time. Parameters left out are interpolated from the other keyframes that have them.
+pub struct CameraKeyframe {
+    /// Time of the keyframe, in the same units as the `time_0` and `time_1` of the scene
+    pub time: Float,
+    /// Position of the camera
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub look_from: Option<Position>,
+    /// Position the camera is looking at
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub look_at: Option<Position>,
+    /// Subjective "up" direction of the camera
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub up: Option<Vec3>,
+    /// Distance at which the camera has been focused to. A `focus_object` or `focus_point` of a physical lens takes precedence over this.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub focus_distance: Option<Float>,
+}
+
+impl CameraInit {
+    /// Returns the camera parameters at the given time, interpolated linearly between the keyframes. Before the first and after the last keyframe with a parameter, the parameter stays still. Parameters without any keyframes keep their values from `self`.
+    #[must_use]
+    pub fn at_time(&self, keyframes: &[CameraKeyframe], time: Float) -> CameraInit {
+        let mut init = self.clone();
+        let lerp = |a: Vec3, b: Vec3, t: Float| a.lerp(&b, t);
+        if let Some(look_from) = interpolate(keyframes, time, |k| k.look_from, lerp) {
+            init.look_from = look_from;
+        }
+        if let Some(look_at) = interpolate(keyframes, time, |k| k.look_at, lerp) {
+            init.look_at = look_at;
+        }
+        if let Some(up) = interpolate(keyframes, time, |k| k.up, lerp) {
+            init.up = up;
+        }
+        let focus_distance = interpolate(
+            keyframes,
+            time,
+            |k| k.focus_distance,
+            |a, b, t| a + t * (b - a),
+        );
+        if let Some(focus_distance) = focus_distance {
+            match &mut init.lens {
+                #[cfg(feature = "std")]
+                LensInit::Realistic(lens) => lens.focus_distance = Some(focus_distance),
+                LensInit::Physical(lens) => lens.focus_distance = Some(focus_distance),
+                LensInit::Simple(lens) => lens.focus_distance = focus_distance,
+            }
+        }
+        init
+    }
+}
+
+/// Interpolates the value of a parameter at the given time, between the nearest keyframes before and after the time that have the parameter
+fn interpolate<T: Copy>(
+    keyframes: &[CameraKeyframe],
+    time: Float,
+    value: impl Fn(&CameraKeyframe) -> Option<T>,
+    lerp: impl Fn(T, T, Float) -> T,
+) -> Option<T> {
+    let mut before: Option<(Float, T)> = None;
+    let mut after: Option<(Float, T)> = None;
+    for keyframe in keyframes {
+        let Some(v) = value(keyframe) else {
+            continue;
+        };
+        if keyframe.time <= time && before.is_none_or(|(t, _)| keyframe.time >= t) {
+            before = Some((keyframe.time, v));
+        }
+        if keyframe.time >= time && after.is_none_or(|(t, _)| keyframe.time < t) {
+            after = Some((keyframe.time, v));
+        }
+    }
+    match (before, after) {
+        (Some((t0, v0)), Some((t1, v1))) if t1 > t0 => Some(lerp(v0, v1, (time - t0) / (t1 - t0))),
+        (Some((_, v)), _) | (None, Some((_, v))) => Some(v),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use crate::camera::{ApertureInit, Projection, ShutterInit, SimpleLensInit};
+
+    use super::*;
+
+    fn init() -> CameraInit {
+        CameraInit {
+            look_from: Position::new(0.0, 0.0, 0.0),
+            look_at: Position::new(0.0, 0.0, -1.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            lens: LensInit::Simple(SimpleLensInit {
+                vertical_fov: 40.0,
+                aperture: 0.0,
+                focus_distance: 1.0,
+            }),
+            projection: Projection::default(),
+            bokeh: ApertureInit::default(),
+            vignetting: 0.0,
+            shutter: ShutterInit::default(),
+        }
+    }
+
+    #[test]
+    fn interpolates_between_keyframes() {
+        let keyframes = vec![
+            CameraKeyframe {
+                time: 1.0,
+                look_from: Some(Position::new(0.0, 0.0, 0.0)),
+                focus_distance: Some(2.0),
+                ..Default::default()
+            },
+            CameraKeyframe {
+                time: 3.0,
+                look_from: Some(Position::new(4.0, 0.0, 0.0)),
+                ..Default::default()
+            },
+        ];
+        let camera = init().at_time(&keyframes, 2.0);
+        assert!((camera.look_from - Position::new(2.0, 0.0, 0.0)).norm() < 1e-6);
+        // Parameters without keyframes are kept, and single keyframes hold their value
+        assert!((camera.look_at - init().look_at).norm() < 1e-6);
+        let LensInit::Simple(lens) = camera.lens else {
+            panic!("The lens changed its kind");
+        };
+        assert!((lens.focus_distance - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn holds_outside_keyframes() {
+        let keyframes = vec![
+            CameraKeyframe {
+                time: 1.0,
+                look_at: Some(Position::new(1.0, 0.0, -1.0)),
+                ..Default::default()
+            },
+            CameraKeyframe {
+                time: 2.0,
+                look_at: Some(Position::new(2.0, 0.0, -1.0)),
+                ..Default::default()
+            },
+        ];
+        let early = init().at_time(&keyframes, 0.0);
+        let late = init().at_time(&keyframes, 5.0);
+        assert!((early.look_at.x - 1.0).abs() < 1e-6);
+        assert!((late.look_at.x - 2.0).abs() < 1e-6);
+    }
+}
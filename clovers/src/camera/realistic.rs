@@ -0,0 +1,511 @@
+//! Realistic camera lens. The rays are traced from the sensor through a system of spherical lens elements, described by a lens prescription table.
+//!
+//! Based on the realistic camera described in the book Physically Based Rendering, chapter [6.4 Realistic Cameras](https://pbr-book.org/3ed-2018/Camera_Models/Realistic_Cameras).
+//!
+//! The lens is traced in its own coordinate system in millimeters, with the sensor at the origin and the optical axis along the positive z axis towards the scene.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{materials::Dispersive, wavelength::Wavelength, Float, Vec2, Vec3};
+
+/// Count of the buckets for the exit pupil bounds, over the distance from the center of the sensor
+const PUPIL_BUCKETS: usize = 64;
+/// Count of the samples per side of the grid used for finding the exit pupil bounds
+const PUPIL_GRID: usize = 64;
+/// Wavelength used for focusing the lens, the helium d-line
+const FOCUS_WAVELENGTH: Wavelength = 587;
+
+/// A single surface of a lens system
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+pub struct LensSurface {
+    /// Radius of curvature in millimeters. Positive when the center of curvature is behind the surface, towards the sensor. Zero for a flat surface, such as the aperture stop.
+    pub radius: Float,
+    /// Distance to the next surface along the optical axis in millimeters. For the last surface, the distance to the sensor.
+    pub thickness: Float,
+    /// Glass between this surface and the next one, or `None` for air
+    pub glass: Option<Dispersive>,
+    /// Radius of the clear aperture of the surface in millimeters. Rays outside it are blocked.
+    pub aperture_radius: Float,
+}
+
+impl LensSurface {
+    fn refractive_index(&self, wavelength: Wavelength) -> Float {
+        self.glass
+            .as_ref()
+            .map_or(1.0, |glass| glass.refractive_index(wavelength))
+    }
+
+    /// Returns true for a flat surface with air on both sides, which only limits the rays
+    fn is_stop(&self, previous: Option<&LensSurface>) -> bool {
+        self.radius == 0.0 && self.glass.is_none() && previous.is_none_or(|p| p.glass.is_none())
+    }
+}
+
+/// Parses a lens prescription table. Each line describes a surface from the front of the lens towards the sensor, with whitespace separated columns: curvature radius, thickness, refractive index, Abbe number and aperture diameter. All lengths are in millimeters. The Abbe number column is optional, allowing the four-column tables of Physically Based Rendering to be used as is.
+///
+/// A refractive index of zero or one means air. An Abbe number of zero means glass without dispersion. Empty lines and lines starting with `#` are ignored.
+///
+/// # Errors
+/// Returns an error if a line does not have four or five numeric columns, or if there are no surfaces.
+pub fn parse_prescription(text: &str) -> Result<Vec<LensSurface>, String> {
+    let mut surfaces = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let columns: Vec<Float> = line
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|error| format!("Line {}: {error}", number + 1))?;
+        let (radius, thickness, refractive_index, abbe_number, diameter) = match columns[..] {
+            [radius, thickness, index, diameter] => (radius, thickness, index, 0.0, diameter),
+            [radius, thickness, index, abbe, diameter] => {
+                (radius, thickness, index, abbe, diameter)
+            }
+            _ => {
+                return Err(format!(
+                    "Line {}: expected four or five columns",
+                    number + 1
+                ))
+            }
+        };
+        let glass = if refractive_index.abs() < Float::EPSILON
+            || (refractive_index - 1.0).abs() < Float::EPSILON
+        {
+            None
+        } else if abbe_number > 0.0 {
+            Some(Dispersive::from_abbe(refractive_index, abbe_number))
+        } else {
+            Some(Dispersive::new(refractive_index, 0.0))
+        };
+        surfaces.push(LensSurface {
+            radius,
+            thickness,
+            glass,
+            aperture_radius: diameter / 2.0,
+        });
+    }
+    if surfaces.is_empty() {
+        return Err(String::from("No lens surfaces"));
+    }
+    Ok(surfaces)
+}
+
+/// Limits the clear aperture of the aperture stop to the given diameter in millimeters, for stopping the lens down
+pub fn set_stop_diameter(surfaces: &mut [LensSurface], diameter: Float) {
+    for index in 0..surfaces.len() {
+        let previous = index.checked_sub(1).map(|i| surfaces[i].clone());
+        if surfaces[index].is_stop(previous.as_ref()) {
+            surfaces[index].aperture_radius = diameter / 2.0;
+        }
+    }
+}
+
+/// A lens system focused at a given distance, with precomputed bounds of the exit pupil
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+pub struct LensSystem {
+    /// Surfaces from the front of the lens towards the sensor
+    surfaces: Vec<LensSurface>,
+    /// Positions of the surface vertices on the optical axis
+    vertices: Vec<Float>,
+    /// Half of the width and height of the sensor area used for the image
+    half_sensor: Vec2,
+    /// Bounds of the exit pupil on the plane of the rear surface as `[min_x, min_y, max_x, max_y]`, for sensor positions on the positive x axis, bucketed by the distance from the center
+    pupil_bounds: Vec<[Float; 4]>,
+    /// Area of the exit pupil bounds at the center of the sensor, for normalizing the radiometric weights of the rays
+    center_pupil_area: Float,
+}
+
+impl LensSystem {
+    /// Creates a lens system with the given surfaces, moving the sensor to focus at `focus_distance` millimeters in front of the first surface. The `sensor_size` is the width and height of the sensor area used for the image.
+    ///
+    /// # Errors
+    /// Returns an error if the lens has no surfaces, or if it cannot focus at the given distance.
+    pub fn new(
+        mut surfaces: Vec<LensSurface>,
+        sensor_size: Vec2,
+        focus_distance: Float,
+    ) -> Result<Self, String> {
+        if surfaces.is_empty() {
+            return Err(String::from("The lens has no surfaces"));
+        }
+        let vertices = vertex_positions(&surfaces);
+        let image_position = focus_position(&surfaces, &vertices, focus_distance)
+            .ok_or("The lens cannot focus at the given distance")?;
+        let last = surfaces.len() - 1;
+        // Move the sensor to where the rays from the focus distance converge
+        surfaces[last].thickness -= image_position;
+        if surfaces[last].thickness <= 0.0 {
+            return Err(String::from("The lens cannot focus at the given distance"));
+        }
+        let vertices = vertex_positions(&surfaces);
+        let mut system = LensSystem {
+            surfaces,
+            vertices,
+            half_sensor: sensor_size / 2.0,
+            pupil_bounds: Vec::new(),
+            center_pupil_area: 1.0,
+        };
+        system.pupil_bounds = (0..PUPIL_BUCKETS)
+            .map(|bucket| system.compute_pupil_bounds(bucket))
+            .collect();
+        let center_pupil_area = bounds_area(system.pupil_bounds[0]);
+        if center_pupil_area > 0.0 {
+            system.center_pupil_area = center_pupil_area;
+        }
+        Ok(system)
+    }
+
+    /// Generates a ray leaving the front of the lens, for the given position on the image in the range `0..1` and the given sample of the exit pupil in the range `0..1`. Returns the origin and direction in the coordinate system of the lens, or `None` if the ray is blocked within the lens.
+    ///
+    /// Also returns the radiometric weight of the ray, `cos⁴θ · A / z²` for the angle `θ` of the ray leaving the sensor, the area `A` of the sampled exit pupil bounds and the distance `z` of the rear surface from the sensor, as in Physically Based Rendering. The weight is normalized by its value at the center of the sensor, so that the exposure in the middle of the image matches the other cameras.
+    #[must_use]
+    pub fn generate(
+        &self,
+        image_position: Vec2,
+        pupil_sample: Vec2,
+        wavelength: Wavelength,
+    ) -> Option<(Vec3, Vec3, Float)> {
+        // The lens forms an inverted image on the sensor
+        let sensor = Vec2::new(
+            (1.0 - 2.0 * image_position.x) * self.half_sensor.x,
+            (1.0 - 2.0 * image_position.y) * self.half_sensor.y,
+        );
+        let distance = sensor.norm();
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let bucket = ((distance / self.half_sensor.norm() * PUPIL_BUCKETS as Float) as usize)
+            .min(PUPIL_BUCKETS - 1);
+        let [min_x, min_y, max_x, max_y] = self.pupil_bounds[bucket];
+        if min_x > max_x {
+            return None;
+        }
+        let pupil = Vec2::new(
+            min_x + pupil_sample.x * (max_x - min_x),
+            min_y + pupil_sample.y * (max_y - min_y),
+        );
+        // The bounds are for sensor positions on the positive x axis; rotate them to the actual position
+        let (sin, cos) = if distance > 0.0 {
+            (sensor.y / distance, sensor.x / distance)
+        } else {
+            (0.0, 1.0)
+        };
+        let pupil = Vec2::new(cos * pupil.x - sin * pupil.y, sin * pupil.x + cos * pupil.y);
+        let origin = Vec3::new(sensor.x, sensor.y, 0.0);
+        let target = Vec3::new(pupil.x, pupil.y, self.rear_vertex());
+        let offset = target - origin;
+        // The distance z of the rear surface cancels out in the normalization
+        let cos_theta = offset.z / offset.norm();
+        let weight =
+            cos_theta.powi(4) * bounds_area(self.pupil_bounds[bucket]) / self.center_pupil_area;
+        let (origin, direction) = self.trace_from_sensor(origin, offset, wavelength)?;
+        Some((origin, direction, weight))
+    }
+
+    fn rear_vertex(&self) -> Float {
+        self.vertices[self.vertices.len() - 1]
+    }
+
+    /// Traces a ray from the sensor through the lens towards the scene
+    fn trace_from_sensor(
+        &self,
+        mut origin: Vec3,
+        direction: Vec3,
+        wavelength: Wavelength,
+    ) -> Option<(Vec3, Vec3)> {
+        let mut direction = direction.normalize();
+        for index in (0..self.surfaces.len()).rev() {
+            let surface = &self.surfaces[index];
+            let from = surface.refractive_index(wavelength);
+            let to = match index {
+                0 => 1.0,
+                _ => self.surfaces[index - 1].refractive_index(wavelength),
+            };
+            (origin, direction) =
+                interact(surface, self.vertices[index], origin, direction, from / to)?;
+        }
+        Some((origin, direction))
+    }
+
+    /// Finds the bounds of the exit pupil for the sensor positions within the given bucket, by tracing a grid of rays towards the rear surface from both ends of the bucket
+    #[allow(clippy::cast_precision_loss)]
+    fn compute_pupil_bounds(&self, bucket: usize) -> [Float; 4] {
+        let rear = &self.surfaces[self.surfaces.len() - 1];
+        let half_extent = 1.5 * rear.aperture_radius;
+        let cell = 2.0 * half_extent / PUPIL_GRID as Float;
+        let max_distance = self.half_sensor.norm();
+        let mut bounds = [
+            Float::INFINITY,
+            Float::INFINITY,
+            Float::NEG_INFINITY,
+            Float::NEG_INFINITY,
+        ];
+        for end in [bucket, bucket + 1] {
+            let distance = end as Float / PUPIL_BUCKETS as Float * max_distance;
+            let origin = Vec3::new(distance, 0.0, 0.0);
+            for i in 0..PUPIL_GRID {
+                for j in 0..PUPIL_GRID {
+                    let x = -half_extent + (i as Float + 0.5) * cell;
+                    let y = -half_extent + (j as Float + 0.5) * cell;
+                    let target = Vec3::new(x, y, self.rear_vertex());
+                    if self
+                        .trace_from_sensor(origin, target - origin, FOCUS_WAVELENGTH)
+                        .is_some()
+                    {
+                        // Grow by the whole grid cell, so that the edges of the pupil are not missed
+                        bounds[0] = bounds[0].min(x - cell);
+                        bounds[1] = bounds[1].min(y - cell);
+                        bounds[2] = bounds[2].max(x + cell);
+                        bounds[3] = bounds[3].max(y + cell);
+                    }
+                }
+            }
+        }
+        bounds
+    }
+}
+
+/// Returns the area of exit pupil bounds given as `[min_x, min_y, max_x, max_y]`
+fn bounds_area([min_x, min_y, max_x, max_y]: [Float; 4]) -> Float {
+    (max_x - min_x).max(0.0) * (max_y - min_y).max(0.0)
+}
+
+/// Returns the positions of the surface vertices on the optical axis, with the sensor at the origin
+fn vertex_positions(surfaces: &[LensSurface]) -> Vec<Float> {
+    let mut vertices = Vec::with_capacity(surfaces.len());
+    let mut position = 0.0;
+    for surface in surfaces.iter().rev() {
+        position += surface.thickness;
+        vertices.push(position);
+    }
+    vertices.reverse();
+    vertices
+}
+
+/// Traces a paraxial ray from the point on the optical axis at `focus_distance` in front of the lens, and returns the position where it crosses the axis behind the lens
+fn focus_position(
+    surfaces: &[LensSurface],
+    vertices: &[Float],
+    focus_distance: Float,
+) -> Option<Float> {
+    let front = vertices[0];
+    // A small height on the front surface keeps the ray paraxial
+    let height = 0.05 * surfaces[0].aperture_radius;
+    let mut direction = Vec3::new(height, 0.0, -focus_distance).normalize();
+    // Start just in front of the lens instead of at the focus distance, which loses precision for distant focus
+    let mut origin = Vec3::new(height, 0.0, front) - direction;
+    for (index, surface) in surfaces.iter().enumerate() {
+        let from = match index {
+            0 => 1.0,
+            _ => surfaces[index - 1].refractive_index(FOCUS_WAVELENGTH),
+        };
+        let to = surface.refractive_index(FOCUS_WAVELENGTH);
+        (origin, direction) = interact(surface, vertices[index], origin, direction, from / to)?;
+    }
+    if direction.x >= 0.0 {
+        // The ray does not converge towards the axis
+        return None;
+    }
+    let t = -origin.x / direction.x;
+    Some(origin.z + t * direction.z)
+}
+
+/// Intersects the ray with the surface at the given vertex position, checks the clear aperture, and refracts the ray with the given ratio of the refractive indices. Returns `None` if the ray misses the surface, is blocked, or is totally internally reflected.
+fn interact(
+    surface: &LensSurface,
+    vertex: Float,
+    origin: Vec3,
+    direction: Vec3,
+    eta: Float,
+) -> Option<(Vec3, Vec3)> {
+    let (t, normal) = if surface.radius == 0.0 {
+        let t = (vertex - origin.z) / direction.z;
+        (t, Vec3::new(0.0, 0.0, 1.0))
+    } else {
+        let center = Vec3::new(0.0, 0.0, vertex - surface.radius);
+        let offset = origin - center;
+        let b = offset.dot(&direction);
+        let c = offset.norm_squared() - surface.radius * surface.radius;
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        // The surface is the cap of the sphere around the vertex: the nearer or the farther intersection, depending on the direction of the ray and the curvature
+        let t = if (direction.z > 0.0) == (surface.radius < 0.0) {
+            -b - root
+        } else {
+            -b + root
+        };
+        let point = origin + t * direction;
+        (t, (point - center).normalize())
+    };
+    if t <= 0.0 {
+        return None;
+    }
+    let point = origin + t * direction;
+    if point.x * point.x + point.y * point.y > surface.aperture_radius * surface.aperture_radius {
+        return None;
+    }
+    if (eta - 1.0).abs() < Float::EPSILON {
+        return Some((point, direction));
+    }
+    // Refraction with the normal facing against the incoming ray
+    let normal = if normal.dot(&direction) > 0.0 {
+        -normal
+    } else {
+        normal
+    };
+    let cos_incident = -normal.dot(&direction);
+    let sin2_transmitted = eta * eta * (1.0 - cos_incident * cos_incident);
+    if sin2_transmitted > 1.0 {
+        return None;
+    }
+    let cos_transmitted = (1.0 - sin2_transmitted).sqrt();
+    let refracted = eta * direction + (eta * cos_incident - cos_transmitted) * normal;
+    Some((point, refracted.normalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Double Gauss 50mm f/2 lens, from the scenes of Physically Based Rendering
+    const DOUBLE_GAUSS: &str = "
+        # radius thickness index aperture
+        29.475   3.76   1.67   25.2
+        84.83    0.12   1      25.2
+        19.275   4.025  1.67   23
+        40.77    3.275  1.699  23
+        12.75    5.705  1      18
+        0        4.5    0      17.1
+        -14.495  1.18   1.603  17
+        40.77    6.065  1.658  20
+        -20.385  0.19   1      20
+        437.065  3.22   1.717  20
+        -39.73   0      1      20
+    ";
+
+    fn lens(focus_distance: Float) -> LensSystem {
+        let surfaces = parse_prescription(DOUBLE_GAUSS).unwrap();
+        LensSystem::new(surfaces, Vec2::new(36.0, 24.0), focus_distance).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let surfaces = parse_prescription(DOUBLE_GAUSS).unwrap();
+        assert_eq!(surfaces.len(), 11);
+        assert!(surfaces[5].is_stop(Some(&surfaces[4])));
+        assert!(parse_prescription("1 2 3").is_err());
+        assert!(parse_prescription("# only a comment").is_err());
+    }
+
+    #[test]
+    fn cannot_focus_closer_than_focal_length() {
+        let surfaces = parse_prescription(DOUBLE_GAUSS).unwrap();
+        assert!(LensSystem::new(surfaces, Vec2::new(36.0, 24.0), 10.0).is_err());
+    }
+
+    #[test]
+    fn abbe_number() {
+        let glass = Dispersive::from_abbe(1.5168, 64.17);
+        assert!((glass.refractive_index(588) - 1.5168).abs() < 1e-3);
+        assert!(glass.refractive_index(486) > glass.refractive_index(656));
+    }
+
+    #[test]
+    fn focus_moves_sensor_back() {
+        let far = lens(1e6);
+        let near = lens(500.0);
+        // Focusing closer moves the lens away from the sensor
+        assert!(near.vertices[0] > far.vertices[0]);
+        // Focused at infinity, the sensor sits roughly at the focal length behind the lens
+        assert!(far.rear_vertex() > 20.0 && far.rear_vertex() < 50.0);
+    }
+
+    #[test]
+    fn center_ray_goes_forward() {
+        let lens = lens(1000.0);
+        let (origin, direction, _weight) = lens
+            .generate(Vec2::new(0.5, 0.5), Vec2::new(0.5, 0.5), 550)
+            .unwrap();
+        assert!(origin.z > lens.rear_vertex());
+        assert!(direction.z > 0.99);
+    }
+
+    #[test]
+    fn focused_rays_converge() {
+        let focus_distance = 1000.0;
+        let lens = lens(focus_distance);
+        let focus_plane = lens.vertices[0] + focus_distance;
+        // Rays from the same sensor position through different parts of the pupil meet on the plane of focus
+        let mut points = Vec::new();
+        for sample in [(0.3, 0.5), (0.5, 0.5), (0.7, 0.5), (0.5, 0.3), (0.5, 0.7)] {
+            let sample = Vec2::new(sample.0, sample.1);
+            if let Some((origin, direction, _weight)) =
+                lens.generate(Vec2::new(0.6, 0.5), sample, FOCUS_WAVELENGTH)
+            {
+                let t = (focus_plane - origin.z) / direction.z;
+                points.push(origin + t * direction);
+            }
+        }
+        assert!(points.len() >= 3);
+        for point in &points {
+            assert!((point - points[0]).norm() < 1.0, "{points:?}");
+        }
+    }
+
+    /// Estimates the exposure at the given horizontal image position by averaging the weights over a grid of pupil samples
+    #[allow(clippy::cast_precision_loss)]
+    fn exposure(lens: &LensSystem, x: Float, weighted: bool) -> Float {
+        const GRID: usize = 48;
+        let mut sum = 0.0;
+        for i in 0..GRID {
+            for j in 0..GRID {
+                let sample = Vec2::new(
+                    (i as Float + 0.5) / GRID as Float,
+                    (j as Float + 0.5) / GRID as Float,
+                );
+                if let Some((_, _, weight)) =
+                    lens.generate(Vec2::new(x, 0.5), sample, FOCUS_WAVELENGTH)
+                {
+                    sum += if weighted { weight } else { 1.0 };
+                }
+            }
+        }
+        sum / (GRID * GRID) as Float
+    }
+
+    #[test]
+    fn exposure_continuous_across_buckets() {
+        let lens = lens(1000.0);
+        // Image position of the boundary between two pupil buckets, on the horizontal axis through the center
+        #[allow(clippy::cast_precision_loss)]
+        let distance = 44.0 / PUPIL_BUCKETS as Float * lens.half_sensor.norm();
+        let boundary = 0.5 - distance / (4.0 * lens.half_sensor.x);
+        let inner = exposure(&lens, boundary + 1e-4, true);
+        let outer = exposure(&lens, boundary - 1e-4, true);
+        // The fraction of unblocked rays alone jumps by more than 3% here, as the pupil bounds grow
+        assert!((inner - outer).abs() < 0.015 * inner, "{inner} != {outer}");
+    }
+
+    #[test]
+    fn exposure_falls_off_towards_edges() {
+        let lens = lens(1000.0);
+        let center = exposure(&lens, 0.5, true);
+        let edge = exposure(&lens, 0.02, true);
+        assert!(center > 0.5 && center <= 1.0, "{center}");
+        assert!(edge < center, "{edge} >= {center}");
+        // The weights account for the area of the pupil bounds, unlike the fraction of unblocked rays
+        assert!(edge < exposure(&lens, 0.02, false));
+    }
+}
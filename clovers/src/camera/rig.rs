@@ -0,0 +1,211 @@
+//! Camera rigs for rendering multiple views of the same scene, such as the two eyes of a stereo pair or the six faces of a cube map.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use nalgebra::Unit;
+
+use crate::{Direction, Float, Position, Vec3};
+
+use super::{Camera, CameraInit, LensInit, Projection, SimpleLensInit};
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde-derive", serde(tag = "kind"))]
+/// Represents the camera rigs that can be described in a Scene file
+pub enum RigInit {
+    /// Stereo pair of cameras, side by side
+    Stereo {
+        /// Distance between the eyes, in scene units
+        interocular: Float,
+        /// Distance of the plane where the two views line up, in scene units. Objects in front of it appear in front of the screen. The views are shifted like the images of a shift lens, keeping the eyes parallel. Defaults to infinity, with no shift.
+        #[cfg_attr(feature = "serde-derive", serde(default))]
+        convergence: Option<Float>,
+    },
+    /// Omni-directional stereo panorama: an equirectangular panorama for each eye, with the eyes on a circle turning towards each direction of the panorama
+    OmniStereo {
+        /// Distance between the eyes, in scene units
+        interocular: Float,
+    },
+    /// Six square perspective views with a 90 degree field of view, covering all directions around the camera
+    CubeMap,
+}
+
+#[derive(Clone, Debug)]
+/// A single view of a camera rig
+pub struct RigView {
+    /// Name of the view, such as `left` or `front`
+    pub name: &'static str,
+    /// The camera of the view
+    pub camera: Camera,
+}
+
+impl RigInit {
+    /// Returns the aspect ratio of each view, for an image with the given aspect ratio. The faces of a cube map are always square.
+    #[must_use]
+    pub fn view_aspect_ratio(&self, aspect_ratio: Float) -> Float {
+        match self {
+            RigInit::CubeMap => 1.0,
+            RigInit::Stereo { .. } | RigInit::OmniStereo { .. } => aspect_ratio,
+        }
+    }
+
+    /// Returns the views of the rig around the camera described by `init`. The parameters are as in [`Camera::from_init`], with the `aspect_ratio` of a single view.
+    ///
+    /// # Errors
+    /// Returns an error if the camera of a view cannot be created.
+    pub fn views(
+        &self,
+        init: &CameraInit,
+        focus_target: Option<Position>,
+        aspect_ratio: Float,
+        time_0: Float,
+        time_1: Float,
+    ) -> Result<Vec<RigView>, String> {
+        let camera =
+            |init: &CameraInit| Camera::from_init(init, focus_target, aspect_ratio, time_0, time_1);
+        let w: Direction = Unit::new_normalize(init.look_from - init.look_at);
+        let u: Direction = Unit::new_normalize(init.up.cross(&w));
+        let v: Direction = Unit::new_normalize(w.cross(&u));
+        match self {
+            RigInit::Stereo {
+                interocular,
+                convergence,
+            } => [("left", -0.5), ("right", 0.5)]
+                .into_iter()
+                .map(|(name, side)| {
+                    let offset = side * interocular;
+                    let mut eye = init.clone();
+                    eye.look_from += offset * *u;
+                    eye.look_at += offset * *u;
+                    let eye = camera(&eye)?;
+                    // Shift the image towards the center, so that the views of both eyes meet at the convergence distance
+                    let shift = convergence.map_or(0.0, |convergence| {
+                        -offset * eye.focus_distance / convergence / eye.horizontal.norm()
+                    });
+                    Ok(RigView {
+                        name,
+                        camera: eye.with_shift(shift),
+                    })
+                })
+                .collect(),
+            RigInit::OmniStereo { interocular } => {
+                let mut panorama = init.clone();
+                panorama.projection = Projection::Equirectangular;
+                [("left", -0.5), ("right", 0.5)]
+                    .into_iter()
+                    .map(|(name, side)| {
+                        Ok(RigView {
+                            name,
+                            camera: camera(&panorama)?.with_eye_offset(side * interocular),
+                        })
+                    })
+                    .collect()
+            }
+            RigInit::CubeMap => {
+                let faces: [(&str, Vec3, Vec3); 6] = [
+                    ("right", *u, *v),
+                    ("left", -*u, *v),
+                    ("top", *v, *w),
+                    ("bottom", -*v, -*w),
+                    ("front", -*w, *v),
+                    ("back", *w, *v),
+                ];
+                let mut views = vec![];
+                for (name, forward, up) in faces {
+                    let mut face = init.clone();
+                    face.look_at = init.look_from + forward;
+                    face.up = up;
+                    face.lens = LensInit::Simple(SimpleLensInit {
+                        vertical_fov: 90.0,
+                        aperture: 0.0,
+                        focus_distance: 1.0,
+                    });
+                    face.projection = Projection::Perspective;
+                    face.vignetting = 0.0;
+                    views.push(RigView {
+                        name,
+                        camera: Camera::from_init(&face, None, 1.0, time_0, time_1)?,
+                    });
+                }
+                Ok(views)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        camera::{ApertureInit, ShutterInit},
+        Vec2,
+    };
+
+    use super::*;
+
+    fn init() -> CameraInit {
+        CameraInit {
+            look_from: Position::new(0.0, 0.0, 0.0),
+            look_at: Position::new(0.0, 0.0, -1.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            lens: LensInit::Simple(SimpleLensInit {
+                vertical_fov: 40.0,
+                aperture: 0.0,
+                focus_distance: 10.0,
+            }),
+            projection: Projection::default(),
+            bokeh: ApertureInit::default(),
+            vignetting: 0.0,
+            shutter: ShutterInit::default(),
+        }
+    }
+
+    fn center_ray(camera: &Camera, pixel_uv: Vec2) -> (Position, Vec3) {
+        let ray = camera.get_ray(pixel_uv, None, 0.0, 600).unwrap();
+        (ray.origin, *ray.direction)
+    }
+
+    #[test]
+    fn stereo_converges() {
+        let rig = RigInit::Stereo {
+            interocular: 0.064,
+            convergence: Some(5.0),
+        };
+        let views = rig.views(&init(), None, 1.5, 0.0, 1.0).unwrap();
+        let (left, right) = (&views[0].camera, &views[1].camera);
+        assert!(left.origin.x < 0.0 && right.origin.x > 0.0);
+        // The centers of both views meet at the convergence distance
+        let (l_origin, l_direction) = center_ray(left, Vec2::new(0.5, 0.5));
+        let (r_origin, r_direction) = center_ray(right, Vec2::new(0.5, 0.5));
+        let l_point = l_origin + l_direction * (5.0 / -l_direction.z);
+        let r_point = r_origin + r_direction * (5.0 / -r_direction.z);
+        assert!((l_point - r_point).norm() < 1e-4);
+        assert!(l_point.x.abs() < 1e-4);
+    }
+
+    #[test]
+    fn omni_stereo_eyes_turn_around() {
+        let rig = RigInit::OmniStereo { interocular: 0.064 };
+        let views = rig.views(&init(), None, 2.0, 0.0, 1.0).unwrap();
+        let left = &views[0].camera;
+        // Looking forward, the left eye is on the left; looking backward, on the right
+        let (forward_origin, _) = center_ray(left, Vec2::new(0.5, 0.5));
+        let (backward_origin, _) = center_ray(left, Vec2::new(0.0, 0.5));
+        assert!((forward_origin.x + 0.032).abs() < 1e-5);
+        assert!((backward_origin.x - 0.032).abs() < 1e-5);
+    }
+
+    #[test]
+    fn cube_map_faces() {
+        let views = RigInit::CubeMap
+            .views(&init(), None, 1.0, 0.0, 1.0)
+            .unwrap();
+        assert_eq!(views.len(), 6);
+        let (_, top) = center_ray(&views[2].camera, Vec2::new(0.5, 0.5));
+        assert!((top - Vec3::new(0.0, 1.0, 0.0)).norm() < 1e-5);
+        // The corner of a face is at 45 degrees from its center in both directions
+        let (_, corner) = center_ray(&views[4].camera, Vec2::new(1.0, 1.0));
+        assert!((corner - Vec3::new(1.0, 1.0, -1.0).normalize()).norm() < 1e-5);
+    }
+}
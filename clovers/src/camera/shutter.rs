@@ -0,0 +1,241 @@
+//! Shutter of the camera. The shutter curve describes how the transmission of the shutter changes while it opens and closes, and the rolling readout shifts the exposure of each row of the image.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::Float;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde-derive", serde(tag = "kind"))]
+/// Transmission of the shutter over the exposure interval, as can be described in a Scene file
+pub enum ShutterCurve {
+    /// Shutter that opens and closes instantly, the default
+    #[default]
+    Box,
+    /// Shutter that opens and closes linearly
+    Trapezoid {
+        /// Fraction of the exposure interval spent opening
+        open: Float,
+        /// Fraction of the exposure interval spent closing
+        close: Float,
+    },
+    /// Transmission values spaced evenly over the exposure interval, from the start to the end, with linear interpolation in between
+    Custom {
+        /// Transmission values, at least two and not all zero
+        values: Vec<Float>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// Order in which the rows of the image are read out by a rolling shutter
+pub enum RollingDirection {
+    /// From the top row to the bottom row, the default
+    #[default]
+    TopToBottom,
+    /// From the bottom row to the top row
+    BottomToTop,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// Represents the shutter settings that can be described in a Scene file
+pub struct ShutterInit {
+    /// Transmission of the shutter over the exposure of each row. Defaults to a box curve.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub curve: ShutterCurve,
+    /// Fraction of the shutter interval `time_0..time_1` spent reading out the rows of the image. Each row is exposed for the rest of the interval, starting when the previous row has been read out. Defaults to zero, for a global shutter exposing all rows at once.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub readout: Float,
+    /// Order in which the rows are read out
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub direction: RollingDirection,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// The shutter of the camera, used for mapping time samples into times within the shutter interval
+pub struct Shutter {
+    /// Positions of the knots of the piecewise linear transmission curve, from zero to one
+    knots: Vec<Float>,
+    /// Transmission at each knot
+    values: Vec<Float>,
+    /// Cumulative transmission at the end of each segment, normalized to end at one
+    cumulative: Vec<Float>,
+    readout: Float,
+    direction: RollingDirection,
+}
+
+impl Default for Shutter {
+    fn default() -> Self {
+        Shutter::new(ShutterInit::default())
+    }
+}
+
+impl Shutter {
+    /// Creates the [`Shutter`] described by the given [`ShutterInit`].
+    ///
+    /// # Panics
+    /// Panics if the readout is not within `0..=1`, if a trapezoid spends more than the whole interval opening and closing, or if a custom curve has fewer than two values, negative values, or only zeros.
+    #[must_use]
+    pub fn new(init: ShutterInit) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&init.readout),
+            "The shutter readout must be within 0..=1"
+        );
+        let (knots, values) = match init.curve {
+            ShutterCurve::Box => (vec![0.0, 1.0], vec![1.0, 1.0]),
+            ShutterCurve::Trapezoid { open, close } => {
+                assert!(
+                    open >= 0.0 && close >= 0.0 && open + close <= 1.0,
+                    "The shutter must open and close within the exposure interval"
+                );
+                (vec![0.0, open, 1.0 - close, 1.0], vec![0.0, 1.0, 1.0, 0.0])
+            }
+            ShutterCurve::Custom { values } => {
+                assert!(
+                    values.len() >= 2,
+                    "A custom shutter curve needs at least two values"
+                );
+                assert!(
+                    values.iter().all(|&value| value >= 0.0),
+                    "The shutter transmission cannot be negative"
+                );
+                #[allow(clippy::cast_precision_loss)]
+                let last = (values.len() - 1) as Float;
+                #[allow(clippy::cast_precision_loss)]
+                let knots = (0..values.len()).map(|i| i as Float / last).collect();
+                (knots, values)
+            }
+        };
+
+        let mut cumulative = Vec::with_capacity(knots.len() - 1);
+        let mut total = 0.0;
+        for i in 1..knots.len() {
+            total += (knots[i] - knots[i - 1]) * (values[i] + values[i - 1]) / 2.0;
+            cumulative.push(total);
+        }
+        assert!(total > 0.0, "The shutter never opens");
+        for value in &mut cumulative {
+            *value /= total;
+        }
+
+        Shutter {
+            knots,
+            values,
+            cumulative,
+            readout: init.readout,
+            direction: init.direction,
+        }
+    }
+
+    /// Maps a time sample in the range `0..1` into a relative time within the shutter interval, for a pixel at the given vertical image position, where zero is the bottom of the image. The times are distributed in proportion to the transmission of the shutter.
+    #[must_use]
+    pub fn sample(&self, sample: Float, image_y: Float) -> Float {
+        let row = match self.direction {
+            RollingDirection::TopToBottom => 1.0 - image_y,
+            RollingDirection::BottomToTop => image_y,
+        }
+        .clamp(0.0, 1.0);
+        let exposure = self.sample_curve(sample);
+        row * self.readout + exposure * (1.0 - self.readout)
+    }
+
+    /// Maps a sample in the range `0..1` into a position on the curve, in proportion to the transmission
+    fn sample_curve(&self, sample: Float) -> Float {
+        let sample = sample.clamp(0.0, 1.0);
+        let segment = self
+            .cumulative
+            .partition_point(|&value| value < sample)
+            .min(self.cumulative.len() - 1);
+        let start = if segment == 0 {
+            0.0
+        } else {
+            self.cumulative[segment - 1]
+        };
+        let width = self.cumulative[segment] - start;
+        let u = if width > 0.0 {
+            ((sample - start) / width).clamp(0.0, 1.0)
+        } else {
+            0.5
+        };
+        // Inverts the cumulative distribution of the linear density from `a` to `b` within the segment
+        let (a, b) = (self.values[segment], self.values[segment + 1]);
+        let denominator = a + (a * a + u * (b * b - a * a)).sqrt();
+        let position = if denominator > 0.0 {
+            u * (a + b) / denominator
+        } else {
+            0.0
+        };
+        self.knots[segment] + position * (self.knots[segment + 1] - self.knots[segment])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> impl Iterator<Item = Float> {
+        (0..=100u8).map(|i| Float::from(i) / 100.0)
+    }
+
+    #[test]
+    fn box_is_uniform() {
+        let shutter = Shutter::default();
+        for sample in samples() {
+            assert!((shutter.sample(sample, 0.3) - sample).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn trapezoid_is_denser_in_the_middle() {
+        let shutter = Shutter::new(ShutterInit {
+            curve: ShutterCurve::Trapezoid {
+                open: 0.25,
+                close: 0.25,
+            },
+            ..Default::default()
+        });
+        let times: Vec<Float> = samples()
+            .map(|sample| shutter.sample(sample, 0.5))
+            .collect();
+        assert!(times.windows(2).all(|pair| pair[0] <= pair[1] + 1e-6));
+        assert!(times[0].abs() < 1e-6 && (times[100] - 1.0).abs() < 1e-6);
+        // The ramps hold a sixth of the exposure each, compressed into a quarter of the interval
+        let edge = times.iter().filter(|&&time| time < 0.25).count();
+        let middle = times
+            .iter()
+            .filter(|&&time| (0.375..0.625).contains(&time))
+            .count();
+        assert!(edge < middle);
+    }
+
+    #[test]
+    fn custom_skips_closed_segments() {
+        let shutter = Shutter::new(ShutterInit {
+            curve: ShutterCurve::Custom {
+                values: vec![1.0, 1.0, 0.0, 0.0, 1.0],
+            },
+            ..Default::default()
+        });
+        for sample in samples() {
+            let time = shutter.sample(sample, 0.5);
+            assert!(!(0.5 + 1e-3..0.75 - 1e-3).contains(&time), "{time}");
+        }
+    }
+
+    #[test]
+    fn rolling_delays_lower_rows() {
+        let shutter = Shutter::new(ShutterInit {
+            readout: 0.5,
+            ..Default::default()
+        });
+        for sample in samples() {
+            let top = shutter.sample(sample, 1.0);
+            let bottom = shutter.sample(sample, 0.0);
+            assert!((bottom - top - 0.5).abs() < 1e-6);
+            assert!((0.0..=0.5).contains(&top));
+        }
+    }
+}
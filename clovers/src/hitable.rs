@@ -2,18 +2,27 @@
 
 #[cfg(feature = "gl_tf")]
 use crate::objects::GLTFTriangle;
+#[cfg(feature = "heightfield")]
+use crate::objects::Heightfield;
+#[cfg(feature = "obj")]
+use crate::objects::OBJTriangle;
+#[cfg(feature = "ply")]
+use crate::objects::PointCloud;
 
 use crate::{
     aabb::AABB,
     bvh::{build::utils::vec_bounding_box, BVHNode},
-    objects::{Boxy, ConstantMedium, MovingSphere, Quad, RotateY, Sphere, Translate, Triangle},
+    objects::{
+        Boxy, Cone, ConstantMedium, Curve, Cylinder, Disk, KeyframedTransform, MovingSphere, Quad,
+        RotateY, Sphere, Torus, Transform, Translate, Triangle, CSG, SDF,
+    },
     ray::Ray,
     wavelength::Wavelength,
-    Direction, Displacement, Float, HitRecord, Position, Vec3,
+    Direction, Displacement, Float, HitRecord, Position, Vec2, Vec3, EPSILON_SHADOW_ACNE,
 };
 
 use enum_dispatch::enum_dispatch;
-use rand::{rngs::SmallRng, seq::IteratorRandom};
+use rand::rngs::SmallRng;
 
 /// Enumeration of all runtime entities that can be intersected aka "hit" by a [Ray].
 #[enum_dispatch(HitableTrait)]
@@ -22,16 +31,31 @@ use rand::{rngs::SmallRng, seq::IteratorRandom};
 pub enum Hitable<'scene> {
     Boxy(Boxy<'scene>),
     BVHNode(BVHNode<'scene>),
+    Cone(Cone<'scene>),
     ConstantMedium(ConstantMedium<'scene>),
+    CSG(CSG<'scene>),
+    Curve(Curve<'scene>),
+    Cylinder(Cylinder<'scene>),
+    Disk(Disk<'scene>),
+    KeyframedTransform(KeyframedTransform<'scene>),
     MovingSphere(MovingSphere<'scene>),
     Quad(Quad<'scene>),
     RotateY(RotateY<'scene>),
+    SDF(SDF<'scene>),
     Sphere(Sphere<'scene>),
+    Torus(Torus<'scene>),
+    Transform(Transform<'scene>),
     Translate(Translate<'scene>),
     Triangle(Triangle<'scene>),
     Empty(Empty),
     #[cfg(feature = "gl_tf")]
     GLTFTriangle(GLTFTriangle<'scene>),
+    #[cfg(feature = "heightfield")]
+    Heightfield(Heightfield<'scene>),
+    #[cfg(feature = "obj")]
+    OBJTriangle(OBJTriangle<'scene>),
+    #[cfg(feature = "ply")]
+    PointCloud(PointCloud<'scene>),
     HitableList(HitableList<'scene>),
 }
 
@@ -96,8 +120,8 @@ pub trait HitableTrait {
         rng: &mut SmallRng,
     ) -> Float;
 
-    /// Random point on the entity, used for multiple importance sampling.
-    fn random(&self, _origin: Position, _rng: &mut SmallRng) -> Displacement {
+    /// Random point on the entity at the given `time`, as a displacement from the `origin`. The point is determined by the uniform `sample`, with both coordinates in the range `[0..1)`. Used for multiple importance sampling.
+    fn random(&self, _origin: Position, _sample: Vec2, _time: Float) -> Displacement {
         unimplemented!(
             "HitableTrait::random called for a Hitable that has no implementation for it!"
         );
@@ -120,6 +144,25 @@ pub fn get_orientation(ray: &Ray, outward_normal: Direction) -> (bool, Direction
     (front_face, normal)
 }
 
+/// Returns the probability density of sampling the given direction from the origin, for a surface whose `random` method samples points uniformly by area. Sums the contributions of all intersections along the direction, so that it is valid for open and non-convex surfaces too.
+pub(crate) fn uniform_area_pdf(
+    hitable: &impl HitableTrait,
+    ray: &Ray,
+    area: Float,
+    rng: &mut SmallRng,
+) -> Float {
+    let mut pdf = 0.0;
+    let mut distance_min = EPSILON_SHADOW_ACNE;
+    while let Some(hit_record) = hitable.hit(ray, distance_min, Float::INFINITY, rng) {
+        let cosine = ray.direction.dot(&hit_record.normal).abs();
+        if cosine > 0.0 {
+            pdf += hit_record.distance * hit_record.distance / (cosine * area);
+        }
+        distance_min = hit_record.distance + EPSILON_SHADOW_ACNE;
+    }
+    pdf
+}
+
 /// A list of `Hitable`s, occasionally used as the leaf of `BVHNode` when further splitting is not possible or beneficial.
 ///
 /// Hopefully temporary.
@@ -188,8 +231,16 @@ impl HitableTrait for HitableList<'_> {
         self.aabb.centroid()
     }
 
-    fn random(&self, origin: Position, rng: &mut SmallRng) -> Displacement {
-        let hitable = self.hitables.iter().choose(rng).unwrap();
-        hitable.random(origin, rng)
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn random(&self, origin: Position, sample: Vec2, time: Float) -> Displacement {
+        // Pick a hitable with the first coordinate, remapping the remainder back into the full range
+        let scaled = sample.x * self.hitables.len() as Float;
+        let index = (scaled as usize).min(self.hitables.len() - 1);
+        let remainder = (scaled - index as Float).clamp(0.0, 1.0 - Float::EPSILON);
+        self.hitables[index].random(origin, Vec2::new(remainder, sample.y), time)
     }
 }
@@ -17,6 +17,8 @@ pub struct HitRecord<'a> {
     pub v: Float,
     /// Reference to the material at the hitpoint
     pub material: &'a dyn MaterialTrait,
+    /// Direction of the surface along increasing `u`, if the object defines one. Used by anisotropic materials like [`Hair`](crate::materials::Hair).
+    pub tangent: Option<Direction>,
     /// Is the hitpoint at the front of the surface
     pub front_face: bool,
 }
@@ -4,13 +4,17 @@ use alloc::string::String;
 use core::fmt::Debug;
 use nalgebra::Unit;
 
-use crate::{pdf::PDF, ray::Ray, wavelength::Wavelength, Direction, Float, HitRecord, Vec3};
+use crate::{
+    pdf::PDF, random::BounceSample, ray::Ray, wavelength::Wavelength, Direction, Float, HitRecord,
+    Vec3,
+};
 pub mod cone_light;
 pub mod dielectric;
 pub mod diffuse_light;
 pub mod dispersive;
 #[cfg(feature = "gl_tf")]
 pub mod gltf;
+pub mod hair;
 pub mod isotropic;
 pub mod lambertian;
 pub mod metal;
@@ -21,10 +25,10 @@ pub use dielectric::*;
 pub use diffuse_light::*;
 pub use dispersive::*;
 use enum_dispatch::enum_dispatch;
+pub use hair::*;
 pub use isotropic::*;
 pub use lambertian::*;
 pub use metal::*;
-use rand::prelude::SmallRng;
 pub use thin_film::*;
 
 /// Initialization structure for a `Material`. Either contains a `Material` by itself, or a String `name` to be found in a shared material list.
@@ -74,9 +78,9 @@ impl MaterialTrait for Material {
         &self,
         ray: &Ray,
         hit_record: &HitRecord,
-        rng: &mut SmallRng,
+        sample: BounceSample,
     ) -> Option<ScatterRecord<'_>> {
-        self.kind.scatter(ray, hit_record, rng)
+        self.kind.scatter(ray, hit_record, sample)
     }
 
     fn scattering_pdf(&self, hit_record: &HitRecord, scattered: &Ray) -> Option<Float> {
@@ -110,7 +114,7 @@ pub trait MaterialTrait: Debug {
         &self,
         ray: &Ray,
         hit_record: &HitRecord,
-        rng: &mut SmallRng,
+        sample: BounceSample,
     ) -> Option<ScatterRecord<'_>>;
 
     /// TODO: explain
@@ -149,6 +153,8 @@ pub enum Kind {
     ConeLight(ConeLight),
     /// `DiffuseLight` material
     DiffuseLight(DiffuseLight),
+    /// `Hair` material
+    Hair(Hair),
     /// `Metal` material
     Metal(Metal),
     /// `Isotropic` material
@@ -2,19 +2,22 @@
 
 use super::{MaterialTrait, ScatterRecord};
 use crate::{
+    random::BounceSample,
     ray::Ray,
     textures::{SolidColor, Texture, TextureTrait},
     wavelength::Wavelength,
     Float, HitRecord,
 };
 use palette::Xyz;
-use rand::prelude::SmallRng;
 
 /// A cone light material. The material emits light if the incoming ray is within a certain amount of degrees from the surface normal.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConeLight {
     spread: Float,
+    /// Angle in degrees from the surface normal at which the emission starts to fall off smoothly, reaching zero at the `spread`. If not given, the emission is cut off sharply at the `spread`.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    falloff_start: Option<Float>,
     emit: Texture,
 }
 
@@ -23,6 +26,7 @@ impl Default for ConeLight {
     fn default() -> Self {
         ConeLight {
             spread: 10.0,
+            falloff_start: None,
             emit: Texture::SolidColor(SolidColor::new(Xyz::new(100.0, 100.0, 100.0))),
         }
     }
@@ -34,7 +38,7 @@ impl MaterialTrait for ConeLight {
         &self,
         _ray: &Ray,
         _hit_record: &HitRecord,
-        _rng: &mut SmallRng,
+        _sample: BounceSample,
     ) -> Option<ScatterRecord<'_>> {
         None
     }
@@ -57,12 +61,13 @@ impl MaterialTrait for ConeLight {
         }
 
         // HACK: current scene file format allows non-illuminants as light sources. These textures have no `emit()`, use `color()` instead.
-        match self.emit {
+        let emission = match self.emit {
             Texture::SolidColor(_) | Texture::SpatialChecker(_) | Texture::SurfaceChecker(_) => {
                 self.emit.color(ray, wavelength, hit_record)
             }
             _ => self.emit.emit(ray, wavelength, hit_record),
-        }
+        };
+        emission * self.falloff(angle)
     }
 
     fn color(&self, ray: &Ray, wavelength: Wavelength, hit_record: &HitRecord) -> Float {
@@ -76,7 +81,29 @@ impl ConeLight {
     pub fn new(spread: Float, emit: impl Into<Texture>) -> Self {
         ConeLight {
             spread,
+            falloff_start: None,
+            emit: emit.into(),
+        }
+    }
+
+    /// Creates a new [`ConeLight`] material with the given [Texture], whose emission falls off smoothly from the `falloff_start` angle to the `spread` angle, both in degrees.
+    #[must_use]
+    pub fn with_falloff(spread: Float, falloff_start: Float, emit: impl Into<Texture>) -> Self {
+        ConeLight {
+            spread,
+            falloff_start: Some(falloff_start),
             emit: emit.into(),
         }
     }
+
+    /// Returns the relative emission at the given angle in radians from the surface normal. Interpolates the cosine of the angle between the falloff start and the spread, and squares the result, as recommended for the spot lights of `KHR_lights_punctual`.
+    fn falloff(&self, angle: Float) -> Float {
+        let Some(falloff_start) = self.falloff_start else {
+            return 1.0;
+        };
+        let cos_start = falloff_start.to_radians().cos();
+        let cos_end = self.spread.to_radians().cos();
+        let t = ((angle.cos() - cos_end) / (cos_start - cos_end).max(1e-3)).clamp(0.0, 1.0);
+        t * t
+    }
 }
@@ -3,14 +3,13 @@
 use super::{reflect, refract, schlick, MaterialTrait, MaterialType, ScatterRecord};
 use crate::{
     pdf::{ZeroPDF, PDF},
+    random::BounceSample,
     ray::Ray,
     spectrum::spectral_power,
     wavelength::Wavelength,
     Direction, Float, HitRecord,
 };
 use palette::{white_point::E, Xyz};
-use rand::rngs::SmallRng;
-use rand::Rng;
 
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
@@ -38,7 +37,7 @@ impl MaterialTrait for Dielectric {
         &self,
         ray: &Ray,
         hit_record: &HitRecord,
-        rng: &mut SmallRng,
+        sample: BounceSample,
     ) -> Option<ScatterRecord<'_>> {
         let refraction_ratio: Float = if hit_record.front_face {
             1.0 / self.refractive_index
@@ -53,7 +52,7 @@ impl MaterialTrait for Dielectric {
             reflect(direction, hit_record.normal)
         } else {
             let reflect_probability: Float = schlick(cos_theta, refraction_ratio);
-            if rng.random::<Float>() < reflect_probability {
+            if sample.lobe < reflect_probability {
                 reflect(direction, hit_record.normal)
             } else {
                 // Refracted
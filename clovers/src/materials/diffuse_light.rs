@@ -2,13 +2,13 @@
 
 use super::{MaterialTrait, ScatterRecord};
 use crate::{
+    random::BounceSample,
     ray::Ray,
     textures::{SolidColor, Texture, TextureTrait},
     wavelength::Wavelength,
     Float, HitRecord,
 };
 use palette::Xyz;
-use rand::prelude::SmallRng;
 
 /// A diffuse light material. On this material, rays never scatter - the material always emits a color based on its texture.
 #[derive(Clone, Debug)]
@@ -32,7 +32,7 @@ impl MaterialTrait for DiffuseLight {
         &self,
         _ray: &Ray,
         _hit_record: &HitRecord,
-        _rng: &mut SmallRng,
+        _sample: BounceSample,
     ) -> Option<ScatterRecord<'_>> {
         None
     }
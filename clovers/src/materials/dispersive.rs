@@ -13,10 +13,9 @@ Dense flint glass SF10 	    1.7280 	    0.01342
 
 // TODO: consider other options, e.g. Sellmeier https://en.wikipedia.org/wiki/Sellmeier_equation
 
-use rand::{rngs::SmallRng, Rng};
-
 use crate::{
     pdf::{ZeroPDF, PDF},
+    random::BounceSample,
     ray::Ray,
     wavelength::Wavelength,
     Direction, Float, HitRecord,
@@ -53,6 +52,19 @@ impl Dispersive {
         Dispersive { cauchy_a, cauchy_b }
     }
 
+    /// Creates a new [Dispersive] material from the refractive index at the helium d-line of 587.6 nm and the Abbe number, as listed in glass catalogs and lens prescriptions.
+    #[must_use]
+    pub fn from_abbe(refractive_index: Float, abbe_number: Float) -> Self {
+        // Fraunhofer F, d and C lines, in micrometers
+        const F: Float = 0.4861;
+        const D: Float = 0.5876;
+        const C: Float = 0.6563;
+        // The Abbe number is (n_d - 1) / (n_F - n_C), and with the Cauchy equation n_F - n_C = B / F² - B / C²
+        let cauchy_b = (refractive_index - 1.0) / (abbe_number * (1.0 / (F * F) - 1.0 / (C * C)));
+        let cauchy_a = refractive_index - cauchy_b / (D * D);
+        Dispersive::new(cauchy_a, cauchy_b)
+    }
+
     /// Calculates the refractive index of the material for the given wavelength
     #[must_use]
     pub fn refractive_index(&self, wavelength: Wavelength) -> Float {
@@ -72,7 +84,7 @@ impl MaterialTrait for Dispersive {
         &self,
         ray: &Ray,
         hit_record: &HitRecord,
-        rng: &mut SmallRng,
+        sample: BounceSample,
     ) -> Option<ScatterRecord<'_>> {
         // Calculate refractive index based on the wavelength of the incoming material
         let refractive_index = self.refractive_index(ray.wavelength);
@@ -90,7 +102,7 @@ impl MaterialTrait for Dispersive {
             reflect(direction, hit_record.normal)
         } else {
             let reflect_probability: Float = schlick(cos_theta, refraction_ratio);
-            if rng.random::<Float>() < reflect_probability {
+            if sample.lobe < reflect_probability {
                 reflect(direction, hit_record.normal)
             } else {
                 // Refracted
@@ -9,11 +9,10 @@ use palette::{
     chromatic_adaptation::AdaptInto, convert::IntoColorUnclamped, white_point::E, LinSrgb, Srgb,
     Srgba, Xyz,
 };
-use rand::rngs::SmallRng;
 
 use crate::{
-    pdf::{ZeroPDF, PDF},
-    random::random_unit_vector,
+    pdf::{CosinePDF, ZeroPDF, PDF},
+    random::{sample_unit_vector, BounceSample},
     ray::Ray,
     spectrum::spectral_power,
     textures::TextureTrait,
@@ -82,7 +81,7 @@ impl MaterialTrait for GLTFMaterial {
         &self,
         ray: &Ray,
         hit_record: &HitRecord,
-        rng: &mut SmallRng,
+        sample: BounceSample,
     ) -> Option<ScatterRecord<'_>> {
         let (metalness, roughness) = self.sample_metalness_roughness(hit_record);
         let normal: Direction = self.sample_normal(hit_record);
@@ -91,7 +90,7 @@ impl MaterialTrait for GLTFMaterial {
         if metalness > 0.0 {
             // TODO: borrowed from metal, should this be different?
             let reflected: Direction = reflect(ray.direction, normal);
-            let direction = *reflected + roughness * *random_unit_vector(rng);
+            let direction = *reflected + roughness * *sample_unit_vector(sample.scatter);
             let direction = Unit::new_normalize(direction);
 
             Some(ScatterRecord {
@@ -108,7 +107,8 @@ impl MaterialTrait for GLTFMaterial {
             Some(ScatterRecord {
                 specular_ray: None,
                 material_type: MaterialType::Diffuse,
-                pdf_ptr: PDF::ZeroPDF(ZeroPDF::new()),
+                // Matches the cosine-weighted scattering_pdf below
+                pdf_ptr: PDF::CosinePDF(CosinePDF::new(hit_record.normal)),
             })
         }
     }
@@ -0,0 +1,508 @@
+//! Hair material.
+//! Based on [A Practical and Controllable Hair and Fur Model for Production Path Tracing](https://doi.org/10.1111/cgf.12830) by Chiang et al., which extends the [Marschner](https://doi.org/10.1145/882262.882345) model.
+
+#![allow(clippy::cast_precision_loss)]
+
+use nalgebra::Unit;
+
+use crate::{
+    onb::ONB,
+    pdf::{ZeroPDF, PDF},
+    random::BounceSample,
+    ray::Ray,
+    wavelength::Wavelength,
+    Direction, Float, HitRecord, Vec3, PI,
+};
+
+use super::{MaterialTrait, MaterialType, ScatterRecord};
+
+/// Number of explicitly modeled scattering lobes: R, TT and TRT. Higher order scattering is summed into one additional lobe.
+const P_MAX: usize = 3;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// A hair material, for rendering fibers such as hair, fur and other strands. Should be used with [Curves](crate::objects::Curve), which provide the fiber direction and the offset across the fiber width.
+///
+/// The color of the fiber is determined by the concentrations of the two pigments found in human hair. Eumelanin ranges from around `0.3` for blonde hair, to `1.3` for brown hair, and `8.0` for black hair. Pheomelanin adds red and orange tones.
+pub struct Hair {
+    /// Concentration of the eumelanin pigment
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_eumelanin"))]
+    pub eumelanin: Float,
+    /// Concentration of the pheomelanin pigment
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub pheomelanin: Float,
+    /// Longitudinal roughness, in the range `0..1`
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_roughness"))]
+    pub beta_m: Float,
+    /// Azimuthal roughness, in the range `0..1`
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_roughness"))]
+    pub beta_n: Float,
+    /// Tilt angle of the scales on the fiber surface, in degrees
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_alpha"))]
+    pub alpha: Float,
+    /// Refractive index of the fiber interior
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_eta"))]
+    pub eta: Float,
+}
+
+fn default_eumelanin() -> Float {
+    1.3
+}
+
+fn default_roughness() -> Float {
+    0.3
+}
+
+fn default_alpha() -> Float {
+    2.0
+}
+
+fn default_eta() -> Float {
+    1.55
+}
+
+impl Default for Hair {
+    fn default() -> Self {
+        Hair::new(default_eumelanin(), 0.0)
+    }
+}
+
+/// Direction dependent quantities of a single scattering event, expressed in the local coordinate frame of the fiber
+struct Geometry {
+    sin_theta_o: Float,
+    cos_theta_o: Float,
+    phi_o: Float,
+    h: Float,
+    gamma_o: Float,
+    gamma_t: Float,
+}
+
+impl Hair {
+    /// Creates a new [Hair] material with the given pigment concentrations and default roughness, scale tilt and refractive index.
+    #[must_use]
+    pub fn new(eumelanin: Float, pheomelanin: Float) -> Self {
+        Hair {
+            eumelanin,
+            pheomelanin,
+            beta_m: default_roughness(),
+            beta_n: default_roughness(),
+            alpha: default_alpha(),
+            eta: default_eta(),
+        }
+    }
+
+    /// Returns the absorption coefficient of the fiber interior at the given wavelength, relative to the fiber radius
+    #[must_use]
+    pub fn sigma_a(&self, wavelength: Wavelength) -> Float {
+        // Power law fits for the absorption spectra of the pigments, normalized at 650nm
+        let relative = wavelength as Float / 650.0;
+        self.eumelanin * 0.419 * relative.powf(-3.33)
+            + self.pheomelanin * 0.187 * relative.powf(-4.75)
+    }
+
+    /// Returns the longitudinal variances of each lobe
+    fn variances(&self) -> [Float; P_MAX + 1] {
+        let beta = self.beta_m;
+        let v = 0.726 * beta + 0.812 * beta * beta + 3.7 * beta.powi(20);
+        let v = v * v;
+        [v, 0.25 * v, 4.0 * v, 4.0 * v]
+    }
+
+    /// Returns the logistic scale factor of the azimuthal distribution
+    fn scale(&self) -> Float {
+        let beta = self.beta_n;
+        (PI / 8.0).sqrt() * (0.265 * beta + 1.194 * beta * beta + 5.372 * beta.powi(22))
+    }
+
+    /// Returns the sines and cosines of the scale tilt angle multiplied by 1, 2 and 4
+    fn tilts(&self) -> [(Float, Float); 3] {
+        let sin = self.alpha.to_radians().sin();
+        let cos = (1.0 - sin * sin).max(0.0).sqrt();
+        let double = (2.0 * cos * sin, cos * cos - sin * sin);
+        let quadruple = (
+            2.0 * double.1 * double.0,
+            double.1 * double.1 - double.0 * double.0,
+        );
+        [(sin, cos), double, quadruple]
+    }
+
+    /// Returns the longitudinal angle of the outgoing direction, shifted by the scale tilt of the given lobe
+    fn tilted(&self, p: usize, sin_theta: Float, cos_theta: Float) -> (Float, Float) {
+        let tilts = self.tilts();
+        let (sin_theta, cos_theta) = match p {
+            0 => (
+                sin_theta * tilts[1].1 - cos_theta * tilts[1].0,
+                cos_theta * tilts[1].1 + sin_theta * tilts[1].0,
+            ),
+            1 => (
+                sin_theta * tilts[0].1 + cos_theta * tilts[0].0,
+                cos_theta * tilts[0].1 - sin_theta * tilts[0].0,
+            ),
+            2 => (
+                sin_theta * tilts[2].1 + cos_theta * tilts[2].0,
+                cos_theta * tilts[2].1 - sin_theta * tilts[2].0,
+            ),
+            _ => (sin_theta, cos_theta),
+        };
+        (sin_theta, cos_theta.abs())
+    }
+
+    /// Computes the direction dependent quantities for the given outgoing direction in the local frame, and the offset across the fiber width
+    fn geometry(&self, wo: Vec3, h: Float) -> Geometry {
+        let sin_theta_o = wo.x.clamp(-1.0, 1.0);
+        let cos_theta_o = (1.0 - sin_theta_o * sin_theta_o).max(0.0).sqrt();
+        let phi_o = wo.z.atan2(wo.y);
+        let gamma_o = h.asin();
+        // Modified index of refraction for the projection into the normal plane
+        let etap = (self.eta * self.eta - sin_theta_o * sin_theta_o).sqrt() / cos_theta_o;
+        let gamma_t = (h / etap).clamp(-1.0, 1.0).asin();
+        Geometry {
+            sin_theta_o,
+            cos_theta_o,
+            phi_o,
+            h,
+            gamma_o,
+            gamma_t,
+        }
+    }
+
+    /// Returns the attenuations of each lobe at the given wavelength
+    fn attenuations(&self, geometry: &Geometry, wavelength: Wavelength) -> [Float; P_MAX + 1] {
+        // Transmittance through the interior of the fiber, for a single pass
+        let sin_theta_t = geometry.sin_theta_o / self.eta;
+        let cos_theta_t = (1.0 - sin_theta_t * sin_theta_t).max(0.0).sqrt();
+        let cos_gamma_t = geometry.gamma_t.cos();
+        let transmittance = (-self.sigma_a(wavelength) * (2.0 * cos_gamma_t / cos_theta_t)).exp();
+
+        let cos_gamma_o = (1.0 - geometry.h * geometry.h).max(0.0).sqrt();
+        let f = fresnel_dielectric(geometry.cos_theta_o * cos_gamma_o, self.eta);
+        let r = f;
+        let tt = (1.0 - f) * (1.0 - f) * transmittance;
+        let trt = tt * transmittance * f;
+        let rest = trt * f * transmittance / (1.0 - transmittance * f);
+        [r, tt, trt, rest]
+    }
+
+    /// Evaluates the sum of the lobes at the given incoming and outgoing directions, weighted by the given per-lobe weights
+    fn lobes(&self, geometry: &Geometry, wi: Vec3, weights: &[Float; P_MAX + 1]) -> Float {
+        let sin_theta_i = wi.x.clamp(-1.0, 1.0);
+        let cos_theta_i = (1.0 - sin_theta_i * sin_theta_i).max(0.0).sqrt();
+        let phi = wi.z.atan2(wi.y) - geometry.phi_o;
+        let variances = self.variances();
+        let scale = self.scale();
+
+        let mut sum = 0.0;
+        for p in 0..P_MAX {
+            let (sin_theta_o, cos_theta_o) =
+                self.tilted(p, geometry.sin_theta_o, geometry.cos_theta_o);
+            sum += longitudinal(
+                cos_theta_i,
+                cos_theta_o,
+                sin_theta_i,
+                sin_theta_o,
+                variances[p],
+            ) * weights[p]
+                * azimuthal(phi, p, scale, geometry.gamma_o, geometry.gamma_t);
+        }
+        sum += longitudinal(
+            cos_theta_i,
+            geometry.cos_theta_o,
+            sin_theta_i,
+            geometry.sin_theta_o,
+            variances[P_MAX],
+        ) * weights[P_MAX]
+            / (2.0 * PI);
+        sum
+    }
+
+    /// Evaluates the scattering function at the given local directions, for the given wavelength. Includes the cosine factor of the incoming direction.
+    #[must_use]
+    pub fn evaluate(&self, wo: Vec3, wi: Vec3, h: Float, wavelength: Wavelength) -> Float {
+        let geometry = self.geometry(wo, h);
+        let attenuations = self.attenuations(&geometry, wavelength);
+        self.lobes(&geometry, wi, &attenuations)
+    }
+
+    /// Returns the probability density of sampling the given local incoming direction, when importance sampling with the given wavelength.
+    #[must_use]
+    pub fn pdf(&self, wo: Vec3, wi: Vec3, h: Float, wavelength: Wavelength) -> Float {
+        let geometry = self.geometry(wo, h);
+        let probabilities = lobe_probabilities(self.attenuations(&geometry, wavelength));
+        self.lobes(&geometry, wi, &probabilities)
+    }
+
+    /// Samples an incoming direction in the local frame, for the given outgoing direction and wavelength.
+    #[must_use]
+    pub fn sample(&self, wo: Vec3, h: Float, wavelength: Wavelength, sample: BounceSample) -> Vec3 {
+        let geometry = self.geometry(wo, h);
+        let probabilities = lobe_probabilities(self.attenuations(&geometry, wavelength));
+
+        // Pick a lobe based on its share of the attenuation
+        let mut choice = sample.lobe;
+        let mut p = P_MAX;
+        for (lobe, probability) in probabilities.iter().enumerate().take(P_MAX) {
+            if choice < *probability {
+                p = lobe;
+                break;
+            }
+            choice -= probability;
+        }
+        // The remainder of the choice within the picked lobe is uniform, and reused for the azimuthal sample
+        let azimuthal = if probabilities[p] > 0.0 {
+            (choice / probabilities[p]).clamp(0.0, 1.0 - Float::EPSILON)
+        } else {
+            choice.clamp(0.0, 1.0 - Float::EPSILON)
+        };
+
+        // Sample the longitudinal distribution around the tilted specular cone
+        let v = self.variances()[p];
+        let (sin_theta_op, cos_theta_op) =
+            self.tilted(p, geometry.sin_theta_o, geometry.cos_theta_o);
+        let u: Float = sample.scatter.x.max(1e-5);
+        let cos_theta = 1.0 + v * (u + (1.0 - u) * (-2.0 / v).exp()).ln();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let cos_phi = (2.0 * PI * sample.scatter.y).cos();
+        let sin_theta_i = -cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op;
+        let cos_theta_i = (1.0 - sin_theta_i * sin_theta_i).max(0.0).sqrt();
+
+        // Sample the azimuthal distribution
+        let dphi = if p < P_MAX {
+            deflection(p, geometry.gamma_o, geometry.gamma_t)
+                + sample_trimmed_logistic(azimuthal, self.scale(), -PI, PI)
+        } else {
+            2.0 * PI * azimuthal
+        };
+        let phi_i = geometry.phi_o + dphi;
+
+        Vec3::new(
+            sin_theta_i,
+            cos_theta_i * phi_i.cos(),
+            cos_theta_i * phi_i.sin(),
+        )
+    }
+}
+
+impl MaterialTrait for Hair {
+    /// Scatter function for the [Hair] material. Samples one of the lobes of the fiber, proportionally to its attenuation at the wavelength of the ray. The returned [`ScatterRecord`] will have a probability density function of [`ZeroPDF`] and material type of [`MaterialType::Specular`].
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sample: BounceSample,
+    ) -> Option<ScatterRecord<'_>> {
+        let onb = fiber_frame(ray, hit_record);
+        let wo = onb.to_local(-*ray.direction);
+        let wi = self.sample(wo, fiber_offset(hit_record), ray.wavelength, sample);
+        let direction = Unit::new_normalize(onb.to_world(wi));
+        Some(ScatterRecord {
+            specular_ray: Some(Ray {
+                origin: hit_record.position,
+                direction,
+                time: ray.time,
+                wavelength: ray.wavelength,
+            }),
+            material_type: MaterialType::Specular,
+            pdf_ptr: PDF::ZeroPDF(ZeroPDF::new()),
+        })
+    }
+
+    /// Returns the sum of the lobe attenuations. The lobes are sampled proportionally to their attenuations, so the ratio of the scattering function and the sampling density reduces to this sum.
+    fn color(&self, ray: &Ray, wavelength: Wavelength, hit_record: &HitRecord) -> Float {
+        let onb = fiber_frame(ray, hit_record);
+        let wo = onb.to_local(-*ray.direction);
+        let geometry = self.geometry(wo, fiber_offset(hit_record));
+        self.attenuations(&geometry, wavelength).iter().sum()
+    }
+
+    /// Returns true. The lobe is picked based on the hero wavelength, and the attenuations of the other wavelengths do not match that choice.
+    fn is_wavelength_dependent(&self) -> bool {
+        true
+    }
+}
+
+/// Returns the local coordinate frame of the fiber at the hit point. The `u` axis runs along the fiber, `v` across the fiber width, and `w` points towards the viewer.
+fn fiber_frame(ray: &Ray, hit_record: &HitRecord) -> ONB {
+    let tangent = match hit_record.tangent {
+        Some(tangent) => tangent,
+        None => ONB::build_from_w(hit_record.normal).u,
+    };
+    let wo = -*ray.direction;
+    let towards = wo - wo.dot(&tangent) * *tangent;
+    let w: Direction = if towards.norm_squared() > 1e-12 {
+        Unit::new_normalize(towards)
+    } else {
+        // Looking along the fiber; any perpendicular direction will do
+        ONB::build_from_w(tangent).u
+    };
+    let v = Unit::new_normalize(w.cross(&tangent));
+    ONB { u: tangent, v, w }
+}
+
+/// Returns the offset of the hit point across the fiber width, in the range `-1..1`
+fn fiber_offset(hit_record: &HitRecord) -> Float {
+    (2.0 * hit_record.v - 1.0).clamp(-1.0, 1.0)
+}
+
+/// Returns the normalized attenuations, used as the probabilities of sampling each lobe
+fn lobe_probabilities(attenuations: [Float; P_MAX + 1]) -> [Float; P_MAX + 1] {
+    let sum: Float = attenuations.iter().sum();
+    if sum <= 0.0 {
+        return [1.0 / (P_MAX + 1) as Float; P_MAX + 1];
+    }
+    attenuations.map(|a| a / sum)
+}
+
+/// Longitudinal scattering function
+fn longitudinal(
+    cos_theta_i: Float,
+    cos_theta_o: Float,
+    sin_theta_i: Float,
+    sin_theta_o: Float,
+    v: Float,
+) -> Float {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1 {
+        // Evaluated in log space for numerical stability at low roughness
+        (log_bessel_i0(a) - b - 1.0 / v + core::f32::consts::LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        ((-b).exp() * bessel_i0(a)) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+/// Azimuthal scattering function of the given lobe
+fn azimuthal(phi: Float, p: usize, scale: Float, gamma_o: Float, gamma_t: Float) -> Float {
+    let mut dphi = phi - deflection(p, gamma_o, gamma_t);
+    while dphi > PI {
+        dphi -= 2.0 * PI;
+    }
+    while dphi < -PI {
+        dphi += 2.0 * PI;
+    }
+    trimmed_logistic(dphi, scale, -PI, PI)
+}
+
+/// Returns the net change in azimuth of the given lobe
+fn deflection(p: usize, gamma_o: Float, gamma_t: Float) -> Float {
+    let p = p as Float;
+    2.0 * p * gamma_t - 2.0 * gamma_o + p * PI
+}
+
+/// Modified Bessel function of the first kind, of order zero
+fn bessel_i0(x: Float) -> Float {
+    let x2 = x * x;
+    let mut value = 0.0;
+    let mut term = 1.0;
+    for i in 1..=10 {
+        value += term;
+        term *= x2 / (4.0 * (i * i) as Float);
+    }
+    value
+}
+
+/// Natural logarithm of the modified Bessel function of the first kind, of order zero
+fn log_bessel_i0(x: Float) -> Float {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        bessel_i0(x).ln()
+    }
+}
+
+fn logistic(x: Float, scale: Float) -> Float {
+    let x = x.abs();
+    let e = (-x / scale).exp();
+    e / (scale * (1.0 + e) * (1.0 + e))
+}
+
+fn logistic_cdf(x: Float, scale: Float) -> Float {
+    1.0 / (1.0 + (-x / scale).exp())
+}
+
+/// Logistic distribution normalized over the range `min..max`
+fn trimmed_logistic(x: Float, scale: Float, min: Float, max: Float) -> Float {
+    logistic(x, scale) / (logistic_cdf(max, scale) - logistic_cdf(min, scale))
+}
+
+/// Samples the logistic distribution normalized over the range `min..max`
+fn sample_trimmed_logistic(sample: Float, scale: Float, min: Float, max: Float) -> Float {
+    let range = logistic_cdf(max, scale) - logistic_cdf(min, scale);
+    let x = -scale * (1.0 / (sample * range + logistic_cdf(min, scale)) - 1.0).ln();
+    x.clamp(min, max)
+}
+
+/// Fresnel reflectance of unpolarized light at a dielectric interface
+fn fresnel_dielectric(cos_theta_i: Float, eta: Float) -> Float {
+    let cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let (cos_theta_i, eta) = if cos_theta_i < 0.0 {
+        (-cos_theta_i, 1.0 / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+    let sin_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0).sqrt() / eta;
+    if sin_theta_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin_theta_t * sin_theta_t).max(0.0).sqrt();
+    let parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::*;
+
+    /// Returns a random direction, uniformly distributed on the unit sphere
+    fn uniform_sphere(rng: &mut SmallRng) -> Vec3 {
+        let z = 1.0 - 2.0 * rng.random::<Float>();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.random::<Float>();
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    #[test]
+    fn white_furnace() {
+        // Without absorption, no energy is lost
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut hair = Hair::new(0.0, 0.0);
+        for beta in [0.2, 0.5, 0.9] {
+            hair.beta_m = beta;
+            hair.beta_n = beta;
+            let wo = uniform_sphere(&mut rng);
+            let count = 100_000;
+            let mut sum = 0.0;
+            for _ in 0..count {
+                let h = -1.0 + 2.0 * rng.random::<Float>();
+                let wi = uniform_sphere(&mut rng);
+                sum += hair.evaluate(wo, wi, h, 600);
+            }
+            // Monte Carlo estimate with uniform sphere sampling
+            let estimate = sum * 4.0 * PI / count as Float;
+            assert!((estimate - 1.0).abs() < 0.05, "beta {beta}: {estimate}");
+        }
+    }
+
+    #[test]
+    fn sampling_matches_pdf() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let hair = Hair::new(1.3, 0.2);
+        let wo = Unit::new_normalize(Vec3::new(0.3, 0.2, 0.9)).into_inner();
+        for h in [-0.8, 0.0, 0.5] {
+            for _ in 0..100 {
+                let wi = hair.sample(wo, h, 550, BounceSample::random(&mut rng));
+                assert!((wi.norm() - 1.0).abs() < 1e-3);
+                let f = hair.evaluate(wo, wi, h, 550);
+                let pdf = hair.pdf(wo, wi, h, 550);
+                assert!(pdf > 0.0);
+                // Lobes are sampled proportionally to their attenuations, so the weight is their sum
+                let geometry = hair.geometry(wo, h);
+                let expected: Float = hair.attenuations(&geometry, 550).iter().sum();
+                assert!((f / pdf - expected).abs() <= 1e-3 * expected.max(1.0));
+            }
+        }
+    }
+}
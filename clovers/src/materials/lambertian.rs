@@ -3,12 +3,12 @@
 use super::{MaterialTrait, MaterialType, ScatterRecord};
 use crate::{
     pdf::{CosinePDF, PDF},
+    random::BounceSample,
     ray::Ray,
     textures::{Texture, TextureTrait},
     wavelength::Wavelength,
     Float, HitRecord, PI,
 };
-use rand::prelude::SmallRng;
 
 #[derive(Clone, Debug, Default)]
 #[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
@@ -24,7 +24,7 @@ impl MaterialTrait for Lambertian {
         &self,
         _ray: &Ray,
         hit_record: &HitRecord,
-        _rng: &mut SmallRng,
+        _sample: BounceSample,
     ) -> Option<ScatterRecord<'_>> {
         Some(ScatterRecord {
             material_type: MaterialType::Diffuse,
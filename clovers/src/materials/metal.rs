@@ -3,14 +3,13 @@
 use super::{reflect, MaterialTrait, MaterialType, ScatterRecord};
 use crate::{
     pdf::{ZeroPDF, PDF},
-    random::random_unit_vector,
+    random::{sample_unit_vector, BounceSample},
     ray::Ray,
     textures::{Texture, TextureTrait},
     wavelength::Wavelength,
     Direction, Float, HitRecord,
 };
 use nalgebra::Unit;
-use rand::prelude::SmallRng;
 
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
@@ -28,10 +27,10 @@ impl MaterialTrait for Metal {
         &self,
         ray: &Ray,
         hit_record: &HitRecord,
-        rng: &mut SmallRng,
+        sample: BounceSample,
     ) -> Option<ScatterRecord<'_>> {
         let reflected: Direction = reflect(ray.direction, hit_record.normal);
-        let direction = *reflected + self.fuzz * *random_unit_vector(rng);
+        let direction = *reflected + self.fuzz * *sample_unit_vector(sample.scatter);
         let direction = Unit::new_normalize(direction);
         Some(ScatterRecord {
             specular_ray: Some(Ray {
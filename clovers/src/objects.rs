@@ -1,23 +1,42 @@
 //! Various literal objects and meta-object utilities for creating content in [Scenes](crate::scenes::Scene).
 
+use alloc::format;
+use alloc::string::String;
+
 use crate::{
+    bvh::BvhAlgorithm,
     hitable::{Hitable, HitableList},
     materials::{Material, MaterialInit, SharedMaterial},
     Box,
 };
 
 pub mod boxy; // avoid keyword
+pub mod cone;
 pub mod constant_medium;
+pub mod csg;
+pub mod curve;
+pub mod cylinder;
+pub mod disk;
 #[cfg(feature = "gl_tf")]
 pub mod gltf;
+#[cfg(feature = "heightfield")]
+pub mod heightfield;
+pub mod keyframed_transform;
 pub mod moving_sphere;
+#[cfg(feature = "obj")]
+pub mod obj;
 #[cfg(feature = "ply")]
 pub mod ply;
+#[cfg(feature = "ply")]
+pub mod point_cloud;
 pub mod quad;
 pub mod rotate;
+pub mod sdf;
 pub mod sphere;
 #[cfg(feature = "stl")]
 pub mod stl;
+pub mod torus;
+pub mod transform;
 pub mod translate;
 pub mod triangle;
 
@@ -25,17 +44,32 @@ pub mod triangle;
 pub use self::gltf::*;
 use alloc::vec::Vec;
 pub use boxy::*; // avoid keyword
+pub use cone::*;
 pub use constant_medium::*;
+pub use csg::*;
+pub use curve::*;
+pub use cylinder::*;
+pub use disk::*;
+#[cfg(feature = "heightfield")]
+pub use heightfield::*;
+pub use keyframed_transform::*;
 pub use moving_sphere::*;
+#[cfg(feature = "obj")]
+pub use obj::*;
 #[cfg(feature = "ply")]
 pub use ply::*;
+#[cfg(feature = "ply")]
+pub use point_cloud::*;
 pub use quad::*;
 pub use rotate::*;
+pub use sdf::*;
 pub use sphere::*;
 #[cfg(feature = "stl")]
 pub use stl::*;
+pub use torus::*;
 #[cfg(feature = "traces")]
 use tracing::warn;
+pub use transform::*;
 pub use translate::*;
 pub use triangle::*;
 
@@ -59,8 +93,25 @@ pub struct ObjectList {
 pub enum Object {
     /// Boxy object initializer
     Boxy(BoxyInit),
+    /// Cone object initializer
+    Cone(ConeInit),
     /// `ConstantMedium` object initializer
     ConstantMedium(ConstantMediumInit),
+    /// CSG object initializer
+    CSG(CSGInit),
+    /// Curves object initializer
+    Curves(CurvesInit),
+    /// Cylinder object initializer
+    Cylinder(CylinderInit),
+    /// Disk object initializer
+    Disk(DiskInit),
+    #[cfg(feature = "heightfield")]
+    /// Heightfield object initializer
+    Heightfield(HeightfieldInit),
+    /// `Instances` object initializer
+    Instances(InstancesInit),
+    /// `KeyframedTransform` object initializer
+    KeyframedTransform(KeyframedTransformInit),
     /// `MovingSphere` object initializer
     MovingSphere(MovingSphereInit),
     /// `ObjectList` object initializer
@@ -69,6 +120,8 @@ pub enum Object {
     Quad(QuadInit),
     /// `RotateY` object initializer
     RotateY(RotateInit),
+    /// SDF object initializer
+    SDF(SDFInit),
     /// Sphere object initializer
     Sphere(SphereInit),
     #[cfg(feature = "stl")]
@@ -77,43 +130,117 @@ pub enum Object {
     #[cfg(feature = "ply")]
     /// PLY object initializer
     PLY(PLYInit),
+    #[cfg(feature = "ply")]
+    /// `PointCloud` object initializer
+    PointCloud(PointCloudInit),
     #[cfg(feature = "gl_tf")]
     /// GLTF object initializer
     GLTF(GLTFInit),
+    #[cfg(feature = "obj")]
+    /// OBJ object initializer
+    OBJ(OBJInit),
+    /// Torus object initializer
+    Torus(TorusInit),
+    /// Transform object initializer
+    Transform(TransformInit),
     /// Translate object initializer
     Translate(TranslateInit),
     /// Triangle object initializer
     Triangle(TriangleInit),
 }
 
-#[must_use]
-/// Initializes an `Object` into a `Hitable`.
-pub fn object_to_hitable(obj: Object, materials: &[SharedMaterial]) -> Hitable<'_> {
+#[allow(clippy::too_many_lines)]
+/// Initializes an `Object` into a `Hitable`. Shared objects of transforms and instances get a BVH of their own, built with the given algorithm.
+///
+/// # Errors
+/// Returns an error if a transformation matrix of the object is not invertible, if the keyframes of a keyframed transform are invalid, if the control points of curves are invalid, or if a heightfield cannot be loaded.
+pub fn object_to_hitable(
+    obj: Object,
+    materials: &[SharedMaterial],
+    bvh_algorithm: BvhAlgorithm,
+) -> Result<Hitable<'_>, String> {
     // TODO: reduce repetition!
 
-    match obj {
+    let hitable = match obj {
         Object::Boxy(x) => {
             let material = initialize_material(x.material, materials);
             Hitable::Boxy(Boxy::new(x.corner_0, x.corner_1, material))
         }
+        Object::Cone(x) => {
+            let material = initialize_material(x.material, materials);
+            Hitable::Cone(Cone::new(
+                x.center, x.axis, x.radius, x.height, x.capped, material,
+            ))
+        }
         Object::ConstantMedium(x) => {
             let obj = *x.boundary;
-            let obj: Hitable = object_to_hitable(obj, materials);
+            let obj: Hitable = object_to_hitable(obj, materials, bvh_algorithm)?;
             Hitable::ConstantMedium(ConstantMedium::new(Box::new(obj), x.density, x.texture))
         }
+        Object::CSG(x) => {
+            let a: Hitable = object_to_hitable(*x.a, materials, bvh_algorithm)?;
+            let b: Hitable = object_to_hitable(*x.b, materials, bvh_algorithm)?;
+            Hitable::CSG(CSG::new(x.operation, Box::new(a), Box::new(b)))
+        }
+        Object::Curves(curves_init) => {
+            Hitable::HitableList(HitableList::new(initialize_curves(curves_init, materials)?))
+        }
+        Object::Cylinder(x) => {
+            let material = initialize_material(x.material, materials);
+            Hitable::Cylinder(Cylinder::new(
+                x.center, x.axis, x.radius, x.height, x.capped, material,
+            ))
+        }
+        Object::Disk(x) => {
+            let material = initialize_material(x.material, materials);
+            Hitable::Disk(Disk::new(
+                x.center,
+                x.normal,
+                x.radius,
+                x.inner_radius,
+                material,
+            ))
+        }
+        #[cfg(feature = "heightfield")]
+        Object::Heightfield(heightfield_init) => {
+            Hitable::Heightfield(initialize_heightfield(heightfield_init, materials)?)
+        }
+        Object::Instances(x) => {
+            let obj = *x.object;
+            let obj: Hitable = object_to_hitable(obj, materials, bvh_algorithm)?;
+            let shared = shared_hitable(obj, bvh_algorithm);
+            let instances: Vec<Hitable> = x
+                .transforms
+                .iter()
+                .enumerate()
+                .map(|(index, steps)| {
+                    Transform::new(shared.clone(), transform_matrix(steps))
+                        .map(Hitable::Transform)
+                        .ok_or_else(|| format!("Instance {index} has a non-invertible transform"))
+                })
+                .collect::<Result<_, _>>()?;
+            Hitable::HitableList(HitableList::new(instances))
+        }
+        Object::KeyframedTransform(x) => {
+            let obj = *x.object;
+            let obj: Hitable = object_to_hitable(obj, materials, bvh_algorithm)?;
+            Hitable::KeyframedTransform(KeyframedTransform::new(
+                shared_hitable(obj, bvh_algorithm),
+                &x.keyframes,
+            )?)
+        }
         Object::MovingSphere(x) => {
             let material = initialize_material(x.material, materials);
             Hitable::MovingSphere(MovingSphere::new(
-                // TODO: time
-                x.center_0, x.center_1, 0.0, 1.0, x.radius, material,
+                x.center_0, x.center_1, x.time_0, x.time_1, x.radius, material,
             ))
         }
         Object::ObjectList(x) => {
             let objects: Vec<Hitable> = x
                 .objects
                 .into_iter()
-                .map(|object| -> Hitable { object_to_hitable(object, materials) })
-                .collect();
+                .map(|object| object_to_hitable(object, materials, bvh_algorithm))
+                .collect::<Result<_, _>>()?;
             Hitable::HitableList(HitableList::new(objects))
         }
         Object::Quad(x) => {
@@ -122,9 +249,19 @@ pub fn object_to_hitable(obj: Object, materials: &[SharedMaterial]) -> Hitable<'
         }
         Object::RotateY(x) => {
             let obj = *x.object;
-            let obj: Hitable = object_to_hitable(obj, materials);
+            let obj: Hitable = object_to_hitable(obj, materials, bvh_algorithm)?;
             Hitable::RotateY(RotateY::new(Box::new(obj), x.angle))
         }
+        Object::SDF(x) => {
+            let material = initialize_material(x.material, materials);
+            Hitable::SDF(SDF::new(
+                x.shape,
+                x.corner_0,
+                x.corner_1,
+                x.step_scale,
+                material,
+            ))
+        }
         Object::Sphere(x) => {
             let material = initialize_material(x.material, materials);
             Hitable::Sphere(Sphere::new(x.center, x.radius, material))
@@ -139,24 +276,51 @@ pub fn object_to_hitable(obj: Object, materials: &[SharedMaterial]) -> Hitable<'
             let ply = initialize_ply(ply_init, materials);
             Hitable::HitableList(HitableList::new(ply.hitables))
         }
+        #[cfg(feature = "ply")]
+        Object::PointCloud(point_cloud_init) => {
+            Hitable::PointCloud(initialize_point_cloud(point_cloud_init, materials))
+        }
         #[cfg(feature = "gl_tf")]
-        Object::GLTF(x) => {
-            let gltf = GLTF::new(x);
-            Hitable::HitableList(HitableList::new(gltf.hitables))
+        Object::GLTF(x) => Hitable::HitableList(HitableList::new(x.into())),
+        #[cfg(feature = "obj")]
+        Object::OBJ(obj_init) => {
+            let obj = initialize_obj(obj_init, materials);
+            Hitable::HitableList(HitableList::new(obj.hitables))
+        }
+        Object::Torus(x) => {
+            let material = initialize_material(x.material, materials);
+            Hitable::Torus(Torus::new(
+                x.center,
+                x.axis,
+                x.major_radius,
+                x.minor_radius,
+                material,
+            ))
+        }
+        Object::Transform(x) => {
+            let obj = *x.object;
+            let obj: Hitable = object_to_hitable(obj, materials, bvh_algorithm)?;
+            let transform = Transform::new(
+                shared_hitable(obj, bvh_algorithm),
+                transform_matrix(&x.transform),
+            )
+            .ok_or("Transform matrix is not invertible")?;
+            Hitable::Transform(transform)
         }
         Object::Translate(x) => {
             let obj = *x.object;
-            let obj: Hitable = object_to_hitable(obj, materials);
+            let obj: Hitable = object_to_hitable(obj, materials, bvh_algorithm)?;
             Hitable::Translate(Translate::new(Box::new(obj), x.offset))
         }
         Object::Triangle(x) => {
             let material = initialize_material(x.material, materials);
             Hitable::Triangle(Triangle::new(x.q, x.u, x.v, material))
         }
-    }
+    };
+    Ok(hitable)
 }
 
-fn initialize_material<'scene>(
+pub(crate) fn initialize_material<'scene>(
     material_init: MaterialInit,
     materials: &'scene [SharedMaterial],
 ) -> &'scene Material {
@@ -186,3 +350,12 @@ fn initialize_material<'scene>(
     };
     material
 }
+
+/// Creates an empty temporary directory for the files of a test, unique to the given `name` and the test process. Used by the tests of the file-based objects.
+#[cfg(all(test, any(feature = "gl_tf", feature = "obj")))]
+pub(crate) fn test_directory(name: &str) -> std::path::PathBuf {
+    let directory = std::env::temp_dir().join(format!("clovers-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
@@ -121,6 +121,7 @@ impl HitableTrait for ConstantMedium<'_> {
             u,
             v,
             material: &self.phase_function,
+            tangent: None,
             front_face,
         })
     }
@@ -0,0 +1,262 @@
+//! Constructive solid geometry: combining two closed objects with boolean operations.
+
+use crate::{
+    aabb::AABB,
+    hitable::{Hitable, HitableTrait},
+    ray::Ray,
+    wavelength::Wavelength,
+    Box, Direction, Float, HitRecord, Position, EPSILON_SHADOW_ACNE,
+};
+use rand::rngs::SmallRng;
+
+use super::Object;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// Boolean operation used for combining the objects of a [CSG] object.
+pub enum CSGOperation {
+    /// Points inside either of the objects
+    Union,
+    /// Points inside both of the objects
+    Intersection,
+    /// Points inside the first object, but not inside the second object
+    Difference,
+}
+
+impl CSGOperation {
+    /// Returns whether a point is inside the combined object, given whether it is inside each of the two objects
+    #[must_use]
+    pub fn inside(&self, inside_a: bool, inside_b: bool) -> bool {
+        match self {
+            CSGOperation::Union => inside_a || inside_b,
+            CSGOperation::Intersection => inside_a && inside_b,
+            CSGOperation::Difference => inside_a && !inside_b,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// `CSGInit` structure describes the necessary data for constructing a [CSG] object.
+pub struct CSGInit {
+    /// Used for multiple importance sampling. Not supported for CSG objects: the scene loader ignores it with a warning.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub priority: bool,
+    /// The boolean operation to use
+    pub operation: CSGOperation,
+    /// The first encased [Object]. Must be a closed object.
+    pub a: Box<Object>,
+    /// The second encased [Object]. Must be a closed object.
+    pub b: Box<Object>,
+}
+
+#[derive(Debug, Clone)]
+/// Constructive solid geometry object. Combines two closed objects with a boolean [`CSGOperation`]. The surfaces of the result keep the materials of the object they originate from.
+///
+/// The hit is evaluated by walking along the [Ray] through the surfaces of both objects, keeping track of whether the ray is inside each of them. The first surface where the inside-state of the combined object changes is the hit.
+///
+/// CSG objects cannot be used as priority objects, as sampling random points on only the remaining parts of the surfaces is not supported.
+pub struct CSG<'scene> {
+    operation: CSGOperation,
+    a: Box<Hitable<'scene>>,
+    b: Box<Hitable<'scene>>,
+    aabb: AABB,
+}
+
+impl<'scene> CSG<'scene> {
+    /// Creates a new `CSG` object, combining the two given objects with the given operation.
+    ///
+    /// # Panics
+    /// This method may panic if the given objects do not have valid `AABB`s.
+    #[must_use]
+    pub fn new(operation: CSGOperation, a: Box<Hitable<'scene>>, b: Box<Hitable<'scene>>) -> Self {
+        let aabb_a = a.aabb().unwrap();
+        let aabb_b = b.aabb().unwrap();
+        let aabb = match operation {
+            CSGOperation::Union => AABB::combine(aabb_a, aabb_b),
+            // If the objects do not overlap, the result is empty: collapse the box into a single point
+            CSGOperation::Intersection => AABB::intersection(aabb_a, aabb_b)
+                .unwrap_or_else(|| AABB::new_from_coords(aabb_a.centroid(), aabb_a.centroid())),
+            CSGOperation::Difference => aabb_a.clone(),
+        };
+        CSG {
+            operation,
+            a,
+            b,
+            aabb,
+        }
+    }
+}
+
+impl HitableTrait for CSG<'_> {
+    /// Hit method for the [CSG] object. Returns the first [`HitRecord`] on the surface of the combined object within the given distance interval.
+    fn hit(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        // The children are queried without the upper limit: the inside-state at the start of the ray depends on surfaces beyond `distance_max` too
+        let mut hit_a = self.a.hit(ray, distance_min, Float::INFINITY, rng);
+        let mut hit_b = self.b.hit(ray, distance_min, Float::INFINITY, rng);
+        // For closed objects, the ray starts inside an object if the first surface it hits is exited from
+        let mut inside_a = hit_a.as_ref().is_some_and(|hit| !hit.front_face);
+        let mut inside_b = hit_b.as_ref().is_some_and(|hit| !hit.front_face);
+
+        loop {
+            // Advance to the nearest surface of either object
+            let from_a = match (&hit_a, &hit_b) {
+                (None, None) => return None,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (Some(a), Some(b)) => a.distance <= b.distance,
+            };
+            let hit = if from_a { &hit_a } else { &hit_b };
+            let hit = hit.as_ref()?;
+            if hit.distance > distance_max {
+                return None;
+            }
+            let inside_before = self.operation.inside(inside_a, inside_b);
+            if from_a {
+                inside_a = hit.front_face;
+            } else {
+                inside_b = hit.front_face;
+            }
+            let inside_after = self.operation.inside(inside_a, inside_b);
+
+            if inside_before != inside_after {
+                // The surface is a boundary of the combined object. The normal already faces against the ray, only the orientation needs to be updated.
+                let mut record = if from_a { hit_a? } else { hit_b? };
+                record.front_face = inside_after;
+                return Some(record);
+            }
+
+            // Not a boundary, continue past the surface
+            let distance = hit.distance + EPSILON_SHADOW_ACNE;
+            if from_a {
+                hit_a = self.a.hit(ray, distance, Float::INFINITY, rng);
+            } else {
+                hit_b = self.b.hit(ray, distance, Float::INFINITY, rng);
+            }
+        }
+    }
+
+    /// Returns the axis-aligned bounding box [AABB] of the combined object. This may be larger than the actual combined object.
+    fn aabb(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+
+    /// Returns zero, as random points on the surface are not supported
+    fn pdf_value(
+        &self,
+        _origin: Position,
+        _direction: Direction,
+        _wavelength: Wavelength,
+        _time: Float,
+        _rng: &mut SmallRng,
+    ) -> Float {
+        0.0
+    }
+
+    fn centroid(&self) -> Position {
+        self.aabb.centroid()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use nalgebra::Unit;
+    use rand::SeedableRng;
+
+    use crate::{materials::Material, objects::Sphere, Vec3};
+
+    use super::*;
+
+    fn spheres(operation: CSGOperation, material: &Material) -> CSG<'_> {
+        // Two overlapping unit spheres along the x axis
+        let a = Hitable::Sphere(Sphere::new(Position::new(-0.5, 0.0, 0.0), 1.0, material));
+        let b = Hitable::Sphere(Sphere::new(Position::new(0.5, 0.0, 0.0), 1.0, material));
+        CSG::new(operation, Box::new(a), Box::new(b))
+    }
+
+    fn ray_along_x(origin: Float) -> Ray {
+        Ray {
+            origin: Position::new(origin, 0.0, 0.0),
+            direction: Unit::new_normalize(Vec3::new(1.0, 0.0, 0.0)),
+            time: 0.0,
+            wavelength: 600,
+        }
+    }
+
+    #[test]
+    fn operations() {
+        let mut rng = SmallRng::from_os_rng();
+        let material = Box::default();
+        let ray = ray_along_x(-10.0);
+        let expected = [
+            (CSGOperation::Union, 8.5, 11.5),
+            (CSGOperation::Intersection, 9.5, 10.5),
+            (CSGOperation::Difference, 8.5, 9.5),
+        ];
+        for (operation, entry, exit) in expected {
+            let csg = spheres(operation, &material);
+            let hit = csg
+                .hit(&ray, 0.0, Float::INFINITY, &mut rng)
+                .expect("No entry hit");
+            assert!((hit.distance - entry).abs() <= 1e-4, "{operation:?} entry");
+            assert!(hit.front_face);
+            let hit = csg
+                .hit(&ray, hit.distance + 1e-3, Float::INFINITY, &mut rng)
+                .expect("No exit hit");
+            assert!((hit.distance - exit).abs() <= 1e-4, "{operation:?} exit");
+            assert!(!hit.front_face);
+            // The normal faces against the ray
+            assert!(hit.normal.dot(&ray.direction) < 0.0);
+        }
+    }
+
+    #[test]
+    fn ray_starting_inside() {
+        let mut rng = SmallRng::from_os_rng();
+        let material = Box::default();
+        // Starting inside the intersection of the spheres
+        let csg = spheres(CSGOperation::Intersection, &material);
+        let hit = csg
+            .hit(&ray_along_x(0.0), 0.0, Float::INFINITY, &mut rng)
+            .expect("No exit hit");
+        assert!((hit.distance - 0.5).abs() <= 1e-4);
+        assert!(!hit.front_face);
+        // Starting inside the hole of the difference
+        let csg = spheres(CSGOperation::Difference, &material);
+        assert!(csg
+            .hit(&ray_along_x(0.8), 0.0, Float::INFINITY, &mut rng)
+            .is_none());
+    }
+
+    #[test]
+    fn finite_distance_max() {
+        let mut rng = SmallRng::from_os_rng();
+        let material = Box::default();
+        // Starting inside the first sphere only: the exit of the first sphere lies beyond the limit, the intersection is entered within it
+        let ray = ray_along_x(-1.0);
+        let csg = spheres(CSGOperation::Intersection, &material);
+        let hit = csg
+            .hit(&ray, 0.0, 1.0, &mut rng)
+            .expect("No entry hit within the limit");
+        assert!((hit.distance - 0.5).abs() <= 1e-4);
+        assert!(hit.front_face);
+        // Starting inside the union: the surface of the second sphere is not a boundary
+        let csg = spheres(CSGOperation::Union, &material);
+        assert!(csg.hit(&ray, 0.0, 1.0, &mut rng).is_none());
+        // Starting inside the difference: the first boundary lies beyond the limit
+        let csg = spheres(CSGOperation::Difference, &material);
+        let hit = csg
+            .hit(&ray, 0.0, 1.0, &mut rng)
+            .expect("No exit hit within the limit");
+        assert!((hit.distance - 0.5).abs() <= 1e-4);
+        assert!(!hit.front_face);
+        assert!(csg.hit(&ray, 0.0, 0.4, &mut rng).is_none());
+    }
+}
@@ -0,0 +1,462 @@
+//! Curves: ribbons and cylinders along cubic Bézier splines. Useful for rendering hair, fur, grass and cables.
+//! Intersected with recursive subdivision, based on [Ray Tracing for Curves Primitive](https://jcgt.org/published/0002/01/01/) by Nakamaru and Ohta.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use nalgebra::Unit;
+use rand::rngs::SmallRng;
+
+use crate::{
+    aabb::AABB,
+    hitable::{Hitable, HitableTrait},
+    materials::{Material, MaterialInit, SharedMaterial},
+    onb::ONB,
+    ray::Ray,
+    wavelength::Wavelength,
+    Direction, Displacement, Float, HitRecord, Position, Vec2, Vec3,
+};
+
+use super::initialize_material;
+
+/// Maximum number of subdivisions used when intersecting a curve segment
+const MAX_DEPTH: u8 = 10;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// The shape of the cross-section of a [Curve].
+pub enum CurveMode {
+    /// A flat ribbon that always faces the ray
+    Ribbon,
+    /// A ribbon that always faces the ray, with its normal bent to shade it like a cylinder
+    #[default]
+    Cylinder,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// `CurveInit` structure describes a single strand of a [`CurvesInit`] object.
+pub struct CurveInit {
+    /// Control points of the cubic Bézier spline. Consecutive segments share their end points, so the number of points must be `3n + 1`.
+    pub points: Vec<Position>,
+    /// Width of the strand at its start and end. The width changes linearly along the strand.
+    pub widths: [Float; 2],
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// `CurvesInit` structure describes the necessary data for constructing a list of [Curves](Curve).
+pub struct CurvesInit {
+    /// Used for multiple importance sampling. Not supported for curves: the scene loader ignores it with a warning.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub priority: bool,
+    /// Shape of the cross-section of the curves
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub mode: CurveMode,
+    /// The strands of the object
+    pub curves: Vec<CurveInit>,
+    /// Material of the curves. The [Hair](crate::materials::Hair) material is designed for them.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub material: MaterialInit,
+}
+
+/// Initializes the strands of a [`CurvesInit`] into a list of [Curve] segments.
+///
+/// # Errors
+/// Returns an error if the number of control points of a strand is not `3n + 1`.
+pub fn initialize_curves(
+    curves_init: CurvesInit,
+    materials: &[SharedMaterial],
+) -> Result<Vec<Hitable<'_>>, String> {
+    let material = initialize_material(curves_init.material, materials);
+    let mut hitables = Vec::new();
+    for (index, curve) in curves_init.curves.into_iter().enumerate() {
+        let points = curve.points;
+        if points.len() < 4 || (points.len() - 1) % 3 != 0 {
+            return Err(format!(
+                "Curve {index} must have 3n + 1 control points, got {}",
+                points.len()
+            ));
+        }
+        let [width_start, width_end] = curve.widths;
+        let segments = (points.len() - 1) / 3;
+        for segment in 0..segments {
+            #[allow(clippy::cast_precision_loss)]
+            let (t0, t1) = (
+                segment as Float / segments as Float,
+                (segment + 1) as Float / segments as Float,
+            );
+            let control_points = [
+                points[3 * segment],
+                points[3 * segment + 1],
+                points[3 * segment + 2],
+                points[3 * segment + 3],
+            ];
+            let widths = [
+                width_start + t0 * (width_end - width_start),
+                width_start + t1 * (width_end - width_start),
+            ];
+            hitables.push(Hitable::Curve(Curve::new(
+                control_points,
+                widths,
+                curves_init.mode,
+                material,
+            )));
+        }
+    }
+    Ok(hitables)
+}
+
+#[derive(Debug, Clone)]
+/// A single cubic Bézier curve segment with a linearly varying width. The surface of the curve is a ribbon that always faces the incoming ray.
+///
+/// The `u` coordinate of the [`HitRecord`] runs along the curve, and `v` across its width. The tangent of the curve is provided for anisotropic materials.
+pub struct Curve<'scene> {
+    points: [Position; 4],
+    widths: [Float; 2],
+    mode: CurveMode,
+    max_depth: u8,
+    material: &'scene Material,
+    aabb: AABB,
+}
+
+impl<'scene> Curve<'scene> {
+    /// Creates a new `Curve` segment with the given control points, start and end widths, cross-section mode and material.
+    #[must_use]
+    pub fn new(
+        points: [Position; 4],
+        widths: [Float; 2],
+        mode: CurveMode,
+        material: &'scene Material,
+    ) -> Self {
+        let half_width = 0.5 * widths[0].max(widths[1]);
+        let extent = Vec3::new(half_width, half_width, half_width);
+        let min = points.iter().fold(points[0], |a, b| a.inf(b));
+        let max = points.iter().fold(points[0], |a, b| a.sup(b));
+        let mut aabb = AABB::new_from_coords(min - extent, max + extent);
+        aabb.pad();
+
+        // Subdivide until the segments are flat compared to the width of the curve
+        let flatness = (0..2)
+            .map(|i| (points[i] - 2.0 * points[i + 1] + points[i + 2]).norm())
+            .fold(0.0, Float::max);
+        let epsilon = 0.05 * widths[0].max(widths[1]);
+        let depth = (0.5 * (core::f32::consts::SQRT_2 * 6.0 * flatness / (8.0 * epsilon)).log2())
+            .round()
+            .clamp(0.0, Float::from(MAX_DEPTH));
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let max_depth = depth as u8;
+
+        Curve {
+            points,
+            widths,
+            mode,
+            max_depth,
+            material,
+            aabb,
+        }
+    }
+
+    /// Returns the width of the curve at the given parameter
+    fn width(&self, u: Float) -> Float {
+        self.widths[0] + u * (self.widths[1] - self.widths[0])
+    }
+
+    /// Recursively subdivides the given part of the curve, with control points in ray space, until the segments are flat enough to be intersected as lines. Returns the distance and curve parameter of the closest hit.
+    fn subdivide_hit(
+        &self,
+        points: &[Vec3; 4],
+        (u0, u1): (Float, Float),
+        depth: u8,
+        distance_min: Float,
+        closest: &mut Float,
+    ) -> Option<(Float, Float)> {
+        // The ray runs along the z axis, so it can only hit if the bounds of the segment contain it
+        let half_width = 0.5 * self.width(u0).max(self.width(u1));
+        let min = points.iter().fold(points[0], |a, b| a.inf(b));
+        let max = points.iter().fold(points[0], |a, b| a.sup(b));
+        if min.x - half_width > 0.0
+            || max.x + half_width < 0.0
+            || min.y - half_width > 0.0
+            || max.y + half_width < 0.0
+            || max.z + half_width < distance_min
+            || min.z - half_width > *closest
+        {
+            return None;
+        }
+
+        if depth > 0 {
+            let (first, second) = split(points);
+            let middle = 0.5 * (u0 + u1);
+            let first = self.subdivide_hit(&first, (u0, middle), depth - 1, distance_min, closest);
+            let second =
+                self.subdivide_hit(&second, (middle, u1), depth - 1, distance_min, closest);
+            // The closest distance is updated on every hit, so a hit on the second half is always closer
+            return second.or(first);
+        }
+
+        // Reject hits beyond the perpendiculars of the tangents at the ends of the segment
+        let start =
+            (points[1].y - points[0].y) * -points[0].y + points[0].x * (points[0].x - points[1].x);
+        let end =
+            (points[2].y - points[3].y) * -points[3].y + points[3].x * (points[3].x - points[2].x);
+        if start < 0.0 || end < 0.0 {
+            return None;
+        }
+
+        // Closest point to the ray on the line through the segment end points
+        let segment = Vec2::new(points[3].x - points[0].x, points[3].y - points[0].y);
+        let length_squared = segment.norm_squared();
+        if length_squared == 0.0 {
+            return None;
+        }
+        let w = Vec2::new(-points[0].x, -points[0].y).dot(&segment) / length_squared;
+        let w = w.clamp(0.0, 1.0);
+        let u = u0 + w * (u1 - u0);
+        let half_width = 0.5 * self.width(u);
+        let (point, _) = bezier(points, w);
+        if point.x * point.x + point.y * point.y > half_width * half_width {
+            return None;
+        }
+        if point.z <= distance_min || point.z >= *closest {
+            return None;
+        }
+        // Rays starting on the curve itself, e.g. scattered from it, cannot hit it again
+        if point.norm_squared() <= (1.1 * half_width) * (1.1 * half_width) {
+            return None;
+        }
+
+        *closest = point.z;
+        Some((point.z, u))
+    }
+}
+
+impl HitableTrait for Curve<'_> {
+    /// Hit method for the [Curve] object. Returns a [`HitRecord`] if the given [Ray] intersects with the curve at the given distance interval.
+    fn hit(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        _rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        // Transform the control points into a coordinate system where the ray starts at the origin and runs along the z axis
+        let onb = ONB::build_from_w(ray.direction);
+        let points = self.points.map(|point| onb.to_local(point - ray.origin));
+        let mut closest = distance_max;
+        let (distance, u) = self.subdivide_hit(
+            &points,
+            (0.0, 1.0),
+            self.max_depth,
+            distance_min,
+            &mut closest,
+        )?;
+
+        let position = ray.evaluate(distance);
+        let (center, derivative) = bezier(&self.points, u);
+        let tangent = Unit::new_normalize(derivative);
+        // The ribbon faces the ray, perpendicular to the tangent
+        let towards = -*ray.direction + ray.direction.dot(&tangent) * *tangent;
+        let towards: Direction = if towards.norm_squared() > 1e-12 {
+            Unit::new_normalize(towards)
+        } else {
+            ONB::build_from_w(tangent).u
+        };
+        let across = towards.cross(&tangent);
+        // Offset of the hit point across the width, in the range -1..1
+        let h = ((position - center).dot(&across) / (0.5 * self.width(u))).clamp(-1.0, 1.0);
+        let normal = match self.mode {
+            CurveMode::Ribbon => towards,
+            CurveMode::Cylinder => {
+                Unit::new_normalize(h * across + (1.0 - h * h).sqrt() * *towards)
+            }
+        };
+
+        Some(HitRecord {
+            distance,
+            position,
+            normal,
+            u,
+            v: 0.5 * (h + 1.0),
+            material: self.material,
+            tangent: Some(tangent),
+            front_face: true,
+        })
+    }
+
+    /// Returns the axis-aligned bounding box [AABB] of the curve.
+    fn aabb(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+
+    /// Returns zero. Curves cannot be sampled, and should not be used as priority objects.
+    fn pdf_value(
+        &self,
+        _origin: Position,
+        _direction: Direction,
+        _wavelength: Wavelength,
+        _time: Float,
+        _rng: &mut SmallRng,
+    ) -> Float {
+        0.0
+    }
+
+    fn random(&self, _origin: Position, _sample: Vec2, _time: Float) -> Displacement {
+        unimplemented!("Curves cannot be sampled, and should not be used as priority objects")
+    }
+
+    fn centroid(&self) -> Position {
+        self.aabb.centroid()
+    }
+}
+
+/// Evaluates the cubic Bézier curve with the given control points. Returns the point and the derivative at the given parameter.
+#[must_use]
+fn bezier(points: &[Vec3; 4], t: Float) -> (Vec3, Vec3) {
+    let lerp = |a: Vec3, b: Vec3| a + t * (b - a);
+    let first = [
+        lerp(points[0], points[1]),
+        lerp(points[1], points[2]),
+        lerp(points[2], points[3]),
+    ];
+    let second = [lerp(first[0], first[1]), lerp(first[1], first[2])];
+    let derivative = second[1] - second[0];
+    // Degenerate control points can result in a zero derivative at the ends
+    let derivative = if derivative.norm_squared() > 0.0 {
+        3.0 * derivative
+    } else {
+        points[3] - points[0]
+    };
+    (lerp(second[0], second[1]), derivative)
+}
+
+/// Splits the cubic Bézier curve with the given control points into two halves
+#[must_use]
+fn split(points: &[Vec3; 4]) -> ([Vec3; 4], [Vec3; 4]) {
+    let [p0, p1, p2, p3] = *points;
+    let middle = (p0 + 3.0 * p1 + 3.0 * p2 + p3) / 8.0;
+    (
+        [p0, (p0 + p1) / 2.0, (p0 + 2.0 * p1 + p2) / 4.0, middle],
+        [middle, (p1 + 2.0 * p2 + p3) / 4.0, (p2 + p3) / 2.0, p3],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn ray(origin: Position, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction: Unit::new_normalize(direction),
+            time: 0.0,
+            wavelength: 600,
+        }
+    }
+
+    /// A curve bending from the origin to `x = 2` along the x axis, with the middle raised up along y
+    fn arch(mode: CurveMode, material: &Material) -> Curve<'_> {
+        let points = [
+            Position::new(0.0, 0.0, 0.0),
+            Position::new(0.0, 1.0, 0.0),
+            Position::new(2.0, 1.0, 0.0),
+            Position::new(2.0, 0.0, 0.0),
+        ];
+        Curve::new(points, [0.2, 0.2], mode, material)
+    }
+
+    #[test]
+    fn hit_top_of_arch() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let material = Material::default();
+        // The top of the arch is at u = 0.5, height 0.75
+        let curve = arch(CurveMode::Cylinder, &material);
+        let hit = curve
+            .hit(
+                &ray(Position::new(1.0, 0.75, -5.0), Vec3::new(0.0, 0.0, 1.0)),
+                0.0,
+                Float::INFINITY,
+                &mut rng,
+            )
+            .expect("No hit on the center line");
+        assert!((hit.distance - 5.0).abs() <= 1e-3);
+        assert!((hit.u - 0.5).abs() <= 1e-2);
+        assert!((hit.v - 0.5).abs() <= 1e-2);
+        assert!((hit.tangent.unwrap().x - 1.0).abs() <= 1e-3);
+        assert!((hit.normal.z + 1.0).abs() <= 1e-3);
+
+        // Near the edge of the cylinder, the normal is bent sideways
+        let hit = curve
+            .hit(
+                &ray(Position::new(1.0, 0.84, -5.0), Vec3::new(0.0, 0.0, 1.0)),
+                0.0,
+                Float::INFINITY,
+                &mut rng,
+            )
+            .expect("No hit near the edge");
+        assert!((hit.v - 0.5).abs() > 0.3);
+        assert!(hit.normal.y > 0.5);
+
+        // Outside of the width
+        assert!(curve
+            .hit(
+                &ray(Position::new(1.0, 0.9, -5.0), Vec3::new(0.0, 0.0, 1.0)),
+                0.0,
+                Float::INFINITY,
+                &mut rng,
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn no_self_intersection() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let material = Material::default();
+        let curve = arch(CurveMode::Ribbon, &material);
+        let direction = Vec3::new(0.0, 0.0, 1.0);
+        let hit = curve
+            .hit(
+                &ray(Position::new(1.0, 0.75, -5.0), direction),
+                0.0,
+                Float::INFINITY,
+                &mut rng,
+            )
+            .unwrap();
+        // A ray scattered from the hit point does not hit the curve again
+        for scattered in [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.3, -1.0, 0.2),
+            Vec3::new(-1.0, 0.0, 0.5),
+        ] {
+            assert!(curve
+                .hit(
+                    &ray(hit.position, scattered),
+                    0.001,
+                    Float::INFINITY,
+                    &mut rng
+                )
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn initialize_checks_point_count() {
+        let materials = [SharedMaterial::default()];
+        let curves_init = |count| CurvesInit {
+            priority: false,
+            mode: CurveMode::Ribbon,
+            curves: vec![CurveInit {
+                points: vec![Position::new(0.0, 0.0, 0.0); count],
+                widths: [0.1, 0.1],
+            }],
+            material: MaterialInit::default(),
+        };
+        let segments = initialize_curves(curves_init(7), &materials).expect("Valid curve rejected");
+        assert_eq!(segments.len(), 2);
+        assert!(initialize_curves(curves_init(3), &materials).is_err());
+        assert!(initialize_curves(curves_init(5), &materials).is_err());
+    }
+}
@@ -0,0 +1,321 @@
+//! A cylinder object, either open or capped.
+
+use crate::{
+    aabb::AABB,
+    hitable::{uniform_area_pdf, HitableTrait},
+    materials::{Material, MaterialInit},
+    objects::disk::{disk_extent, hit_local_disk},
+    onb::ONB,
+    random::remap_choice,
+    ray::Ray,
+    wavelength::Wavelength,
+    Direction, Displacement, Float, HitRecord, Position, Vec2, Vec3, PI,
+};
+use nalgebra::Unit;
+use rand::rngs::SmallRng;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// `CylinderInit` structure describes the necessary data for constructing a [Cylinder].
+pub struct CylinderInit {
+    /// Used for multiple importance sampling
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub priority: bool,
+    /// Center of the bottom of the cylinder.
+    pub center: Position,
+    /// Direction of the axis of the cylinder, from the bottom towards the top. Does not need to be normalized.
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_axis"))]
+    pub axis: Vec3,
+    /// Radius of the cylinder.
+    pub radius: Float,
+    /// Height of the cylinder along its axis.
+    pub height: Float,
+    /// Whether the ends of the cylinder are closed with disks.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub capped: bool,
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    /// Material of the cylinder.
+    pub material: MaterialInit,
+}
+
+#[cfg(feature = "serde-derive")]
+fn default_axis() -> Vec3 {
+    Vec3::new(0.0, 1.0, 0.0)
+}
+
+#[derive(Debug, Clone)]
+/// A cylinder object. The surface coordinates go around the axis in `u` and along the axis in `v`. On the caps, `v` is the relative distance from the axis.
+pub struct Cylinder<'scene> {
+    center: Position,
+    onb: ONB,
+    radius: Float,
+    height: Float,
+    capped: bool,
+    area: Float,
+    material: &'scene Material,
+    aabb: AABB,
+}
+
+impl<'scene> Cylinder<'scene> {
+    /// Creates a new `Cylinder` object with the given bottom center, axis, radius, height and material.
+    #[must_use]
+    pub fn new(
+        center: Position,
+        axis: Vec3,
+        radius: Float,
+        height: Float,
+        capped: bool,
+        material: &'scene Material,
+    ) -> Self {
+        let onb = ONB::build_from_w(Unit::new_normalize(axis));
+        let top = center + height * *onb.w;
+        let extent = disk_extent(&onb.w, radius);
+        let mut aabb = AABB::combine(
+            &AABB::new_from_coords(center - extent, center + extent),
+            &AABB::new_from_coords(top - extent, top + extent),
+        );
+        aabb.pad();
+        let mut area = 2.0 * PI * radius * height;
+        if capped {
+            area += 2.0 * PI * radius * radius;
+        }
+
+        Cylinder {
+            center,
+            onb,
+            radius,
+            height,
+            capped,
+            area,
+            material,
+            aabb,
+        }
+    }
+}
+
+impl HitableTrait for Cylinder<'_> {
+    /// Hit method for the [Cylinder] object. Returns a [`HitRecord`] if the given [Ray] intersects with the cylinder at the given distance interval.
+    fn hit(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        _rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        let origin = self.onb.to_local(ray.origin - self.center);
+        let direction = self.onb.to_local(*ray.direction);
+        let in_range = |distance: Float| distance > distance_min && distance < distance_max;
+        // Nearest hit so far: distance, outward normal in local coordinates, and the surface coordinates
+        let mut nearest: Option<(Float, Vec3, Float, Float)> = None;
+
+        // Side of the cylinder
+        let a = direction.x * direction.x + direction.y * direction.y;
+        let half_b = origin.x * direction.x + origin.y * direction.y;
+        let c = origin.x * origin.x + origin.y * origin.y - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if a > 0.0 && discriminant > 0.0 {
+            let root = discriminant.sqrt();
+            for distance in [(-half_b - root) / a, (-half_b + root) / a] {
+                let local = origin + distance * direction;
+                if in_range(distance) && (0.0..=self.height).contains(&local.z) {
+                    let normal = Vec3::new(local.x, local.y, 0.0) / self.radius;
+                    let u = local.y.atan2(local.x) / (2.0 * PI) + 0.5;
+                    let v = local.z / self.height;
+                    nearest = Some((distance, normal, u, v));
+                    break;
+                }
+            }
+        }
+
+        // Caps of the cylinder
+        if self.capped {
+            for (z, normal) in [(0.0, -1.0), (self.height, 1.0)] {
+                if let Some((distance, radius)) = hit_local_disk(origin, direction, z, self.radius)
+                {
+                    if in_range(distance) && nearest.is_none_or(|(nearest, ..)| distance < nearest)
+                    {
+                        let local = origin + distance * direction;
+                        let u = local.y.atan2(local.x) / (2.0 * PI) + 0.5;
+                        let v = radius / self.radius;
+                        nearest = Some((distance, Vec3::new(0.0, 0.0, normal), u, v));
+                    }
+                }
+            }
+        }
+
+        let (distance, normal, u, v) = nearest?;
+        let outward_normal = Unit::new_normalize(self.onb.to_world(normal));
+        let mut record = HitRecord {
+            distance,
+            position: ray.evaluate(distance),
+            normal: outward_normal,
+            u,
+            v,
+            material: self.material,
+            tangent: None,
+            front_face: false, // TODO: fix having to declare it before calling face_normal
+        };
+        record.set_face_normal(ray, outward_normal);
+        Some(record)
+    }
+
+    /// Returns the axis-aligned bounding box [AABB] for the cylinder.
+    fn aabb(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+
+    /// Returns the probability density of sampling the given direction with the `random` method of the cylinder.
+    fn pdf_value(
+        &self,
+        origin: Position,
+        direction: Direction,
+        wavelength: Wavelength,
+        time: Float,
+        rng: &mut SmallRng,
+    ) -> Float {
+        let ray = Ray {
+            origin,
+            direction,
+            time,
+            wavelength,
+        };
+        uniform_area_pdf(self, &ray, self.area, rng)
+    }
+
+    /// Returns a random point on the surface of the cylinder, uniformly distributed by area
+    fn random(&self, origin: Position, sample: Vec2, _time: Float) -> Displacement {
+        let side_area = 2.0 * PI * self.radius * self.height;
+        let phi = 2.0 * PI * sample.x;
+        let (side, v) = remap_choice(sample.y, side_area / self.area);
+        let local = if side {
+            Vec3::new(
+                self.radius * phi.cos(),
+                self.radius * phi.sin(),
+                self.height * v,
+            )
+        } else {
+            let (top, v) = remap_choice(v, 0.5);
+            let radius = self.radius * v.sqrt();
+            let z = if top { self.height } else { 0.0 };
+            Vec3::new(radius * phi.cos(), radius * phi.sin(), z)
+        };
+        let point = self.center + self.onb.to_world(local);
+        point - origin
+    }
+
+    fn centroid(&self) -> Position {
+        self.center + 0.5 * self.height * *self.onb.w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use rand::{Rng, SeedableRng};
+
+    use crate::random::random_unit_vector;
+
+    use super::*;
+
+    fn ray(origin: Position, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction: Unit::new_normalize(direction),
+            time: 0.0,
+            wavelength: 600,
+        }
+    }
+
+    #[test]
+    fn hit_side_and_caps() {
+        let mut rng = SmallRng::from_os_rng();
+        let material = Box::default();
+        let new = |capped| {
+            Cylinder::new(
+                Position::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                1.0,
+                2.0,
+                capped,
+                &material,
+            )
+        };
+        let (open, capped) = (new(false), new(true));
+
+        // From the outside, hitting the side
+        let side = ray(Position::new(-10.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let record = open
+            .hit(&side, 0.0, Float::INFINITY, &mut rng)
+            .expect("No hit on the cylinder");
+        assert!((record.distance - 9.0).abs() <= 1e-4);
+        assert!((*record.normal - Vec3::new(-1.0, 0.0, 0.0)).norm() <= 1e-4);
+        assert!(record.front_face);
+
+        // From the inside, the normal is flipped to face the ray
+        let inside = ray(Position::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let record = open
+            .hit(&inside, 0.0, Float::INFINITY, &mut rng)
+            .expect("No hit on the cylinder");
+        assert!((record.distance - 1.0).abs() <= 1e-4);
+        assert!((*record.normal - Vec3::new(-1.0, 0.0, 0.0)).norm() <= 1e-4);
+        assert!(!record.front_face);
+
+        // Along the axis, through the open ends or hitting the top cap
+        let axis = ray(Position::new(0.0, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(open.hit(&axis, 0.0, Float::INFINITY, &mut rng).is_none());
+        let record = capped
+            .hit(&axis, 0.0, Float::INFINITY, &mut rng)
+            .expect("No hit on the cylinder");
+        assert!((record.distance - 8.0).abs() <= 1e-4);
+        assert!((*record.normal - Vec3::new(0.0, 1.0, 0.0)).norm() <= 1e-4);
+        assert!(record.front_face);
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn random_matches_pdf_value() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let material = Box::default();
+        let cylinder = Cylinder::new(
+            Position::new(1.0, -1.0, 2.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            1.0,
+            2.0,
+            true,
+            &material,
+        );
+        // From the inside of the closed cylinder, every direction hits the surface exactly once
+        let origin = cylinder.centroid();
+        let pdf_value =
+            |direction, rng: &mut SmallRng| cylinder.pdf_value(origin, direction, 600, 0.0, rng);
+        let count = 20_000;
+
+        // The density integrates to one over all directions
+        let integral = (0..count)
+            .map(|_| pdf_value(random_unit_vector(&mut rng), &mut rng))
+            .sum::<Float>()
+            * 4.0
+            * PI
+            / count as Float;
+        assert!((integral - 1.0).abs() <= 0.02, "{integral}");
+
+        // The random points are on the surface, and weighting them with the density covers all directions
+        let solid_angle = (0..count)
+            .map(|_| {
+                let sample = Vec2::new(rng.random(), rng.random());
+                let displacement = cylinder.random(origin, sample, 0.0);
+                let direction = Unit::new_normalize(displacement);
+                let record = cylinder
+                    .hit(&ray(origin, *direction), 0.0, Float::INFINITY, &mut rng)
+                    .expect("No hit on the cylinder");
+                assert!((record.distance - displacement.norm()).abs() <= 1e-3);
+                1.0 / pdf_value(direction, &mut rng)
+            })
+            .sum::<Float>()
+            / count as Float;
+        assert!(
+            (solid_angle / (4.0 * PI) - 1.0).abs() <= 0.02,
+            "{solid_angle}"
+        );
+    }
+}
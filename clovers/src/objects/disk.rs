@@ -0,0 +1,287 @@
+//! A disk object, optionally with a hole in the middle, i.e. an annulus.
+
+use crate::{
+    aabb::AABB,
+    hitable::{get_orientation, uniform_area_pdf, HitableTrait},
+    materials::{Material, MaterialInit},
+    onb::ONB,
+    ray::Ray,
+    wavelength::Wavelength,
+    Direction, Displacement, Float, HitRecord, Position, Vec2, Vec3, EPSILON_RECT_THICKNESS, PI,
+};
+use nalgebra::Unit;
+use rand::rngs::SmallRng;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// `DiskInit` structure describes the necessary data for constructing a [Disk].
+pub struct DiskInit {
+    /// Used for multiple importance sampling
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub priority: bool,
+    /// Center of the disk.
+    pub center: Position,
+    /// Normal vector of the disk. Does not need to be normalized.
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_normal"))]
+    pub normal: Vec3,
+    /// Outer radius of the disk.
+    pub radius: Float,
+    /// Inner radius of the disk. If larger than zero, the disk is an annulus with a hole in the middle.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub inner_radius: Float,
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    /// Material of the disk.
+    pub material: MaterialInit,
+}
+
+#[cfg(feature = "serde-derive")]
+fn default_normal() -> Vec3 {
+    Vec3::new(0.0, 1.0, 0.0)
+}
+
+#[derive(Debug, Clone)]
+/// A flat disk object. With a non-zero inner radius, the disk is an annulus.
+pub struct Disk<'scene> {
+    center: Position,
+    onb: ONB,
+    radius: Float,
+    inner_radius: Float,
+    area: Float,
+    material: &'scene Material,
+    aabb: AABB,
+}
+
+impl<'scene> Disk<'scene> {
+    /// Creates a new `Disk` object with the given center, normal, outer and inner radius, and material.
+    #[must_use]
+    pub fn new(
+        center: Position,
+        normal: Vec3,
+        radius: Float,
+        inner_radius: Float,
+        material: &'scene Material,
+    ) -> Self {
+        let onb = ONB::build_from_w(Unit::new_normalize(normal));
+        let extent = disk_extent(&onb.w, radius);
+        let mut aabb = AABB::new_from_coords(center - extent, center + extent);
+        aabb.pad();
+        let area = PI * (radius * radius - inner_radius * inner_radius);
+
+        Disk {
+            center,
+            onb,
+            radius,
+            inner_radius,
+            area,
+            material,
+            aabb,
+        }
+    }
+}
+
+impl HitableTrait for Disk<'_> {
+    /// Hit method for the [Disk] object. Returns a [`HitRecord`] if the given [Ray] intersects with the disk at the given distance interval.
+    fn hit(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        _rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        let origin = self.onb.to_local(ray.origin - self.center);
+        let direction = self.onb.to_local(*ray.direction);
+        let (distance, radius) = hit_local_disk(origin, direction, 0.0, self.radius)?;
+        if distance <= distance_min || distance >= distance_max || radius < self.inner_radius {
+            return None;
+        }
+
+        let position = ray.evaluate(distance);
+        let (front_face, normal) = get_orientation(ray, self.onb.w);
+        let (x, y) = (
+            origin.x + distance * direction.x,
+            origin.y + distance * direction.y,
+        );
+        let u = y.atan2(x) / (2.0 * PI) + 0.5;
+        let v = (self.radius - radius) / (self.radius - self.inner_radius);
+
+        Some(HitRecord {
+            distance,
+            position,
+            normal,
+            u,
+            v,
+            material: self.material,
+            tangent: None,
+            front_face,
+        })
+    }
+
+    /// Returns the axis-aligned bounding box [AABB] for the disk.
+    fn aabb(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+
+    /// Returns the probability density of sampling the given direction with the `random` method of the disk.
+    fn pdf_value(
+        &self,
+        origin: Position,
+        direction: Direction,
+        wavelength: Wavelength,
+        time: Float,
+        rng: &mut SmallRng,
+    ) -> Float {
+        let ray = Ray {
+            origin,
+            direction,
+            time,
+            wavelength,
+        };
+        uniform_area_pdf(self, &ray, self.area, rng)
+    }
+
+    /// Returns a random point on the surface of the disk, uniformly distributed by area
+    fn random(&self, origin: Position, sample: Vec2, _time: Float) -> Displacement {
+        let inner_squared = self.inner_radius * self.inner_radius;
+        let radius =
+            (inner_squared + sample.x * (self.radius * self.radius - inner_squared)).sqrt();
+        let phi = 2.0 * PI * sample.y;
+        let local = Vec3::new(radius * phi.cos(), radius * phi.sin(), 0.0);
+        let point = self.center + self.onb.to_world(local);
+        point - origin
+    }
+
+    fn centroid(&self) -> Position {
+        self.center
+    }
+}
+
+/// Returns the half-extents of the axis-aligned bounding box of a circle with the given normal and radius
+#[must_use]
+pub(crate) fn disk_extent(normal: &Direction, radius: Float) -> Vec3 {
+    normal.map(|n| radius * (1.0 - n * n).max(0.0).sqrt())
+}
+
+/// Intersects a ray given in local coordinates with a disk lying on the local `xy` plane at the given height `z`. Returns the distance to the intersection and the distance of the intersection from the axis.
+#[must_use]
+pub(crate) fn hit_local_disk(
+    origin: Vec3,
+    direction: Vec3,
+    z: Float,
+    radius: Float,
+) -> Option<(Float, Float)> {
+    // No hit if the ray is parallel to the plane
+    if direction.z.abs() < EPSILON_RECT_THICKNESS {
+        return None;
+    }
+    let distance = (z - origin.z) / direction.z;
+    let x = origin.x + distance * direction.x;
+    let y = origin.y + distance * direction.y;
+    let distance_from_axis = (x * x + y * y).sqrt();
+    if distance_from_axis > radius {
+        return None;
+    }
+    Some((distance, distance_from_axis))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use rand::{Rng, SeedableRng};
+
+    use crate::random::random_unit_vector;
+
+    use super::*;
+
+    fn ray(origin: Position, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction: Unit::new_normalize(direction),
+            time: 0.0,
+            wavelength: 600,
+        }
+    }
+
+    #[test]
+    fn hit_annulus() {
+        let mut rng = SmallRng::from_os_rng();
+        let material = Box::default();
+        let disk = Disk::new(
+            Position::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            2.0,
+            1.0,
+            &material,
+        );
+
+        // From above, the front side
+        let above = ray(Position::new(1.5, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let record = disk
+            .hit(&above, 0.0, Float::INFINITY, &mut rng)
+            .expect("No hit on the disk");
+        assert!((record.distance - 10.0).abs() <= 1e-4);
+        assert!((*record.normal - Vec3::new(0.0, 1.0, 0.0)).norm() <= 1e-4);
+        assert!(record.front_face);
+
+        // From below, the normal is flipped to face the ray
+        let below = ray(Position::new(1.5, -10.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let record = disk
+            .hit(&below, 0.0, Float::INFINITY, &mut rng)
+            .expect("No hit on the disk");
+        assert!((record.distance - 10.0).abs() <= 1e-4);
+        assert!((*record.normal - Vec3::new(0.0, -1.0, 0.0)).norm() <= 1e-4);
+        assert!(!record.front_face);
+
+        // Through the hole, and past the outer edge
+        let hole = ray(Position::new(0.5, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(disk.hit(&hole, 0.0, Float::INFINITY, &mut rng).is_none());
+        let outside = ray(Position::new(2.5, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(disk.hit(&outside, 0.0, Float::INFINITY, &mut rng).is_none());
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn random_matches_pdf_value() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let material = Box::default();
+        let disk = Disk::new(
+            Position::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            2.0,
+            1.0,
+            &material,
+        );
+        let origin = Position::new(0.0, 1.0, 0.0);
+        let pdf_value =
+            |direction, rng: &mut SmallRng| disk.pdf_value(origin, direction, 600, 0.0, rng);
+        let count = 20_000;
+
+        // The density integrates to one over all directions
+        let integral = (0..count)
+            .map(|_| pdf_value(random_unit_vector(&mut rng), &mut rng))
+            .sum::<Float>()
+            * 4.0
+            * PI
+            / count as Float;
+        assert!((integral - 1.0).abs() <= 0.03, "{integral}");
+
+        // The random points are on the surface, and weighting them with the density gives the solid angle of the annulus seen from its axis
+        let solid_angle = (0..count)
+            .map(|_| {
+                let sample = Vec2::new(rng.random(), rng.random());
+                let displacement = disk.random(origin, sample, 0.0);
+                let direction = Unit::new_normalize(displacement);
+                let record = disk
+                    .hit(&ray(origin, *direction), 0.0, Float::INFINITY, &mut rng)
+                    .expect("No hit on the disk");
+                assert!((record.distance - displacement.norm()).abs() <= 1e-3);
+                1.0 / pdf_value(direction, &mut rng)
+            })
+            .sum::<Float>()
+            / count as Float;
+        let expected = 2.0 * PI * (1.0 / Float::sqrt(2.0) - 1.0 / Float::sqrt(5.0));
+        assert!(
+            (solid_angle / expected - 1.0).abs() <= 0.02,
+            "{solid_angle}"
+        );
+    }
+}
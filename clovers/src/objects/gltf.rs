@@ -3,8 +3,14 @@
 use alloc::string::String;
 use alloc::vec::Vec;
 #[cfg(feature = "gl_tf")]
-use gltf::{image::Data, Mesh, Node};
-use nalgebra::Unit;
+use gltf::{
+    camera::Projection,
+    image::Data,
+    khr_lights_punctual::{Kind as LightKind, Light},
+    Camera, Mesh, Node,
+};
+use nalgebra::{Matrix3, Matrix4, Point3, Unit};
+use palette::LinSrgb;
 use rand::rngs::SmallRng;
 #[cfg(feature = "traces")]
 use tracing::debug;
@@ -12,31 +18,76 @@ use tracing::debug;
 use crate::{
     aabb::AABB,
     bvh::build::utils::vec_bounding_box,
+    camera::{
+        ApertureInit, CameraInit, LensInit, Projection as CameraProjection, ShutterInit,
+        SimpleLensInit,
+    },
+    colorinit::{ColorInit, TypedColorInit},
     hitable::{get_orientation, Hitable, HitableTrait},
     interval::Interval,
-    materials::gltf::GLTFMaterial,
+    materials::{gltf::GLTFMaterial, ConeLight, DiffuseLight, Kind, Material},
+    objects::{Quad, Sphere},
+    onb::ONB,
     ray::Ray,
+    textures::{SolidColor, Texture},
     wavelength::Wavelength,
-    Direction, Float, HitRecord, Position, Vec3, EPSILON_RECT_THICKNESS, EPSILON_SHADOW_ACNE,
+    Direction, Float, HitRecord, Position, Vec3, EPSILON_RECT_THICKNESS, EPSILON_SHADOW_ACNE, PI,
 };
 
 /// GLTF initialization structure
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
 pub struct GLTFInit {
-    /// Used for multiple importance sampling
+    /// Used for multiple importance sampling, for both the meshes and the emitters created for the punctual lights of the file. The small emitters of point and spot lights are rarely hit by chance, so lights generally need the priority.
     #[cfg_attr(feature = "serde-derive", serde(default))]
     pub priority: bool,
     /// Path of the .gltf file
     pub path: String,
+    /// If true, the first camera found in the file is used as the camera of the scene
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub camera: bool,
+    /// Radius of the emitters created for the point and spot lights of the file. Punctual lights have no size in glTF, but a renderable emitter needs one.
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_light_radius"))]
+    pub light_radius: Float,
 }
 
-impl From<GLTFInit> for Vec<Hitable<'static>> {
-    fn from(gltf: GLTFInit) -> Self {
+#[cfg(feature = "serde-derive")]
+fn default_light_radius() -> Float {
+    0.05
+}
+
+/// Angular radius in degrees of the emitters created for directional lights
+const DIRECTIONAL_LIGHT_SPREAD: Float = 5.0;
+
+/// Luminous efficacy in lumens per watt, used for converting the photometric light intensities of glTF into radiometric ones
+const LUMINOUS_EFFICACY: Float = 683.0;
+
+/// Internal GLTF object representation after initialization.
+#[derive(Debug, Clone)]
+pub struct GLTF<'scene> {
+    /// Hitables of the `GLTF` object. Most likely a list of `GLTFTriangle`s.
+    pub hitables: Vec<Hitable<'scene>>,
+    /// Emitters created for the `KHR_lights_punctual` lights of the file
+    pub lights: Vec<Hitable<'scene>>,
+    /// The first camera of the file, if requested with [`GLTFInit::camera`] and found
+    pub camera: Option<CameraInit>,
+    /// Axis-aligned bounding box of the object
+    pub aabb: AABB,
+}
+
+impl GLTF<'_> {
+    #[must_use]
+    /// Create a new GLTF object with the given initialization parameters.
+    ///
+    /// # Panics
+    /// This method may panic if the referenced .gltf file cannot be opened or parsed, or if no finite bounding box can be created for the given `hitables`.
+    pub fn new(gltf_init: GLTFInit) -> Self {
         let mut hitables: Vec<Hitable> = Vec::new();
+        let mut punctual_lights: Vec<(Light, Matrix4<Float>)> = Vec::new();
+        let mut cameras: Vec<(Camera, Matrix4<Float>)> = Vec::new();
 
         // Go through the objects in the gltf file
-        let (document, buffers, images) = gltf::import(gltf.path).unwrap();
+        let (document, buffers, images) = gltf::import(gltf_init.path).unwrap();
         let document: &'static gltf::Document = Box::leak(Box::new(document));
         let images: &'static Vec<Data> = Box::leak(Box::new(images));
         let materials: &'static Vec<gltf::Material> =
@@ -46,63 +97,102 @@ impl From<GLTFInit> for Vec<Hitable<'static>> {
             debug!("found scene");
             for node in scene.nodes() {
                 debug!("found node");
-                parse_node(&node, &mut hitables, &buffers, materials, images);
+                let mut context = NodeContext {
+                    hitables: &mut hitables,
+                    punctual_lights: &mut punctual_lights,
+                    cameras: &mut cameras,
+                    buffers: &buffers,
+                    materials,
+                    images,
+                };
+                parse_node(&node, &Matrix4::identity(), &mut context);
             }
         }
         debug!("hitable count: {}", &hitables.len());
+        let aabb = vec_bounding_box(&hitables).expect("No bounding box for hitables");
+
+        let lights: Vec<Hitable> = punctual_lights
+            .iter()
+            .map(|(light, transform)| {
+                light_to_hitable(light, transform, gltf_init.light_radius, &aabb)
+            })
+            .collect();
+        debug!("light count: {}", &lights.len());
+
+        let camera = if gltf_init.camera {
+            cameras
+                .first()
+                .map(|(camera, transform)| camera_to_init(camera, transform))
+        } else {
+            None
+        };
 
-        hitables
+        GLTF {
+            hitables,
+            lights,
+            camera,
+            aabb,
+        }
     }
 }
 
-/// Internal GLTF object representation after initialization.
-#[derive(Debug, Clone)]
-pub struct GLTF<'scene> {
-    /// Hitables of the `GLTF` object. Most likely a list of `GLTFTriangle`s.
-    pub hitables: Vec<Hitable<'scene>>,
-    /// Axis-aligned bounding box of the object
-    pub aabb: AABB,
+impl From<GLTFInit> for Vec<Hitable<'static>> {
+    /// Returns the hitables of the [`GLTF`] object, followed by the emitters of its lights.
+    fn from(gltf: GLTFInit) -> Self {
+        let mut gltf = GLTF::new(gltf);
+        gltf.hitables.append(&mut gltf.lights);
+        gltf.hitables
+    }
 }
 
-impl GLTF<'_> {
-    #[must_use]
-    /// Create a new STL object with the given initialization parameters.
-    ///
-    /// # Panics
-    /// This method may panic if no finite bounding box can be created for the given `hitables`.
-    pub fn new(gltf_init: GLTFInit) -> Self {
-        let hitables: Vec<Hitable> = gltf_init.into();
-        let aabb = vec_bounding_box(&hitables).expect("No bounding box for hitables");
-
-        GLTF { hitables, aabb }
-    }
+/// Shared state for the recursive node traversal
+struct NodeContext<'a> {
+    hitables: &'a mut Vec<Hitable<'static>>,
+    punctual_lights: &'a mut Vec<(Light<'static>, Matrix4<Float>)>,
+    cameras: &'a mut Vec<(Camera<'static>, Matrix4<Float>)>,
+    buffers: &'a [gltf::buffer::Data],
+    materials: &'static [gltf::Material<'static>],
+    images: &'static [Data],
 }
 
-fn parse_node(
-    node: &Node,
-    hitables: &mut Vec<Hitable<'static>>,
-    buffers: &Vec<gltf::buffer::Data>,
-    materials: &'static Vec<gltf::Material>,
-    images: &'static Vec<Data>,
-) {
+fn parse_node(node: &Node<'static>, parent: &Matrix4<Float>, context: &mut NodeContext) {
+    // Node transforms are relative to the parent node
+    let transform: Matrix4<Float> = parent * Matrix4::from(node.transform().matrix());
     // Handle direct meshes
     if let Some(mesh) = node.mesh() {
-        parse_mesh(&mesh, hitables, buffers, materials, images);
+        parse_mesh(&mesh, &transform, context);
+    }
+    if let Some(light) = node.light() {
+        debug!("found light");
+        context.punctual_lights.push((light, transform));
+    }
+    if let Some(camera) = node.camera() {
+        debug!("found camera");
+        context.cameras.push((camera, transform));
     }
     // Handle nesting
     for child in node.children() {
-        parse_node(&child, hitables, buffers, materials, images);
+        parse_node(&child, &transform, context);
     }
 }
 
-fn parse_mesh(
-    mesh: &Mesh,
-    hitables: &mut Vec<Hitable<'static>>,
-    buffers: &[gltf::buffer::Data],
-    materials: &'static [gltf::Material],
-    images: &'static [Data],
-) {
+fn parse_mesh(mesh: &Mesh, transform: &Matrix4<Float>, context: &mut NodeContext) {
     debug!("found mesh");
+    let linear: Matrix3<Float> = transform.fixed_view::<3, 3>(0, 0).into();
+    let normal_matrix = normal_matrix(transform);
+    let transform_normal = |normal: [Float; 3]| -> [Float; 3] {
+        (normal_matrix * Vec3::from(normal)).normalize().into()
+    };
+    let transform_tangent = |tangent: [Float; 4]| -> [Float; 4] {
+        let [x, y, z]: [Float; 3] = (linear * Vec3::new(tangent[0], tangent[1], tangent[2]))
+            .normalize()
+            .into();
+        [x, y, z, tangent[3]]
+    };
+    let buffers = context.buffers;
+    let materials = context.materials;
+    let images = context.images;
+
     for primitive in mesh.primitives() {
         debug!("found primitive");
         match primitive.mode() {
@@ -113,7 +203,8 @@ fn parse_mesh(
                 let mut all_positions: Vec<Vec3> = Vec::new();
                 if let Some(iter) = reader.read_positions() {
                     for vertex_position in iter {
-                        all_positions.push(vertex_position.into());
+                        let position: Vec3 = vertex_position.into();
+                        all_positions.push(transform.transform_point(&position.into()).coords);
                     }
                 }
 
@@ -137,9 +228,12 @@ fn parse_mesh(
                         .unwrap()
                         .into_f32()
                         .collect();
-                    let all_normals: Option<Vec<_>> = reader.read_normals().map(Iterator::collect);
-                    let all_tangents: Option<Vec<_>> =
-                        reader.read_tangents().map(Iterator::collect);
+                    let all_normals: Option<Vec<_>> = reader
+                        .read_normals()
+                        .map(|normals| normals.map(transform_normal).collect());
+                    let all_tangents: Option<Vec<_>> = reader
+                        .read_tangents()
+                        .map(|tangents| tangents.map(transform_tangent).collect());
 
                     while i < len {
                         let triangle = [
@@ -178,13 +272,136 @@ fn parse_mesh(
                     }
                 }
 
-                hitables.append(&mut trianglelist);
+                context.hitables.append(&mut trianglelist);
             }
             _ => unimplemented!(),
         }
     }
 }
 
+/// Returns the matrix for transforming normals with the given transform. Normals need to be transformed with the inverse transpose in order to stay perpendicular to the surface.
+fn normal_matrix(transform: &Matrix4<Float>) -> Matrix3<Float> {
+    let linear: Matrix3<Float> = transform.fixed_view::<3, 3>(0, 0).into();
+    linear
+        .try_inverse()
+        .map_or(linear, |inverse| inverse.transpose())
+}
+
+/// Creates an emitter approximating the given `KHR_lights_punctual` light. Point lights become small spheres, spot lights become small quads emitting within the cone of the spot and falling off between its inner and outer angle, and directional lights become large quads outside of the bounding box of the object, emitting within a narrow cone.
+fn light_to_hitable(
+    light: &Light,
+    transform: &Matrix4<Float>,
+    radius: Float,
+    aabb: &AABB,
+) -> Hitable<'static> {
+    let position: Position = transform.transform_point(&Point3::origin()).coords;
+    // Lights point towards the local negative z axis
+    let direction: Direction =
+        Unit::new_normalize(transform.transform_vector(&Vec3::new(0.0, 0.0, -1.0)));
+    let [red, green, blue] = light.color();
+    // Photometric intensity, candela for point and spot lights and lux for directional lights
+    let intensity = light.intensity() / LUMINOUS_EFFICACY;
+    let emission = |scale: Float| -> Texture {
+        let color = LinSrgb::new(red * scale, green * scale, blue * scale);
+        SolidColor::new(ColorInit::TypedColor(TypedColorInit::LinSrgb(color))).into()
+    };
+    // Quad facing the given direction, centered at the given position
+    let facing_quad = |center: Position, size: Float, material: &'static Material| {
+        let onb = ONB::build_from_w(direction);
+        let u: Vec3 = *onb.v * size;
+        let v: Vec3 = *onb.u * size;
+        Hitable::Quad(Quad::new(center - (u + v) / 2.0, u, v, material))
+    };
+
+    match light.kind() {
+        LightKind::Point => {
+            // Radiant intensity of a uniformly emitting sphere is its radiance times its projected area
+            let area = PI * radius * radius;
+            let material = leak_light(DiffuseLight::new(emission(intensity / area)).into());
+            Hitable::Sphere(Sphere::new(position, radius, material))
+        }
+        LightKind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        } => {
+            let size = 2.0 * radius;
+            let area = size * size;
+            let material = leak_light(
+                ConeLight::with_falloff(
+                    outer_cone_angle.to_degrees(),
+                    inner_cone_angle.to_degrees(),
+                    emission(intensity / area),
+                )
+                .into(),
+            );
+            facing_quad(position, size, material)
+        }
+        LightKind::Directional => {
+            // Irradiance within a cone of a given half-angle is the radiance times the projected solid angle
+            let spread = DIRECTIONAL_LIGHT_SPREAD.to_radians().sin();
+            let material = leak_light(
+                ConeLight::new(
+                    DIRECTIONAL_LIGHT_SPREAD,
+                    emission(intensity / (PI * spread * spread)),
+                )
+                .into(),
+            );
+            let extent =
+                (aabb.x.size().powi(2) + aabb.y.size().powi(2) + aabb.z.size().powi(2)).sqrt();
+            let center: Position = aabb.centroid() - *direction * extent;
+            facing_quad(center, 2.0 * extent, material)
+        }
+    }
+}
+
+fn leak_light(kind: Kind) -> &'static Material {
+    // TODO: don't leak memory
+    Box::leak(Box::new(Material {
+        kind,
+        thin_film: None,
+    }))
+}
+
+/// Converts a glTF camera into a [`CameraInit`]
+fn camera_to_init(camera: &Camera, transform: &Matrix4<Float>) -> CameraInit {
+    // Cameras look towards the local negative z axis, with the local positive y axis up
+    let look_from: Position = transform.transform_point(&Point3::origin()).coords;
+    let forward: Vec3 = transform
+        .transform_vector(&Vec3::new(0.0, 0.0, -1.0))
+        .normalize();
+    let up: Vec3 = transform
+        .transform_vector(&Vec3::new(0.0, 1.0, 0.0))
+        .normalize();
+    let (vertical_fov, projection) = match camera.projection() {
+        Projection::Perspective(perspective) => (
+            perspective.yfov().to_degrees(),
+            CameraProjection::Perspective,
+        ),
+        // The magnification is half of the visible height
+        Projection::Orthographic(orthographic) => (
+            0.0,
+            CameraProjection::Orthographic {
+                height: 2.0 * orthographic.ymag(),
+            },
+        ),
+    };
+
+    CameraInit {
+        look_from,
+        look_at: look_from + forward,
+        up,
+        lens: LensInit::Simple(SimpleLensInit {
+            vertical_fov,
+            aperture: 0.0,
+            focus_distance: 1.0,
+        }),
+        projection,
+        bokeh: ApertureInit::default(),
+        vignetting: 0.0,
+        shutter: ShutterInit::default(),
+    }
+}
+
 /// Internal GLTF object representation after initialization.
 #[derive(Debug, Clone)]
 pub struct GLTFTriangle<'scene> {
@@ -288,6 +505,7 @@ impl HitableTrait for GLTFTriangle<'_> {
             u: alpha,
             v: beta,
             material: self.material,
+            tangent: None,
             front_face,
         })
     }
@@ -336,3 +554,165 @@ fn hit_ab(a: Float, b: Float) -> bool {
     // Triangle: a+b must be <=1.0
     (0.0..=1.0).contains(&a) && (0.0..=1.0).contains(&b) && (a + b <= 1.0)
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+    use nalgebra::{Rotation3, Vector3};
+    use rand::SeedableRng;
+    use std::{fs, path::PathBuf};
+
+    use crate::objects::test_directory;
+
+    use super::*;
+
+    /// A single triangle in the plane `x + y = 0`, in a child node scaled along the x axis. The parent node moves it and the lights. The spot light points down, the directional light towards the negative z axis.
+    const GLTF: &str = r#"{
+        "asset": { "version": "2.0" },
+        "extensionsUsed": ["KHR_lights_punctual"],
+        "extensions": { "KHR_lights_punctual": { "lights": [
+            { "type": "point", "intensity": 683 },
+            { "type": "spot", "intensity": 683, "spot": { "innerConeAngle": 0.2, "outerConeAngle": 0.4 } },
+            { "type": "directional", "intensity": 683 }
+        ] } },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [
+            { "translation": [10, 0, 0], "children": [1, 2, 3, 4] },
+            { "scale": [2, 1, 1], "mesh": 0 },
+            { "translation": [0, 3, 0], "extensions": { "KHR_lights_punctual": { "light": 0 } } },
+            { "translation": [0, 5, 0], "rotation": [-0.70710677, 0, 0, 0.70710677], "extensions": { "KHR_lights_punctual": { "light": 1 } } },
+            { "extensions": { "KHR_lights_punctual": { "light": 2 } } }
+        ],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 }, "indices": 3, "material": 0 }] }],
+        "materials": [{ "pbrMetallicRoughness": { "baseColorFactor": [0.5, 0.5, 0.5, 1], "metallicFactor": 0 } }],
+        "buffers": [{ "uri": "triangle.bin", "byteLength": 104 }],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 36, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 72, "byteLength": 24 },
+            { "buffer": 0, "byteOffset": 96, "byteLength": 6 }
+        ],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, -1, 0], "max": [1, 0, 1] },
+            { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" },
+            { "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2" },
+            { "bufferView": 3, "componentType": 5123, "count": 3, "type": "SCALAR" }
+        ]
+    }"#;
+
+    /// Writes the .gltf file and its buffer into a temporary directory. Returns the path of the .gltf file.
+    fn write_files(name: &str) -> PathBuf {
+        let directory = test_directory(&format!("gltf-{name}"));
+
+        let normal = core::f32::consts::FRAC_1_SQRT_2;
+        let positions: [f32; 9] = [0.0, 0.0, 0.0, 1.0, -1.0, 0.0, 0.0, 0.0, 1.0];
+        let normals: [f32; 9] = [
+            normal, normal, 0.0, normal, normal, 0.0, normal, normal, 0.0,
+        ];
+        let tex_coords: [f32; 6] = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0];
+        let floats = positions.iter().chain(&normals).chain(&tex_coords);
+        let mut buffer: Vec<u8> = floats.flat_map(|f| f.to_le_bytes()).collect();
+        buffer.extend([0_u16, 1, 2, 0].iter().flat_map(|i| i.to_le_bytes()));
+        fs::write(directory.join("triangle.bin"), buffer).unwrap();
+        let path = directory.join("triangle.gltf");
+        fs::write(&path, GLTF).unwrap();
+        path
+    }
+
+    fn gltf(name: &str) -> GLTF<'static> {
+        GLTF::new(GLTFInit {
+            priority: false,
+            path: write_files(name).to_string_lossy().into(),
+            camera: false,
+            light_radius: 0.1,
+        })
+    }
+
+    fn ray(origin: Position, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction: Unit::new_normalize(direction),
+            time: 0.0,
+            wavelength: 600,
+        }
+    }
+
+    #[test]
+    fn node_transforms_compose() {
+        let gltf = gltf("transforms");
+        assert_eq!(gltf.hitables.len(), 1);
+
+        // The child scales the triangle along x, then the parent moves it
+        assert!((gltf.aabb.x.min - 10.0).abs() <= 1e-3);
+        assert!((gltf.aabb.x.max - 12.0).abs() <= 1e-3);
+        assert!((gltf.aabb.y.min + 1.0).abs() <= 1e-3);
+        assert!((gltf.aabb.z.max - 1.0).abs() <= 1e-3);
+
+        // The scaled triangle lies in the plane x + 2y = 10
+        let mut rng = SmallRng::seed_from_u64(0);
+        let origin = Position::new(10.5, -0.25, 0.25) + Vec3::new(1.0, 2.0, 0.0);
+        let record = gltf.hitables[0]
+            .hit(
+                &ray(origin, Vec3::new(-1.0, -2.0, 0.0)),
+                0.0,
+                Float::INFINITY,
+                &mut rng,
+            )
+            .expect("No hit on the triangle");
+        assert!((record.distance - Float::sqrt(5.0)).abs() <= 1e-4);
+        assert!((*record.normal - Vec3::new(1.0, 2.0, 0.0).normalize()).norm() <= 1e-4);
+    }
+
+    #[test]
+    fn normals_use_inverse_transpose() {
+        let transform = Matrix4::new_translation(&Vec3::new(10.0, 0.0, 0.0))
+            * Rotation3::from_axis_angle(&Vector3::z_axis(), 0.5).to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&Vec3::new(2.0, 1.0, 0.5));
+        let linear: Matrix3<Float> = transform.fixed_view::<3, 3>(0, 0).into();
+        // The edges of the surface stay perpendicular to the normal after the transform
+        let normal = Vec3::new(1.0, 1.0, 1.0);
+        let edges = [Vec3::new(1.0, -1.0, 0.0), Vec3::new(0.0, 1.0, -1.0)];
+        let transformed = normal_matrix(&transform) * normal;
+        for edge in edges {
+            assert!(transformed.dot(&(linear * edge)).abs() <= 1e-5);
+        }
+        // Transforming the normal like the positions would tilt it
+        assert!((linear * normal).dot(&(linear * edges[0])).abs() > 0.1);
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn punctual_lights() {
+        let gltf = gltf("lights");
+        let [point, spot, directional] = &gltf.lights[..] else {
+            panic!("Expected three lights, found {}", gltf.lights.len());
+        };
+
+        // The lights are moved by their parent node
+        assert!(matches!(point, Hitable::Sphere(_)));
+        assert!((point.centroid() - Position::new(10.0, 3.0, 0.0)).norm() <= 1e-4);
+
+        // The spot light points down, with full emission within the inner cone, falling off towards the outer cone
+        let mut rng = SmallRng::seed_from_u64(0);
+        let position = Position::new(10.0, 5.0, 0.0);
+        let emission = |angle: Float, rng: &mut SmallRng| {
+            let direction = Vec3::new(angle.sin(), angle.cos(), 0.0);
+            let ray = ray(position - direction, direction);
+            let record = spot
+                .hit(&ray, 0.0, Float::INFINITY, rng)
+                .expect("No hit on the spot light");
+            assert!(record.front_face);
+            record.material.emit(&ray, 600, &record)
+        };
+        let full = emission(0.0, &mut rng);
+        assert!(full > 0.0);
+        assert!((emission(0.15, &mut rng) - full).abs() <= 1e-4 * full);
+        let partial = emission(0.3, &mut rng);
+        assert!(partial > 0.0 && partial < full);
+        assert!(emission(0.5, &mut rng).abs() <= 0.0);
+
+        // The directional light is placed outside of the object, opposite to its direction
+        assert!(directional.centroid().z > gltf.aabb.z.max);
+    }
+}
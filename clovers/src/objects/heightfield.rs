@@ -0,0 +1,510 @@
+//! A heightfield object for terrains, loaded from a height image.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use nalgebra::Unit;
+use rand::rngs::SmallRng;
+
+use crate::{
+    aabb::AABB,
+    hitable::HitableTrait,
+    materials::{Material, MaterialInit, SharedMaterial},
+    objects::initialize_material,
+    ray::Ray,
+    wavelength::Wavelength,
+    Direction, Float, HitRecord, Position, Vec3,
+};
+
+/// `HeightfieldInit` structure describes the necessary data for constructing a [Heightfield].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+pub struct HeightfieldInit {
+    /// Used for multiple importance sampling. Not supported for heightfields: the scene loader ignores it with a warning.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub priority: bool,
+    /// Path of the height image. Grayscale images are mapped to heights between zero and one, and the values of EXR images are used as-is. Colored images are converted to grayscale.
+    pub path: String,
+    /// Corner of the heightfield with the smallest coordinates. The first pixel of the image is at this corner.
+    pub corner: Position,
+    /// Size of the heightfield. The image is stretched over the `x` and `z` sizes, and the heights are multiplied by the `y` size.
+    pub size: Vec3,
+    /// Material of the heightfield
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub material: MaterialInit,
+}
+
+/// Initializes a Heightfield
+///
+/// # Errors
+/// Returns an error if the height image cannot be opened or decoded, or if it is smaller than two by two pixels.
+pub fn initialize_heightfield(
+    heightfield_init: HeightfieldInit,
+    materials: &[SharedMaterial],
+) -> Result<Heightfield<'_>, String> {
+    let material = initialize_material(heightfield_init.material, materials);
+    let image = image::open(&heightfield_init.path)
+        .map_err(|error| {
+            format!(
+                "Could not open the height image {}: {error}",
+                heightfield_init.path
+            )
+        })?
+        .to_luma32f();
+    let columns = image.width() as usize;
+    let rows = image.height() as usize;
+    let heights = image.pixels().map(|pixel| pixel[0]).collect();
+    Heightfield::new(
+        heights,
+        columns,
+        rows,
+        heightfield_init.corner,
+        heightfield_init.size,
+        material,
+    )
+    .map_err(String::from)
+}
+
+/// A level of the min/max mipmap of a [Heightfield]. Each node covers `2^level` by `2^level` cells of the heightfield, and stores the smallest and largest height within them.
+#[derive(Clone, Debug)]
+struct MipLevel {
+    columns: usize,
+    rows: usize,
+    bounds: Vec<(Float, Float)>,
+}
+
+/// A heightfield object. The heights are sampled on a regular grid, and each cell of the grid is split into two triangles. Rays are intersected by traversing a min/max mipmap of the heights from the top level down, only testing the triangles of the cells whose bounds are hit.
+///
+/// The normals are interpolated from the slopes of the heightfield, and the surface coordinates map the whole heightfield to the `0..1` range, matching the orientation of an [`ImageTexture`](crate::textures::ImageTexture) of the same image.
+///
+/// Heightfields cannot be used as priority objects, as sampling random points on the surface is not supported.
+#[derive(Clone, Debug)]
+pub struct Heightfield<'scene> {
+    corner: Position,
+    size: Vec3,
+    /// Number of samples along the `x` axis
+    columns: usize,
+    /// Number of samples along the `z` axis
+    rows: usize,
+    /// Scene-space heights of the samples, row by row
+    heights: Vec<Float>,
+    /// Min/max mipmap of the heights, starting from the individual cells
+    mipmap: Vec<MipLevel>,
+    material: &'scene Material,
+    aabb: AABB,
+}
+
+impl<'scene> Heightfield<'scene> {
+    /// Creates a new `Heightfield` from the given grid of heights, stored row by row. The heights are multiplied by the `y` size.
+    ///
+    /// # Errors
+    /// Returns an error if the grid is smaller than two by two samples, or if the number of heights does not match the size of the grid.
+    pub fn new(
+        heights: Vec<Float>,
+        columns: usize,
+        rows: usize,
+        corner: Position,
+        size: Vec3,
+        material: &'scene Material,
+    ) -> Result<Self, &'static str> {
+        if columns < 2 || rows < 2 {
+            return Err("Heightfield needs at least two by two samples");
+        }
+        if heights.len() != columns * rows {
+            return Err("Heightfield size mismatch");
+        }
+        let heights: Vec<Float> = heights
+            .into_iter()
+            .map(|height| corner.y + height * size.y)
+            .collect();
+
+        // The lowest level of the mipmap contains the bounds of each cell
+        let mut bounds = Vec::with_capacity((columns - 1) * (rows - 1));
+        for row in 0..rows - 1 {
+            for column in 0..columns - 1 {
+                let corners = [
+                    heights[row * columns + column],
+                    heights[row * columns + column + 1],
+                    heights[(row + 1) * columns + column],
+                    heights[(row + 1) * columns + column + 1],
+                ];
+                let min = corners.iter().copied().fold(Float::INFINITY, Float::min);
+                let max = corners
+                    .iter()
+                    .copied()
+                    .fold(Float::NEG_INFINITY, Float::max);
+                bounds.push((min, max));
+            }
+        }
+        let mut mipmap = vec![MipLevel {
+            columns: columns - 1,
+            rows: rows - 1,
+            bounds,
+        }];
+        // Each higher level combines two by two nodes of the level below, until a single node remains
+        while let Some(below) = mipmap.last().filter(|level| level.columns * level.rows > 1) {
+            let level_columns = below.columns.div_ceil(2);
+            let level_rows = below.rows.div_ceil(2);
+            let mut bounds = Vec::with_capacity(level_columns * level_rows);
+            for row in 0..level_rows {
+                for column in 0..level_columns {
+                    let mut node = (Float::INFINITY, Float::NEG_INFINITY);
+                    for child_row in (2 * row)..(2 * row + 2).min(below.rows) {
+                        for child_column in (2 * column)..(2 * column + 2).min(below.columns) {
+                            let child = below.bounds[child_row * below.columns + child_column];
+                            node = (node.0.min(child.0), node.1.max(child.1));
+                        }
+                    }
+                    bounds.push(node);
+                }
+            }
+            mipmap.push(MipLevel {
+                columns: level_columns,
+                rows: level_rows,
+                bounds,
+            });
+        }
+
+        let (min, max) = mipmap[mipmap.len() - 1].bounds[0];
+        let mut aabb = AABB::new_from_coords(
+            Position::new(corner.x, min, corner.z),
+            Position::new(corner.x + size.x, max, corner.z + size.z),
+        );
+        aabb.pad();
+
+        Ok(Heightfield {
+            corner,
+            size,
+            columns,
+            rows,
+            heights,
+            mipmap,
+            material,
+            aabb,
+        })
+    }
+
+    /// Returns the size of a single cell along the `x` and `z` axes
+    #[allow(clippy::cast_precision_loss)]
+    fn cell_size(&self) -> (Float, Float) {
+        (
+            self.size.x / (self.columns - 1) as Float,
+            self.size.z / (self.rows - 1) as Float,
+        )
+    }
+
+    /// Returns the scene-space position of the sample at the given grid coordinates
+    #[allow(clippy::cast_precision_loss)]
+    fn vertex(&self, column: usize, row: usize) -> Position {
+        let (dx, dz) = self.cell_size();
+        Position::new(
+            self.corner.x + column as Float * dx,
+            self.heights[row * self.columns + column],
+            self.corner.z + row as Float * dz,
+        )
+    }
+
+    /// Returns the normal of the heightfield at the given sample, based on the slopes towards the neighboring samples
+    #[allow(clippy::cast_precision_loss)]
+    fn vertex_normal(&self, column: usize, row: usize) -> Vec3 {
+        let (dx, dz) = self.cell_size();
+        let (left, right) = (column.saturating_sub(1), (column + 1).min(self.columns - 1));
+        let (back, front) = (row.saturating_sub(1), (row + 1).min(self.rows - 1));
+        let height = |column: usize, row: usize| self.heights[row * self.columns + column];
+        let slope_x = (height(right, row) - height(left, row)) / ((right - left) as Float * dx);
+        let slope_z =
+            (height(column, front) - height(column, back)) / ((front - back) as Float * dz);
+        Vec3::new(-slope_x, 1.0, -slope_z).normalize()
+    }
+
+    /// Recursively traverses the mipmap node at the given level and grid coordinates, updating the closest triangle hit found so far
+    fn traverse(
+        &self,
+        ray: &Ray,
+        node: (usize, usize, usize),
+        distance_min: Float,
+        distance_max: Float,
+        closest: &mut Option<(Float, usize, usize)>,
+    ) {
+        let (level, column, row) = node;
+        let distance_max = closest.map_or(distance_max, |(distance, ..)| distance);
+        if self
+            .node_entry(ray, node, distance_min, distance_max)
+            .is_none()
+        {
+            return;
+        }
+
+        if level == 0 {
+            // Test the two triangles of the cell
+            let p00 = self.vertex(column, row);
+            let p10 = self.vertex(column + 1, row);
+            let p01 = self.vertex(column, row + 1);
+            let p11 = self.vertex(column + 1, row + 1);
+            for (a, b, c) in [(p00, p10, p11), (p00, p11, p01)] {
+                if let Some(distance) = hit_triangle(ray, a, b, c) {
+                    let distance_max = closest.map_or(distance_max, |(distance, ..)| distance);
+                    if distance > distance_min && distance < distance_max {
+                        *closest = Some((distance, column, row));
+                    }
+                }
+            }
+            return;
+        }
+
+        // Visit the children from the nearest to the farthest, so that the farther ones can often be skipped
+        let below = &self.mipmap[level - 1];
+        let mut children: [(Float, usize, usize); 4] = [(Float::INFINITY, 0, 0); 4];
+        let mut count = 0;
+        for child_row in (2 * row)..(2 * row + 2).min(below.rows) {
+            for child_column in (2 * column)..(2 * column + 2).min(below.columns) {
+                let child = (level - 1, child_column, child_row);
+                if let Some(entry) = self.node_entry(ray, child, distance_min, distance_max) {
+                    children[count] = (entry, child_column, child_row);
+                    count += 1;
+                }
+            }
+        }
+        children[..count].sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        for &(entry, child_column, child_row) in &children[..count] {
+            if closest.is_some_and(|(distance, ..)| distance < entry) {
+                break;
+            }
+            self.traverse(
+                ray,
+                (level - 1, child_column, child_row),
+                distance_min,
+                distance_max,
+                closest,
+            );
+        }
+    }
+
+    /// Returns the distance at which the ray enters the bounding box of the given mipmap node, if it is hit within the given distance interval
+    #[allow(clippy::cast_precision_loss)]
+    fn node_entry(
+        &self,
+        ray: &Ray,
+        (level, column, row): (usize, usize, usize),
+        distance_min: Float,
+        distance_max: Float,
+    ) -> Option<Float> {
+        let mip = &self.mipmap[level];
+        let (min_height, max_height) = mip.bounds[row * mip.columns + column];
+        let (dx, dz) = self.cell_size();
+        let cells = self.mipmap[0].columns.min((column + 1) << level) - (column << level);
+        let x0 = self.corner.x + (column << level) as Float * dx;
+        let x1 = x0 + cells as Float * dx;
+        let cells = self.mipmap[0].rows.min((row + 1) << level) - (row << level);
+        let z0 = self.corner.z + (row << level) as Float * dz;
+        let z1 = z0 + cells as Float * dz;
+
+        let mut entry = distance_min;
+        let mut exit = distance_max;
+        for (axis, min, max) in [(0, x0, x1), (1, min_height, max_height), (2, z0, z1)] {
+            // Avoid a division by zero for rays parallel to the axis
+            if ray.direction[axis] == 0.0 {
+                if ray.origin[axis] < min || ray.origin[axis] > max {
+                    return None;
+                }
+                continue;
+            }
+            let inverse = 1.0 / ray.direction[axis];
+            let t0 = (min - ray.origin[axis]) * inverse;
+            let t1 = (max - ray.origin[axis]) * inverse;
+            entry = entry.max(t0.min(t1));
+            exit = exit.min(t0.max(t1));
+            if exit < entry {
+                return None;
+            }
+        }
+        Some(entry)
+    }
+}
+
+impl HitableTrait for Heightfield<'_> {
+    /// Hit method for the [Heightfield] object. Returns a [`HitRecord`] if the given [Ray] intersects with the heightfield at the given distance interval.
+    #[allow(clippy::cast_precision_loss)]
+    fn hit(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        _rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        let top = (self.mipmap.len() - 1, 0, 0);
+        let mut closest = None;
+        self.traverse(ray, top, distance_min, distance_max, &mut closest);
+        let (distance, column, row) = closest?;
+
+        let position = ray.evaluate(distance);
+        let (dx, dz) = self.cell_size();
+        let fraction_x = ((position.x - self.corner.x) / dx - column as Float).clamp(0.0, 1.0);
+        let fraction_z = ((position.z - self.corner.z) / dz - row as Float).clamp(0.0, 1.0);
+
+        // Orientation from the geometry of the cell, shading normal interpolated from the slopes
+        let geometric_normal = (self.vertex(column, row + 1) - self.vertex(column, row))
+            .cross(&(self.vertex(column + 1, row) - self.vertex(column, row)));
+        let front_face = ray.direction.dot(&geometric_normal) < 0.0;
+        let shading_normal = (1.0 - fraction_z)
+            * ((1.0 - fraction_x) * self.vertex_normal(column, row)
+                + fraction_x * self.vertex_normal(column + 1, row))
+            + fraction_z
+                * ((1.0 - fraction_x) * self.vertex_normal(column, row + 1)
+                    + fraction_x * self.vertex_normal(column + 1, row + 1));
+        let shading_normal: Direction = Unit::new_normalize(shading_normal);
+        let normal = if front_face {
+            shading_normal
+        } else {
+            -shading_normal
+        };
+
+        Some(HitRecord {
+            distance,
+            position,
+            normal,
+            u: (position.x - self.corner.x) / self.size.x,
+            v: 1.0 - (position.z - self.corner.z) / self.size.z,
+            material: self.material,
+            tangent: None,
+            front_face,
+        })
+    }
+
+    /// Returns the axis-aligned bounding box [AABB] of the heightfield.
+    fn aabb(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+
+    /// Returns zero, as random points on the surface are not supported
+    fn pdf_value(
+        &self,
+        _origin: Position,
+        _direction: Direction,
+        _wavelength: Wavelength,
+        _time: Float,
+        _rng: &mut SmallRng,
+    ) -> Float {
+        0.0
+    }
+
+    fn centroid(&self) -> Position {
+        self.aabb.centroid()
+    }
+}
+
+/// Möller-Trumbore ray-triangle intersection. Returns the distance to the intersection, if any.
+#[allow(clippy::many_single_char_names)]
+fn hit_triangle(ray: &Ray, a: Position, b: Position, c: Position) -> Option<Float> {
+    let edge_1 = b - a;
+    let edge_2 = c - a;
+    let p = ray.direction.cross(&edge_2);
+    let determinant = edge_1.dot(&p);
+    if determinant.abs() < Float::EPSILON {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let s = ray.origin - a;
+    let u = s.dot(&p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&edge_1);
+    let v = ray.direction.dot(&q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    Some(edge_2.dot(&q) * inverse)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::vec;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn ray_down(x: Float, z: Float) -> Ray {
+        Ray {
+            origin: Position::new(x, 10.0, z),
+            direction: Unit::new_normalize(Vec3::new(0.0, -1.0, 0.0)),
+            time: 0.0,
+            wavelength: 600,
+        }
+    }
+
+    #[test]
+    fn mipmap_bounds() {
+        let material = Box::default();
+        // Five by three samples, a single peak
+        let mut heights = vec![0.0; 15];
+        heights[7] = 1.0;
+        let heightfield = Heightfield::new(
+            heights,
+            5,
+            3,
+            Position::new(0.0, 0.0, 0.0),
+            Vec3::new(4.0, 2.0, 2.0),
+            &material,
+        )
+        .expect("Invalid heightfield");
+        let levels: Vec<(usize, usize)> = heightfield
+            .mipmap
+            .iter()
+            .map(|level| (level.columns, level.rows))
+            .collect();
+        assert_eq!(levels, vec![(4, 2), (2, 1), (1, 1)]);
+        assert_eq!(heightfield.mipmap[2].bounds[0], (0.0, 2.0));
+        let aabb = heightfield.aabb().expect("No AABB for the heightfield");
+        assert!(aabb.y.max >= 2.0);
+        assert!(aabb.x.max >= 4.0);
+    }
+
+    #[test]
+    fn hit_slope() {
+        let mut rng = SmallRng::from_os_rng();
+        let material = Box::default();
+        // A slope rising along the x axis, from zero to four
+        let heights = vec![0.0, 0.5, 1.0, 0.0, 0.5, 1.0, 0.0, 0.5, 1.0];
+        let heightfield = Heightfield::new(
+            heights,
+            3,
+            3,
+            Position::new(0.0, 0.0, 0.0),
+            Vec3::new(4.0, 4.0, 4.0),
+            &material,
+        )
+        .expect("Invalid heightfield");
+        for x in [0.5, 1.3, 2.0, 3.7] {
+            let hit = heightfield
+                .hit(&ray_down(x, 1.7), 0.0, Float::INFINITY, &mut rng)
+                .expect("No hit on the heightfield");
+            assert!((hit.position.y - x).abs() <= 1e-4);
+            assert!(hit.front_face);
+            let expected = Vec3::new(-1.0, 1.0, 0.0).normalize();
+            assert!((hit.normal.into_inner() - expected).norm() <= 1e-4);
+        }
+        assert!(heightfield
+            .hit(&ray_down(5.0, 1.0), 0.0, Float::INFINITY, &mut rng)
+            .is_none());
+    }
+
+    #[test]
+    fn invalid_grids_are_rejected() {
+        let material = Box::default();
+        let corner = Position::new(0.0, 0.0, 0.0);
+        let size = Vec3::new(1.0, 1.0, 1.0);
+        assert!(Heightfield::new(vec![0.0; 4], 4, 1, corner, size, &material).is_err());
+        assert!(Heightfield::new(vec![0.0; 5], 2, 2, corner, size, &material).is_err());
+        let heightfield_init = HeightfieldInit {
+            priority: false,
+            path: String::from("does/not/exist.png"),
+            corner,
+            size,
+            material: MaterialInit::default(),
+        };
+        assert!(initialize_heightfield(heightfield_init, &[SharedMaterial::default()]).is_err());
+    }
+}
@@ -0,0 +1,388 @@
+//! Utility object for moving, rotating and scaling another object over time, for motion blur.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use nalgebra::{Matrix3, Matrix4, Point3, UnitQuaternion};
+use rand::rngs::SmallRng;
+
+use crate::{
+    aabb::AABB,
+    hitable::{Hitable, HitableTrait},
+    objects::transform::{hit_transformed, local_ray, transformed_aabb},
+    ray::Ray,
+    wavelength::Wavelength,
+    Box, Direction, Displacement, Float, HitRecord, Position, Vec2, Vec3,
+};
+
+use super::Object;
+
+/// Number of evaluated steps per keyframe interval when computing the bounding box of the motion
+const MOTION_BOUNDS_STEPS: usize = 16;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// A keyframe of a [`KeyframedTransform`]: the translation, rotation and scale of the object at a given time. The object is first scaled, then rotated, then translated.
+pub struct Keyframe {
+    /// Time of the keyframe, in the same units as the `time_0` and `time_1` of the scene
+    pub time: Float,
+    /// Translation of the object
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub translation: Vec3,
+    /// Rotation of the object. Described as three angles, `roll`, `pitch`, `yaw`, applied in that order.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub rotation: Vec3,
+    /// Scaling factor for the object along each axis
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_scale"))]
+    pub scale: Vec3,
+}
+
+#[cfg(feature = "serde-derive")]
+fn default_scale() -> Vec3 {
+    Vec3::new(1.0, 1.0, 1.0)
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// `KeyframedTransformInit` structure describes the necessary data for constructing a [`KeyframedTransform`].
+pub struct KeyframedTransformInit {
+    /// Used for multiple importance sampling
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub priority: bool,
+    /// The encased [Object] to move
+    pub object: Box<Object>,
+    /// Keyframes of the motion. Needs at least one keyframe.
+    pub keyframes: Vec<Keyframe>,
+}
+
+/// Internal representation of a [Keyframe], with the rotation converted to a quaternion for interpolation
+#[derive(Clone, Debug)]
+struct Pose {
+    time: Float,
+    translation: Vec3,
+    rotation: UnitQuaternion<Float>,
+    scale: Vec3,
+}
+
+impl From<&Keyframe> for Pose {
+    fn from(keyframe: &Keyframe) -> Self {
+        Pose {
+            time: keyframe.time,
+            translation: keyframe.translation,
+            rotation: UnitQuaternion::from_euler_angles(
+                keyframe.rotation[0].to_radians(),
+                keyframe.rotation[1].to_radians(),
+                keyframe.rotation[2].to_radians(),
+            ),
+            scale: keyframe.scale,
+        }
+    }
+}
+
+impl Pose {
+    /// Linear interpolation of the translation and scale, spherical linear interpolation of the rotation
+    fn interpolate(&self, other: &Pose, t: Float) -> Pose {
+        Pose {
+            time: self.time + t * (other.time - self.time),
+            translation: self.translation.lerp(&other.translation, t),
+            rotation: self.rotation.slerp(&other.rotation, t),
+            scale: self.scale.lerp(&other.scale, t),
+        }
+    }
+
+    /// Transformation from object space to world space
+    fn matrix(&self) -> Matrix4<Float> {
+        Matrix4::new_translation(&self.translation)
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+
+    /// Transformation from world space to object space
+    fn inverse(&self) -> Matrix4<Float> {
+        Matrix4::new_nonuniform_scaling(&self.scale.map(|s| 1.0 / s))
+            * self.rotation.inverse().to_homogeneous()
+            * Matrix4::new_translation(&-self.translation)
+    }
+
+    /// Linear part of the transformation from object space to world space
+    fn linear(&self) -> Matrix3<Float> {
+        self.rotation.to_rotation_matrix().into_inner() * Matrix3::from_diagonal(&self.scale)
+    }
+
+    /// Transformation for the normals from object space to world space
+    fn normal_matrix(&self) -> Matrix3<Float> {
+        self.rotation.to_rotation_matrix().into_inner()
+            * Matrix3::from_diagonal(&self.scale.map(|s| 1.0 / s))
+    }
+}
+
+#[derive(Debug, Clone)]
+/// `KeyframedTransform` object. It wraps the given [Object] and has adjusted `hit()` and `bounding_box()` methods based on the keyframes given. The transformation is interpolated between the keyframes at the `time` of each [Ray], producing motion blur. Before the first and after the last keyframe, the object stays still.
+pub struct KeyframedTransform<'scene> {
+    object: Arc<Hitable<'scene>>,
+    poses: Vec<Pose>,
+    aabb: Option<AABB>,
+}
+
+impl<'scene> KeyframedTransform<'scene> {
+    /// Creates a new `KeyframedTransform` object. It wraps the given [Object] and has adjusted `hit()` and `bounding_box()` methods based on the keyframes given.
+    ///
+    /// # Errors
+    /// Returns an error if no keyframes are given, or if a keyframe has a zero scale component, as the transformation would not be invertible.
+    pub fn new(object: Arc<Hitable<'scene>>, keyframes: &[Keyframe]) -> Result<Self, &'static str> {
+        if keyframes.is_empty() {
+            return Err("KeyframedTransform needs at least one keyframe");
+        }
+        if keyframes
+            .iter()
+            .any(|keyframe| keyframe.scale.iter().any(|&s| s == 0.0 || !s.is_finite()))
+        {
+            return Err("KeyframedTransform keyframes need non-zero, finite scales");
+        }
+        let mut poses: Vec<Pose> = keyframes.iter().map(Pose::from).collect();
+        poses.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        // The bounding box of the motion is the union of the bounding boxes along the motion. Rotations do not move the corners in straight lines, so evaluate multiple steps between each pair of keyframes.
+        let aabb = object.aabb().map(|bbox| {
+            let mut aabb = transformed_aabb(bbox, &poses[0].matrix());
+            for pair in poses.windows(2) {
+                for step in 1..=MOTION_BOUNDS_STEPS {
+                    #[allow(clippy::cast_precision_loss)]
+                    let t = step as Float / MOTION_BOUNDS_STEPS as Float;
+                    let pose = pair[0].interpolate(&pair[1], t);
+                    aabb = AABB::combine(&aabb, &transformed_aabb(bbox, &pose.matrix()));
+                }
+            }
+            aabb
+        });
+
+        Ok(KeyframedTransform {
+            object,
+            poses,
+            aabb,
+        })
+    }
+
+    /// Returns the interpolated pose of the object at the given time
+    fn pose(&self, time: Float) -> Pose {
+        let first = &self.poses[0];
+        let last = &self.poses[self.poses.len() - 1];
+        if time <= first.time {
+            return first.clone();
+        }
+        if time >= last.time {
+            return last.clone();
+        }
+        // Find the keyframe interval containing the time
+        let index = self.poses.partition_point(|pose| pose.time <= time);
+        let (before, after) = (&self.poses[index - 1], &self.poses[index]);
+        let t = (time - before.time) / (after.time - before.time);
+        before.interpolate(after, t)
+    }
+}
+
+impl HitableTrait for KeyframedTransform<'_> {
+    /// Hit method for the [`KeyframedTransform`] object. Finds the transformation-adjusted [`HitRecord`] for the possible intersection of the [Ray] with the encased [Object] at the time of the [Ray].
+    fn hit(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        let pose = self.pose(ray.time);
+        hit_transformed(
+            &self.object,
+            &pose.inverse(),
+            &pose.linear(),
+            &pose.normal_matrix(),
+            ray,
+            distance_min,
+            distance_max,
+            rng,
+        )
+    }
+
+    /// Bounding box method for the [`KeyframedTransform`] object. Finds the axis-aligned bounding box [AABB] containing the encased [Object] during the entire motion.
+    fn aabb(&self) -> Option<&AABB> {
+        self.aabb.as_ref()
+    }
+
+    /// Returns the probability density function value of the encased [Object] in the object space at the given time. This is exact for uniform scaling.
+    fn pdf_value(
+        &self,
+        origin: Position,
+        direction: Direction,
+        wavelength: Wavelength,
+        time: Float,
+        rng: &mut SmallRng,
+    ) -> Float {
+        let ray = Ray {
+            origin,
+            direction,
+            time,
+            wavelength,
+        };
+        let (local_ray, _scale) = local_ray(&ray, &self.pose(time).inverse());
+        self.object
+            .pdf_value(local_ray.origin, local_ray.direction, wavelength, time, rng)
+    }
+
+    /// Returns a random point on the encased [Object] in its pose at the given time, matching [`KeyframedTransform::pdf_value`].
+    fn random(&self, origin: Position, sample: Vec2, time: Float) -> Displacement {
+        let pose = self.pose(time);
+        let local_origin: Position = pose.inverse().transform_point(&Point3::from(origin)).coords;
+        let local_point: Position = local_origin + self.object.random(local_origin, sample, time);
+        let point: Position = pose
+            .matrix()
+            .transform_point(&Point3::from(local_point))
+            .coords;
+        point - origin
+    }
+
+    fn centroid(&self) -> Position {
+        self.aabb
+            .as_ref()
+            .map_or_else(|| self.object.centroid(), AABB::centroid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::vec;
+    use nalgebra::Unit;
+    use rand::SeedableRng;
+
+    use crate::objects::Sphere;
+
+    use super::*;
+
+    fn ray_at(time: Float) -> Ray {
+        Ray {
+            origin: Position::new(0.0, 0.0, -10.0),
+            direction: Unit::new_unchecked(Vec3::new(0.0, 0.0, 1.0)),
+            time,
+            wavelength: 600,
+        }
+    }
+
+    #[test]
+    fn moving_sphere() {
+        let mut rng = SmallRng::from_os_rng();
+        let material = Box::default();
+        let sphere = Hitable::Sphere(Sphere::new(Position::new(0.0, 0.0, 0.0), 1.0, &material));
+        let keyframes = vec![
+            Keyframe {
+                time: 0.0,
+                translation: Vec3::new(0.0, 0.0, 0.0),
+                rotation: Vec3::new(0.0, 0.0, 0.0),
+                scale: Vec3::new(1.0, 1.0, 1.0),
+            },
+            Keyframe {
+                time: 1.0,
+                translation: Vec3::new(10.0, 0.0, 0.0),
+                rotation: Vec3::new(0.0, 0.0, 0.0),
+                scale: Vec3::new(1.0, 1.0, 1.0),
+            },
+        ];
+        let transform =
+            KeyframedTransform::new(Arc::new(sphere), &keyframes).expect("Invalid keyframes");
+
+        let aabb = transform.aabb().expect("No AABB for the transform");
+        assert!(aabb.x.min <= -1.0);
+        assert!(aabb.x.max >= 11.0);
+
+        assert!(transform
+            .hit(&ray_at(0.0), 0.0, Float::INFINITY, &mut rng)
+            .is_some());
+        assert!(transform
+            .hit(&ray_at(1.0), 0.0, Float::INFINITY, &mut rng)
+            .is_none());
+        // Still at the last keyframe after the motion has ended
+        assert!(transform
+            .hit(&ray_at(2.0), 0.0, Float::INFINITY, &mut rng)
+            .is_none());
+    }
+
+    #[test]
+    fn interpolated_pose() {
+        let material = Box::default();
+        let sphere = Hitable::Sphere(Sphere::new(Position::new(0.0, 0.0, 0.0), 1.0, &material));
+        let keyframes = vec![
+            Keyframe {
+                time: 1.0,
+                translation: Vec3::new(2.0, 0.0, 0.0),
+                rotation: Vec3::new(0.0, 90.0, 0.0),
+                scale: Vec3::new(3.0, 3.0, 3.0),
+            },
+            Keyframe {
+                time: 0.0,
+                translation: Vec3::new(0.0, 0.0, 0.0),
+                rotation: Vec3::new(0.0, 0.0, 0.0),
+                scale: Vec3::new(1.0, 1.0, 1.0),
+            },
+        ];
+        let transform =
+            KeyframedTransform::new(Arc::new(sphere), &keyframes).expect("Invalid keyframes");
+
+        let pose = transform.pose(0.5);
+        assert!((pose.translation - Vec3::new(1.0, 0.0, 0.0)).norm() <= 1e-5);
+        assert!((pose.scale - Vec3::new(2.0, 2.0, 2.0)).norm() <= 1e-5);
+        assert!((pose.rotation.angle() - 45.0_f32.to_radians()).abs() <= 1e-5);
+        let identity = pose.matrix() * pose.inverse();
+        assert!((identity - Matrix4::identity()).norm() <= 1e-5);
+    }
+
+    #[test]
+    fn random_matches_pdf_value_at_time() {
+        let mut rng = SmallRng::from_os_rng();
+        let material = Box::default();
+        let sphere = Hitable::Sphere(Sphere::new(Position::new(0.0, 0.0, 0.0), 1.0, &material));
+        let keyframes = vec![
+            Keyframe {
+                time: 0.0,
+                translation: Vec3::new(0.0, 0.0, 0.0),
+                rotation: Vec3::new(0.0, 0.0, 0.0),
+                scale: Vec3::new(1.0, 1.0, 1.0),
+            },
+            Keyframe {
+                time: 1.0,
+                translation: Vec3::new(10.0, 0.0, 0.0),
+                rotation: Vec3::new(0.0, 0.0, 0.0),
+                scale: Vec3::new(1.0, 1.0, 1.0),
+            },
+        ];
+        let transform =
+            KeyframedTransform::new(Arc::new(sphere), &keyframes).expect("Invalid keyframes");
+        let origin = Position::new(5.0, 0.0, -10.0);
+        let direction_at = |time| {
+            let displacement = transform.random(origin, Vec2::new(0.3, 0.6), time);
+            Unit::new_normalize(displacement)
+        };
+        for time in [0.0, 0.5, 1.0] {
+            let direction = direction_at(time);
+            assert!(transform.pdf_value(origin, direction, 600, time, &mut rng) > 0.0);
+        }
+        // The sphere has moved out of the way of the directions sampled at the other end of the motion
+        assert!(transform.pdf_value(origin, direction_at(1.0), 600, 0.0, &mut rng) <= 0.0);
+        assert!(transform.pdf_value(origin, direction_at(0.0), 600, 1.0, &mut rng) <= 0.0);
+    }
+
+    #[test]
+    fn invalid_keyframes_are_rejected() {
+        let material = Box::default();
+        let sphere = Arc::new(Hitable::Sphere(Sphere::new(
+            Position::new(0.0, 0.0, 0.0),
+            1.0,
+            &material,
+        )));
+        assert!(KeyframedTransform::new(sphere.clone(), &[]).is_err());
+        let keyframes = vec![Keyframe {
+            time: 0.0,
+            translation: Vec3::new(0.0, 0.0, 0.0),
+            rotation: Vec3::new(0.0, 0.0, 0.0),
+            scale: Vec3::new(1.0, 0.0, 1.0),
+        }];
+        assert!(KeyframedTransform::new(sphere, &keyframes).is_err());
+    }
+}
@@ -22,6 +22,12 @@ pub struct MovingSphereInit {
     pub center_0: Position,
     /// Center point of the sphere at `time_1`
     pub center_1: Position,
+    /// Time at which the sphere is at `center_0`. Defaults to `0.0`.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub time_0: Float,
+    /// Time at which the sphere is at `center_1`. Defaults to `1.0`.
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_time_1"))]
+    pub time_1: Float,
     /// Radius of the sphere.
     pub radius: Float,
     #[cfg_attr(feature = "serde-derive", serde(default))]
@@ -29,6 +35,11 @@ pub struct MovingSphereInit {
     pub material: MaterialInit,
 }
 
+#[cfg(feature = "serde-derive")]
+fn default_time_1() -> Float {
+    1.0
+}
+
 #[derive(Debug, Clone)]
 /// A moving sphere object. This is represented by one `radius`, two center points `center_0` `center_1`, two times `time_0` `time_1`, and a [Material]. Any [Rays](Ray) hitting the object will also have an internal `time` value, which will be used for determining the interpolated position of the sphere at that time. With lots of rays hitting every pixel but at randomized times, we get temporal multiplexing and an approximation of perceived motion blur.
 pub struct MovingSphere<'scene> {
@@ -131,6 +142,7 @@ impl HitableTrait for MovingSphere<'_> {
                     u,
                     v,
                     material: self.material,
+                    tangent: None,
                     front_face: false, // TODO: fix having to declare it before calling face_normal
                 };
                 record.set_face_normal(ray, outward_normal);
@@ -150,6 +162,7 @@ impl HitableTrait for MovingSphere<'_> {
                     u,
                     v,
                     material: self.material,
+                    tangent: None,
                     front_face: false, // TODO: fix having to declare it before calling face_normal
                 };
                 record.set_face_normal(ray, outward_normal);
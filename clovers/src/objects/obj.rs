@@ -0,0 +1,499 @@
+//! Wavefront OBJ utilities, including mapping of MTL materials
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use nalgebra::{Rotation3, Unit};
+use palette::{white_point::E, Xyz};
+use rand::rngs::SmallRng;
+use std::path::Path;
+#[cfg(feature = "traces")]
+use tracing::{debug, warn};
+
+use crate::{
+    aabb::AABB,
+    bvh::build::utils::vec_bounding_box,
+    colorinit::ColorInit,
+    hitable::{Hitable, HitableTrait},
+    materials::{
+        Dielectric, DiffuseLight, Kind, Lambertian, Material, MaterialInit, Metal, SharedMaterial,
+    },
+    objects::{initialize_material, Triangle},
+    ray::Ray,
+    textures::{ImageTexture, SolidColor, Texture},
+    wavelength::Wavelength,
+    Direction, Displacement, Float, HitRecord, Position, Vec2, Vec3,
+};
+
+/// Internal OBJ object representation after initialization.
+#[derive(Debug, Clone)]
+pub struct OBJ<'scene> {
+    /// Primitives of the `OBJ` object. Most likely a list of `OBJTriangle`s.
+    pub hitables: Vec<Hitable<'scene>>,
+    /// Axis-aligned bounding box of the object
+    pub aabb: AABB,
+}
+
+/// OBJ structure. This gets converted into an internal representation using [`OBJTriangle`]s
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+pub struct OBJInit {
+    /// Used for multiple importance sampling
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub priority: bool,
+    /// Path of the .obj file. A material library referenced with `mtllib` is looked up relative to this file.
+    pub path: String,
+    /// Material to use for the faces that do not have a material assigned in the .mtl file
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub material: MaterialInit,
+    /// Names of the groups or objects to import from the file. If empty, all groups are imported.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub groups: Vec<String>,
+    /// Scaling factor for the object
+    pub scale: Float,
+    /// Location of the object in the rendered scene
+    pub center: Position,
+    /// Rotation of the object. Described as three angles, `roll`, `pitch`, `yaw`, applied in that order.
+    pub rotation: Vec3,
+}
+
+#[must_use]
+/// Initializes an OBJ
+///
+/// # Panics
+/// This method may panic if the referenced .obj file cannot be opened or if it cannot be parsed.
+pub fn initialize_obj<'scene>(
+    obj_init: OBJInit,
+    materials: &'scene [SharedMaterial],
+) -> OBJ<'scene> {
+    let options = tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        ignore_points: true,
+        ignore_lines: true,
+    };
+    // TODO: error handling!
+    let (models, mtl_materials) = tobj::load_obj(&obj_init.path, &options).unwrap();
+    let directory = Path::new(&obj_init.path).parent().unwrap_or(Path::new(""));
+    let mtl_materials: Vec<&'scene Material> = match mtl_materials {
+        Ok(mtl_materials) => mtl_materials
+            .iter()
+            .map(|mtl| {
+                // TODO: do not leak memory
+                let material: &'scene Material =
+                    Box::leak(Box::new(mtl_to_material(mtl, directory)));
+                material
+            })
+            .collect(),
+        #[cfg_attr(not(feature = "traces"), allow(unused_variables))]
+        Err(error) => {
+            #[cfg(feature = "traces")]
+            warn!("could not load the material library: {error}");
+            Vec::new()
+        }
+    };
+    let default_material = initialize_material(obj_init.material, materials);
+
+    let rotation = Rotation3::from_euler_angles(
+        obj_init.rotation[0].to_radians(),
+        obj_init.rotation[1].to_radians(),
+        obj_init.rotation[2].to_radians(),
+    );
+    let position = |positions: &[Float], index: usize| -> Vec3 {
+        let vertex = Vec3::new(
+            positions[3 * index],
+            positions[3 * index + 1],
+            positions[3 * index + 2],
+        );
+        rotation * vertex * obj_init.scale + obj_init.center
+    };
+    let normal = |normals: &[Float], index: usize| -> Vec3 {
+        let normal = Vec3::new(
+            normals[3 * index],
+            normals[3 * index + 1],
+            normals[3 * index + 2],
+        );
+        rotation * normal
+    };
+
+    let mut hitables = Vec::new();
+    for model in models {
+        if !obj_init.groups.is_empty() && !obj_init.groups.contains(&model.name) {
+            continue;
+        }
+        #[cfg(feature = "traces")]
+        debug!("found group `{}`", model.name);
+
+        let mesh = model.mesh;
+        let material: &'scene Material = mesh
+            .material_id
+            .and_then(|id| mtl_materials.get(id).copied())
+            .unwrap_or(default_material);
+        let has_normals = !mesh.normals.is_empty();
+        let has_tex_coords = !mesh.texcoords.is_empty();
+
+        for face in mesh.indices.chunks_exact(3) {
+            let indices = [face[0] as usize, face[1] as usize, face[2] as usize];
+            let [a, b, c] = indices.map(|i| position(&mesh.positions, i));
+            let triangle = Triangle::from_coordinates(a, b, c, material);
+            let normals =
+                has_normals.then(|| indices.map(|i| Unit::new_normalize(normal(&mesh.normals, i))));
+            let tex_coords = has_tex_coords.then(|| {
+                indices.map(|i| Vec2::new(mesh.texcoords[2 * i], mesh.texcoords[2 * i + 1]))
+            });
+            hitables.push(Hitable::OBJTriangle(OBJTriangle::new(
+                triangle, normals, tex_coords,
+            )));
+        }
+    }
+    // TODO: remove unwrap
+    let aabb = vec_bounding_box(&hitables).unwrap();
+
+    OBJ { hitables, aabb }
+}
+
+/// Converts a material from a .mtl file into a [`Material`].
+///
+/// The mapping is an approximation, as the MTL format describes a Phong-like shading model:
+/// - an emissive color `Ke` results in a [`DiffuseLight`]
+/// - a dissolve `d` below one results in a [`Dielectric`] with the refractive index `Ni`, tinted with the transmission filter `Tf`
+/// - a specular color `Ks` brighter than the diffuse color `Kd` results in a [`Metal`], with the fuzz derived from the specular exponent `Ns`
+/// - otherwise, a [`Lambertian`] with the diffuse color `Kd` or the diffuse texture `map_Kd` is used
+#[must_use]
+pub fn mtl_to_material(mtl: &tobj::Material, directory: &Path) -> Material {
+    let diffuse = mtl.diffuse.unwrap_or([0.5, 0.5, 0.5]);
+    let specular = mtl.specular.unwrap_or([0.0, 0.0, 0.0]);
+    let emission = mtl.unknown_param.get("Ke").and_then(|ke| parse_color(ke));
+    let transmission = mtl.unknown_param.get("Tf").and_then(|tf| parse_color(tf));
+    let dissolve = mtl.dissolve.unwrap_or(1.0);
+    let brightest = |color: [Float; 3]| color[0].max(color[1]).max(color[2]);
+
+    let kind: Kind = if let Some(emission) = emission.filter(|&ke| brightest(ke) > 0.0) {
+        Kind::DiffuseLight(DiffuseLight::new(SolidColor::new(ColorInit::Color(
+            emission,
+        ))))
+    } else if dissolve < 1.0 {
+        let color: Xyz<E> = ColorInit::Color(transmission.unwrap_or([1.0, 1.0, 1.0])).into();
+        Kind::Dielectric(Dielectric {
+            refractive_index: mtl.optical_density.unwrap_or(1.5),
+            color,
+        })
+    } else if brightest(specular) > brightest(diffuse) {
+        // Common conversion from a Phong exponent to a roughness value
+        let shininess = mtl.shininess.unwrap_or(0.0).max(0.0);
+        let fuzz = (2.0 / (shininess + 2.0)).sqrt();
+        Kind::Metal(Metal::new(
+            SolidColor::new(ColorInit::Color(specular)),
+            fuzz,
+        ))
+    } else {
+        let albedo: Texture = match &mtl.diffuse_texture {
+            Some(texture) => match ImageTexture::new(directory.join(texture).to_string_lossy()) {
+                Ok(texture) => texture.into(),
+                #[cfg_attr(not(feature = "traces"), allow(unused_variables))]
+                Err(error) => {
+                    #[cfg(feature = "traces")]
+                    warn!("could not load the texture `{texture}`: {error}");
+                    SolidColor::new(ColorInit::Color(diffuse)).into()
+                }
+            },
+            None => SolidColor::new(ColorInit::Color(diffuse)).into(),
+        };
+        Kind::Lambertian(Lambertian::new(albedo))
+    };
+
+    Material {
+        kind,
+        thin_film: None,
+    }
+}
+
+/// Parses a whitespace-separated color triple, as used in the .mtl file format
+fn parse_color(value: &str) -> Option<[Float; 3]> {
+    let mut components = value.split_whitespace().map(str::parse::<Float>);
+    let r = components.next()?.ok()?;
+    // A single value is a shorthand for a gray color
+    let g = components.next().transpose().ok()?.unwrap_or(r);
+    let b = components.next().transpose().ok()?.unwrap_or(g);
+    Some([r, g, b])
+}
+
+/// A triangle from an OBJ file. Wraps a [`Triangle`] and optionally adds vertex normals and texture coordinates, interpolated across the surface of the triangle.
+#[derive(Debug, Clone)]
+pub struct OBJTriangle<'scene> {
+    /// The underlying flat triangle
+    pub triangle: Triangle<'scene>,
+    /// Vertex normals of the corners of the triangle, used for smooth shading
+    pub normals: Option<[Direction; 3]>,
+    /// Texture coordinates of the corners of the triangle
+    pub tex_coords: Option<[Vec2; 3]>,
+}
+
+impl<'scene> OBJTriangle<'scene> {
+    /// Creates a new `OBJTriangle` from a triangle and the optional per-vertex attributes
+    #[must_use]
+    pub fn new(
+        triangle: Triangle<'scene>,
+        normals: Option<[Direction; 3]>,
+        tex_coords: Option<[Vec2; 3]>,
+    ) -> Self {
+        OBJTriangle {
+            triangle,
+            normals,
+            tex_coords,
+        }
+    }
+}
+
+impl HitableTrait for OBJTriangle<'_> {
+    fn hit(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        let mut record = self.triangle.hit(ray, distance_min, distance_max, rng)?;
+        // Barycentric weights of the corners
+        let (alpha, beta) = (record.u, record.v);
+        let gamma = 1.0 - alpha - beta;
+
+        if let Some([n0, n1, n2]) = self.normals {
+            // Keep the face orientation of the geometric normal, only use the interpolated normal for shading
+            let normal: Direction = Unit::new_normalize(gamma * *n0 + alpha * *n1 + beta * *n2);
+            let outward = if normal.dot(&self.triangle.normal) < 0.0 {
+                -normal
+            } else {
+                normal
+            };
+            record.normal = if record.front_face { outward } else { -outward };
+        }
+
+        if let Some([t0, t1, t2]) = self.tex_coords {
+            let coord: Vec2 = gamma * t0 + alpha * t1 + beta * t2;
+            record.u = coord[0];
+            record.v = coord[1];
+        }
+
+        Some(record)
+    }
+
+    fn aabb(&self) -> Option<&AABB> {
+        self.triangle.aabb()
+    }
+
+    fn pdf_value(
+        &self,
+        origin: Position,
+        direction: Direction,
+        wavelength: Wavelength,
+        time: Float,
+        rng: &mut SmallRng,
+    ) -> Float {
+        self.triangle
+            .pdf_value(origin, direction, wavelength, time, rng)
+    }
+
+    fn random(&self, origin: Position, sample: Vec2, time: Float) -> Displacement {
+        self.triangle.random(origin, sample, time)
+    }
+
+    fn centroid(&self) -> Position {
+        self.triangle.centroid()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{format, vec};
+    use core::fmt::Write;
+    use rand::SeedableRng;
+    use std::{fs, path::PathBuf};
+
+    use crate::objects::test_directory;
+    use crate::random::{sample_unit_vector, BounceSample};
+
+    use super::*;
+
+    /// Names of the single-triangle groups of the test file, in order along the x axis. The first one has no material in the .mtl file.
+    const GROUPS: [&str; 6] = ["plain", "lamp", "glass", "metal", "red", "blue"];
+
+    const MTL: &str = "
+newmtl lamp
+Kd 0.8 0.8 0.8
+Ke 4 4 4
+
+newmtl glass
+Kd 0.8 0.8 0.8
+d 0.5
+Ni 1.33
+Tf 0.9 1 0.9
+
+newmtl metal
+Kd 0.1 0.1 0.1
+Ks 0.9 0.9 0.9
+Ns 98
+
+newmtl textured
+Kd 0.5 0.5 0.5
+map_Kd texture.png
+";
+
+    /// Writes an .obj file with a unit triangle for each of the [`GROUPS`], the .mtl file and a texture with a red and a blue pixel into a temporary directory. Returns the path of the .obj file.
+    fn write_files(name: &str) -> PathBuf {
+        let directory = test_directory(&format!("obj-{name}"));
+
+        let mut obj = String::from("mtllib materials.mtl\nvn 0 0 1\n");
+        for (index, group) in GROUPS.iter().enumerate() {
+            let x = 2 * index;
+            let u = if *group == "blue" { 0.75 } else { 0.25 };
+            writeln!(
+                obj,
+                "o {group}\nv {x} 0 0\nv {} 0 0\nv {x} 1 0\nvt {u} 0.5",
+                x + 1
+            )
+            .unwrap();
+            match *group {
+                "plain" => (),
+                "red" | "blue" => obj += "usemtl textured\n",
+                material => writeln!(obj, "usemtl {material}").unwrap(),
+            }
+            let (v, t) = (3 * index + 1, index + 1);
+            writeln!(obj, "f {v}/{t}/1 {}/{t}/1 {}/{t}/1", v + 1, v + 2).unwrap();
+        }
+        fs::write(directory.join("materials.mtl"), MTL).unwrap();
+        let texture = image::RgbImage::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                image::Rgb([255, 0, 0])
+            } else {
+                image::Rgb([0, 0, 255])
+            }
+        });
+        texture.save(directory.join("texture.png")).unwrap();
+        let path = directory.join("test.obj");
+        fs::write(&path, obj).unwrap();
+        path
+    }
+
+    fn obj_init(path: &Path) -> OBJInit {
+        OBJInit {
+            priority: false,
+            path: path.to_string_lossy().into(),
+            material: MaterialInit::Shared("fallback".into()),
+            groups: Vec::new(),
+            scale: 1.0,
+            center: Position::new(0.0, 0.0, 0.0),
+            rotation: Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn ray(origin: Position, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction: Unit::new_normalize(direction),
+            time: 0.0,
+            wavelength: 650,
+        }
+    }
+
+    fn hit<'a>(obj: &'a OBJ, ray: &Ray) -> HitRecord<'a> {
+        let mut rng = SmallRng::seed_from_u64(0);
+        obj.hitables
+            .iter()
+            .find_map(|hitable| hitable.hit(ray, 0.0, Float::INFINITY, &mut rng))
+            .expect("No hit on the OBJ")
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn mtl_materials() {
+        let path = write_files("materials");
+        let materials = vec![SharedMaterial {
+            name: "fallback".into(),
+            material: Material::default(),
+        }];
+        let obj = initialize_obj(obj_init(&path), &materials);
+        assert_eq!(obj.hitables.len(), GROUPS.len());
+        let records: Vec<(Ray, HitRecord)> = (0..GROUPS.len())
+            .map(|index| {
+                let origin = Position::new(2.0 * index as Float + 0.25, 0.25, 10.0);
+                let ray = ray(origin, Vec3::new(0.0, 0.0, -1.0));
+                let record = hit(&obj, &ray);
+                (ray, record)
+            })
+            .collect();
+        let index = |group: &str| GROUPS.iter().position(|g| *g == group).unwrap();
+        let material = |group: &str| match &obj.hitables[index(group)] {
+            Hitable::OBJTriangle(triangle) => triangle.triangle.material,
+            _ => panic!("The hitable is not an OBJ triangle"),
+        };
+
+        // Faces without a material use the material of the object
+        assert!(core::ptr::eq(
+            material("plain"),
+            &raw const materials[0].material
+        ));
+
+        assert!(matches!(material("lamp").kind, Kind::DiffuseLight(_)));
+
+        let Kind::Dielectric(glass) = &material("glass").kind else {
+            panic!("The glass material is not a dielectric");
+        };
+        assert!((glass.refractive_index - 1.33).abs() <= 1e-6);
+
+        // The fuzz displaces the reflection of a ray hitting the triangle head-on by a fixed offset
+        assert!(matches!(material("metal").kind, Kind::Metal(_)));
+        let (ray, record) = &records[index("metal")];
+        let sample = BounceSample {
+            scatter: Vec2::new(0.0, 0.5),
+            ..BounceSample::random(&mut SmallRng::seed_from_u64(0))
+        };
+        assert!((sample_unit_vector(sample.scatter).x - 1.0).abs() <= 1e-6);
+        let scattered = record
+            .material
+            .scatter(ray, record, sample)
+            .and_then(|scatter| scatter.specular_ray)
+            .expect("No specular ray from the metal");
+        let fuzz = Float::sqrt(2.0 / (98.0 + 2.0));
+        assert!((scattered.direction.x / scattered.direction.z - fuzz).abs() <= 1e-5);
+
+        // The texture coordinates of the two triangles point to the red and the blue pixel of the texture
+        assert!(matches!(material("red").kind, Kind::Lambertian(_)));
+        let color = |group: &str| {
+            let (ray, record) = &records[index(group)];
+            record.material.color(ray, 650, record)
+        };
+        assert!(color("red") > 2.0 * color("blue"));
+    }
+
+    #[test]
+    fn transform_and_groups() {
+        let path = write_files("transform");
+        let materials = vec![SharedMaterial {
+            name: "fallback".into(),
+            material: Material::default(),
+        }];
+        let obj = initialize_obj(
+            OBJInit {
+                groups: vec!["metal".into()],
+                scale: 2.0,
+                center: Position::new(10.0, 0.0, 0.0),
+                rotation: Vec3::new(90.0, 0.0, 0.0),
+                ..obj_init(&path)
+            },
+            &materials,
+        );
+        assert_eq!(obj.hitables.len(), 1);
+
+        // The triangle is scaled, then rotated from the xy plane into the xz plane, then moved
+        assert!((obj.aabb.x.min - 22.0).abs() <= 1e-3);
+        assert!((obj.aabb.x.max - 24.0).abs() <= 1e-3);
+        assert!((obj.aabb.z.max - 2.0).abs() <= 1e-3);
+
+        // The vertex normals are rotated along with the triangle
+        let ray = ray(Position::new(22.5, -10.0, 0.5), Vec3::new(0.0, 1.0, 0.0));
+        let record = hit(&obj, &ray);
+        assert!((record.distance - 10.0).abs() <= 1e-4);
+        assert!((*record.normal - Vec3::new(0.0, -1.0, 0.0)).norm() <= 1e-4);
+        assert!(record.front_face);
+    }
+}
@@ -121,7 +121,7 @@ pub fn initialize_ply<'scene>(
 #[allow(trivial_numeric_casts)]
 #[allow(clippy::cast_precision_loss)]
 #[allow(clippy::cast_possible_truncation)]
-fn property_to_float(p: &Property) -> Float {
+pub(crate) fn property_to_float(p: &Property) -> Float {
     match *p {
         Property::Int(i) => i as Float,
         Property::UInt(u) => u as Float,
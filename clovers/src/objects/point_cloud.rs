@@ -0,0 +1,493 @@
+//! Point clouds, e.g. from LIDAR scans and photogrammetry. Reads the vertices of a PLY file, ignoring any faces.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use nalgebra::{Rotation3, Unit};
+use palette::{chromatic_adaptation::AdaptInto, white_point::E, Srgb, Xyz};
+use ply_rs::{
+    parser::Parser,
+    ply::{DefaultElement, Property},
+};
+use rand::rngs::SmallRng;
+
+use crate::{
+    aabb::AABB,
+    hitable::{get_orientation, HitableTrait},
+    materials::{Material, MaterialInit, MaterialTrait, ScatterRecord, SharedMaterial},
+    random::BounceSample,
+    ray::Ray,
+    spectrum::spectral_power,
+    wavelength::Wavelength,
+    Direction, Displacement, Float, HitRecord, Position, Vec2, Vec3,
+};
+
+use super::{initialize_material, ply::property_to_float};
+
+/// Maximum number of points in a leaf node of the point cloud hierarchy
+const LEAF_SIZE: usize = 4;
+/// Maximum depth of the traversal stack. The hierarchy is built with median splits, so this is enough for any number of points addressable with `u32` indices.
+const STACK_SIZE: usize = 64;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// The shape used for rendering each point of a [`PointCloud`].
+pub enum PointShape {
+    /// A sphere around the point
+    #[default]
+    Sphere,
+    /// A disc oriented by the normal of the point. If the file has no normals, the disc faces the ray.
+    Disc,
+}
+
+/// `PointCloudInit` structure describes the necessary data for constructing a [`PointCloud`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+pub struct PointCloudInit {
+    /// Used for multiple importance sampling. Not supported for point clouds: the scene loader ignores it with a warning.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub priority: bool,
+    /// Path of the .ply file
+    pub path: String,
+    /// Material to use for the points. If the file has vertex colors, they replace the color of the material.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub material: MaterialInit,
+    /// Scaling factor for the object
+    pub scale: Float,
+    /// Location of the object in the rendered scene
+    pub center: Position,
+    /// Rotation of the object. Described as three angles, `roll`, `pitch`, `yaw`, applied in that order.
+    pub rotation: Vec3,
+    /// Radius of each point, in scene units
+    pub radius: Float,
+    /// Shape used for rendering each point
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub shape: PointShape,
+}
+
+#[must_use]
+/// Initializes a [`PointCloud`] from the vertices of a PLY file. Reads the `x`, `y` and `z` properties, and optionally the `nx`, `ny`, `nz` normals and the `red`, `green`, `blue` colors.
+///
+/// # Panics
+/// This method may panic if the referenced .ply file cannot be opened or if it cannot be parsed.
+pub fn initialize_point_cloud(
+    point_cloud_init: PointCloudInit,
+    materials: &[SharedMaterial],
+) -> PointCloud<'_> {
+    let material = initialize_material(point_cloud_init.material, materials);
+
+    // TODO: error handling!
+    let mut f = std::fs::File::open(point_cloud_init.path).unwrap();
+    let parser = Parser::<DefaultElement>::new();
+    let ply = parser.read_ply(&mut f).unwrap();
+    let vertices = &ply.payload["vertex"];
+
+    let rotation = Rotation3::from_euler_angles(
+        point_cloud_init.rotation[0].to_radians(),
+        point_cloud_init.rotation[1].to_radians(),
+        point_cloud_init.rotation[2].to_radians(),
+    );
+    let mut points = Vec::with_capacity(vertices.len());
+    for vertex in vertices {
+        let position = Vec3::new(
+            property_to_float(&vertex["x"]),
+            property_to_float(&vertex["y"]),
+            property_to_float(&vertex["z"]),
+        );
+        let position = rotation * position * point_cloud_init.scale + point_cloud_init.center;
+        let normal = vertex_normal(vertex).map(|normal| rotation * normal);
+        let color = vertex_color(vertex);
+        points.push(Point {
+            position,
+            normal,
+            color,
+        });
+    }
+
+    PointCloud::new(
+        points,
+        point_cloud_init.radius,
+        point_cloud_init.shape,
+        material,
+    )
+}
+
+/// Returns the normal of the vertex, if it has one
+fn vertex_normal(vertex: &DefaultElement) -> Option<Direction> {
+    let normal = Vec3::new(
+        property_to_float(vertex.get("nx")?),
+        property_to_float(vertex.get("ny")?),
+        property_to_float(vertex.get("nz")?),
+    );
+    Unit::try_new(normal, 0.0)
+}
+
+/// Returns the color of the vertex, if it has one
+fn vertex_color(vertex: &DefaultElement) -> Option<Xyz<E>> {
+    let color = Srgb::new(
+        property_to_unit(vertex.get("red")?),
+        property_to_unit(vertex.get("green")?),
+        property_to_unit(vertex.get("blue")?),
+    );
+    Some(color.adapt_into())
+}
+
+/// Converts a color channel property into the range `0..1`
+#[allow(clippy::cast_precision_loss)]
+fn property_to_unit(p: &Property) -> Float {
+    match *p {
+        Property::UChar(u) => Float::from(u) / 255.0,
+        Property::UShort(u) => Float::from(u) / 65535.0,
+        _ => property_to_float(p),
+    }
+}
+
+/// A single point of a [`PointCloud`], as read from the file
+#[derive(Clone, Debug)]
+pub struct Point {
+    /// Position of the point
+    pub position: Position,
+    /// Optional normal of the point, used for orienting discs
+    pub normal: Option<Direction>,
+    /// Optional color of the point
+    pub color: Option<Xyz<E>>,
+}
+
+/// A node of the point cloud hierarchy, in a compact depth-first layout. Interior nodes have their first child directly after them and the second child at `offset`. Leaf nodes refer to `count` points starting at `offset`.
+#[derive(Clone, Debug)]
+struct PointNode {
+    aabb: AABB,
+    offset: u32,
+    count: u32,
+}
+
+#[derive(Clone, Debug)]
+/// A material wrapper for a colored point. Scatters like the material of the [`PointCloud`], but with the color of the point.
+pub struct PointColor<'scene> {
+    material: &'scene Material,
+    color: Xyz<E>,
+}
+
+impl MaterialTrait for PointColor<'_> {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sample: BounceSample,
+    ) -> Option<ScatterRecord<'_>> {
+        self.material.scatter(ray, hit_record, sample)
+    }
+
+    fn scattering_pdf(&self, hit_record: &HitRecord, scattered: &Ray) -> Option<Float> {
+        self.material.scattering_pdf(hit_record, scattered)
+    }
+
+    fn emit(&self, ray: &Ray, wavelength: Wavelength, hit_record: &HitRecord) -> Float {
+        self.material.emit(ray, wavelength, hit_record)
+    }
+
+    /// Returns the spectral reflectance of the color of the point.
+    fn color(&self, _ray: &Ray, wavelength: Wavelength, _hit_record: &HitRecord) -> Float {
+        spectral_power(self.color, wavelength)
+    }
+
+    fn is_wavelength_dependent(&self) -> bool {
+        self.material.is_wavelength_dependent()
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A point cloud. Each point is rendered as a small sphere or disc. The points are stored in a dedicated bounding volume hierarchy with compact nodes, instead of being separate objects in the scene.
+pub struct PointCloud<'scene> {
+    positions: Vec<Position>,
+    normals: Vec<Direction>,
+    colors: Vec<PointColor<'scene>>,
+    nodes: Vec<PointNode>,
+    radius: Float,
+    shape: PointShape,
+    material: &'scene Material,
+}
+
+impl<'scene> PointCloud<'scene> {
+    /// Creates a new `PointCloud` with the given points, point radius, point shape and material. Normals and colors are used only if every point has them.
+    ///
+    /// # Panics
+    /// This method may panic if the list of points is empty, or if it has more points than can be addressed with `u32` indices.
+    #[must_use]
+    pub fn new(
+        mut points: Vec<Point>,
+        radius: Float,
+        shape: PointShape,
+        material: &'scene Material,
+    ) -> Self {
+        assert!(!points.is_empty(), "point cloud must have points");
+        assert!(u32::try_from(points.len()).is_ok(), "too many points");
+
+        let mut nodes = Vec::new();
+        build(&mut points, 0, radius, &mut nodes);
+
+        let positions = points.iter().map(|point| point.position).collect();
+        let normals = points
+            .iter()
+            .map(|point| point.normal)
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default();
+        let colors = points
+            .iter()
+            .map(|point| point.color.map(|color| PointColor { material, color }))
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default();
+
+        PointCloud {
+            positions,
+            normals,
+            colors,
+            nodes,
+            radius,
+            shape,
+            material,
+        }
+    }
+
+    /// Intersects the given ray with a single point. Returns the distance and the outward normal.
+    fn hit_point(
+        &self,
+        index: usize,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+    ) -> Option<(Float, Direction)> {
+        let position = self.positions[index];
+        let oc = ray.origin - position;
+        match self.shape {
+            PointShape::Sphere => {
+                let half_b = oc.dot(&ray.direction);
+                let c = oc.norm_squared() - self.radius * self.radius;
+                let discriminant = half_b * half_b - c;
+                if discriminant < 0.0 {
+                    return None;
+                }
+                let root = discriminant.sqrt();
+                let distance = [-half_b - root, -half_b + root]
+                    .into_iter()
+                    .find(|distance| *distance > distance_min && *distance < distance_max)?;
+                let normal = Unit::new_normalize(ray.evaluate(distance) - position);
+                Some((distance, normal))
+            }
+            PointShape::Disc => {
+                // Without normals, the disc faces the ray
+                let normal = match self.normals.get(index) {
+                    Some(normal) => *normal,
+                    None => -ray.direction,
+                };
+                let denominator = ray.direction.dot(&normal);
+                if denominator.abs() < Float::EPSILON {
+                    return None;
+                }
+                let distance = -oc.dot(&normal) / denominator;
+                if distance <= distance_min || distance >= distance_max {
+                    return None;
+                }
+                if (ray.evaluate(distance) - position).norm_squared() > self.radius * self.radius {
+                    return None;
+                }
+                Some((distance, normal))
+            }
+        }
+    }
+}
+
+/// Recursively builds the hierarchy for the given points, reordering them so that each leaf refers to a contiguous range. `first` is the index of the first given point in the complete list.
+fn build(points: &mut [Point], first: usize, radius: Float, nodes: &mut Vec<PointNode>) {
+    let padding = Vec3::new(radius, radius, radius);
+    let (min, max) = points.iter().fold(
+        (points[0].position, points[0].position),
+        |(min, max), point| (min.inf(&point.position), max.sup(&point.position)),
+    );
+    let mut aabb = AABB::new_from_coords(min - padding, max + padding);
+    aabb.pad();
+
+    let index = nodes.len();
+    #[allow(clippy::cast_possible_truncation)]
+    nodes.push(PointNode {
+        aabb,
+        offset: first as u32,
+        count: points.len() as u32,
+    });
+    if points.len() <= LEAF_SIZE {
+        return;
+    }
+
+    // Median split along the longest axis
+    let extent = max - min;
+    let axis = extent.imax();
+    let middle = points.len() / 2;
+    points.select_nth_unstable_by(middle, |a, b| a.position[axis].total_cmp(&b.position[axis]));
+    let (left, right) = points.split_at_mut(middle);
+    build(left, first, radius, nodes);
+    let second = nodes.len();
+    build(right, first + middle, radius, nodes);
+    #[allow(clippy::cast_possible_truncation)]
+    {
+        nodes[index].offset = second as u32;
+    }
+    nodes[index].count = 0;
+}
+
+impl HitableTrait for PointCloud<'_> {
+    /// Hit method for the [`PointCloud`] object. Returns a [`HitRecord`] for the closest point the given [Ray] intersects with at the given distance interval.
+    fn hit(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        _rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        let mut closest: Option<(usize, Float, Direction)> = None;
+        let mut distance_max = distance_max;
+        let mut stack = [0_usize; STACK_SIZE];
+        let mut stack_size = 1;
+
+        while stack_size > 0 {
+            stack_size -= 1;
+            let node_index = stack[stack_size];
+            let node = &self.nodes[node_index];
+            if !node.aabb.hit(ray, distance_min, distance_max) {
+                continue;
+            }
+            let offset = node.offset as usize;
+            if node.count > 0 {
+                for index in offset..offset + node.count as usize {
+                    if let Some((distance, normal)) =
+                        self.hit_point(index, ray, distance_min, distance_max)
+                    {
+                        distance_max = distance;
+                        closest = Some((index, distance, normal));
+                    }
+                }
+            } else {
+                // The first child is directly after its parent
+                stack[stack_size] = offset;
+                stack[stack_size + 1] = node_index + 1;
+                stack_size += 2;
+            }
+        }
+
+        let (index, distance, normal) = closest?;
+        let (front_face, normal) = get_orientation(ray, normal);
+        let material: &dyn MaterialTrait = match self.colors.get(index) {
+            Some(color) => color,
+            None => self.material,
+        };
+        Some(HitRecord {
+            distance,
+            position: ray.evaluate(distance),
+            normal,
+            u: 0.5,
+            v: 0.5,
+            material,
+            tangent: None,
+            front_face,
+        })
+    }
+
+    /// Returns the axis-aligned bounding box [AABB] of the point cloud.
+    fn aabb(&self) -> Option<&AABB> {
+        Some(&self.nodes[0].aabb)
+    }
+
+    /// Returns zero. Point clouds cannot be sampled, and should not be used as priority objects.
+    fn pdf_value(
+        &self,
+        _origin: Position,
+        _direction: Direction,
+        _wavelength: Wavelength,
+        _time: Float,
+        _rng: &mut SmallRng,
+    ) -> Float {
+        0.0
+    }
+
+    fn random(&self, _origin: Position, _sample: Vec2, _time: Float) -> Displacement {
+        unimplemented!("Point clouds cannot be sampled, and should not be used as priority objects")
+    }
+
+    fn centroid(&self) -> Position {
+        self.nodes[0].aabb.centroid()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    fn random_points(count: usize, rng: &mut SmallRng) -> Vec<Point> {
+        (0..count)
+            .map(|_| Point {
+                position: Vec3::new(rng.random(), rng.random(), rng.random()) * 10.0,
+                normal: None,
+                color: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hierarchy_matches_brute_force() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let material = Material::default();
+        let points = random_points(1000, &mut rng);
+        let cloud = PointCloud::new(points, 0.2, PointShape::Sphere, &material);
+        for _ in 0..200 {
+            let ray = Ray {
+                origin: Vec3::new(rng.random(), rng.random(), -1.0) * 10.0,
+                direction: Unit::new_normalize(Vec3::new(
+                    rng.random::<Float>() - 0.5,
+                    rng.random::<Float>() - 0.5,
+                    1.0,
+                )),
+                time: 0.0,
+                wavelength: 600,
+            };
+            let expected = (0..cloud.positions.len())
+                .filter_map(|index| cloud.hit_point(index, &ray, 0.001, Float::INFINITY))
+                .map(|(distance, _)| distance)
+                .min_by(Float::total_cmp);
+            let hit = cloud.hit(&ray, 0.001, Float::INFINITY, &mut rng);
+            assert_eq!(expected, hit.map(|hit| hit.distance));
+        }
+    }
+
+    #[test]
+    fn colored_discs() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let material = Material::default();
+        let points = vec![Point {
+            position: Vec3::new(0.0, 0.0, 0.0),
+            normal: Some(Unit::new_normalize(Vec3::new(0.0, 1.0, 0.0))),
+            color: Some(Xyz::new(0.2, 0.3, 0.4)),
+        }];
+        let cloud = PointCloud::new(points, 1.0, PointShape::Disc, &material);
+        let ray = Ray {
+            origin: Vec3::new(0.5, 5.0, 0.0),
+            direction: Unit::new_normalize(Vec3::new(0.0, -1.0, 0.0)),
+            time: 0.0,
+            wavelength: 600,
+        };
+        let hit = cloud
+            .hit(&ray, 0.001, Float::INFINITY, &mut rng)
+            .expect("No hit on the disc");
+        assert!((hit.distance - 5.0).abs() <= 1e-5);
+        assert!((hit.normal.y - 1.0).abs() <= 1e-5);
+        let expected = spectral_power(Xyz::new(0.2, 0.3, 0.4), 600);
+        assert!((hit.material.color(&ray, 600, &hit) - expected).abs() <= 1e-5);
+        // Discs are flat, so a ray along the disc misses it
+        let ray = Ray {
+            origin: Vec3::new(-5.0, 0.5, 0.0),
+            direction: Unit::new_normalize(Vec3::new(1.0, 0.0, 0.0)),
+            time: 0.0,
+            wavelength: 600,
+        };
+        assert!(cloud.hit(&ray, 0.001, Float::INFINITY, &mut rng).is_none());
+    }
+}
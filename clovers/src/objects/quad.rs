@@ -5,13 +5,12 @@ use crate::hitable::HitableTrait;
 use crate::materials::MaterialInit;
 use crate::wavelength::Wavelength;
 use crate::{
-    aabb::AABB, hitable::get_orientation, materials::Material, ray::Ray, Float, HitRecord, Vec3,
-    EPSILON_RECT_THICKNESS,
+    aabb::AABB, hitable::get_orientation, materials::Material, ray::Ray, Float, HitRecord, Vec2,
+    Vec3, EPSILON_RECT_THICKNESS,
 };
 use crate::{Direction, Displacement, Position, EPSILON_SHADOW_ACNE};
 use nalgebra::Unit;
 use rand::rngs::SmallRng;
-use rand::Rng;
 
 /// Initialization structure for a Quad object.
 #[derive(Clone, Debug)]
@@ -127,6 +126,7 @@ impl HitableTrait for Quad<'_> {
             u: alpha,
             v: beta,
             material: self.material,
+            tangent: None,
             front_face,
         })
     }
@@ -164,10 +164,10 @@ impl HitableTrait for Quad<'_> {
     }
 
     /// Returns a random point on the quadrilateral surface
-    fn random(&self, origin: Position, rng: &mut SmallRng) -> Displacement {
+    fn random(&self, origin: Position, sample: Vec2, _time: Float) -> Displacement {
         let point: Position = self.q // world-coordinate corner + random distances along edge vectors
-                + (rng.random::<Float>() * self.u)
-                + (rng.random::<Float>() * self.v);
+                + (sample.x * self.u)
+                + (sample.y * self.v);
         point - origin
     }
 
@@ -144,6 +144,13 @@ impl HitableTrait for RotateY<'_> {
         normal[2] = -self.sin_theta * hit_record.normal[0] + self.cos_theta * hit_record.normal[2];
 
         let normal = Unit::new_normalize(normal);
+        let tangent = hit_record.tangent.map(|tangent| {
+            Unit::new_normalize(Vec3::new(
+                self.cos_theta * tangent.x + self.sin_theta * tangent.z,
+                tangent.y,
+                -self.sin_theta * tangent.x + self.cos_theta * tangent.z,
+            ))
+        });
 
         let mut record = HitRecord {
             distance,
@@ -152,6 +159,7 @@ impl HitableTrait for RotateY<'_> {
             u: hit_record.u,
             v: hit_record.v,
             material: hit_record.material,
+            tangent,
             front_face: false, // TODO: fix having to declare it before calling face_normal
         };
         record.set_face_normal(&rotated_r, normal);
@@ -0,0 +1,404 @@
+//! Signed distance field objects, intersected by sphere tracing.
+
+use crate::{
+    aabb::AABB,
+    hitable::HitableTrait,
+    materials::{Material, MaterialInit},
+    ray::Ray,
+    wavelength::Wavelength,
+    Box, Direction, Float, HitRecord, Position, Vec2, Vec3,
+};
+use nalgebra::Unit;
+use rand::rngs::SmallRng;
+
+/// Maximum number of steps taken along a ray before giving up
+const MAX_STEPS: usize = 512;
+/// Distance to the surface considered a hit, relative to the size of the bounding box
+const HIT_THRESHOLD: Float = 1e-5;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde-derive", serde(tag = "kind"))]
+/// A node in the expression tree describing a signed distance field. The leaves are primitive shapes and the inner nodes are operators combining or modifying them.
+pub enum SDFNode {
+    /// A sphere
+    Sphere {
+        /// Center of the sphere
+        #[cfg_attr(feature = "serde-derive", serde(default))]
+        center: Position,
+        /// Radius of the sphere
+        radius: Float,
+    },
+    /// An axis-aligned box, optionally with rounded edges
+    Box {
+        /// Center of the box
+        #[cfg_attr(feature = "serde-derive", serde(default))]
+        center: Position,
+        /// Half of the size of the box along each axis
+        half_extents: Vec3,
+        /// Radius of the rounding of the edges. The rounding grows the box outwards.
+        #[cfg_attr(feature = "serde-derive", serde(default))]
+        rounding: Float,
+    },
+    /// A torus around the `y` axis
+    Torus {
+        /// Center of the torus
+        #[cfg_attr(feature = "serde-derive", serde(default))]
+        center: Position,
+        /// Distance from the center of the torus to the center of the tube
+        major_radius: Float,
+        /// Radius of the tube
+        minor_radius: Float,
+    },
+    /// A capsule, i.e. a line segment with a radius
+    Capsule {
+        /// First end point of the line segment
+        a: Position,
+        /// Second end point of the line segment
+        b: Position,
+        /// Radius of the capsule
+        radius: Float,
+    },
+    /// Union of two shapes, optionally blended smoothly together
+    Union {
+        /// First shape
+        a: Box<SDFNode>,
+        /// Second shape
+        b: Box<SDFNode>,
+        /// Size of the smooth blending region. Zero results in a sharp union.
+        #[cfg_attr(feature = "serde-derive", serde(default))]
+        smoothness: Float,
+    },
+    /// The first shape with the second shape subtracted from it, optionally blended smoothly
+    Subtraction {
+        /// Shape to subtract from
+        a: Box<SDFNode>,
+        /// Shape to subtract
+        b: Box<SDFNode>,
+        /// Size of the smooth blending region. Zero results in a sharp subtraction.
+        #[cfg_attr(feature = "serde-derive", serde(default))]
+        smoothness: Float,
+    },
+    /// Infinite repetition of a shape in a grid
+    Repetition {
+        /// Shape to repeat. Should fit within a single cell of the grid, centered at the origin.
+        node: Box<SDFNode>,
+        /// Size of a cell of the grid along each axis. Zero disables the repetition along that axis.
+        period: Vec3,
+    },
+    /// Twist of a shape around the `y` axis
+    Twist {
+        /// Shape to twist
+        node: Box<SDFNode>,
+        /// Twisting rate, in degrees per unit of height
+        rate: Float,
+    },
+}
+
+impl SDFNode {
+    /// Evaluates the signed distance from the given point to the surface described by the node. Negative values are inside the shape.
+    #[must_use]
+    pub fn distance(&self, point: Position) -> Float {
+        match self {
+            SDFNode::Sphere { center, radius } => (point - center).norm() - radius,
+            SDFNode::Box {
+                center,
+                half_extents,
+                rounding,
+            } => {
+                let q: Vec3 = (point - center).abs() - half_extents;
+                let outside = q.map(|x| x.max(0.0)).norm();
+                let inside = q.max().min(0.0);
+                outside + inside - rounding
+            }
+            SDFNode::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => {
+                let p = point - center;
+                let q = Vec2::new(p.xz().norm() - major_radius, p.y);
+                q.norm() - minor_radius
+            }
+            SDFNode::Capsule { a, b, radius } => {
+                let pa = point - a;
+                let ba = b - a;
+                let h = (pa.dot(&ba) / ba.norm_squared()).clamp(0.0, 1.0);
+                (pa - ba * h).norm() - radius
+            }
+            SDFNode::Union { a, b, smoothness } => {
+                smooth_min(a.distance(point), b.distance(point), *smoothness)
+            }
+            SDFNode::Subtraction { a, b, smoothness } => {
+                -smooth_min(-a.distance(point), b.distance(point), *smoothness)
+            }
+            SDFNode::Repetition { node, period } => {
+                let local = point.zip_map(period, |x, period| {
+                    if period > 0.0 {
+                        x - period * (x / period).round()
+                    } else {
+                        x
+                    }
+                });
+                node.distance(local)
+            }
+            SDFNode::Twist { node, rate } => {
+                let angle = (rate * point.y).to_radians();
+                let (sin, cos) = angle.sin_cos();
+                let local = Vec3::new(
+                    cos * point.x + sin * point.z,
+                    point.y,
+                    -sin * point.x + cos * point.z,
+                );
+                node.distance(local)
+            }
+        }
+    }
+}
+
+/// Polynomial smooth minimum of two distances. With a non-positive smoothness, this is the ordinary minimum.
+fn smooth_min(a: Float, b: Float, smoothness: Float) -> Float {
+    if smoothness <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / smoothness).clamp(0.0, 1.0);
+    b + (a - b) * h - smoothness * h * (1.0 - h)
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// `SDFInit` structure describes the necessary data for constructing a [SDF] object.
+pub struct SDFInit {
+    /// Used for multiple importance sampling. Not supported for SDF objects: the scene loader ignores it with a warning.
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub priority: bool,
+    /// The expression tree describing the signed distance field
+    pub shape: SDFNode,
+    /// First corner of the bounding box of the shape. The shape is only evaluated within the bounding box.
+    pub corner_0: Position,
+    /// Second corner of the bounding box of the shape
+    pub corner_1: Position,
+    /// Multiplier for the step length of the sphere tracing. Operators like the twist distort the distances, and need a value below one to avoid stepping through the surface.
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_step_scale"))]
+    pub step_scale: Float,
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    /// Material of the shape
+    pub material: MaterialInit,
+}
+
+#[cfg(feature = "serde-derive")]
+fn default_step_scale() -> Float {
+    1.0
+}
+
+#[derive(Clone, Debug)]
+/// Signed distance field object. The surface is found by sphere tracing: stepping along the [Ray] by the distance to the nearest surface, until the distance is small enough. The normals are computed from the gradient of the distance field, and the surface coordinates are a planar projection onto the `xy` plane of the bounding box.
+///
+/// SDF objects cannot be used as priority objects, as sampling random points on the surface is not supported.
+pub struct SDF<'scene> {
+    shape: SDFNode,
+    step_scale: Float,
+    threshold: Float,
+    material: &'scene Material,
+    aabb: AABB,
+}
+
+impl<'scene> SDF<'scene> {
+    /// Creates a new `SDF` object with the given expression tree, bounding box corners, step scale and material.
+    #[must_use]
+    pub fn new(
+        shape: SDFNode,
+        corner_0: Position,
+        corner_1: Position,
+        step_scale: Float,
+        material: &'scene Material,
+    ) -> Self {
+        let aabb = AABB::new_from_coords(corner_0, corner_1);
+        let threshold = HIT_THRESHOLD * (corner_1 - corner_0).norm();
+        SDF {
+            shape,
+            step_scale,
+            threshold,
+            material,
+            aabb,
+        }
+    }
+
+    /// Returns the entry and exit distances of the ray through the bounding box, if it is hit
+    fn clip(&self, ray: &Ray) -> Option<(Float, Float)> {
+        let mut entry = Float::NEG_INFINITY;
+        let mut exit = Float::INFINITY;
+        for axis in 0..3 {
+            let interval = self.aabb.axis(axis);
+            let inverse = 1.0 / ray.direction[axis];
+            let t0 = (interval.min - ray.origin[axis]) * inverse;
+            let t1 = (interval.max - ray.origin[axis]) * inverse;
+            entry = entry.max(t0.min(t1));
+            exit = exit.min(t0.max(t1));
+        }
+        (entry <= exit).then_some((entry, exit))
+    }
+
+    /// Returns the gradient of the distance field at the given point, using the tetrahedron technique
+    fn gradient(&self, point: Position) -> Vec3 {
+        let h = self.threshold;
+        [
+            Vec3::new(1.0, -1.0, -1.0),
+            Vec3::new(-1.0, -1.0, 1.0),
+            Vec3::new(-1.0, 1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+        ]
+        .iter()
+        .map(|k| k * self.shape.distance(point + k * h))
+        .sum()
+    }
+}
+
+impl HitableTrait for SDF<'_> {
+    /// Hit method for the [SDF] object. Returns a [`HitRecord`] if the given [Ray] reaches the surface of the distance field within the given distance interval.
+    fn hit(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        _rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        let (entry, exit) = self.clip(ray)?;
+        let mut distance = entry.max(distance_min);
+        let end = exit.min(distance_max);
+        let mut steps = 0;
+        let mut field = self.shape.distance(ray.evaluate(distance));
+
+        // If the ray starts on a surface, e.g. after a reflection or refraction, first step away from it
+        while field.abs() < self.threshold && distance < end && steps < MAX_STEPS {
+            distance += self.threshold;
+            field = self.shape.distance(ray.evaluate(distance));
+            steps += 1;
+        }
+        // Rays starting inside the shape march towards the surface from the inside
+        let side = field.signum();
+
+        loop {
+            if distance > end || steps >= MAX_STEPS {
+                return None;
+            }
+            if side * field < self.threshold {
+                break;
+            }
+            distance += side * field * self.step_scale;
+            field = self.shape.distance(ray.evaluate(distance));
+            steps += 1;
+        }
+
+        let position = ray.evaluate(distance);
+        let outward_normal = Unit::new_normalize(self.gradient(position));
+        let (min, max) = self.aabb.bounding_positions();
+        let relative = (position - min).component_div(&(max - min));
+        let mut record = HitRecord {
+            distance,
+            position,
+            normal: outward_normal,
+            u: relative.x,
+            v: relative.y,
+            material: self.material,
+            tangent: None,
+            front_face: false, // TODO: fix having to declare it before calling face_normal
+        };
+        record.set_face_normal(ray, outward_normal);
+        Some(record)
+    }
+
+    /// Returns the user-supplied bounding box of the [SDF] object.
+    fn aabb(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+
+    /// Returns zero, as random points on the surface are not supported
+    fn pdf_value(
+        &self,
+        _origin: Position,
+        _direction: Direction,
+        _wavelength: Wavelength,
+        _time: Float,
+        _rng: &mut SmallRng,
+    ) -> Float {
+        0.0
+    }
+
+    fn centroid(&self) -> Position {
+        self.aabb.centroid()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn smooth_union_distance() {
+        let sphere = |x| SDFNode::Sphere {
+            center: Position::new(x, 0.0, 0.0),
+            radius: 1.0,
+        };
+        let sharp = SDFNode::Union {
+            a: Box::new(sphere(-1.0)),
+            b: Box::new(sphere(1.0)),
+            smoothness: 0.0,
+        };
+        let smooth = SDFNode::Union {
+            a: Box::new(sphere(-1.0)),
+            b: Box::new(sphere(1.0)),
+            smoothness: 0.5,
+        };
+        let origin = Position::new(0.0, 0.0, 0.0);
+        assert!(sharp.distance(origin).abs() <= 1e-6);
+        // The smooth union fills in the gap between the spheres
+        assert!(smooth.distance(origin) < 0.0);
+        let far = Position::new(10.0, 0.0, 0.0);
+        assert!((smooth.distance(far) - 8.0).abs() <= 1e-6);
+    }
+
+    #[test]
+    fn sphere_trace_box() {
+        let mut rng = SmallRng::from_os_rng();
+        let material = Box::default();
+        let shape = SDFNode::Box {
+            center: Position::new(0.0, 0.0, 0.0),
+            half_extents: Vec3::new(1.0, 1.0, 1.0),
+            rounding: 0.0,
+        };
+        let sdf = SDF::new(
+            shape,
+            Position::new(-2.0, -2.0, -2.0),
+            Position::new(2.0, 2.0, 2.0),
+            1.0,
+            &material,
+        );
+        let ray = Ray {
+            origin: Position::new(0.2, 0.3, -10.0),
+            direction: Unit::new_normalize(Vec3::new(0.0, 0.0, 1.0)),
+            time: 0.0,
+            wavelength: 600,
+        };
+        let hit = sdf
+            .hit(&ray, 0.0, Float::INFINITY, &mut rng)
+            .expect("No hit on the box");
+        assert!((hit.distance - 9.0).abs() <= 1e-3);
+        assert!((hit.normal.z + 1.0).abs() <= 1e-3);
+        assert!(hit.front_face);
+
+        // Continuing from the surface, the ray exits the box on the other side
+        let inside = Ray {
+            origin: hit.position,
+            ..ray
+        };
+        let hit = sdf
+            .hit(&inside, 0.001, Float::INFINITY, &mut rng)
+            .expect("No exit hit on the box");
+        assert!((hit.distance - 2.0).abs() <= 1e-3);
+        assert!(!hit.front_face);
+    }
+}
@@ -7,10 +7,10 @@ use crate::{
     onb::ONB,
     ray::Ray,
     wavelength::Wavelength,
-    Direction, Displacement, Float, HitRecord, Position, Vec3, EPSILON_SHADOW_ACNE, PI,
+    Direction, Displacement, Float, HitRecord, Position, Vec2, Vec3, EPSILON_SHADOW_ACNE, PI,
 };
 use nalgebra::Unit;
-use rand::{rngs::SmallRng, Rng};
+use rand::rngs::SmallRng;
 
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
@@ -96,6 +96,7 @@ impl HitableTrait for Sphere<'_> {
                     u,
                     v,
                     material: self.material,
+                    tangent: None,
                     front_face: false, // TODO: fix having to declare it before calling face_normal
                 };
                 record.set_face_normal(ray, outward_normal);
@@ -115,6 +116,7 @@ impl HitableTrait for Sphere<'_> {
                     u,
                     v,
                     material: self.material,
+                    tangent: None,
                     front_face: false, // TODO: fix having to declare it before calling face_normal
                 };
                 record.set_face_normal(ray, outward_normal);
@@ -159,11 +161,11 @@ impl HitableTrait for Sphere<'_> {
 
     // TODO: improve correctness & optimization!
     /// Utility function from Ray Tracing: The Rest of Your Life.
-    fn random(&self, origin: Position, rng: &mut SmallRng) -> Displacement {
+    fn random(&self, origin: Position, sample: Vec2, _time: Float) -> Displacement {
         let offset: Position = self.center - origin;
         let distance_squared: Float = offset.norm_squared();
         let uvw = ONB::build_from_w(Unit::new_normalize(offset));
-        let vec = random_to_sphere(self.radius, distance_squared, rng);
+        let vec = random_to_sphere(self.radius, distance_squared, sample);
         let vec = Unit::new_normalize(vec);
         *uvw.local(vec)
     }
@@ -174,9 +176,9 @@ impl HitableTrait for Sphere<'_> {
 }
 
 /// Internal helper.
-fn random_to_sphere(radius: Float, distance_squared: Float, rng: &mut SmallRng) -> Vec3 {
-    let r1: Float = rng.random();
-    let r2: Float = rng.random();
+fn random_to_sphere(radius: Float, distance_squared: Float, sample: Vec2) -> Vec3 {
+    let r1: Float = sample.x;
+    let r2: Float = sample.y;
     let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
 
     let phi = 2.0 * PI * r1;
@@ -0,0 +1,387 @@
+//! A torus object.
+
+use crate::{
+    aabb::AABB,
+    hitable::{uniform_area_pdf, HitableTrait},
+    materials::{Material, MaterialInit},
+    objects::disk::disk_extent,
+    onb::ONB,
+    ray::Ray,
+    wavelength::Wavelength,
+    Direction, Displacement, Float, HitRecord, Position, Vec2, Vec3, PI,
+};
+use nalgebra::Unit;
+use rand::rngs::SmallRng;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// `TorusInit` structure describes the necessary data for constructing a [Torus].
+pub struct TorusInit {
+    /// Used for multiple importance sampling
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub priority: bool,
+    /// Center of the torus.
+    pub center: Position,
+    /// Direction of the axis of symmetry of the torus. Does not need to be normalized.
+    #[cfg_attr(feature = "serde-derive", serde(default = "default_axis"))]
+    pub axis: Vec3,
+    /// Distance from the center of the torus to the center of the tube.
+    pub major_radius: Float,
+    /// Radius of the tube.
+    pub minor_radius: Float,
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    /// Material of the torus.
+    pub material: MaterialInit,
+}
+
+#[cfg(feature = "serde-derive")]
+fn default_axis() -> Vec3 {
+    Vec3::new(0.0, 1.0, 0.0)
+}
+
+#[derive(Debug, Clone)]
+/// A torus object. The surface coordinates go around the axis of symmetry in `u` and around the tube in `v`.
+pub struct Torus<'scene> {
+    center: Position,
+    onb: ONB,
+    major_radius: Float,
+    minor_radius: Float,
+    area: Float,
+    material: &'scene Material,
+    aabb: AABB,
+}
+
+impl<'scene> Torus<'scene> {
+    /// Creates a new `Torus` object with the given center, axis, major and minor radius, and material.
+    #[must_use]
+    pub fn new(
+        center: Position,
+        axis: Vec3,
+        major_radius: Float,
+        minor_radius: Float,
+        material: &'scene Material,
+    ) -> Self {
+        let onb = ONB::build_from_w(Unit::new_normalize(axis));
+        // The torus is a sphere of the minor radius swept along a circle of the major radius
+        let extent = disk_extent(&onb.w, major_radius).add_scalar(minor_radius);
+        let mut aabb = AABB::new_from_coords(center - extent, center + extent);
+        aabb.pad();
+        let area = 4.0 * PI * PI * major_radius * minor_radius;
+
+        Torus {
+            center,
+            onb,
+            major_radius,
+            minor_radius,
+            area,
+            material,
+            aabb,
+        }
+    }
+
+    /// Returns the angle around the tube for the uniform `sample`. The outer side of the tube has more area than the inner side, so the density of the angle is proportional to the distance from the axis. Its cumulative distribution is inverted with Newton's method, falling back to bisection.
+    fn tube_angle(&self, sample: Float) -> Float {
+        let target = 2.0 * PI * self.major_radius * sample;
+        let (mut low, mut high) = (0.0, 2.0 * PI);
+        let mut theta = 2.0 * PI * sample;
+        for _ in 0..16 {
+            let error = self.major_radius * theta + self.minor_radius * theta.sin() - target;
+            if error.abs() <= 1e-6 * self.major_radius {
+                break;
+            }
+            if error > 0.0 {
+                high = theta;
+            } else {
+                low = theta;
+            }
+            theta -= error / (self.major_radius + self.minor_radius * theta.cos());
+            if !(low..=high).contains(&theta) {
+                theta = 0.5 * (low + high);
+            }
+        }
+        theta
+    }
+}
+
+impl HitableTrait for Torus<'_> {
+    /// Hit method for the [Torus] object. Returns a [`HitRecord`] if the given [Ray] intersects with the torus at the given distance interval.
+    #[allow(clippy::many_single_char_names)]
+    fn hit(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        _rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        // The quartic equation is numerically sensitive, so it is solved in double precision
+        let origin = self.onb.to_local(ray.origin - self.center).cast::<f64>();
+        let direction = self.onb.to_local(*ray.direction).cast::<f64>();
+        let major = f64::from(self.major_radius);
+        let minor = f64::from(self.minor_radius);
+
+        // Early exit with the bounding sphere. The ray origin is also moved near the torus, to improve the accuracy of the solution.
+        let bound = major + minor;
+        let half_b = origin.dot(&direction);
+        let c = origin.norm_squared() - bound * bound;
+        let discriminant = half_b * half_b - c;
+        if discriminant <= 0.0 {
+            return None;
+        }
+        let offset = -half_b - discriminant.sqrt();
+        let origin = origin + offset * direction;
+
+        // (|p|² + R² - r²)² = 4R² (p.x² + p.y²), with p = o + t d and |d| = 1
+        let e = origin.dot(&direction);
+        let f = origin.norm_squared() + major * major - minor * minor;
+        let four_major_squared = 4.0 * major * major;
+        let c3 = 4.0 * e;
+        let c2 = 4.0 * e * e + 2.0 * f
+            - four_major_squared * (direction.x * direction.x + direction.y * direction.y);
+        let c1 = 4.0 * e * f
+            - 2.0 * four_major_squared * (origin.x * direction.x + origin.y * direction.y);
+        let c0 = f * f - four_major_squared * (origin.x * origin.x + origin.y * origin.y);
+
+        let (roots, count) = solve_quartic(c3, c2, c1, c0);
+        #[allow(clippy::cast_possible_truncation)]
+        let distance = roots[..count]
+            .iter()
+            .map(|root| (root + offset) as Float)
+            .filter(|&distance| distance > distance_min && distance < distance_max)
+            .min_by(Float::total_cmp)?;
+
+        let local = self.onb.to_local(ray.evaluate(distance) - self.center);
+        let distance_from_axis = local.x.hypot(local.y);
+        let tube_center = Vec3::new(local.x, local.y, 0.0) * self.major_radius / distance_from_axis;
+        let outward_normal = Unit::new_normalize(self.onb.to_world(local - tube_center));
+        let u = local.y.atan2(local.x) / (2.0 * PI) + 0.5;
+        let v = local.z.atan2(distance_from_axis - self.major_radius) / (2.0 * PI) + 0.5;
+        let mut record = HitRecord {
+            distance,
+            position: ray.evaluate(distance),
+            normal: outward_normal,
+            u,
+            v,
+            material: self.material,
+            tangent: None,
+            front_face: false, // TODO: fix having to declare it before calling face_normal
+        };
+        record.set_face_normal(ray, outward_normal);
+        Some(record)
+    }
+
+    /// Returns the axis-aligned bounding box [AABB] for the torus.
+    fn aabb(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+
+    /// Returns the probability density of sampling the given direction with the `random` method of the torus.
+    fn pdf_value(
+        &self,
+        origin: Position,
+        direction: Direction,
+        wavelength: Wavelength,
+        time: Float,
+        rng: &mut SmallRng,
+    ) -> Float {
+        let ray = Ray {
+            origin,
+            direction,
+            time,
+            wavelength,
+        };
+        uniform_area_pdf(self, &ray, self.area, rng)
+    }
+
+    /// Returns a random point on the surface of the torus, uniformly distributed by area
+    fn random(&self, origin: Position, sample: Vec2, _time: Float) -> Displacement {
+        let theta = self.tube_angle(sample.x);
+        let phi = 2.0 * PI * sample.y;
+        let distance_from_axis = self.major_radius + self.minor_radius * theta.cos();
+        let local = Vec3::new(
+            distance_from_axis * phi.cos(),
+            distance_from_axis * phi.sin(),
+            self.minor_radius * theta.sin(),
+        );
+        let point = self.center + self.onb.to_world(local);
+        point - origin
+    }
+
+    fn centroid(&self) -> Position {
+        self.center
+    }
+}
+
+/// Finds the real roots of the monic quartic equation `x⁴ + a x³ + b x² + c x + d = 0` using Ferrari's method. Returns the roots in an array, along with the number of roots found.
+#[must_use]
+#[allow(clippy::many_single_char_names)]
+fn solve_quartic(a: f64, b: f64, c: f64, d: f64) -> ([f64; 4], usize) {
+    // Depressed quartic y⁴ + p y² + q y + r = 0, with x = y - a / 4
+    let shift = a / 4.0;
+    let a_squared = a * a;
+    let p = b - 3.0 * a_squared / 8.0;
+    let q = c - a * b / 2.0 + a_squared * a / 8.0;
+    let r = d - a * c / 4.0 + a_squared * b / 16.0 - 3.0 * a_squared * a_squared / 256.0;
+
+    let mut roots = [0.0; 4];
+    let mut count = 0;
+    let mut push_quadratic = |linear: f64, constant: f64| {
+        let discriminant = linear * linear / 4.0 - constant;
+        if discriminant >= 0.0 {
+            let root = discriminant.sqrt();
+            for y in [-linear / 2.0 - root, -linear / 2.0 + root] {
+                roots[count] = y - shift;
+                count += 1;
+            }
+        }
+    };
+
+    // Largest root of the resolvent cubic m³ + p m² + (p²/4 - r) m - q²/8 = 0
+    let m = solve_cubic_largest(p, p * p / 4.0 - r, -q * q / 8.0);
+    if m <= 1e-12 {
+        // Biquadratic equation: solve for y² and take the square roots
+        let discriminant = p * p / 4.0 - r;
+        if discriminant >= 0.0 {
+            let root = discriminant.sqrt();
+            for y_squared in [-p / 2.0 - root, -p / 2.0 + root] {
+                push_quadratic(0.0, -y_squared);
+            }
+        }
+    } else {
+        // (y² + p/2 + m)² = (s y - q / 2s)², with s = √(2m)
+        let s = (2.0 * m).sqrt();
+        push_quadratic(-s, p / 2.0 + m + q / (2.0 * s));
+        push_quadratic(s, p / 2.0 + m - q / (2.0 * s));
+    }
+
+    // Polish the roots with a couple of Newton iterations on the original equation
+    for root in &mut roots[..count] {
+        for _ in 0..2 {
+            let x = *root;
+            let value = (((x + a) * x + b) * x + c) * x + d;
+            let derivative = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+            if derivative != 0.0 {
+                *root = x - value / derivative;
+            }
+        }
+    }
+
+    (roots, count)
+}
+
+/// Finds the largest real root of the monic cubic equation `x³ + a x² + b x + c = 0`.
+#[must_use]
+#[allow(clippy::many_single_char_names)]
+fn solve_cubic_largest(a: f64, b: f64, c: f64) -> f64 {
+    // Depressed cubic z³ + p z + q = 0, with x = z - a / 3
+    let shift = a / 3.0;
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a * a * a / 27.0 - a * b / 3.0 + c;
+    let discriminant = q * q / 4.0 + p * p * p / 27.0;
+
+    let z = if discriminant > 0.0 {
+        // One real root
+        let root = discriminant.sqrt();
+        (-q / 2.0 + root).cbrt() + (-q / 2.0 - root).cbrt()
+    } else if p == 0.0 {
+        0.0
+    } else {
+        // Three real roots, the largest one is given by the trigonometric solution with k = 0
+        let amplitude = 2.0 * (-p / 3.0).sqrt();
+        let angle = ((3.0 * q / (2.0 * p)) * (-3.0 / p).sqrt()).clamp(-1.0, 1.0);
+        amplitude * (angle.acos() / 3.0).cos()
+    };
+    let mut x = z - shift;
+
+    // Polish the root with a Newton iteration
+    let value = ((x + a) * x + b) * x + c;
+    let derivative = (3.0 * x + 2.0 * a) * x + b;
+    if derivative != 0.0 {
+        x -= value / derivative;
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn quartic_roots() {
+        // (x - 1)(x - 2)(x + 3)(x - 4) = x⁴ - 4x³ - 7x² + 34x - 24
+        let (mut roots, count) = solve_quartic(-4.0, -7.0, 34.0, -24.0);
+        assert_eq!(count, 4);
+        roots.sort_by(f64::total_cmp);
+        for (root, expected) in roots.iter().zip([-3.0, 1.0, 2.0, 4.0]) {
+            assert!((root - expected).abs() <= 1e-9, "{root} != {expected}");
+        }
+        // x⁴ + 1 has no real roots
+        assert_eq!(solve_quartic(0.0, 0.0, 0.0, 1.0).1, 0);
+    }
+
+    #[test]
+    fn hit_through_hole() {
+        let mut rng = SmallRng::from_os_rng();
+        let material = Box::default();
+        let torus = Torus::new(
+            Position::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            2.0,
+            0.5,
+            &material,
+        );
+        let ray = |origin: Position, direction: Vec3| Ray {
+            origin,
+            direction: Unit::new_normalize(direction),
+            time: 0.0,
+            wavelength: 600,
+        };
+
+        // Along the axis of symmetry, through the hole
+        let through = ray(Position::new(0.0, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(torus
+            .hit(&through, 0.0, Float::INFINITY, &mut rng)
+            .is_none());
+
+        // Along the plane of the torus, hitting the outer side of the tube
+        let side = ray(Position::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let record = torus
+            .hit(&side, 0.0, Float::INFINITY, &mut rng)
+            .expect("No hit on the torus");
+        assert!((record.distance - 7.5).abs() <= 1e-4);
+        assert!((record.normal.x + 1.0).abs() <= 1e-4);
+        assert!(record.front_face);
+
+        // From the top, hitting the top of the tube
+        let top = ray(Position::new(0.0, 10.0, 2.0), Vec3::new(0.0, -1.0, 0.0));
+        let record = torus
+            .hit(&top, 0.0, Float::INFINITY, &mut rng)
+            .expect("No hit on the torus");
+        assert!((record.distance - 9.5).abs() <= 1e-4);
+        assert!((record.normal.y - 1.0).abs() <= 1e-4);
+    }
+
+    #[test]
+    fn tube_angle_follows_area() {
+        let material = Box::default();
+        let torus = Torus::new(
+            Position::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            2.0,
+            0.5,
+            &material,
+        );
+        // The fraction of the area up to the sampled angle matches the sample
+        for sample in [0.0, 0.1, 0.25, 0.5, 0.8, 0.999] {
+            let theta = torus.tube_angle(sample);
+            let fraction = (2.0 * theta + 0.5 * theta.sin()) / (2.0 * 2.0 * PI);
+            assert!((fraction - sample).abs() <= 1e-5, "{sample}: {fraction}");
+        }
+        // The outer side of the tube is sampled more densely than the inner side
+        let outer = torus.tube_angle(0.1);
+        let inner = torus.tube_angle(0.6) - torus.tube_angle(0.5);
+        assert!(outer < inner);
+    }
+}
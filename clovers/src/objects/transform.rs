@@ -0,0 +1,383 @@
+//! Utility objects for transforming another object with an arbitrary affine transformation, and for instancing an object multiple times.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use nalgebra::{Isometry3, Matrix3, Matrix4, Point3, Quaternion, Rotation3, Unit, UnitQuaternion};
+use rand::rngs::SmallRng;
+
+use crate::{
+    aabb::AABB,
+    bvh::{BVHNode, BvhAlgorithm},
+    hitable::{Hitable, HitableTrait},
+    ray::Ray,
+    wavelength::Wavelength,
+    Box, Direction, Displacement, Float, HitRecord, Position, Vec2, Vec3,
+};
+
+use super::Object;
+
+/// A single step of an affine transformation. A list of steps is applied in order, the first step being applied to the object first.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde-derive", serde(rename_all = "snake_case"))]
+pub enum TransformStep {
+    /// Translation by the given offset
+    Translate(Vec3),
+    /// Rotation around the given axis
+    Rotate {
+        /// Axis of the rotation
+        axis: Vec3,
+        /// Angle of the rotation, in degrees
+        angle: Float,
+    },
+    /// Scaling with the given factor along each axis
+    Scale(Vec3),
+    /// Rotation described by a quaternion, given in the order `[x, y, z, w]`
+    Quaternion([Float; 4]),
+    /// Moves the object to `from` and orients it so that its local positive z axis points towards `to`, with its local positive y axis towards `up`
+    LookAt {
+        /// New location of the object
+        from: Position,
+        /// Location the object faces towards
+        to: Position,
+        /// The subjective "up" direction for the object
+        up: Vec3,
+    },
+    /// An arbitrary affine transformation matrix, given as a list of rows
+    Matrix([[Float; 4]; 4]),
+}
+
+impl TransformStep {
+    /// Returns the homogeneous transformation matrix of this step.
+    #[must_use]
+    pub fn matrix(&self) -> Matrix4<Float> {
+        match self {
+            TransformStep::Translate(offset) => Matrix4::new_translation(offset),
+            TransformStep::Rotate { axis, angle } => {
+                Rotation3::from_axis_angle(&Unit::new_normalize(*axis), angle.to_radians())
+                    .to_homogeneous()
+            }
+            TransformStep::Scale(factors) => Matrix4::new_nonuniform_scaling(factors),
+            TransformStep::Quaternion([x, y, z, w]) => {
+                UnitQuaternion::from_quaternion(Quaternion::new(*w, *x, *y, *z)).to_homogeneous()
+            }
+            TransformStep::LookAt { from, to, up } => {
+                Isometry3::face_towards(&Point3::from(*from), &Point3::from(*to), up)
+                    .to_homogeneous()
+            }
+            TransformStep::Matrix(rows) => Matrix4::from_fn(|row, column| rows[row][column]),
+        }
+    }
+}
+
+/// Combines a list of [`TransformStep`]s into a single homogeneous transformation matrix. The first step is applied to the object first.
+#[must_use]
+pub fn transform_matrix(steps: &[TransformStep]) -> Matrix4<Float> {
+    steps
+        .iter()
+        .fold(Matrix4::identity(), |matrix, step| step.matrix() * matrix)
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// `TransformInit` structure describes the necessary data for constructing a [Transform].
+pub struct TransformInit {
+    /// Used for multiple importance sampling
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub priority: bool,
+    /// The encased [Object] to transform
+    pub object: Box<Object>,
+    /// The steps of the transformation, applied in order
+    pub transform: Vec<TransformStep>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+/// `InstancesInit` structure describes the necessary data for instancing an [Object] multiple times. The object is initialized and its BVH built only once, and shared by all the instances.
+pub struct InstancesInit {
+    /// Used for multiple importance sampling
+    #[cfg_attr(feature = "serde-derive", serde(default))]
+    pub priority: bool,
+    /// The encased [Object] to instance
+    pub object: Box<Object>,
+    /// The transformations of the instances, one list of steps per instance
+    pub transforms: Vec<Vec<TransformStep>>,
+}
+
+/// Prepares a [Hitable] for being shared between [Transform]s. Lists of hitables, such as meshes, get a BVH of their own, built with the given algorithm.
+#[must_use]
+pub fn shared_hitable(hitable: Hitable, bvh_algorithm: BvhAlgorithm) -> Arc<Hitable> {
+    let hitable = match hitable {
+        Hitable::HitableList(list) => {
+            Hitable::BVHNode(BVHNode::from_list(bvh_algorithm, list.hitables))
+        }
+        hitable => hitable,
+    };
+    Arc::new(hitable)
+}
+
+#[derive(Debug, Clone)]
+/// Transform object. It wraps the given [Object] and has adjusted `hit()` and `bounding_box()` methods based on the affine transformation matrix given. The wrapped object can be shared between multiple `Transform`s.
+pub struct Transform<'scene> {
+    object: Arc<Hitable<'scene>>,
+    /// Transformation from object space to world space
+    matrix: Matrix4<Float>,
+    /// Transformation from world space to object space
+    inverse: Matrix4<Float>,
+    /// Linear part of the transformation from object space to world space, for the tangents
+    linear: Matrix3<Float>,
+    /// Transformation for the normals from object space to world space
+    normal_matrix: Matrix3<Float>,
+    aabb: Option<AABB>,
+}
+
+/// Returns the axis-aligned bounding box of the given [AABB] after transforming all of its corners with the given matrix
+pub(crate) fn transformed_aabb(aabb: &AABB, matrix: &Matrix4<Float>) -> AABB {
+    let mut min: Vec3 = Vec3::repeat(Float::INFINITY);
+    let mut max: Vec3 = Vec3::repeat(Float::NEG_INFINITY);
+    for x in [aabb.x.min, aabb.x.max] {
+        for y in [aabb.y.min, aabb.y.max] {
+            for z in [aabb.z.min, aabb.z.max] {
+                let corner = matrix.transform_point(&Point3::new(x, y, z));
+                min = min.inf(&corner.coords);
+                max = max.sup(&corner.coords);
+            }
+        }
+    }
+    AABB::new_from_coords(min, max)
+}
+
+/// Returns the given ray in the object space, and the length of its direction vector before normalization
+pub(crate) fn local_ray(ray: &Ray, inverse: &Matrix4<Float>) -> (Ray, Float) {
+    let origin: Position = inverse.transform_point(&Point3::from(ray.origin)).coords;
+    let direction: Vec3 = inverse.transform_vector(&ray.direction);
+    let scale = direction.norm();
+    let ray = Ray {
+        origin,
+        direction: Unit::new_unchecked(direction / scale),
+        time: ray.time,
+        wavelength: ray.wavelength,
+    };
+    (ray, scale)
+}
+
+/// Intersects the given world space ray with an object in its object space, and returns the [`HitRecord`] in world space. The `linear` part of the forward transformation is used for the tangents, and the `normal_matrix` for the normals.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn hit_transformed<'a>(
+    object: &'a Hitable,
+    inverse: &Matrix4<Float>,
+    linear: &Matrix3<Float>,
+    normal_matrix: &Matrix3<Float>,
+    ray: &Ray,
+    distance_min: Float,
+    distance_max: Float,
+    rng: &mut SmallRng,
+) -> Option<HitRecord<'a>> {
+    // Distances along the ray scale with the length of the transformed direction
+    let (local_ray, scale) = local_ray(ray, inverse);
+    let mut record = object.hit(&local_ray, distance_min * scale, distance_max * scale, rng)?;
+
+    // The orientation of the normal relative to the ray is preserved by the transformation, so `front_face` stays as-is
+    record.distance /= scale;
+    record.position = ray.evaluate(record.distance);
+    record.normal = Unit::new_normalize(normal_matrix * *record.normal);
+    // Tangents transform with the forward matrix
+    if let Some(tangent) = record.tangent {
+        record.tangent = Some(Unit::new_normalize(linear * *tangent));
+    }
+    Some(record)
+}
+
+impl<'scene> Transform<'scene> {
+    /// Creates a new `Transform` object. It wraps the given [Object] and has adjusted `hit()` and `bounding_box()` methods based on the `matrix` given. Returns `None` if the matrix is not invertible, e.g. when it scales by zero along some axis.
+    #[must_use]
+    pub fn new(object: Arc<Hitable<'scene>>, matrix: Matrix4<Float>) -> Option<Self> {
+        let inverse = matrix.try_inverse()?;
+        let linear: Matrix3<Float> = matrix.fixed_view::<3, 3>(0, 0).into();
+        let normal_matrix: Matrix3<Float> = inverse.fixed_view::<3, 3>(0, 0).transpose();
+
+        let aabb = object.aabb().map(|bbox| transformed_aabb(bbox, &matrix));
+
+        Some(Transform {
+            object,
+            matrix,
+            inverse,
+            linear,
+            normal_matrix,
+            aabb,
+        })
+    }
+}
+
+impl HitableTrait for Transform<'_> {
+    /// Hit method for the [Transform] object. Finds the transformation-adjusted [`HitRecord`] for the possible intersection of the [Ray] with the encased [Object].
+    fn hit(
+        &self,
+        ray: &Ray,
+        distance_min: Float,
+        distance_max: Float,
+        rng: &mut SmallRng,
+    ) -> Option<HitRecord<'_>> {
+        hit_transformed(
+            &self.object,
+            &self.inverse,
+            &self.linear,
+            &self.normal_matrix,
+            ray,
+            distance_min,
+            distance_max,
+            rng,
+        )
+    }
+
+    /// Bounding box method for the [Transform] object. Finds the axis-aligned bounding box [AABB] for the encased [Object] after the transformation.
+    fn aabb(&self) -> Option<&AABB> {
+        self.aabb.as_ref()
+    }
+
+    /// Returns the probability density function value of the encased [Object] in the object space. This is exact for rigid transformations and uniform scaling, which preserve angles.
+    fn pdf_value(
+        &self,
+        origin: Position,
+        direction: Direction,
+        wavelength: Wavelength,
+        time: Float,
+        rng: &mut SmallRng,
+    ) -> Float {
+        let ray = Ray {
+            origin,
+            direction,
+            time,
+            wavelength,
+        };
+        let (local_ray, _scale) = local_ray(&ray, &self.inverse);
+        self.object
+            .pdf_value(local_ray.origin, local_ray.direction, wavelength, time, rng)
+    }
+
+    fn random(&self, origin: Position, sample: Vec2, time: Float) -> Displacement {
+        let local_origin: Position = self.inverse.transform_point(&Point3::from(origin)).coords;
+        let local_point: Position = local_origin + self.object.random(local_origin, sample, time);
+        let point: Position = self
+            .matrix
+            .transform_point(&Point3::from(local_point))
+            .coords;
+        point - origin
+    }
+
+    fn centroid(&self) -> Position {
+        self.matrix
+            .transform_point(&Point3::from(self.object.centroid()))
+            .coords
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::vec;
+    use rand::SeedableRng;
+
+    use crate::objects::{Curve, CurveMode, Sphere};
+
+    use super::*;
+
+    const RAY: Ray = Ray {
+        origin: Position::new(0.0, 0.0, -10.0),
+        direction: Unit::new_unchecked(Vec3::new(0.0, 0.0, 1.0)),
+        time: 0.0,
+        wavelength: 600,
+    };
+
+    #[test]
+    fn scaled_translated_sphere() {
+        let mut rng = SmallRng::from_os_rng();
+        let material = Box::default();
+        let sphere = Hitable::Sphere(Sphere::new(Position::new(0.0, 0.0, 0.0), 1.0, &material));
+        let steps = vec![
+            TransformStep::Scale(Vec3::new(2.0, 2.0, 2.0)),
+            TransformStep::Translate(Vec3::new(0.0, 0.0, 3.0)),
+        ];
+        let transform = Transform::new(Arc::new(sphere), transform_matrix(&steps))
+            .expect("Transform matrix is not invertible");
+
+        let aabb = transform.aabb().expect("No AABB for the transform");
+        assert!((aabb.z.min - 1.0).abs() <= 1e-5);
+        assert!((aabb.z.max - 5.0).abs() <= 1e-5);
+
+        let hit_record = transform
+            .hit(&RAY, 0.0, Float::INFINITY, &mut rng)
+            .expect("No hit record for transform and ray");
+        assert!((hit_record.distance - 11.0).abs() <= 1e-5);
+        assert!((hit_record.position - Position::new(0.0, 0.0, 1.0)).norm() <= 1e-5);
+        assert!((*hit_record.normal - Vec3::new(0.0, 0.0, -1.0)).norm() <= 1e-5);
+        assert!(hit_record.front_face);
+
+        // The hit is beyond the maximum distance in world space, even though it would not be in object space
+        assert!(transform.hit(&RAY, 0.0, 10.0, &mut rng).is_none());
+    }
+
+    #[test]
+    fn non_uniform_scale_normal() {
+        let mut rng = SmallRng::from_os_rng();
+        let material = Box::default();
+        let sphere = Hitable::Sphere(Sphere::new(Position::new(0.0, 0.0, 0.0), 1.0, &material));
+        // Rotate the scaled sphere so that the stretched x axis faces the ray
+        let steps = vec![
+            TransformStep::Scale(Vec3::new(4.0, 1.0, 1.0)),
+            TransformStep::Rotate {
+                axis: Vec3::new(0.0, 1.0, 0.0),
+                angle: 90.0,
+            },
+        ];
+        let transform = Transform::new(Arc::new(sphere), transform_matrix(&steps))
+            .expect("Transform matrix is not invertible");
+
+        let hit_record = transform
+            .hit(&RAY, 0.0, Float::INFINITY, &mut rng)
+            .expect("No hit record for transform and ray");
+        assert!((hit_record.distance - 6.0).abs() <= 1e-4);
+        assert!((*hit_record.normal - Vec3::new(0.0, 0.0, -1.0)).norm() <= 1e-4);
+    }
+
+    #[test]
+    fn non_uniform_scale_tangent() {
+        let mut rng = SmallRng::from_os_rng();
+        let material = Box::default();
+        // A straight curve along the diagonal of the xy plane
+        let points = [-1.0, -1.0 / 3.0, 1.0 / 3.0, 1.0].map(|t| Position::new(t, t, 0.0));
+        let curve = Hitable::Curve(Curve::new(points, [0.2, 0.2], CurveMode::Ribbon, &material));
+        let steps = vec![TransformStep::Scale(Vec3::new(2.0, 1.0, 1.0))];
+        let transform = Transform::new(Arc::new(curve), transform_matrix(&steps))
+            .expect("Transform matrix is not invertible");
+
+        let hit_record = transform
+            .hit(&RAY, 0.0, Float::INFINITY, &mut rng)
+            .expect("No hit record for transform and ray");
+        let tangent = hit_record.tangent.expect("No tangent for the curve");
+        // Tangents follow the stretched curve
+        let expected = Vec3::new(2.0, 1.0, 0.0).normalize();
+        assert!((tangent.dot(&expected).abs() - 1.0).abs() <= 1e-4);
+    }
+
+    #[test]
+    fn steps_apply_in_order() {
+        let steps = vec![
+            TransformStep::Translate(Vec3::new(1.0, 0.0, 0.0)),
+            TransformStep::Rotate {
+                axis: Vec3::new(0.0, 0.0, 1.0),
+                angle: 90.0,
+            },
+        ];
+        let point = transform_matrix(&steps).transform_point(&Point3::origin());
+        assert!((point.coords - Vec3::new(0.0, 1.0, 0.0)).norm() <= 1e-5);
+    }
+
+    #[test]
+    fn zero_scale_is_rejected() {
+        let material = Box::default();
+        let sphere = Hitable::Sphere(Sphere::new(Position::new(0.0, 0.0, 0.0), 1.0, &material));
+        let steps = vec![TransformStep::Scale(Vec3::new(1.0, 0.0, 1.0))];
+        assert!(Transform::new(Arc::new(sphere), transform_matrix(&steps)).is_none());
+    }
+}
@@ -6,12 +6,11 @@ use crate::interval::Interval;
 use crate::materials::MaterialInit;
 use crate::wavelength::Wavelength;
 use crate::{
-    aabb::AABB, materials::Material, ray::Ray, Float, HitRecord, Vec3, EPSILON_RECT_THICKNESS,
+    aabb::AABB, materials::Material, ray::Ray, Float, HitRecord, Vec2, Vec3, EPSILON_RECT_THICKNESS,
 };
 use crate::{Direction, Displacement, Position, EPSILON_SHADOW_ACNE};
 use nalgebra::Unit;
 use rand::rngs::SmallRng;
-use rand::Rng;
 
 /// Initialization structure for a triangle primitive
 #[derive(Clone, Debug)]
@@ -158,6 +157,7 @@ impl HitableTrait for Triangle<'_> {
             v: beta,
             material: self.material,
             normal: self.normal,
+            tangent: None,
             front_face: false,
         };
         record.set_face_normal(ray, self.normal);
@@ -201,10 +201,10 @@ impl HitableTrait for Triangle<'_> {
     }
 
     /// Returns a random point on the triangle surface
-    fn random(&self, origin: Position, rng: &mut SmallRng) -> Displacement {
+    fn random(&self, origin: Position, sample: Vec2, _time: Float) -> Displacement {
         // Random square coordinate
-        let mut a = rng.random::<Float>();
-        let mut b = rng.random::<Float>();
+        let mut a = sample.x;
+        let mut b = sample.y;
 
         // If we're beyond the diagonal, rotate around a point by flipping on both axes
         if a + b > 1.0 {
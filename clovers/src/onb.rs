@@ -38,4 +38,16 @@ impl ONB {
         let d = vec.x * *self.u + vec.y * *self.v + vec.z * *self.w;
         Unit::new_normalize(d)
     }
+
+    /// Converts a vector from world space into the local coordinates of the basis, in the order `u`, `v`, `w`.
+    #[must_use]
+    pub fn to_local(&self, vec: Vec3) -> Vec3 {
+        Vec3::new(vec.dot(&self.u), vec.dot(&self.v), vec.dot(&self.w))
+    }
+
+    /// Converts a vector from the local coordinates of the basis into world space. Inverse of [`ONB::to_local`].
+    #[must_use]
+    pub fn to_world(&self, vec: Vec3) -> Vec3 {
+        vec.x * *self.u + vec.y * *self.v + vec.z * *self.w
+    }
 }
@@ -5,13 +5,12 @@
 use crate::{
     hitable::{Hitable, HitableTrait},
     onb::ONB,
-    random::{random_cosine_direction, random_unit_vector},
+    random::{remap_choice, sample_cosine_direction, sample_unit_vector, BounceSample},
     wavelength::Wavelength,
     Box, Direction, Float, Position, PI,
 };
 use enum_dispatch::enum_dispatch;
 use rand::rngs::SmallRng;
-use rand::Rng;
 
 #[enum_dispatch(PDFTrait)]
 #[derive(Debug, Clone)]
@@ -34,7 +33,7 @@ pub trait PDFTrait {
     ) -> Float;
 
     // TODO: verify correctness & explain for all impls
-    fn generate(&self, rng: &mut SmallRng) -> Position;
+    fn generate(&self, sample: BounceSample) -> Position;
 }
 
 #[derive(Debug, Clone)]
@@ -67,21 +66,27 @@ impl PDFTrait for CosinePDF {
         }
     }
 
-    fn generate(&self, rng: &mut SmallRng) -> Position {
-        *self.uvw.local(random_cosine_direction(rng))
+    fn generate(&self, sample: BounceSample) -> Position {
+        *self.uvw.local(sample_cosine_direction(sample.scatter))
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct HitablePDF<'scene> {
     origin: Position,
+    time: Float,
     hitable: &'scene Hitable<'scene>,
 }
 
 impl<'scene> HitablePDF<'scene> {
+    /// Creates a PDF for sampling directions towards the `hitable` from the `origin`, with moving hitables in their position at the given `time`
     #[must_use]
-    pub fn new(hitable: &'scene Hitable, origin: Position) -> Self {
-        HitablePDF { origin, hitable }
+    pub fn new(hitable: &'scene Hitable, origin: Position, time: Float) -> Self {
+        HitablePDF {
+            origin,
+            time,
+            hitable,
+        }
     }
 }
 
@@ -97,8 +102,8 @@ impl PDFTrait for HitablePDF<'_> {
             .pdf_value(self.origin, direction, wavelength, time, rng)
     }
 
-    fn generate(&self, rng: &mut SmallRng) -> Position {
-        self.hitable.random(self.origin, rng)
+    fn generate(&self, sample: BounceSample) -> Position {
+        self.hitable.random(self.origin, sample.light, self.time)
     }
 }
 
@@ -131,11 +136,14 @@ impl PDFTrait for MixturePDF<'_> {
             + 0.5 * self.pdf2.value(direction, wavelength, time, rng)
     }
 
-    fn generate(&self, rng: &mut SmallRng) -> Position {
-        if rng.random::<bool>() {
-            self.pdf1.generate(rng)
+    fn generate(&self, sample: BounceSample) -> Position {
+        // The remapped choice stays available for nested mixtures
+        let (first, mixture) = remap_choice(sample.mixture, 0.5);
+        let sample = BounceSample { mixture, ..sample };
+        if first {
+            self.pdf1.generate(sample)
         } else {
-            self.pdf2.generate(rng)
+            self.pdf2.generate(sample)
         }
     }
 }
@@ -161,9 +169,9 @@ impl PDFTrait for SpherePDF {
         1.0 / (4.0 * PI)
     }
 
-    fn generate(&self, rng: &mut SmallRng) -> Position {
+    fn generate(&self, sample: BounceSample) -> Position {
         // TODO: verify correctness! radius?
-        *random_unit_vector(rng)
+        *sample_unit_vector(sample.scatter)
     }
 }
 
@@ -189,8 +197,8 @@ impl PDFTrait for ZeroPDF {
         0.0
     }
 
-    fn generate(&self, rng: &mut SmallRng) -> Position {
-        *random_unit_vector(rng)
+    fn generate(&self, sample: BounceSample) -> Position {
+        *sample_unit_vector(sample.scatter)
     }
 }
 
@@ -199,3 +207,34 @@ impl Default for ZeroPDF {
         Self::new()
     }
 }
+
+/// Multiple importance sampling weight of a sample drawn from the distribution with density `pdf`, when the same direction could also have been drawn from the distribution with density `other_pdf`. Uses the power heuristic with an exponent of two, as described in [Physically Based Rendering, 2.2.1](https://pbr-book.org/4ed/Monte_Carlo_Integration/Improving_Efficiency#MultipleImportanceSampling).
+#[must_use]
+pub fn power_heuristic(pdf: Float, other_pdf: Float) -> Float {
+    let f = pdf * pdf;
+    let g = other_pdf * other_pdf;
+    if f + g > 0.0 {
+        f / (f + g)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn power_heuristic_weights_sum_to_one() {
+        let (a, b) = (0.3, 1.7);
+        let sum = power_heuristic(a, b) + power_heuristic(b, a);
+        assert!((sum - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn power_heuristic_single_distribution() {
+        assert!((power_heuristic(0.5, 0.0) - 1.0).abs() < 1e-6);
+        assert!((power_heuristic(0.0, 0.5) - 0.0).abs() < 1e-6);
+        assert!((power_heuristic(0.0, 0.0) - 0.0).abs() < 1e-6);
+    }
+}
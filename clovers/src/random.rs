@@ -6,6 +6,70 @@ use rand::rngs::SmallRng;
 use rand::Rng;
 use rand_distr::{Distribution, UnitDisc, UnitSphere};
 
+/// Largest float below one. Used for clamping sample values into the range `[0..1)`.
+pub const ONE_MINUS_EPSILON: Float = 1.0 - Float::EPSILON / 2.0;
+
+/// Uniform sample values for the random decisions made at a single bounce of a path. Provided by the sampler of the renderer, so that the benefits of well-distributed samples extend beyond the camera ray. All values are in the range `[0..1)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BounceSample {
+    /// Sample for the direction of the scattering distribution of the material
+    pub scatter: Vec2,
+    /// Sample for the position on the priority objects, when sampling the light
+    pub light: Vec2,
+    /// Choice between the distributions of a [`MixturePDF`](crate::pdf::MixturePDF)
+    pub mixture: Float,
+    /// Choice between the lobes of a material, such as reflection and refraction
+    pub lobe: Float,
+}
+
+impl BounceSample {
+    /// Creates a new `BounceSample` from the random number generator
+    #[must_use]
+    pub fn random(rng: &mut SmallRng) -> Self {
+        BounceSample {
+            scatter: Vec2::new(rng.random(), rng.random()),
+            light: Vec2::new(rng.random(), rng.random()),
+            mixture: rng.random(),
+            lobe: rng.random(),
+        }
+    }
+}
+
+/// Chooses between two options with the given probability of picking the first one. Returns the choice, and the sample remapped back into the range `[0..1)` for further use.
+#[must_use]
+pub fn remap_choice(sample: Float, probability: Float) -> (bool, Float) {
+    if sample < probability {
+        (true, (sample / probability).min(ONE_MINUS_EPSILON))
+    } else {
+        (
+            false,
+            ((sample - probability) / (1.0 - probability)).min(ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+/// Maps a uniform sample onto a uniformly distributed direction.
+#[must_use]
+pub fn sample_unit_vector(sample: Vec2) -> Direction {
+    let z = 1.0 - 2.0 * sample.y;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * sample.x;
+    Unit::new_normalize(Vec3::new(r * phi.cos(), r * phi.sin(), z))
+}
+
+/// Maps a uniform sample onto a cosine-weighted direction around the `z` axis.
+#[must_use]
+pub fn sample_cosine_direction(sample: Vec2) -> Direction {
+    let z = (1.0 - sample.y).sqrt();
+
+    let phi = 2.0 * PI * sample.x;
+    let x = phi.cos() * sample.y.sqrt();
+    let y = phi.sin() * sample.y.sqrt();
+
+    let v: Vec3 = Vec3::new(x, y, z);
+    Unit::new_unchecked(v)
+}
+
 /// Internal helper.
 #[must_use]
 pub fn random_unit_vector(rng: &mut SmallRng) -> Direction {
@@ -23,16 +87,7 @@ pub fn random_in_unit_disk(rng: &mut SmallRng) -> Vec2 {
 /// Internal helper.
 #[must_use]
 pub fn random_cosine_direction(rng: &mut SmallRng) -> Direction {
-    let r1: Float = rng.random();
-    let r2: Float = rng.random();
-    let z = (1.0 - r2).sqrt();
-
-    let phi = 2.0 * PI * r1;
-    let x = phi.cos() * r2.sqrt();
-    let y = phi.sin() * r2.sqrt();
-
-    let v: Vec3 = Vec3::new(x, y, z);
-    Unit::new_unchecked(v)
+    sample_cosine_direction(Vec2::new(rng.random(), rng.random()))
 }
 
 /// Internal helper.
@@ -46,3 +101,20 @@ pub fn random_on_hemisphere(normal: Vec3, rng: &mut SmallRng) -> Direction {
         -in_unit_sphere
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remapped_choice_covers_range() {
+        let (first, remapped) = remap_choice(0.1, 0.25);
+        assert!(first);
+        assert!((remapped - 0.4).abs() < 1e-6);
+        let (first, remapped) = remap_choice(0.625, 0.25);
+        assert!(!first);
+        assert!((remapped - 0.5).abs() < 1e-6);
+        // The end of the range stays below one
+        assert!(remap_choice(ONE_MINUS_EPSILON, 0.5).1 < 1.0);
+    }
+}
@@ -1,5 +1,7 @@
 //! Textures enable different surface textures for colorizing objects in various ways.
 
+#[cfg(feature = "image")]
+pub mod image_texture;
 pub mod solid_color;
 pub mod spatial_checker;
 pub mod surface_checker;
@@ -8,6 +10,8 @@ pub mod surface_checker;
 use crate::illuminants::*;
 use crate::materials::gltf::GLTFMaterial;
 use enum_dispatch::enum_dispatch;
+#[cfg(feature = "image")]
+pub use image_texture::*;
 pub use solid_color::*;
 pub use spatial_checker::*;
 pub use surface_checker::*;
@@ -26,6 +30,9 @@ pub enum Texture {
     SpatialChecker(SpatialChecker),
     /// `SurfaceChecker` texture
     SurfaceChecker(SurfaceChecker),
+    /// `ImageTexture` texture
+    #[cfg(feature = "image")]
+    ImageTexture(ImageTexture),
     /// GLTF material as a texture - a bit of a hack
     #[cfg(feature = "gltf")]
     #[cfg_attr(feature = "serde-derive", serde(skip))]
@@ -0,0 +1,102 @@
+//! A texture backed by an image file, sampled using the surface coordinates of an object.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use palette::{chromatic_adaptation::AdaptInto, white_point::E, Srgb, Xyz};
+
+use super::TextureTrait;
+use crate::ray::Ray;
+use crate::spectrum::spectral_power;
+use crate::wavelength::Wavelength;
+use crate::{Float, HitRecord};
+
+/// Initialization structure for an image texture.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImageTextureInit {
+    /// Path of the image file. Any format supported by the `image` crate with the enabled features can be used.
+    pub path: String,
+}
+
+impl TryFrom<ImageTextureInit> for ImageTexture {
+    type Error = image::ImageError;
+
+    fn try_from(value: ImageTextureInit) -> Result<Self, Self::Error> {
+        ImageTexture::new(value.path)
+    }
+}
+
+impl From<ImageTexture> for ImageTextureInit {
+    fn from(value: ImageTexture) -> Self {
+        ImageTextureInit { path: value.path }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-derive", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde-derive",
+    serde(try_from = "ImageTextureInit", into = "ImageTextureInit")
+)]
+/// A texture backed by an image file. The surface coordinates `u` and `v` of the [`HitRecord`] are mapped onto the image, with `v = 0` at the bottom edge. Coordinates outside of the `0..1` range repeat the image.
+pub struct ImageTexture {
+    /// Path of the image file the texture was loaded from.
+    pub path: String,
+    width: usize,
+    height: usize,
+    /// Pixels of the image, row by row starting from the top-left corner
+    pixels: Vec<Xyz<E>>,
+}
+
+impl ImageTexture {
+    /// Loads a new image texture from the given path.
+    ///
+    /// # Errors
+    /// Returns an error if the image file cannot be opened or decoded.
+    pub fn new(path: impl Into<String>) -> Result<Self, image::ImageError> {
+        let path: String = path.into();
+        let image = image::open(&path)?.into_rgb8();
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+        let pixels = image
+            .pixels()
+            .map(|pixel| {
+                let color: Srgb<u8> = Srgb::from_components((pixel[0], pixel[1], pixel[2]));
+                let color: Srgb = color.into_format();
+                let color: Xyz<E> = color.adapt_into();
+                color
+            })
+            .collect();
+
+        Ok(ImageTexture {
+            path,
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Returns the color of the pixel nearest to the given surface coordinates
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn sample(&self, u: Float, v: Float) -> Xyz<E> {
+        // TODO: other wrapping modes, this is "repeat"
+        let u = u - u.floor();
+        let v = v - v.floor();
+        // Image rows start from the top, texture coordinates from the bottom
+        let x = (u * self.width as Float) as usize;
+        let y = ((1.0 - v) * self.height as Float) as usize;
+        let x = x.min(self.width - 1);
+        let y = y.min(self.height - 1);
+        self.pixels[x + self.width * y]
+    }
+}
+
+impl TextureTrait for ImageTexture {
+    /// Evaluates the color of the image at the surface coordinates of the hit.
+    fn color(&self, _ray: &Ray, wavelength: Wavelength, hit_record: &HitRecord) -> Float {
+        let color = self.sample(hit_record.u, hit_record.v);
+        spectral_power(color, wavelength)
+    }
+}
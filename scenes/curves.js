@@ -0,0 +1,161 @@
+let fs = require("fs");
+let path = require("path");
+let random_float = (min, max) => {
+  return Math.random() * (max - min) + min;
+};
+let round = (point) => point.map((x) => Math.round(x * 10) / 10);
+
+let time_0 = 0.0;
+let time_1 = 1.0;
+
+let scene = {
+  time_0,
+  time_1,
+  camera: {
+    look_from: [0.0, 250.0, -900.0],
+    look_at: [0.0, 100.0, 0.0],
+    up: [0.0, 1.0, 0.0],
+    vertical_fov: 40.0,
+    aperture: 0.0,
+    focus_distance: 900.0,
+  },
+  background_color: [0.0, 0.0, 0.0],
+  objects: [],
+  materials: [],
+};
+
+scene.materials.push({
+  name: "floor",
+  kind: "Lambertian",
+  albedo: {
+    kind: "SolidColor",
+    color: [0.5, 0.5, 0.5],
+  },
+});
+scene.objects.push({
+  kind: "Quad",
+  q: [-2000.0, 0.0, -1000.0],
+  u: [4000.0, 0.0, 0.0],
+  v: [0.0, 0.0, 3000.0],
+  material: "floor",
+});
+
+scene.materials.push({
+  name: "lamp",
+  kind: "DiffuseLight",
+  emit: {
+    kind: "SolidColor",
+    color: [3.0, 3.0, 3.0],
+  },
+});
+scene.objects.push({
+  kind: "Disk",
+  priority: true,
+  center: [0.0, 700.0, -100.0],
+  normal: [0.0, -1.0, 0.0],
+  radius: 250.0,
+  material: "lamp",
+});
+
+// Tufts of hair, each strand arching outwards and drooping towards the tip
+let tuft = (center_x, material) => {
+  let curves = [];
+  for (let i = 0; i < 200; i++) {
+    let angle = random_float(0.0, 2.0 * Math.PI);
+    let distance = random_float(0.0, 25.0);
+    let x = center_x + distance * Math.cos(angle);
+    let z = distance * Math.sin(angle);
+    let lean = random_float(0.0, 2.0 * Math.PI);
+    let spread = random_float(60.0, 120.0);
+    let height = random_float(180.0, 240.0);
+    let points = [];
+    for (let j = 0; j <= 6; j++) {
+      let t = j / 6;
+      points.push(
+        round([
+          x + spread * t * t * Math.cos(lean),
+          (height * Math.sin(t * Math.PI * 0.6)) / Math.sin(Math.PI * 0.6),
+          z + spread * t * t * Math.sin(lean),
+        ])
+      );
+    }
+    curves.push({ points, widths: [1.5, 0.5] });
+  }
+  scene.objects.push({ kind: "Curves", curves, material });
+};
+
+scene.materials.push({ name: "blonde", kind: "Hair", eumelanin: 0.3 });
+tuft(-250.0, "blonde");
+scene.materials.push({ name: "brown", kind: "Hair", eumelanin: 1.3 });
+tuft(0.0, "brown");
+scene.materials.push({
+  name: "red",
+  kind: "Hair",
+  eumelanin: 0.3,
+  pheomelanin: 2.5,
+});
+tuft(250.0, "red");
+
+// Blades of grass as flat ribbons
+scene.materials.push({
+  name: "grass",
+  kind: "Lambertian",
+  albedo: {
+    kind: "SolidColor",
+    color: [0.2, 0.6, 0.1],
+  },
+});
+let blades = [];
+for (let i = 0; i < 300; i++) {
+  let x = random_float(-450.0, 450.0);
+  let z = random_float(-250.0, -120.0);
+  let lean = random_float(-30.0, 30.0);
+  let height = random_float(40.0, 90.0);
+  blades.push({
+    points: [
+      [x, 0.0, z],
+      [x, height * 0.4, z],
+      [x + lean * 0.5, height * 0.8, z],
+      [x + lean, height, z],
+    ].map(round),
+    widths: [4.0, 0.5],
+  });
+}
+scene.objects.push({
+  kind: "Curves",
+  mode: "Ribbon",
+  curves: blades,
+  material: "grass",
+});
+
+// A cable looping over the hair
+scene.materials.push({
+  name: "copper",
+  kind: "Metal",
+  albedo: {
+    kind: "SolidColor",
+    color: { hex: "#b87333" },
+  },
+  fuzz: 0.2,
+});
+scene.objects.push({
+  kind: "Curves",
+  curves: [
+    {
+      points: [
+        [-450.0, 6.0, 200.0],
+        [-250.0, 6.0, 100.0],
+        [-150.0, 200.0, 300.0],
+        [0.0, 150.0, 250.0],
+        [150.0, 100.0, 200.0],
+        [250.0, 6.0, 100.0],
+        [450.0, 6.0, 200.0],
+      ],
+      widths: [12.0, 12.0],
+    },
+  ],
+  material: "copper",
+});
+
+let json = JSON.stringify(scene);
+fs.writeFileSync(path.join(__dirname, "curves.json"), json);